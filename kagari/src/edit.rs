@@ -0,0 +1,100 @@
+//! # edit
+//! 修改Kagari.toml中的依赖，保留原有的格式和注释
+use toml_edit::{table, Document, InlineTable, Item, Value};
+
+use crate::manifest::Dependency;
+
+fn parse(text: &str) -> Result<Document, String> {
+    text.parse::<Document>()
+        .map_err(|e| format!("配置文件解析错误:{}", e))
+}
+
+/// 添加依赖，已经存在的同名依赖会被替换
+pub fn add_dep(text: &str, name: &str, dep: &Dependency) -> Result<String, String> {
+    let mut doc = parse(text)?;
+    let deps = doc
+        .entry("deps")
+        .or_insert(table())
+        .as_table_like_mut()
+        .ok_or("Kagari.toml中的deps不是表")?;
+    let mut value = InlineTable::new();
+    if let Some(version) = &dep.version {
+        value.insert("version", version.into());
+    }
+    if !dep.path.is_empty() {
+        value.insert("path", dep.path.as_str().into());
+    }
+    if let Some(git) = &dep.git {
+        value.insert("git", git.into());
+    }
+    if let Some(head) = &dep.head {
+        value.insert("head", head.into());
+    }
+    value.fmt();
+    deps.insert(name, Item::Value(Value::InlineTable(value)));
+    Ok(doc.to_string())
+}
+
+/// 删除依赖，依赖不存在时报错
+pub fn remove_dep(text: &str, name: &str) -> Result<String, String> {
+    let mut doc = parse(text)?;
+    doc.get_mut("deps")
+        .and_then(Item::as_table_like_mut)
+        .and_then(|deps| deps.remove(name))
+        .ok_or_else(|| format!("Kagari.toml中没有依赖{}", name))?;
+    Ok(doc.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Config;
+
+    #[test]
+    fn test_edit_deps() {
+        let text = r#"# 项目配置
+project = "app"   # 项目名
+entry = "main.pi"
+
+[deps]
+# 本地依赖
+b = { path = "b" }
+"#;
+        let added = add_dep(
+            text,
+            "foo",
+            &Dependency {
+                version: Some("^1.2".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(added.starts_with("# 项目配置\nproject = \"app\"   # 项目名\n"));
+        assert!(added.contains("# 本地依赖\nb = { path = \"b\" }\n"));
+        assert!(added.contains("foo = { version = \"^1.2\" }"));
+        let config: Config = toml::from_str(&added).unwrap();
+        assert_eq!(config.deps.as_ref().unwrap().len(), 2);
+
+        let removed = remove_dep(&added, "b").unwrap();
+        assert!(!removed.contains("path = \"b\""));
+        assert!(removed.contains("project = \"app\"   # 项目名"));
+        assert!(remove_dep(&removed, "b").is_err());
+
+        // 没有[deps]时新建
+        let added = add_dep(
+            "project = \"app\"\nentry = \"main.pi\"\n",
+            "pl_test",
+            &Dependency {
+                git: Some("https://github.com/Pivot-Studio/pl_test.git".to_string()),
+                head: Some("main".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&added).unwrap();
+        assert_eq!(
+            config.deps.unwrap()["pl_test"].head.as_deref(),
+            Some("main")
+        );
+    }
+}
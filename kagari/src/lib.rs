@@ -3,17 +3,31 @@ use std::{
     process::{Command, Output},
 };
 
-pub fn download_repo(
-    repo_url: &str,
-    target_dir: &str,
-) -> (Option<Result<Output, std::io::Error>>, PathBuf) {
+pub mod edit;
+pub mod lockfile;
+pub mod manifest;
+pub mod registry;
+pub mod resolve;
+pub mod source;
+pub mod tree;
+pub mod vendor;
+
+/// git仓库克隆到的目录，各个commit的副本在它的上级目录中
+pub fn repo_dir(repo_url: &str, target_dir: &str) -> PathBuf {
     let branch = "default";
     let sub_dir = repo_url
         .strip_prefix("http://")
         .or_else(|| repo_url.strip_prefix("https://"))
         .expect("Invalid repo url")
         .trim_end_matches(".git");
-    let target_dir = Path::new(target_dir).join(sub_dir).join(branch);
+    Path::new(target_dir).join(sub_dir).join(branch)
+}
+
+pub fn download_repo(
+    repo_url: &str,
+    target_dir: &str,
+) -> (Option<Result<Output, std::io::Error>>, PathBuf) {
+    let target_dir = repo_dir(repo_url, target_dir);
     if !target_dir.exists() {
         std::fs::create_dir_all(&target_dir).expect("Failed to create target directory");
     } else {
@@ -0,0 +1,186 @@
+//! # lockfile
+//! Kagari.lock，记录依赖解析的结果
+//!
+//! 每个依赖包（包括间接依赖）一项，记录版本、来源、校验和以及它依赖的包，
+//! 只要Kagari.toml没有变化，之后的构建都会使用这里记录的版本
+use std::{
+    collections::BTreeMap,
+    fs::{self, read_to_string},
+    io,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const LOCKFILE: &str = "Kagari.lock";
+const LOCKFILE_VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Lockfile {
+    pub version: u32,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            packages: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LockedPackage {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// 见[`SourceId`](crate::source::SourceId)
+    pub source: String,
+    /// 本地目录中的依赖随时可能被修改，没有校验和
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+}
+
+/// 旧版本的Kagari.lock只记录了git依赖的commit
+#[derive(Deserialize)]
+struct LegacySum {
+    name: String,
+    git: Option<LegacyGitInfo>,
+}
+
+#[derive(Deserialize)]
+struct LegacyGitInfo {
+    url: String,
+    commit: String,
+}
+
+impl Lockfile {
+    /// 读取Kagari.lock，文件不存在时返回`None`
+    pub fn load(path: &Path) -> Result<Option<Lockfile>, String> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Ok(lock) = toml::from_str::<Lockfile>(&text) {
+            return Ok(Some(lock));
+        }
+        let legacy = toml::from_str::<BTreeMap<String, LegacySum>>(&text)
+            .map_err(|e| format!("{}解析错误:{}", path.display(), e))?;
+        let packages = legacy
+            .into_values()
+            .filter_map(|sum| {
+                let git = sum.git?;
+                Some(LockedPackage {
+                    name: sum.name,
+                    version: None,
+                    source: format!("git+{}#{}", git.url, git.commit),
+                    checksum: None,
+                    dependencies: vec![],
+                })
+            })
+            .collect();
+        Ok(Some(Lockfile {
+            version: LOCKFILE_VERSION,
+            packages,
+        }))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string_pretty(self).map_err(|e| format!("error: {:?}", e))?;
+        fs::write(path, text).map_err(|e| format!("error: {:?}", e))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LockedPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+}
+
+/// 目录中所有文件的sha256，文件按相对路径排序，忽略`.git`、`target`和Kagari.lock
+pub fn checksum(dir: &Path) -> io::Result<String> {
+    let mut files = vec![];
+    collect_files(dir, dir, &mut files)?;
+    files.sort();
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(file.as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(dir.join(&file))?);
+        hasher.update([0]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default();
+        if name == ".git" || name == "target" || name == LOCKFILE {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            let rel = path.strip_prefix(root).unwrap();
+            // 不同平台上的校验和相同
+            let rel = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push(rel);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockfile() {
+        let dir = std::env::temp_dir().join("kagari_test_lockfile");
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let lock = dir.join(LOCKFILE);
+        assert_eq!(Lockfile::load(&lock).unwrap(), None);
+        fs::write(
+            &lock,
+            r#"[pl_test]
+name = "pl_test"
+
+[pl_test.git]
+url = "https://github.com/Pivot-Studio/pl_test.git"
+commit = "ffcf2de"
+"#,
+        )
+        .unwrap();
+        let legacy = Lockfile::load(&lock).unwrap().unwrap();
+        assert_eq!(
+            legacy.get("pl_test").unwrap().source,
+            "git+https://github.com/Pivot-Studio/pl_test.git#ffcf2de"
+        );
+        legacy.save(&lock).unwrap();
+        assert_eq!(Lockfile::load(&lock).unwrap().unwrap(), legacy);
+
+        fs::write(dir.join("main.pi"), "fn main() i64 {}").unwrap();
+        fs::write(dir.join("src").join("a.pi"), "a").unwrap();
+        let sum = checksum(&dir).unwrap();
+        assert_eq!(sum.len(), 64);
+        // Kagari.lock不影响校验和
+        fs::write(&lock, "").unwrap();
+        assert_eq!(checksum(&dir).unwrap(), sum);
+        fs::write(dir.join("src").join("a.pi"), "b").unwrap();
+        assert_ne!(checksum(&dir).unwrap(), sum);
+        _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,3 +1,216 @@
+use std::{
+    env,
+    fs::{self, read_to_string},
+    path::Path,
+    process::exit,
+};
+
+use clap::{Parser, Subcommand};
+use colored::Colorize;
+use kagari::{
+    edit::{add_dep, remove_dep},
+    manifest::{find_root, find_workspace, Config, Dependency, MANIFEST},
+    registry::Registry,
+    resolve::{resolve, update, Resolve, ResolveOptions},
+    tree::render,
+    vendor::{vendor, VENDOR_REGISTRY},
+};
+
+/// Pivot Lang package manager
+#[derive(Parser)]
+#[command(author, version, about)]
+struct Cli {
+    /// require Kagari.lock to be up to date, fail instead of updating it
+    #[arg(long, global = true)]
+    locked: bool,
+
+    /// never access the network, only use dependencies downloaded before
+    #[arg(long, global = true)]
+    offline: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Add a dependency to Kagari.toml
+    Add {
+        /// Name of the dependency
+        #[arg(value_parser)]
+        name: String,
+        /// Git repository url
+        #[arg(long, value_parser, conflicts_with = "path")]
+        git: Option<String>,
+        /// Branch, tag or commit of the git repository, defaults to HEAD
+        #[arg(long, value_parser, requires = "git")]
+        head: Option<String>,
+        /// Local directory of the dependency
+        #[arg(long, value_parser)]
+        path: Option<String>,
+        /// Version requirement, defaults to the latest version in the registry
+        #[arg(long, value_parser)]
+        version: Option<String>,
+    },
+    /// Remove dependencies from Kagari.toml
+    Remove {
+        /// Names of the dependencies
+        #[arg(value_parser, required = true)]
+        names: Vec<String>,
+    },
+    /// Update dependencies in Kagari.lock to the latest matching versions
+    Update {
+        /// Only update this package
+        #[arg(value_parser)]
+        name: Option<String>,
+    },
+    /// Print the dependency tree
+    Tree,
+    /// Copy all dependencies into a local directory
+    Vendor {
+        /// Directory to copy into, relative to the project root
+        #[arg(value_parser, default_value = "vendor")]
+        dir: String,
+    },
+}
+
 fn main() {
-    println!("Hello, world!");
+    let cli = Cli::parse();
+    let options = ResolveOptions {
+        locked: cli.locked,
+        offline: cli.offline,
+    };
+    if let Err(e) = run(cli.command, options) {
+        eprintln!("{} {}", "error:".red().bold(), e);
+        exit(1);
+    }
+}
+
+fn run(command: Command, options: ResolveOptions) -> Result<(), String> {
+    let cwd = env::current_dir().map_err(|e| e.to_string())?;
+    let root = find_root(&cwd)
+        .ok_or("找不到配置文件～")?
+        .canonicalize()
+        .map_err(|e| e.to_string())?;
+    let config = Config::load(&root)?;
+    // 工作区的成员与整个工作区一起解析依赖
+    let (ws_root, ws_config) = find_workspace(&root).unwrap_or_else(|| (root.clone(), config));
+    match command {
+        Command::Add {
+            name,
+            git,
+            head,
+            path,
+            version,
+        } => {
+            let version = match version {
+                Some(version) => Some(version),
+                None if git.is_none() && path.is_none() => {
+                    Some(latest_version(&ws_root, &ws_config, &name)?)
+                }
+                None => None,
+            };
+            let dep = Dependency {
+                version,
+                path: path.unwrap_or_default(),
+                head: git
+                    .as_ref()
+                    .map(|_| head.unwrap_or_else(|| "HEAD".to_string())),
+                git,
+            };
+            edit_manifest(&root, &ws_root, options, |text| add_dep(text, &name, &dep))?;
+            println!("{} {}", "Added".green().bold(), name);
+        }
+        Command::Remove { names } => {
+            edit_manifest(&root, &ws_root, options, |text| {
+                names
+                    .iter()
+                    .try_fold(text.to_string(), |text, name| remove_dep(&text, name))
+            })?;
+            println!("{} {}", "Removed".green().bold(), names.join(", "));
+        }
+        Command::Update { name } => {
+            let third_party = ws_config.third_party_dir(&ws_root)?;
+            update(&ws_root, &ws_config, &third_party, options, name.as_deref())
+                .map_err(|e| e.to_string())?;
+            println!("{} Kagari.lock", "Updated".green().bold());
+        }
+        Command::Tree => {
+            let resolve = resolve_project(&ws_root, &ws_config, options)?;
+            let name = if ws_config.project.is_empty() {
+                "workspace"
+            } else {
+                &ws_config.project
+            };
+            print!("{}", render(&resolve, name, |s| s.yellow().to_string()));
+        }
+        Command::Vendor { dir } => {
+            let resolve = resolve_project(&ws_root, &ws_config, options)?;
+            let third_party = ws_config.third_party_dir(&ws_root)?;
+            let vendored = vendor(
+                &ws_root,
+                &ws_config,
+                &resolve,
+                &third_party,
+                &ws_root.join(&dir),
+            )?;
+            println!(
+                "{} {}个git依赖和{}个包索引中的依赖到{}",
+                "Vendored".green().bold(),
+                vendored.git,
+                vendored.registry,
+                dir
+            );
+            println!("在Kagari.toml中加入以下配置后，构建时将使用复制的依赖：\n");
+            println!("vendor = \"{}\"", dir);
+            if vendored.registry > 0 {
+                println!(
+                    "registry = \"{}\"",
+                    Path::new(&dir).join(VENDOR_REGISTRY).display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn resolve_project(
+    root: &Path,
+    config: &Config,
+    options: ResolveOptions,
+) -> Result<Resolve, String> {
+    let third_party = config.third_party_dir(root)?;
+    resolve(root, config, &third_party, options).map_err(|e| e.to_string())
+}
+
+/// 包索引中没有被撤回的最新版本，作为版本要求`^<version>`
+fn latest_version(root: &Path, config: &Config, name: &str) -> Result<String, String> {
+    let registry = Registry::from_config(root, config).ok_or(
+        "没有配置包索引，请使用--git或者--path，或者设置Kagari.toml中的registry或者环境变量KAGARI_REGISTRY",
+    )?;
+    registry
+        .versions(name)?
+        .into_iter()
+        .find(|(_, entry)| !entry.yanked)
+        .map(|(v, _)| format!("^{}", v))
+        .ok_or_else(|| format!("包索引中找不到包{}", name))
+}
+
+/// 修改`root`下的Kagari.toml并重新解析`ws_root`的依赖，解析失败时恢复原来的Kagari.toml
+fn edit_manifest(
+    root: &Path,
+    ws_root: &Path,
+    options: ResolveOptions,
+    edit: impl FnOnce(&str) -> Result<String, String>,
+) -> Result<(), String> {
+    let manifest = root.join(MANIFEST);
+    let text = read_to_string(&manifest).map_err(|e| e.to_string())?;
+    let edited = edit(&text)?;
+    fs::write(&manifest, &edited).map_err(|e| e.to_string())?;
+    let re = Config::load(ws_root).and_then(|config| resolve_project(ws_root, &config, options));
+    if let Err(e) = re {
+        fs::write(&manifest, &text).map_err(|e| e.to_string())?;
+        return Err(e);
+    }
+    Ok(())
 }
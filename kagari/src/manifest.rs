@@ -0,0 +1,156 @@
+//! # manifest
+//! 项目的配置文件`Kagari.toml`
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+pub const MANIFEST: &str = "Kagari.toml";
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Default, Hash)]
+pub struct Config {
+    /// 只有`[workspace]`的Kagari.toml没有`project`和`entry`
+    #[serde(default)]
+    pub project: String,
+    /// 包的版本，被其他项目依赖时与依赖的版本要求匹配
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub entry: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deps: Option<BTreeMap<String, Dependency>>,
+    /// `plc build`输出产物的目录，相对于项目根目录，默认为`target`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_dir: Option<String>,
+    /// 本地包索引所在的目录，相对于项目根目录，没有设置时使用环境变量`KAGARI_REGISTRY`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// `kagari vendor`复制git依赖的目录，相对于项目根目录，设置后git依赖从这里读取
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<Workspace>,
+    #[serde(skip)]
+    pub root: String,
+}
+
+impl Config {
+    /// 读取`dir`下的Kagari.toml
+    pub fn load(dir: &Path) -> Result<Config, String> {
+        let path = dir.join(MANIFEST);
+        read_to_string(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+            .and_then(|s| toml::from_str(&s).map_err(|e| format!("配置文件解析错误:{:?}", e)))
+    }
+
+    /// 工作区成员的目录，不是工作区时为空
+    pub fn members(&self, root: &Path) -> Vec<PathBuf> {
+        self.workspace
+            .iter()
+            .flat_map(|ws| &ws.members)
+            .map(|m| {
+                let dir = root.join(m);
+                dir.canonicalize().unwrap_or(dir)
+            })
+            .collect()
+    }
+
+    /// git依赖下载到的目录，设置了`vendor`时为项目中的目录，否则为`$KAGARI_LIB_ROOT/thirdparty`
+    pub fn third_party_dir(&self, root: &Path) -> Result<PathBuf, String> {
+        match &self.vendor {
+            Some(vendor) => Ok(root.join(vendor)),
+            None => env::var("KAGARI_LIB_ROOT")
+                .map(|r| PathBuf::from(r).join("thirdparty"))
+                .map_err(|_| "未设置环境变量KAGARI_LIB_ROOT，无法找到系统库".to_string()),
+        }
+    }
+}
+
+/// 工作区，成员共用根目录下的Kagari.lock和产物目录
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Default, Hash)]
+pub struct Workspace {
+    /// 成员的目录，相对于工作区的根目录
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+/// 查找项目所在的工作区，返回工作区的根目录和配置
+///
+/// 项目本身就是工作区的根目录，或者是某个上级目录中工作区的成员
+pub fn find_workspace(project: &Path) -> Option<(PathBuf, Config)> {
+    let project = project.canonicalize().ok()?;
+    project.ancestors().find_map(|dir| {
+        if !dir.join(MANIFEST).is_file() {
+            return None;
+        }
+        let config = Config::load(dir).ok()?;
+        config.workspace.as_ref()?;
+        if dir == project || config.members(dir).contains(&project) {
+            Some((dir.to_path_buf(), config))
+        } else {
+            None
+        }
+    })
+}
+
+/// 从`dir`向上查找Kagari.toml，返回项目根目录
+pub fn find_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|d| d.join(MANIFEST).is_file())
+        .map(Path::to_path_buf)
+}
+
+/// 依赖项，`git`、`path`和`version`依次决定依赖的来源：
+/// 有`git`时从git仓库下载，有`path`时使用本地目录，否则从包索引中查找满足`version`的版本
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Default, Hash)]
+pub struct Dependency {
+    /// 版本要求，语法与cargo相同，例如`^1.2`、`~0.3.1`、`>=1, <2`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    /// git依赖的分支、tag或者commit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_find_workspace() {
+        let tmp = env::temp_dir().join("kagari_test_workspace");
+        _ = fs::remove_dir_all(&tmp);
+        for (dir, text) in [
+            ("", "[workspace]\nmembers = [\"app\", \"lib\"]\n"),
+            ("app", "project = \"app\"\nentry = \"main.pi\"\n"),
+            ("lib", "project = \"lib\"\nentry = \"lib.pi\"\n"),
+            ("other", "project = \"other\"\nentry = \"main.pi\"\n"),
+        ] {
+            fs::create_dir_all(tmp.join(dir)).unwrap();
+            fs::write(tmp.join(dir).join(MANIFEST), text).unwrap();
+        }
+        let tmp = tmp.canonicalize().unwrap();
+        let (root, config) = find_workspace(&tmp.join("app")).unwrap();
+        assert_eq!(root, tmp);
+        assert!(config.project.is_empty());
+        assert_eq!(
+            config.members(&root),
+            vec![tmp.join("app"), tmp.join("lib")]
+        );
+        assert_eq!(find_workspace(&tmp).unwrap().0, tmp);
+        // 不是成员的项目不属于工作区
+        assert!(find_workspace(&tmp.join("other")).is_none());
+        assert_eq!(find_root(&tmp.join("lib")), Some(tmp.join("lib")));
+        _ = fs::remove_dir_all(&tmp);
+    }
+}
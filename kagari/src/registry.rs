@@ -0,0 +1,137 @@
+//! # registry
+//! 基于本地目录的包索引
+//!
+//! 索引目录的结构如下，`index`下每个包一个文件，记录它所有的版本：
+//! ```text
+//! registry
+//! ├── index
+//! │   └── foo.toml
+//! └── packages
+//!     └── foo
+//!         └── 0.1.0
+//!             ├── Kagari.toml
+//!             └── ...
+//! ```
+//! `foo.toml`的内容：
+//! ```toml
+//! [[versions]]
+//! version = "0.1.0"
+//! checksum = "..."
+//! deps = { bar = "^1.0" }
+//! ```
+//! 版本的源码默认在`packages/<name>/<version>`，也可以用`path`指定其他目录，
+//! 或者用`git`和`commit`指定一个git仓库
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, read_to_string},
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::Config;
+
+/// 索引中一个包的一个版本
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct IndexEntry {
+    pub version: String,
+    /// 源码目录，相对于索引的根目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// 源码的校验和，见[`checksum`](crate::lockfile::checksum)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// 依赖的包名和版本要求，依赖也来自这个索引
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub deps: BTreeMap<String, String>,
+    /// 被撤回的版本只有在Kagari.lock中锁定时才会被使用
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+struct IndexFile {
+    #[serde(default)]
+    versions: Vec<IndexEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registry {
+    root: PathBuf,
+}
+
+impl Registry {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// 项目使用的包索引：Kagari.toml中的`registry`，或者环境变量`KAGARI_REGISTRY`
+    pub fn from_config(root: &Path, config: &Config) -> Option<Self> {
+        config
+            .registry
+            .as_ref()
+            .map(|r| root.join(r))
+            .or_else(|| env::var("KAGARI_REGISTRY").ok().map(PathBuf::from))
+            .map(|r| Self::new(r.canonicalize().unwrap_or(r)))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn index_path(&self, name: &str) -> PathBuf {
+        self.root.join("index").join(name).with_extension("toml")
+    }
+
+    fn index_file(&self, name: &str) -> Result<IndexFile, String> {
+        let path = self.index_path(name);
+        if !path.exists() {
+            return Ok(IndexFile::default());
+        }
+        read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| toml::from_str(&s).map_err(|e| e.to_string()))
+            .map_err(|e| format!("包索引{}解析错误:{}", path.display(), e))
+    }
+
+    /// 包的所有版本，从高到低排列，包不存在时返回空列表
+    pub fn versions(&self, name: &str) -> Result<Vec<(Version, IndexEntry)>, String> {
+        let file = self.index_file(name)?;
+        let mut versions = file
+            .versions
+            .into_iter()
+            .map(|entry| {
+                Version::parse(&entry.version)
+                    .map(|v| (v, entry.clone()))
+                    .map_err(|e| format!("包{}的版本{}不合法:{}", name, entry.version, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        versions.sort_by(|a, b| b.0.cmp(&a.0));
+        Ok(versions)
+    }
+
+    /// 在索引中加入一个版本，已有的同名版本会被替换
+    pub fn add_version(&self, name: &str, entry: IndexEntry) -> Result<(), String> {
+        let mut file = self.index_file(name)?;
+        file.versions.retain(|e| e.version != entry.version);
+        file.versions.push(entry);
+        let path = self.index_path(name);
+        fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+        let text = toml::to_string_pretty(&file).map_err(|e| format!("error: {:?}", e))?;
+        fs::write(&path, text).map_err(|e| format!("error: {:?}", e))
+    }
+
+    /// 版本源码所在的目录，git仓库中的版本由调用者下载
+    pub fn source_dir(&self, name: &str, entry: &IndexEntry) -> PathBuf {
+        match &entry.path {
+            Some(path) => self.root.join(path),
+            None => self.root.join("packages").join(name).join(&entry.version),
+        }
+    }
+}
@@ -0,0 +1,965 @@
+//! # resolve
+//! 依赖解析
+//!
+//! 从项目的直接依赖开始，依次加载依赖包并把它们的依赖加入队列。
+//! 同名的包只会有一个版本：来自包索引的包选择满足所有版本要求的最高版本，
+//! Kagari.lock中锁定的版本满足要求时优先使用锁定的版本。
+//! 后加入的版本要求与已经选择的版本不匹配时，带着新的要求重新解析，
+//! 直到所有要求都被满足或者出现冲突
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use semver::{Version, VersionReq};
+
+use crate::{
+    cp_to_hash_dir, download_repo,
+    lockfile::{checksum, LockedPackage, Lockfile, LOCKFILE},
+    manifest::{Config, Dependency},
+    registry::Registry,
+    repo_dir,
+    source::SourceId,
+};
+
+/// 重新解析的最大次数
+const MAX_ROUNDS: usize = 64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ResolveOptions {
+    /// 依赖必须与Kagari.lock一致，需要更新Kagari.lock时报错
+    pub locked: bool,
+    /// 不访问网络，git依赖只能使用已经下载过的版本
+    pub offline: bool,
+}
+
+/// 一个版本要求以及提出它的包
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    pub from: String,
+    pub req: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// 同一个包的版本要求不能同时满足
+    Conflict {
+        name: String,
+        requirements: Vec<Requirement>,
+    },
+    /// 同一个包来自不同的地方，元素是依赖它的包和来源
+    SourceConflict {
+        name: String,
+        sources: Vec<(String, String)>,
+    },
+    /// 没有满足要求的版本
+    NotFound {
+        name: String,
+        requirement: Requirement,
+    },
+    /// 使用`--locked`时Kagari.lock需要更新
+    LockOutdated(String),
+    /// 使用`--offline`时需要访问网络
+    Offline(String),
+    Checksum {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+    Other(String),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::Conflict { name, requirements } => {
+                write!(f, "依赖冲突：包`{}`的版本要求不能同时满足", name)?;
+                for r in requirements {
+                    write!(f, "\n  - `{}`要求`{}`", r.from, r.req)?;
+                }
+                Ok(())
+            }
+            ResolveError::SourceConflict { name, sources } => {
+                write!(f, "依赖冲突：包`{}`来自不同的地方", name)?;
+                for (from, source) in sources {
+                    write!(f, "\n  - `{}`使用`{}`", from, source)?;
+                }
+                Ok(())
+            }
+            ResolveError::NotFound { name, requirement } => write!(
+                f,
+                "找不到满足`{}`的包`{}`，它被`{}`依赖",
+                requirement.req, name, requirement.from
+            ),
+            ResolveError::LockOutdated(msg) => {
+                write!(f, "Kagari.lock需要更新，但是使用了--locked：{}", msg)
+            }
+            ResolveError::Offline(msg) => write!(f, "离线模式下无法下载依赖：{}", msg),
+            ResolveError::Checksum {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "包`{}`的校验和不匹配，期望为{}，实际为{}",
+                name, expected, actual
+            ),
+            ResolveError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedPackage {
+    pub name: String,
+    pub version: Option<Version>,
+    pub source: SourceId,
+    /// 源码所在的目录
+    pub path: PathBuf,
+    pub checksum: Option<String>,
+    /// 依赖的包名
+    pub deps: Vec<String>,
+}
+
+impl ResolvedPackage {
+    /// 用于提示信息的名字，有版本时带上版本
+    pub fn display_name(&self) -> String {
+        match &self.version {
+            Some(v) => format!("{} {}", self.name, v),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// 解析的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Resolve {
+    /// 项目的直接依赖
+    pub root_deps: Vec<String>,
+    /// 工作区的成员，它们也在`packages`中
+    pub members: Vec<String>,
+    /// 所有依赖包，包括间接依赖
+    pub packages: BTreeMap<String, ResolvedPackage>,
+}
+
+impl Resolve {
+    /// 包的依赖和它们的源码目录，`None`表示项目本身
+    pub fn deps_of(&self, name: Option<&str>) -> BTreeMap<String, PathBuf> {
+        let deps = match name {
+            Some(name) => self.packages.get(name).map(|p| &p.deps),
+            None => Some(&self.root_deps),
+        };
+        deps.into_iter()
+            .flatten()
+            .filter_map(|d| Some((d.clone(), self.packages.get(d)?.path.clone())))
+            .collect()
+    }
+
+    pub fn to_lockfile(&self, root: &Path) -> Lockfile {
+        let packages = self
+            .packages
+            .values()
+            .map(|p| {
+                let source = match &p.source {
+                    // 项目中的本地依赖记录相对路径，项目移动后Kagari.lock依然有效
+                    SourceId::Path(path) => match path.strip_prefix(root) {
+                        Ok(rel) => SourceId::Path(rel.to_path_buf()),
+                        Err(_) => p.source.clone(),
+                    },
+                    _ => p.source.clone(),
+                };
+                LockedPackage {
+                    name: p.name.clone(),
+                    version: p.version.as_ref().map(|v| v.to_string()),
+                    source: source.to_string(),
+                    checksum: p.checksum.clone(),
+                    dependencies: p.deps.clone(),
+                }
+            })
+            .collect();
+        Lockfile {
+            packages,
+            ..Default::default()
+        }
+    }
+}
+
+/// 待加载的依赖
+struct Request {
+    from: String,
+    name: String,
+    dep: Dependency,
+    /// 相对路径的依赖相对于这个目录
+    base: PathBuf,
+}
+
+pub struct Resolver<'a> {
+    root: &'a Path,
+    config: &'a Config,
+    /// git依赖下载到的目录
+    third_party: &'a Path,
+    registry: Option<Registry>,
+    lock: Option<Lockfile>,
+    options: ResolveOptions,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(
+        root: &'a Path,
+        config: &'a Config,
+        third_party: &'a Path,
+        options: ResolveOptions,
+    ) -> Result<Self, ResolveError> {
+        let registry = Registry::from_config(root, config);
+        let lock = Lockfile::load(&root.join(LOCKFILE)).map_err(ResolveError::Other)?;
+        Ok(Self {
+            root,
+            config,
+            third_party,
+            registry,
+            lock,
+            options,
+        })
+    }
+
+    /// Kagari.lock中原有的内容
+    pub fn lockfile(&self) -> Option<&Lockfile> {
+        self.lock.as_ref()
+    }
+
+    pub fn resolve(&self) -> Result<Resolve, ResolveError> {
+        let mut reqs = BTreeMap::new();
+        for _ in 0..MAX_ROUNDS {
+            if let Some(resolve) = self.round(&mut reqs)? {
+                return Ok(resolve);
+            }
+        }
+        Err(ResolveError::Other(
+            "依赖解析失败：找不到满足所有版本要求的组合".to_string(),
+        ))
+    }
+
+    /// 解析一次，有新的版本要求与已经选择的版本冲突时返回`None`
+    fn round(
+        &self,
+        reqs: &mut BTreeMap<String, Vec<Requirement>>,
+    ) -> Result<Option<Resolve>, ResolveError> {
+        let mut packages = BTreeMap::<String, ResolvedPackage>::new();
+        // 第一次引入包的依赖者，用于提示来源冲突
+        let mut introduced = BTreeMap::<String, String>::new();
+        let root_reqs = requests(&self.config.project, self.config, self.root);
+        let root_deps = root_reqs.iter().map(|r| r.name.clone()).collect();
+        let member_reqs = self.member_requests()?;
+        let members = member_reqs.iter().map(|r| r.name.clone()).collect();
+        let mut queue = root_reqs
+            .into_iter()
+            .chain(member_reqs)
+            .collect::<VecDeque<_>>();
+        let mut restart = false;
+        while let Some(req) = queue.pop_front() {
+            let source = self.source_of(&req)?;
+            if let Some(v) = &req.dep.version {
+                let r = Requirement {
+                    from: req.from.clone(),
+                    req: v.clone(),
+                };
+                let list = reqs.entry(req.name.clone()).or_default();
+                if !list.contains(&r) {
+                    list.push(r);
+                }
+            }
+            let name_reqs = reqs.get(&req.name).cloned().unwrap_or_default();
+            if let Some(pkg) = packages.get(&req.name) {
+                if !pkg.source.same_origin(&source) {
+                    return Err(ResolveError::SourceConflict {
+                        name: req.name.clone(),
+                        sources: vec![
+                            (introduced[&req.name].clone(), pkg.source.to_string()),
+                            (req.from.clone(), source.to_string()),
+                        ],
+                    });
+                }
+                if !matches_all(&req.name, pkg.version.as_ref(), &name_reqs)? {
+                    if matches!(pkg.source, SourceId::Registry(_)) {
+                        restart = true;
+                        continue;
+                    }
+                    return Err(unsatisfied(&req.name, name_reqs));
+                }
+                continue;
+            }
+            let (pkg, deps) = self.load(&req, source, &name_reqs)?;
+            queue.extend(deps);
+            introduced.insert(req.name.clone(), req.from.clone());
+            packages.insert(req.name.clone(), pkg);
+        }
+        if restart {
+            return Ok(None);
+        }
+        Ok(Some(Resolve {
+            root_deps,
+            members,
+            packages,
+        }))
+    }
+
+    /// 工作区的成员作为本地依赖加入解析，成员之间的本地依赖只会被解析一次
+    fn member_requests(&self) -> Result<Vec<Request>, ResolveError> {
+        let from = if self.config.project.is_empty() {
+            "workspace"
+        } else {
+            &self.config.project
+        };
+        self.config
+            .members(self.root)
+            .into_iter()
+            .map(|dir| {
+                let config = Config::load(&dir).map_err(ResolveError::Other)?;
+                Ok(Request {
+                    from: from.to_string(),
+                    name: config.project,
+                    dep: Dependency {
+                        path: dir.to_string_lossy().to_string(),
+                        ..Default::default()
+                    },
+                    base: self.root.to_path_buf(),
+                })
+            })
+            .collect()
+    }
+
+    fn source_of(&self, req: &Request) -> Result<SourceId, ResolveError> {
+        if let Some(url) = &req.dep.git {
+            return Ok(SourceId::Git {
+                url: url.clone(),
+                commit: String::new(),
+            });
+        }
+        if !req.dep.path.is_empty() {
+            let path = req.base.join(&req.dep.path);
+            if !path.exists() {
+                return Err(ResolveError::Other(format!(
+                    "依赖{}的路径{}不存在",
+                    req.name,
+                    path.display()
+                )));
+            }
+            return Ok(SourceId::Path(dunce_canonicalize(&path)));
+        }
+        if req.dep.version.is_none() {
+            return Err(ResolveError::Other(format!(
+                "依赖{}没有指定git、path或者version",
+                req.name
+            )));
+        }
+        match &self.registry {
+            Some(registry) => Ok(SourceId::Registry(registry.root().to_path_buf())),
+            None => Err(ResolveError::Other(format!(
+                "依赖{}来自包索引，但是没有配置包索引，请设置Kagari.toml中的registry或者环境变量KAGARI_REGISTRY",
+                req.name
+            ))),
+        }
+    }
+
+    /// 加载依赖包，返回包和它的依赖
+    fn load(
+        &self,
+        req: &Request,
+        source: SourceId,
+        reqs: &[Requirement],
+    ) -> Result<(ResolvedPackage, Vec<Request>), ResolveError> {
+        let (source, path, checksum) = match source {
+            SourceId::Registry(_) => return self.load_from_registry(req, reqs),
+            SourceId::Path(path) => (SourceId::Path(path.clone()), path, None),
+            SourceId::Git { url, .. } => {
+                let head = req.dep.head.clone().ok_or_else(|| {
+                    ResolveError::Other(format!(
+                        "类型为git的依赖项{}未指定分支，无法下载依赖",
+                        req.name
+                    ))
+                })?;
+                let (commit, path) = self.fetch_git(&req.name, &url, &head)?;
+                let sum = checksum(&path).map_err(|e| ResolveError::Other(e.to_string()))?;
+                let source = SourceId::Git { url, commit };
+                self.check_locked_sum(&req.name, &source, &sum)?;
+                (source, path, Some(sum))
+            }
+        };
+        let config = Config::load(&path).map_err(ResolveError::Other)?;
+        let version = match &config.version {
+            Some(v) => Some(Version::parse(v).map_err(|e| {
+                ResolveError::Other(format!("包{}的版本{}不合法:{}", req.name, v, e))
+            })?),
+            None => None,
+        };
+        if !matches_all(&req.name, version.as_ref(), reqs)? {
+            return Err(unsatisfied(&req.name, reqs.to_vec()));
+        }
+        let pkg = ResolvedPackage {
+            name: req.name.clone(),
+            version,
+            source,
+            path: path.clone(),
+            checksum,
+            deps: vec![],
+        };
+        let deps = requests(&pkg.display_name(), &config, &path);
+        Ok((
+            ResolvedPackage {
+                deps: deps.iter().map(|d| d.name.clone()).collect(),
+                ..pkg
+            },
+            deps,
+        ))
+    }
+
+    fn load_from_registry(
+        &self,
+        req: &Request,
+        reqs: &[Requirement],
+    ) -> Result<(ResolvedPackage, Vec<Request>), ResolveError> {
+        let registry = self.registry.as_ref().unwrap();
+        let source = SourceId::Registry(registry.root().to_path_buf());
+        let versions = registry.versions(&req.name).map_err(ResolveError::Other)?;
+        let mut candidates = vec![];
+        for (v, entry) in versions.iter() {
+            if matches_all(&req.name, Some(v), reqs)? {
+                candidates.push((v, entry));
+            }
+        }
+        let locked = self
+            .locked(&req.name)
+            .filter(|p| p.source == source.to_string())
+            .and_then(|p| p.version.clone());
+        let pick = locked
+            .as_ref()
+            .and_then(|lv| candidates.iter().find(|(v, _)| &v.to_string() == lv));
+        let pick = match pick {
+            Some(pick) => *pick,
+            None if self.options.locked => {
+                return Err(ResolveError::LockOutdated(match locked {
+                    Some(lv) => format!("锁定的{} {}不满足版本要求", req.name, lv),
+                    None => format!("依赖{}没有被锁定", req.name),
+                }))
+            }
+            None => *candidates
+                .iter()
+                .find(|(_, entry)| !entry.yanked)
+                .ok_or_else(|| unsatisfied(&req.name, reqs.to_vec()))?,
+        };
+        let (version, entry) = pick;
+        let path = match &entry.git {
+            Some(url) => {
+                let commit = entry.commit.as_ref().ok_or_else(|| {
+                    ResolveError::Other(format!(
+                        "包索引中{} {}的git仓库没有指定commit",
+                        req.name, version
+                    ))
+                })?;
+                self.fetch_git_commit(url, commit)?
+            }
+            None => registry.source_dir(&req.name, entry),
+        };
+        if !path.exists() {
+            return Err(ResolveError::Other(format!(
+                "找不到{} {}的源码{}",
+                req.name,
+                version,
+                path.display()
+            )));
+        }
+        let sum = checksum(&path).map_err(|e| ResolveError::Other(e.to_string()))?;
+        if let Some(expected) = &entry.checksum {
+            if expected != &sum {
+                return Err(ResolveError::Checksum {
+                    name: req.name.clone(),
+                    expected: expected.clone(),
+                    actual: sum,
+                });
+            }
+        }
+        if self.locked(&req.name).and_then(|p| p.version.as_deref())
+            == Some(version.to_string().as_str())
+        {
+            self.check_locked_sum(&req.name, &source, &sum)?;
+        }
+        let display = format!("{} {}", req.name, version);
+        let deps = entry
+            .deps
+            .iter()
+            .map(|(name, v)| Request {
+                from: display.clone(),
+                name: name.clone(),
+                dep: Dependency {
+                    version: Some(v.clone()),
+                    ..Default::default()
+                },
+                base: path.clone(),
+            })
+            .collect::<Vec<_>>();
+        Ok((
+            ResolvedPackage {
+                name: req.name.clone(),
+                version: Some(version.clone()),
+                source,
+                path,
+                checksum: Some(sum),
+                deps: deps.iter().map(|d| d.name.clone()).collect(),
+            },
+            deps,
+        ))
+    }
+
+    fn locked(&self, name: &str) -> Option<&LockedPackage> {
+        self.lock.as_ref().and_then(|l| l.get(name))
+    }
+
+    /// 与Kagari.lock中同一来源的校验和比较
+    fn check_locked_sum(
+        &self,
+        name: &str,
+        source: &SourceId,
+        sum: &str,
+    ) -> Result<(), ResolveError> {
+        match self.locked(name) {
+            Some(LockedPackage {
+                source: locked_source,
+                checksum: Some(expected),
+                ..
+            }) if locked_source == &source.to_string() && expected != sum => {
+                Err(ResolveError::Checksum {
+                    name: name.to_string(),
+                    expected: expected.clone(),
+                    actual: sum.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 下载git依赖，有锁定的commit时使用锁定的commit，返回commit和源码目录
+    fn fetch_git(
+        &self,
+        name: &str,
+        url: &str,
+        head: &str,
+    ) -> Result<(String, PathBuf), ResolveError> {
+        let locked = self
+            .locked(name)
+            .and_then(|p| p.source.parse::<SourceId>().ok());
+        if let Some(SourceId::Git {
+            url: locked_url,
+            commit,
+        }) = locked
+        {
+            if locked_url == url {
+                let path = self.fetch_git_commit(url, &commit)?;
+                return Ok((commit, path));
+            }
+        }
+        if self.options.locked {
+            return Err(ResolveError::LockOutdated(format!(
+                "依赖{}没有被锁定",
+                name
+            )));
+        }
+        let repo = self.clone_repo(url)?;
+        let path = cp_to_hash_dir(repo.to_str().unwrap(), head);
+        let commit = path
+            .file_name()
+            .map(|c| c.to_string_lossy().to_string())
+            // rev-parse失败时得到的是仓库的上级目录
+            .filter(|_| Some(path.as_path()) != repo.parent())
+            .ok_or_else(|| ResolveError::Other(format!("git仓库{}中找不到{}", url, head)))?;
+        Ok((commit, path))
+    }
+
+    /// 下载git仓库中的指定commit，已经下载过时不会访问网络
+    fn fetch_git_commit(&self, url: &str, commit: &str) -> Result<PathBuf, ResolveError> {
+        let repo = repo_dir(url, self.third_party.to_str().unwrap());
+        let path = repo.parent().unwrap().join(commit);
+        if path.exists() {
+            return Ok(path);
+        }
+        let repo = self.clone_repo(url)?;
+        Ok(cp_to_hash_dir(repo.to_str().unwrap(), commit))
+    }
+
+    /// 克隆或者更新git仓库，离线时只能使用已经克隆的仓库
+    fn clone_repo(&self, url: &str) -> Result<PathBuf, ResolveError> {
+        let repo = repo_dir(url, self.third_party.to_str().unwrap());
+        if self.options.offline {
+            if repo.join(".git").exists() {
+                return Ok(repo);
+            }
+            return Err(ResolveError::Offline(url.to_string()));
+        }
+        let (child, repo) = download_repo(url, self.third_party.to_str().unwrap());
+        if let Some(Err(e)) = child {
+            return Err(ResolveError::Other(format!("下载{}失败:{}", url, e)));
+        }
+        if !repo.join(".git").exists() {
+            return Err(ResolveError::Other(format!("下载{}失败", url)));
+        }
+        Ok(repo)
+    }
+}
+
+/// 解析`root`下项目的依赖，并在需要时更新Kagari.lock
+pub fn resolve(
+    root: &Path,
+    config: &Config,
+    third_party: &Path,
+    options: ResolveOptions,
+) -> Result<Resolve, ResolveError> {
+    let resolver = Resolver::new(root, config, third_party, options)?;
+    let old = resolver.lockfile().cloned();
+    save_lock(root, resolver.resolve()?, old.as_ref(), options)
+}
+
+/// 重新解析依赖，不使用Kagari.lock中锁定的`name`，`name`为`None`时更新全部依赖
+pub fn update(
+    root: &Path,
+    config: &Config,
+    third_party: &Path,
+    options: ResolveOptions,
+    name: Option<&str>,
+) -> Result<Resolve, ResolveError> {
+    let mut resolver = Resolver::new(root, config, third_party, options)?;
+    let old = resolver.lock.clone();
+    match name {
+        Some(name) => {
+            let lock = resolver
+                .lock
+                .as_mut()
+                .filter(|l| l.get(name).is_some())
+                .ok_or_else(|| ResolveError::Other(format!("Kagari.lock中没有包{}", name)))?;
+            lock.packages.retain(|p| p.name != name);
+        }
+        None => resolver.lock = None,
+    }
+    save_lock(root, resolver.resolve()?, old.as_ref(), options)
+}
+
+fn save_lock(
+    root: &Path,
+    resolve: Resolve,
+    old: Option<&Lockfile>,
+    options: ResolveOptions,
+) -> Result<Resolve, ResolveError> {
+    let lock = resolve.to_lockfile(root);
+    let unchanged = match old {
+        Some(old) => old == &lock,
+        // 没有依赖的项目不生成Kagari.lock
+        None => lock.packages.is_empty(),
+    };
+    if !unchanged {
+        if options.locked {
+            return Err(ResolveError::LockOutdated(
+                "Kagari.lock与Kagari.toml中的依赖不一致".to_string(),
+            ));
+        }
+        lock.save(&root.join(LOCKFILE))
+            .map_err(ResolveError::Other)?;
+    }
+    Ok(resolve)
+}
+
+fn requests(from: &str, config: &Config, base: &Path) -> Vec<Request> {
+    config
+        .deps
+        .iter()
+        .flatten()
+        .map(|(name, dep)| Request {
+            from: from.to_string(),
+            name: name.clone(),
+            dep: dep.clone(),
+            base: base.to_path_buf(),
+        })
+        .collect()
+}
+
+/// 版本是否满足所有要求，没有版本的包只能满足空的要求
+fn matches_all(
+    name: &str,
+    version: Option<&Version>,
+    reqs: &[Requirement],
+) -> Result<bool, ResolveError> {
+    for r in reqs {
+        let req = VersionReq::parse(&r.req).map_err(|e| {
+            ResolveError::Other(format!(
+                "`{}`对{}的版本要求`{}`不合法:{}",
+                r.from, name, r.req, e
+            ))
+        })?;
+        match version {
+            Some(v) if req.matches(v) => {}
+            _ => return Ok(false),
+        }
+    }
+    Ok(true)
+}
+
+fn unsatisfied(name: &str, mut reqs: Vec<Requirement>) -> ResolveError {
+    if reqs.len() == 1 {
+        ResolveError::NotFound {
+            name: name.to_string(),
+            requirement: reqs.remove(0),
+        }
+    } else {
+        ResolveError::Conflict {
+            name: name.to_string(),
+            requirements: reqs,
+        }
+    }
+}
+
+/// 规范化路径，路径不存在时原样返回
+fn dunce_canonicalize(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .map(|p| {
+            // windows上去掉`\\?\`前缀，与plc中使用的dunce保持一致
+            let s = p.to_string_lossy().to_string();
+            match s.strip_prefix(r"\\?\") {
+                Some(s) => PathBuf::from(s),
+                None => p,
+            }
+        })
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn package(registry: &Path, name: &str, version: &str) {
+        write(
+            &registry
+                .join("packages")
+                .join(name)
+                .join(version)
+                .join("lib.pi"),
+            version,
+        );
+    }
+
+    fn project(dir: &Path, deps: &str) -> Config {
+        write(
+            &dir.join("Kagari.toml"),
+            &format!(
+                "project = \"app\"\nentry = \"main.pi\"\nregistry = \"../registry\"\n[deps]\n{}",
+                deps
+            ),
+        );
+        Config::load(dir).unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let tmp = env::temp_dir().join("kagari_test_resolve");
+        _ = fs::remove_dir_all(&tmp);
+        let registry = tmp.join("registry");
+        for v in ["1.0.0", "1.2.0", "1.3.0", "2.0.0"] {
+            package(&registry, "foo", v);
+        }
+        for v in ["0.1.0", "0.1.5"] {
+            package(&registry, "baz", v);
+        }
+        let foo_index = r#"
+[[versions]]
+version = "1.0.0"
+[[versions]]
+version = "1.2.0"
+deps = { baz = "^0.1" }
+[[versions]]
+version = "1.3.0"
+yanked = true
+[[versions]]
+version = "2.0.0"
+"#;
+        write(&registry.join("index").join("foo.toml"), foo_index);
+        write(
+            &registry.join("index").join("baz.toml"),
+            "[[versions]]\nversion = \"0.1.0\"\n[[versions]]\nversion = \"0.1.5\"\n",
+        );
+        let third_party = tmp.join("thirdparty");
+        let app = tmp.join("app");
+        let config = project(&app, "foo = { version = \"^1\" }\n");
+        let app = app.canonicalize().unwrap();
+
+        // 选择满足要求且没有被撤回的最高版本
+        let re = resolve(&app, &config, &third_party, ResolveOptions::default()).unwrap();
+        assert_eq!(re.root_deps, vec!["foo".to_string()]);
+        assert_eq!(re.packages["foo"].version, Some(Version::new(1, 2, 0)));
+        assert_eq!(re.packages["baz"].version, Some(Version::new(0, 1, 5)));
+        assert_eq!(re.deps_of(Some("foo"))["baz"], re.packages["baz"].path);
+        let lock = Lockfile::load(&app.join(LOCKFILE)).unwrap().unwrap();
+        assert_eq!(lock, re.to_lockfile(&app));
+        assert!(lock.get("baz").unwrap().checksum.is_some());
+
+        // 新版本发布后依然使用锁定的版本
+        package(&registry, "foo", "1.4.0");
+        write(
+            &registry.join("index").join("foo.toml"),
+            &format!("{}[[versions]]\nversion = \"1.4.0\"\n", foo_index),
+        );
+        let locked = ResolveOptions {
+            locked: true,
+            offline: true,
+        };
+        let re = resolve(&app, &config, &third_party, locked).unwrap();
+        assert_eq!(re.packages["foo"].version, Some(Version::new(1, 2, 0)));
+        assert!(matches!(
+            update(&app, &config, &third_party, locked, Some("foo")),
+            Err(ResolveError::LockOutdated(_))
+        ));
+        assert!(update(&app, &config, &third_party, locked, Some("nope")).is_err());
+
+        // 源码被修改
+        write(
+            &registry
+                .join("packages")
+                .join("baz")
+                .join("0.1.5")
+                .join("lib.pi"),
+            "changed",
+        );
+        assert!(matches!(
+            resolve(&app, &config, &third_party, locked),
+            Err(ResolveError::Checksum { .. })
+        ));
+        package(&registry, "baz", "0.1.5");
+
+        // --locked时不能更新Kagari.lock
+        let config = project(&app, "foo = { version = \"^1.3\" }\n");
+        assert!(matches!(
+            resolve(&app, &config, &third_party, locked),
+            Err(ResolveError::LockOutdated(_))
+        ));
+        let re = resolve(&app, &config, &third_party, ResolveOptions::default()).unwrap();
+        assert_eq!(re.packages["foo"].version, Some(Version::new(1, 4, 0)));
+
+        // 版本冲突
+        write(
+            &tmp.join("qux").join("Kagari.toml"),
+            "project = \"qux\"\nentry = \"main.pi\"\n[deps]\nfoo = { version = \"^2\" }\n",
+        );
+        let config = project(
+            &app,
+            "foo = { version = \"^1\" }\nqux = { path = \"../qux\" }\n",
+        );
+        let err = resolve(&app, &config, &third_party, ResolveOptions::default()).unwrap_err();
+        assert_eq!(
+            err,
+            ResolveError::Conflict {
+                name: "foo".to_string(),
+                requirements: vec![
+                    Requirement {
+                        from: "app".to_string(),
+                        req: "^1".to_string()
+                    },
+                    Requirement {
+                        from: "qux".to_string(),
+                        req: "^2".to_string()
+                    },
+                ],
+            }
+        );
+        assert!(err.to_string().contains("`qux`要求`^2`"));
+
+        let config = project(&app, "foo = { version = \"^3\" }\n");
+        assert!(matches!(
+            resolve(&app, &config, &third_party, ResolveOptions::default()),
+            Err(ResolveError::NotFound { .. })
+        ));
+
+        // update不使用锁定的版本
+        package(&registry, "foo", "1.5.0");
+        write(
+            &registry.join("index").join("foo.toml"),
+            &format!(
+                "{}[[versions]]\nversion = \"1.4.0\"\n[[versions]]\nversion = \"1.5.0\"\n",
+                foo_index
+            ),
+        );
+        let config = project(&app, "foo = { version = \"^1\" }\n");
+        let re = resolve(&app, &config, &third_party, ResolveOptions::default()).unwrap();
+        assert_eq!(re.packages["foo"].version, Some(Version::new(1, 4, 0)));
+        let re = update(
+            &app,
+            &config,
+            &third_party,
+            ResolveOptions::default(),
+            Some("foo"),
+        )
+        .unwrap();
+        assert_eq!(re.packages["foo"].version, Some(Version::new(1, 5, 0)));
+
+        // 离线时无法下载git依赖
+        let config = project(
+            &app,
+            "pl_test = { git = \"https://github.com/Pivot-Studio/pl_test.git\", head = \"main\" }\n",
+        );
+        let offline = ResolveOptions {
+            locked: false,
+            offline: true,
+        };
+        assert!(matches!(
+            resolve(&app, &config, &third_party, offline),
+            Err(ResolveError::Offline(_))
+        ));
+        _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn test_resolve_workspace() {
+        let tmp = env::temp_dir().join("kagari_test_resolve_workspace");
+        _ = fs::remove_dir_all(&tmp);
+        package(&tmp.join("registry"), "foo", "1.0.0");
+        write(
+            &tmp.join("registry").join("index").join("foo.toml"),
+            "[[versions]]\nversion = \"1.0.0\"\n",
+        );
+        write(
+            &tmp.join("ws").join("Kagari.toml"),
+            "registry = \"../registry\"\n[workspace]\nmembers = [\"app\", \"lib\"]\n",
+        );
+        write(
+            &tmp.join("ws").join("app").join("Kagari.toml"),
+            "project = \"app\"\nentry = \"main.pi\"\n[deps]\nlib = { path = \"../lib\" }\nfoo = { version = \"^1\" }\n",
+        );
+        write(
+            &tmp.join("ws").join("lib").join("Kagari.toml"),
+            "project = \"lib\"\nentry = \"lib.pi\"\n[deps]\nfoo = { version = \"^1.0\" }\n",
+        );
+        let ws = tmp.join("ws").canonicalize().unwrap();
+        let config = Config::load(&ws).unwrap();
+        let re = resolve(
+            &ws,
+            &config,
+            &tmp.join("thirdparty"),
+            ResolveOptions::default(),
+        )
+        .unwrap();
+        assert!(re.root_deps.is_empty());
+        assert_eq!(re.members, vec!["app".to_string(), "lib".to_string()]);
+        assert_eq!(re.packages.len(), 3);
+        let app_deps = re.deps_of(Some("app"));
+        assert_eq!(app_deps["lib"], ws.join("lib"));
+        assert_eq!(app_deps["foo"], re.deps_of(Some("lib"))["foo"]);
+        // 成员共用工作区根目录的Kagari.lock
+        assert!(ws.join(LOCKFILE).exists());
+        assert!(!ws.join("app").join(LOCKFILE).exists());
+        _ = fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,90 @@
+//! # source
+//! 依赖包的来源，在Kagari.lock中记录为`<kind>+<location>`
+use std::{fmt, path::PathBuf, str::FromStr};
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SourceId {
+    /// 本地目录
+    Path(PathBuf),
+    /// git仓库，`commit`是下载时`head`对应的commit
+    Git { url: String, commit: String },
+    /// 本地包索引的根目录
+    Registry(PathBuf),
+}
+
+impl SourceId {
+    /// 是否来自同一个地方，git仓库的不同commit也是同一个来源
+    pub fn same_origin(&self, other: &SourceId) -> bool {
+        match (self, other) {
+            (SourceId::Git { url: a, .. }, SourceId::Git { url: b, .. }) => a == b,
+            _ => self == other,
+        }
+    }
+
+    pub fn is_path(&self) -> bool {
+        matches!(self, SourceId::Path(_))
+    }
+}
+
+impl fmt::Display for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceId::Path(p) => write!(f, "path+{}", p.display()),
+            SourceId::Git { url, commit } => write!(f, "git+{}#{}", url, commit),
+            SourceId::Registry(p) => write!(f, "registry+{}", p.display()),
+        }
+    }
+}
+
+impl FromStr for SourceId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, location) = s
+            .split_once('+')
+            .ok_or_else(|| format!("非法的依赖来源{}", s))?;
+        match kind {
+            "path" => Ok(SourceId::Path(PathBuf::from(location))),
+            "registry" => Ok(SourceId::Registry(PathBuf::from(location))),
+            "git" => {
+                let (url, commit) = location
+                    .rsplit_once('#')
+                    .ok_or_else(|| format!("git依赖来源{}缺少commit", s))?;
+                Ok(SourceId::Git {
+                    url: url.to_string(),
+                    commit: commit.to_string(),
+                })
+            }
+            _ => Err(format!("非法的依赖来源{}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_id() {
+        let git: SourceId = "git+https://github.com/a/b.git#abc".parse().unwrap();
+        assert_eq!(
+            git,
+            SourceId::Git {
+                url: "https://github.com/a/b.git".to_string(),
+                commit: "abc".to_string()
+            }
+        );
+        assert_eq!(git.to_string(), "git+https://github.com/a/b.git#abc");
+        let other = SourceId::Git {
+            url: "https://github.com/a/b.git".to_string(),
+            commit: "def".to_string(),
+        };
+        assert!(git.same_origin(&other));
+        assert_ne!(git, other);
+        let path: SourceId = "path+/a/b".parse().unwrap();
+        assert!(path.is_path());
+        assert!(!path.same_origin(&git));
+        assert!("git+https://x".parse::<SourceId>().is_err());
+        assert!("svn+x".parse::<SourceId>().is_err());
+    }
+}
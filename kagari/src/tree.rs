@@ -0,0 +1,152 @@
+//! # tree
+//! 以树的形式展示解析出的依赖
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::{
+    resolve::{Resolve, ResolvedPackage},
+    source::SourceId,
+};
+
+/// 重复出现的包只在第一次出现时展开，之后用这个标记
+pub const DUPLICATE_MARK: &str = "(*)";
+
+fn label(pkg: &ResolvedPackage) -> String {
+    let mut label = pkg.name.clone();
+    if let Some(v) = &pkg.version {
+        let _ = write!(label, " v{}", v);
+    }
+    match &pkg.source {
+        SourceId::Path(p) => {
+            let _ = write!(label, " ({})", p.display());
+        }
+        SourceId::Git { url, commit } => {
+            let _ = write!(label, " ({}#{})", url, &commit[..commit.len().min(7)]);
+        }
+        SourceId::Registry(_) => {}
+    }
+    label
+}
+
+/// 依赖树，`highlight`用来突出重复出现的包，例如加上终端颜色
+pub fn render(resolve: &Resolve, root: &str, highlight: impl Fn(&str) -> String) -> String {
+    let mut out = format!("{}\n", root);
+    let mut seen = BTreeSet::new();
+    // 工作区的成员和根目录的依赖一起列在最上层
+    let roots = resolve
+        .root_deps
+        .iter()
+        .chain(&resolve.members)
+        .cloned()
+        .collect::<Vec<_>>();
+    render_deps(resolve, &roots, "", &mut seen, &highlight, &mut out);
+    if out.contains(DUPLICATE_MARK) {
+        let _ = writeln!(
+            out,
+            "\n{} 表示这个包被多个包依赖，它的依赖已经在上面列出",
+            DUPLICATE_MARK
+        );
+    }
+    out
+}
+
+fn render_deps(
+    resolve: &Resolve,
+    deps: &[String],
+    prefix: &str,
+    seen: &mut BTreeSet<String>,
+    highlight: &impl Fn(&str) -> String,
+    out: &mut String,
+) {
+    for (i, dep) in deps.iter().enumerate() {
+        let last = i + 1 == deps.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let pkg = match resolve.packages.get(dep) {
+            Some(pkg) => pkg,
+            None => continue,
+        };
+        if !seen.insert(dep.clone()) {
+            let line = format!("{} {}", label(pkg), DUPLICATE_MARK);
+            let _ = writeln!(out, "{}{}{}", prefix, branch, highlight(&line));
+            continue;
+        }
+        let _ = writeln!(out, "{}{}{}", prefix, branch, label(pkg));
+        render_deps(
+            resolve,
+            &pkg.deps,
+            &format!("{}{}", prefix, indent),
+            seen,
+            highlight,
+            out,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use semver::Version;
+
+    use super::*;
+
+    fn pkg(
+        name: &str,
+        version: Option<Version>,
+        source: SourceId,
+        deps: &[&str],
+    ) -> ResolvedPackage {
+        ResolvedPackage {
+            name: name.to_string(),
+            version,
+            source,
+            path: PathBuf::from(name),
+            checksum: None,
+            deps: deps.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let registry = SourceId::Registry(PathBuf::from("/registry"));
+        let mut resolve = Resolve {
+            root_deps: vec!["foo".to_string(), "qux".to_string()],
+            ..Default::default()
+        };
+        for p in [
+            pkg(
+                "foo",
+                Some(Version::new(1, 2, 0)),
+                registry.clone(),
+                &["baz"],
+            ),
+            pkg("baz", Some(Version::new(0, 1, 5)), registry, &[]),
+            pkg(
+                "qux",
+                None,
+                SourceId::Git {
+                    url: "https://github.com/a/qux.git".to_string(),
+                    commit: "0123456789abcdef".to_string(),
+                },
+                &["foo"],
+            ),
+        ] {
+            resolve.packages.insert(p.name.clone(), p);
+        }
+        let tree = render(&resolve, "app", |s| format!("<{}>", s));
+        assert_eq!(
+            tree,
+            r#"app
+├── foo v1.2.0
+│   └── baz v0.1.5
+└── qux (https://github.com/a/qux.git#0123456)
+    └── <foo v1.2.0 (*)>
+
+(*) 表示这个包被多个包依赖，它的依赖已经在上面列出
+"#
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! # vendor
+//! 把依赖复制到项目中，之后的构建不需要访问网络
+//!
+//! git依赖按照与thirdparty相同的结构复制到vendor目录，设置Kagari.toml中的`vendor`后直接使用；
+//! 来自包索引的依赖复制到`<vendor>/registry`，成为一个只包含用到的版本的包索引。
+//! 本地目录中的依赖不会被复制
+use std::{fs, path::Path};
+
+use crate::{
+    cp_to_hash_dir,
+    manifest::Config,
+    registry::{IndexEntry, Registry},
+    repo_dir,
+    resolve::Resolve,
+    source::SourceId,
+};
+
+/// vendor目录中包索引所在的子目录
+pub const VENDOR_REGISTRY: &str = "registry";
+
+/// 复制的包的数量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vendored {
+    pub git: usize,
+    pub registry: usize,
+}
+
+pub fn vendor(
+    root: &Path,
+    config: &Config,
+    resolve: &Resolve,
+    third_party: &Path,
+    dir: &Path,
+) -> Result<Vendored, String> {
+    let registry = Registry::from_config(root, config);
+    let vendored_registry = Registry::new(dir.join(VENDOR_REGISTRY));
+    let mut vendored = Vendored::default();
+    for pkg in resolve.packages.values() {
+        match &pkg.source {
+            SourceId::Git { url, commit } => {
+                let repo = repo_dir(url, dir.to_str().unwrap());
+                vendored.git += 1;
+                if repo.parent().unwrap().join(commit).exists() {
+                    continue;
+                }
+                if !repo.exists() {
+                    copy_dir(&repo_dir(url, third_party.to_str().unwrap()), &repo)?;
+                }
+                cp_to_hash_dir(repo.to_str().unwrap(), commit);
+            }
+            SourceId::Registry(_) => {
+                let version = pkg.version.as_ref().unwrap().to_string();
+                let deps = registry
+                    .as_ref()
+                    .and_then(|r| r.versions(&pkg.name).ok())
+                    .and_then(|versions| {
+                        versions.into_iter().find(|(v, _)| v.to_string() == version)
+                    })
+                    .map(|(_, entry)| entry.deps)
+                    .ok_or_else(|| format!("包索引中找不到{} {}", pkg.name, version))?;
+                let entry = IndexEntry {
+                    version,
+                    checksum: pkg.checksum.clone(),
+                    deps,
+                    ..Default::default()
+                };
+                let dst = vendored_registry.source_dir(&pkg.name, &entry);
+                if !dst.exists() {
+                    copy_dir(&pkg.path, &dst)?;
+                }
+                vendored_registry.add_version(&pkg.name, entry)?;
+                vendored.registry += 1;
+            }
+            SourceId::Path(_) => {}
+        }
+    }
+    Ok(vendored)
+}
+
+fn copy_dir(src: &Path, dst: &Path) -> Result<(), String> {
+    fs::create_dir_all(dst).map_err(|e| format!("error: {:?}", e))?;
+    fs_extra::dir::copy(
+        src,
+        dst,
+        &fs_extra::dir::CopyOptions::new().content_only(true),
+    )
+    .map(|_| ())
+    .map_err(|e| format!("复制{}失败:{}", src.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::resolve::{resolve, ResolveOptions};
+
+    #[test]
+    fn test_vendor() {
+        let tmp = env::temp_dir().join("kagari_test_vendor");
+        _ = fs::remove_dir_all(&tmp);
+        let registry = tmp.join("registry");
+        let src = registry.join("packages").join("foo").join("1.0.0");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("lib.pi"), "foo").unwrap();
+        fs::create_dir_all(registry.join("index")).unwrap();
+        fs::write(
+            registry.join("index").join("foo.toml"),
+            "[[versions]]\nversion = \"1.0.0\"\n",
+        )
+        .unwrap();
+        let app = tmp.join("app");
+        fs::create_dir_all(&app).unwrap();
+        let manifest =
+            "project = \"app\"\nentry = \"main.pi\"\n[deps]\nfoo = { version = \"^1\" }\n";
+        fs::write(
+            app.join("Kagari.toml"),
+            format!("registry = \"../registry\"\n{}", manifest),
+        )
+        .unwrap();
+        let app = app.canonicalize().unwrap();
+        let config = Config::load(&app).unwrap();
+        let third_party = tmp.join("thirdparty");
+        let re = resolve(&app, &config, &third_party, ResolveOptions::default()).unwrap();
+        let vendored = vendor(&app, &config, &re, &third_party, &app.join("vendor")).unwrap();
+        assert_eq!(
+            vendored,
+            Vendored {
+                git: 0,
+                registry: 1
+            }
+        );
+
+        // 原来的包索引不存在后依然可以使用vendor目录中的包索引
+        fs::remove_dir_all(&registry).unwrap();
+        fs::write(
+            app.join("Kagari.toml"),
+            format!("registry = \"vendor/registry\"\n{}", manifest),
+        )
+        .unwrap();
+        let config = Config::load(&app).unwrap();
+        let offline = ResolveOptions {
+            locked: false,
+            offline: true,
+        };
+        let vendored_re = resolve(&app, &config, &third_party, offline).unwrap();
+        assert_eq!(
+            vendored_re.packages["foo"].checksum,
+            re.packages["foo"].checksum
+        );
+        _ = fs::remove_dir_all(&tmp);
+    }
+}
@@ -17,7 +17,7 @@ use inkwell::{
     context::Context,
     debug_info::*,
     module::{FlagBehavior, Linkage, Module},
-    targets::{InitializationConfig, Target, TargetMachine},
+    targets::{InitializationConfig, Target, TargetMachine, TargetTriple},
     types::{BasicType, BasicTypeEnum, FunctionType, PointerType, StructType},
     values::{
         AnyValue, AnyValueEnum, BasicMetadataValueEnum, BasicValue, BasicValueEnum, CallableValue,
@@ -27,14 +27,14 @@ use inkwell::{
 };
 use rustc_hash::FxHashMap;
 
-use crate::ast::{diag::PLDiag, pass::run_immix_pass};
+use crate::ast::{compiler::TargetOptions, diag::PLDiag, pass::run_immix_pass};
 
 use super::{
     super::{
         ctx::Ctx,
         diag::ErrorCode,
         node::{types::TypedIdentifierNode, TypeNode, TypeNodeEnum},
-        pltype::{ARRType, FNValue, Field, PLType, PriType, RetTypeEnum, STType},
+        pltype::{ARRType, ClosureType, FNValue, Field, PLType, PriType, RetTypeEnum, STType},
         range::{Pos, Range},
     },
     IRBuilder,
@@ -82,6 +82,7 @@ pub fn create_llvm_deps<'ctx>(
     context: &'ctx Context,
     dir: &str,
     file: &str,
+    target: &TargetOptions,
 ) -> (
     Module<'ctx>,
     Builder<'ctx>,
@@ -120,7 +121,7 @@ pub fn create_llvm_deps<'ctx>(
         )]);
         module.add_metadata_flag("CodeView", FlagBehavior::Warning, metacv);
     }
-    let tm = get_target_machine(inkwell::OptimizationLevel::None);
+    let tm = get_target_machine(inkwell::OptimizationLevel::None, target);
     module.set_triple(&tm.get_triple());
     module.set_data_layout(&tm.get_target_data().get_data_layout());
     (module, builder, dibuilder, compile_unit, tm)
@@ -132,36 +133,84 @@ pub struct LLVMBuilder<'a, 'ctx> {
     handle_reverse_table: Arc<RefCell<FxHashMap<AnyValueEnum<'ctx>, ValueHandle>>>,
     block_table: Arc<RefCell<FxHashMap<BlockHandle, BasicBlock<'ctx>>>>,
     block_reverse_table: Arc<RefCell<FxHashMap<BasicBlock<'ctx>, BlockHandle>>>,
-    context: &'ctx Context,                // llvm context
-    builder: &'a Builder<'ctx>,            // llvm builder
-    module: &'a Module<'ctx>,              // llvm module
-    dibuilder: &'a DebugInfoBuilder<'ctx>, // debug info builder
-    diunit: &'a DICompileUnit<'ctx>,       // debug info unit
-    targetmachine: &'a TargetMachine,      // might be used in debug info
-    discope: Cell<DIScope<'ctx>>,          // debug info scope
+    context: &'ctx Context,                     // llvm context
+    builder: &'a Builder<'ctx>,                 // llvm builder
+    module: &'a Module<'ctx>,                   // llvm module
+    dibuilder: &'a DebugInfoBuilder<'ctx>,      // debug info builder
+    diunit: &'a DICompileUnit<'ctx>,            // debug info unit
+    targetmachine: &'a TargetMachine,           // might be used in debug info
+    discope: Cell<DIScope<'ctx>>,               // debug info scope
+    discope_stack: RefCell<Vec<DIScope<'ctx>>>, // outer scopes, restored when a subscope ends
     ditypes_placeholder: Arc<RefCell<FxHashMap<String, RefCell<Vec<MemberType<'ctx>>>>>>, // hold the generated debug info type place holder
     ditypes: Arc<RefCell<FxHashMap<String, DIType<'ctx>>>>, // hold the generated debug info type
     heap_stack_map: Arc<RefCell<FxHashMap<ValueHandle, ValueHandle>>>,
 }
 
-pub fn get_target_machine(level: OptimizationLevel) -> TargetMachine {
-    let triple = &TargetMachine::get_default_triple();
-    let s1 = TargetMachine::get_host_cpu_name();
-    let cpu = s1.to_str().unwrap();
-    let s2 = TargetMachine::get_host_cpu_features();
-    let features = s2.to_str().unwrap();
-    Target::initialize_native(&InitializationConfig::default()).unwrap();
-    let target = Target::from_triple(triple).unwrap();
-    target
+/// 创建目标平台的TargetMachine，目标平台不受支持时panic，
+/// 使用用户输入的目标平台前需要先用[check_target]检查
+pub fn get_target_machine(level: OptimizationLevel, target: &TargetOptions) -> TargetMachine {
+    try_get_target_machine(level, target).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// 检查目标平台是否受支持，不支持时返回错误信息
+pub fn check_target(target: &TargetOptions) -> Result<(), String> {
+    try_get_target_machine(OptimizationLevel::None, target).map(|_| ())
+}
+
+/// 创建目标平台的TargetMachine
+///
+/// 没有指定triple时使用主机平台，cpu和features默认与主机相同；
+/// 指定了triple时（交叉编译）cpu默认为`generic`，features默认为空
+fn try_get_target_machine(
+    level: OptimizationLevel,
+    target: &TargetOptions,
+) -> Result<TargetMachine, String> {
+    let (triple, cpu, features) = match &target.triple {
+        Some(triple) => {
+            Target::initialize_all(&InitializationConfig::default());
+            (
+                TargetTriple::create(triple),
+                target.cpu.clone().unwrap_or_else(|| "generic".to_string()),
+                target.features.clone().unwrap_or_default(),
+            )
+        }
+        None => {
+            Target::initialize_native(&InitializationConfig::default()).unwrap();
+            (
+                TargetMachine::get_default_triple(),
+                target.cpu.clone().unwrap_or_else(|| {
+                    TargetMachine::get_host_cpu_name()
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                }),
+                target.features.clone().unwrap_or_else(|| {
+                    TargetMachine::get_host_cpu_features()
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                }),
+            )
+        }
+    };
+    let name = triple.as_str().to_string_lossy().to_string();
+    let llvm_target =
+        Target::from_triple(&triple).map_err(|e| format!("unsupported target {}: {}", name, e))?;
+    llvm_target
         .create_target_machine(
-            triple,
-            cpu,
-            features,
+            &triple,
+            &cpu,
+            &features,
             level,
             inkwell::targets::RelocMode::DynamicNoPic,
             inkwell::targets::CodeModel::Default,
         )
-        .unwrap()
+        .ok_or_else(|| {
+            format!(
+                "failed to create target machine for {} with cpu `{}` and features `{}`",
+                name, cpu, features
+            )
+        })
 }
 
 impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
@@ -182,6 +231,7 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
             diunit,
             targetmachine: tm,
             discope: Cell::new(diunit.get_file().as_debug_info_scope()),
+            discope_stack: RefCell::new(vec![]),
             ditypes: Arc::new(RefCell::new(FxHashMap::default())),
             ditypes_placeholder: Arc::new(RefCell::new(FxHashMap::default())),
             handle_table: Arc::new(RefCell::new(FxHashMap::default())),
@@ -217,7 +267,7 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
                 size_val,
             )
             .unwrap();
-        if let PLType::STRUCT(_) = pltype {
+        if let PLType::STRUCT(_) | PLType::ENUM(_) = pltype {
             let f = self.get_or_insert_st_visit_fn_handle(&p);
             let i = self.builder.build_ptr_to_int(
                 f.as_global_value().as_pointer_value(),
@@ -226,7 +276,7 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
             );
             let vtable = self.builder.build_struct_gep(p, 0, "vtable").unwrap();
             self.builder.build_store(vtable, i);
-        } else if let PLType::ARR(tp) = pltype {
+        } else if let PLType::ARR(tp @ ARRType { size: Some(_), .. }) = pltype {
             let f = self.gen_or_get_arr_visit_function(ctx, tp);
             let i = self.builder.build_ptr_to_int(
                 f.as_global_value().as_pointer_value(),
@@ -446,14 +496,36 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
                 "elm",
             )
         };
+        self.build_visit_elm(f, elm, &v.element_type.borrow());
+        let i = self.builder.build_load(loop_var, "i").into_int_value();
+        let i = self
+            .builder
+            .build_int_add(i, self.context.i32_type().const_int(1, false), "i");
+        self.builder.build_store(loop_var, i);
+        self.builder.build_unconditional_branch(condbb);
+        self.builder.position_at_end(endbb);
+        self.builder.build_return(None);
+        if let Some(currentbb) = currentbb {
+            self.builder.position_at_end(currentbb);
+        }
+        f
+    }
+
+    /// 根据元素类型调用对应的mark函数，`f`是当前正在生成的visit函数
+    fn build_visit_elm(&self, f: FunctionValue<'ctx>, elm: PointerValue<'ctx>, tp: &PLType) {
         let visitor = f.get_nth_param(1).unwrap().into_pointer_value();
         let visit_ptr_f = get_nth_mark_fn(f, 2);
         // complex type needs to provide a visit function by itself
         // which is stored in the first field of the struct
         let visit_complex_f = get_nth_mark_fn(f, 3);
         let visit_trait_f = get_nth_mark_fn(f, 4);
-        match &*v.element_type.borrow() {
-            PLType::ARR(_) | PLType::STRUCT(_) => {
+        match tp {
+            PLType::ARR(a) if a.is_dynamic() => {
+                // 动态数组元素是指向堆上数组对象的指针
+                self.builder
+                    .build_call(visit_ptr_f, &[visitor.into(), elm.into()], "call");
+            }
+            PLType::ARR(_) | PLType::STRUCT(_) | PLType::ENUM(_) => {
                 // call the visit_complex function
                 self.builder
                     .build_call(visit_complex_f, &[visitor.into(), elm.into()], "call");
@@ -463,17 +535,67 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
                 self.builder
                     .build_call(visit_ptr_f, &[visitor.into(), elm.into()], "call");
             }
-            PLType::TRAIT(_) => {
+            PLType::TRAIT(_) | PLType::CLOSURE(_) => {
                 // call the visit_trait function
                 self.builder
                     .build_call(visit_trait_f, &[visitor.into(), elm.into()], "call");
             }
             _ => {}
         }
+    }
+
+    /// 动态数组对象的visit函数，与定长数组不同，长度需要从对象的第二个字段读取
+    fn gen_or_get_dyn_arr_visit_function(
+        &self,
+        ctx: &mut Ctx<'a>,
+        v: &ARRType,
+    ) -> FunctionValue<'ctx> {
+        let currentbb = self.builder.get_insert_block();
+        self.builder.unset_current_debug_location();
+        let ptrtp = self
+            .dyn_arr_obj_type(v, ctx)
+            .ptr_type(AddressSpace::default());
+        let ftp = self.mark_fn_tp(ptrtp);
+        let fname = &format!(
+            "dyn_arr@{}@{}",
+            v.element_type.borrow().get_full_elm_name(),
+            ctx.plmod.path
+        );
+        if let Some(f) = self.module.get_function(fname) {
+            return f;
+        }
+        let f = self.module.add_function(fname, ftp, None);
+        let bb = self.context.append_basic_block(f, "entry");
+        self.builder.position_at_end(bb);
+        let obj = f.get_nth_param(0).unwrap().into_pointer_value();
+        let len = self.builder.build_struct_gep(obj, 1, "len").unwrap();
+        let len = self.builder.build_load(len, "len").into_int_value();
+        let arr = self.builder.build_struct_gep(obj, 2, "arr").unwrap();
+        let loop_var = self.builder.build_alloca(self.context.i64_type(), "i");
+        self.builder
+            .build_store(loop_var, self.context.i64_type().const_zero());
+        let condbb = self.context.append_basic_block(f, "cond");
+        let loopbb = self.context.append_basic_block(f, "loop");
+        let endbb = self.context.append_basic_block(f, "end");
+        self.builder.build_unconditional_branch(condbb);
+        self.builder.position_at_end(condbb);
         let i = self.builder.build_load(loop_var, "i").into_int_value();
+        let cond = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, i, len, "cond");
+        self.builder.build_conditional_branch(cond, loopbb, endbb);
+        self.builder.position_at_end(loopbb);
+        let elm = unsafe {
+            self.builder.build_gep(
+                arr,
+                &[self.context.i64_type().const_int(0, false), i],
+                "elm",
+            )
+        };
+        self.build_visit_elm(f, elm, &v.element_type.borrow());
         let i = self
             .builder
-            .build_int_add(i, self.context.i32_type().const_int(1, false), "i");
+            .build_int_add(i, self.context.i64_type().const_int(1, false), "i");
         self.builder.build_store(loop_var, i);
         self.builder.build_unconditional_branch(condbb);
         self.builder.position_at_end(endbb);
@@ -608,6 +730,8 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
             ),
             PLType::STRUCT(s) => Some(self.struct_type(s, ctx).as_basic_type_enum()),
             PLType::TRAIT(s) => Some(self.struct_type(s, ctx).as_basic_type_enum()),
+            PLType::ENUM(e) => Some(self.struct_type(&e.get_layout(), ctx).as_basic_type_enum()),
+            PLType::CLOSURE(c) => Some(self.closure_type(c, ctx)),
             PLType::ARR(a) => Some(self.arr_type(a, ctx)),
             PLType::PRIMITIVE(t) => Some(self.get_pri_basic_type(t)),
             PLType::VOID => None,
@@ -643,14 +767,25 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
     /// array type in fact is a struct with two fields,
     /// the first is a function pointer to the visit function(used in gc)
     /// the second is the array itself
+    ///
+    /// 动态数组是指向堆上数组对象的指针，见[`LLVMBuilder::dyn_arr_obj_type`]
     fn arr_type(&self, arrtp: &ARRType, ctx: &mut Ctx<'a>) -> BasicTypeEnum<'ctx> {
+        let size = match arrtp.size {
+            Some(size) => size,
+            None => {
+                return self
+                    .dyn_arr_obj_type(arrtp, ctx)
+                    .ptr_type(AddressSpace::default())
+                    .as_basic_type_enum()
+            }
+        };
         self.context
             .struct_type(
                 &[
                     self.context.i64_type().as_basic_type_enum(),
                     self.get_basic_type_op(&arrtp.element_type.borrow(), ctx)
                         .unwrap()
-                        .array_type(arrtp.size)
+                        .array_type(size)
                         .as_basic_type_enum(),
                 ],
                 false,
@@ -658,6 +793,47 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
             .as_basic_type_enum()
     }
 
+    /// 动态数组对象有三个字段，分别是visit函数、长度和真正的数组，
+    /// 数组在llvm中的长度为0，实际长度由分配时的大小决定
+    fn dyn_arr_obj_type(&self, arrtp: &ARRType, ctx: &mut Ctx<'a>) -> StructType<'ctx> {
+        self.context.struct_type(
+            &[
+                self.context.i64_type().as_basic_type_enum(),
+                self.context.i64_type().as_basic_type_enum(),
+                self.get_basic_type_op(&arrtp.element_type.borrow(), ctx)
+                    .unwrap()
+                    .array_type(0)
+                    .as_basic_type_enum(),
+            ],
+            false,
+        )
+    }
+
+    /// 闭包是一个有两个字段的结构体，第一个是函数指针，第二个是捕获环境
+    ///
+    /// 函数的第一个参数是捕获环境
+    fn closure_type(&self, c: &ClosureType, ctx: &mut Ctx<'a>) -> BasicTypeEnum<'ctx> {
+        let i8ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let mut param_types = vec![i8ptr.into()];
+        for arg in c.arg_types.iter() {
+            param_types.push(self.get_basic_type_op(&arg.borrow(), ctx).unwrap().into());
+        }
+        let fn_type = self
+            .get_ret_type(&c.ret_type.borrow(), ctx)
+            .fn_type(&param_types, false);
+        self.context
+            .struct_type(
+                &[
+                    fn_type
+                        .ptr_type(AddressSpace::default())
+                        .as_basic_type_enum(),
+                    i8ptr.as_basic_type_enum(),
+                ],
+                false,
+            )
+            .as_basic_type_enum()
+    }
+
     fn get_field_di_type(
         &self,
         field: &Field,
@@ -715,12 +891,49 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
         )
     }
 
+    /// 没有源码位置的结构体字段，`offset`的单位是bit
+    fn member_di_type(&self, name: &str, ditype: DIType<'ctx>, offset: u64) -> DIType<'ctx> {
+        self.dibuilder
+            .create_member_type(
+                self.diunit.get_file().as_debug_info_scope(),
+                name,
+                self.diunit.get_file(),
+                0,
+                ditype.get_size_in_bits(),
+                ditype.get_align_in_bits(),
+                offset,
+                DIFlags::PUBLIC,
+                ditype,
+            )
+            .as_type()
+    }
+
     /// # get_ditype
     /// get the debug info type of the pltype
     fn get_ditype(&self, pltp: &PLType, ctx: &mut Ctx<'a>) -> Option<DIType<'ctx>> {
         let td = self.targetmachine.get_target_data();
         match pltp {
-            PLType::FN(_) => self.get_ditype(&PLType::PRIMITIVE(PriType::I64), ctx),
+            PLType::FN(_) => {
+                // 函数类型的值只出现在trait对象的方法表中，显示为指针，
+                // 调试器会在指针的值后面显示它指向的函数名
+                let elemdi = self.get_ditype(&PLType::PRIMITIVE(PriType::U64), ctx)?;
+                let ptrtp = self
+                    .context
+                    .i8_type()
+                    .ptr_type(AddressSpace::default())
+                    .as_basic_type_enum();
+                Some(
+                    self.dibuilder
+                        .create_pointer_type(
+                            "",
+                            elemdi,
+                            td.get_bit_size(&ptrtp),
+                            td.get_preferred_alignment(&ptrtp),
+                            AddressSpace::default(),
+                        )
+                        .as_type(),
+                )
+            }
             PLType::GENERIC(g) => {
                 if g.curpltype.is_some() {
                     let pltype = g.curpltype.as_ref().unwrap();
@@ -730,13 +943,106 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
                 }
             }
             PLType::PLACEHOLDER(_) => self.get_ditype(&PLType::PRIMITIVE(PriType::I64), ctx),
+            PLType::ENUM(e) => self.get_ditype(&PLType::STRUCT(e.get_layout()), ctx),
+            PLType::CLOSURE(c) => {
+                let st_tp = self.closure_type(c, ctx).into_struct_type();
+                let ptrdi = self.get_ditype(&PLType::PRIMITIVE(PriType::U64), ctx)?;
+                let members = ["fn", "env"]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| {
+                        let offset = td.offset_of_element(&st_tp, i as u32).unwrap();
+                        self.dibuilder
+                            .create_member_type(
+                                self.diunit.get_file().as_debug_info_scope(),
+                                name,
+                                self.diunit.get_file(),
+                                0,
+                                ptrdi.get_size_in_bits(),
+                                ptrdi.get_align_in_bits(),
+                                offset * 8,
+                                DIFlags::PUBLIC,
+                                ptrdi,
+                            )
+                            .as_type()
+                    })
+                    .collect::<Vec<_>>();
+                Some(
+                    self.dibuilder
+                        .create_struct_type(
+                            self.diunit.get_file().as_debug_info_scope(),
+                            "closure",
+                            self.diunit.get_file(),
+                            c.range.start.line as u32 + 1,
+                            td.get_bit_size(&st_tp),
+                            td.get_abi_alignment(&st_tp),
+                            DIFlags::PUBLIC,
+                            None,
+                            &members,
+                            0,
+                            None,
+                            "closure",
+                        )
+                        .as_type(),
+                )
+            }
+            PLType::ARR(arr) if arr.is_dynamic() => {
+                // 动态数组是指向数组对象的指针，数组对象显示为结构体`dyn_arr`，
+                // 它的`array`字段长度为0，实际长度是`len`字段
+                let elemdi = self.get_ditype(&arr.element_type.borrow(), ctx)?;
+                let obj_tp = self.dyn_arr_obj_type(arr, ctx);
+                let vtabledi = self.get_ditype(&PLType::PRIMITIVE(PriType::U64), ctx)?;
+                let lendi = self.get_ditype(&PLType::PRIMITIVE(PriType::I64), ctx)?;
+                let arrdi = self
+                    .dibuilder
+                    .create_array_type(elemdi, 0, elemdi.get_align_in_bits(), &[(0..0)])
+                    .as_type();
+                let members = [("_vtable", vtabledi), ("len", lendi), ("array", arrdi)]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, di))| {
+                        let offset = td.offset_of_element(&obj_tp, i as u32).unwrap();
+                        self.member_di_type(name, *di, offset * 8)
+                    })
+                    .collect::<Vec<_>>();
+                let st = self
+                    .dibuilder
+                    .create_struct_type(
+                        self.diunit.get_file().as_debug_info_scope(),
+                        "dyn_arr",
+                        self.diunit.get_file(),
+                        0,
+                        td.get_bit_size(&obj_tp),
+                        td.get_abi_alignment(&obj_tp),
+                        DIFlags::PUBLIC,
+                        None,
+                        &members,
+                        0,
+                        None,
+                        "dyn_arr",
+                    )
+                    .as_type();
+                let ptrtp = self.arr_type(arr, ctx);
+                Some(
+                    self.dibuilder
+                        .create_pointer_type(
+                            "",
+                            st,
+                            td.get_bit_size(&ptrtp),
+                            td.get_preferred_alignment(&ptrtp),
+                            AddressSpace::default(),
+                        )
+                        .as_type(),
+                )
+            }
             PLType::ARR(arr) => {
+                let size = arr.size.unwrap();
                 let elemdi = self.get_ditype(&arr.element_type.borrow(), ctx)?;
                 let etp = &self
                     .get_basic_type_op(&arr.element_type.borrow(), ctx)
                     .unwrap();
                 let arr_st_tp = self.arr_type(arr, ctx).into_struct_type();
-                let size = td.get_bit_size(etp) * arr.size as u64;
+                let size = td.get_bit_size(etp) * size as u64;
                 let align = td.get_preferred_alignment(etp);
                 let st_size = td.get_bit_size(&arr_st_tp);
                 let vtabledi = self.get_ditype(&PLType::PRIMITIVE(PriType::U64), ctx)?;
@@ -754,7 +1060,7 @@ impl<'a, 'ctx> LLVMBuilder<'a, 'ctx> {
                 );
                 let arrdi = self
                     .dibuilder
-                    .create_array_type(elemdi, size, align, &[(0..arr.size as i64)])
+                    .create_array_type(elemdi, size, align, &[(0..arr.size.unwrap() as i64)])
                     .as_type();
                 let offset = td.offset_of_element(&arr_st_tp, 1).unwrap();
                 let arrtp = self.dibuilder.create_member_type(
@@ -1026,6 +1332,7 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for LLVMBuilder<'a, 'ctx> {
     }
     fn new_subscope(&self, start: Pos) {
         let scope = self.discope.get();
+        self.discope_stack.borrow_mut().push(scope);
         self.discope.set(
             self.dibuilder
                 .create_lexical_block(
@@ -1037,6 +1344,11 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for LLVMBuilder<'a, 'ctx> {
                 .as_debug_info_scope(),
         );
     }
+    fn end_subscope(&self) {
+        if let Some(scope) = self.discope_stack.borrow_mut().pop() {
+            self.discope.set(scope);
+        }
+    }
     fn position_at_end_block(&self, block: BlockHandle) {
         self.builder
             .position_at_end(self.block_table.borrow()[&block]);
@@ -1320,7 +1632,7 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for LLVMBuilder<'a, 'ctx> {
             .builder
             .build_phi(self.get_basic_type_op(pltype, ctx).unwrap(), "");
         for (value, block) in vbs {
-            let value = self.get_llvm_value(*value).unwrap().into_int_value();
+            let value: BasicValueEnum = self.get_llvm_value(*value).unwrap().try_into().unwrap();
             let block = self.get_llvm_block(*block).unwrap();
             phi.add_incoming(&[(&value, block)]);
         }
@@ -1422,6 +1734,131 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for LLVMBuilder<'a, 'ctx> {
         let v = self.builder.build_int_neg(v, name);
         self.get_llvm_value_handle(&v.as_any_value_enum())
     }
+    fn build_int_unsigned_div(
+        &self,
+        lhs: ValueHandle,
+        rhs: ValueHandle,
+        name: &str,
+    ) -> ValueHandle {
+        let lhs = self.get_llvm_value(lhs).unwrap().into_int_value();
+        let rhs = self.get_llvm_value(rhs).unwrap().into_int_value();
+        let v = self.builder.build_int_unsigned_div(lhs, rhs, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_int_signed_rem(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str) -> ValueHandle {
+        let lhs = self.get_llvm_value(lhs).unwrap().into_int_value();
+        let rhs = self.get_llvm_value(rhs).unwrap().into_int_value();
+        let v = self.builder.build_int_signed_rem(lhs, rhs, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_int_unsigned_rem(
+        &self,
+        lhs: ValueHandle,
+        rhs: ValueHandle,
+        name: &str,
+    ) -> ValueHandle {
+        let lhs = self.get_llvm_value(lhs).unwrap().into_int_value();
+        let rhs = self.get_llvm_value(rhs).unwrap().into_int_value();
+        let v = self.builder.build_int_unsigned_rem(lhs, rhs, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_xor(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str) -> ValueHandle {
+        let lhs = self.get_llvm_value(lhs).unwrap().into_int_value();
+        let rhs = self.get_llvm_value(rhs).unwrap().into_int_value();
+        let v = self.builder.build_xor(lhs, rhs, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_left_shift(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str) -> ValueHandle {
+        let lhs = self.get_llvm_value(lhs).unwrap().into_int_value();
+        let rhs = self.get_llvm_value(rhs).unwrap().into_int_value();
+        let v = self.builder.build_left_shift(lhs, rhs, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_float_rem(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str) -> ValueHandle {
+        let lhs = self.get_llvm_value(lhs).unwrap().into_float_value();
+        let rhs = self.get_llvm_value(rhs).unwrap().into_float_value();
+        let v = self.builder.build_float_rem(lhs, rhs, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_int_not(&self, v: ValueHandle, name: &str) -> ValueHandle {
+        let v = self.get_llvm_value(v).unwrap().into_int_value();
+        let v = self.builder.build_not(v, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_right_shift(
+        &self,
+        lhs: ValueHandle,
+        rhs: ValueHandle,
+        sign_extend: bool,
+        name: &str,
+    ) -> ValueHandle {
+        let lhs = self.get_llvm_value(lhs).unwrap().into_int_value();
+        let rhs = self.get_llvm_value(rhs).unwrap().into_int_value();
+        let v = self.builder.build_right_shift(lhs, rhs, sign_extend, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_int_s_extend(&self, v: ValueHandle, ty: &PriType, name: &str) -> ValueHandle {
+        let v = self.get_llvm_value(v).unwrap().into_int_value();
+        let ty = self.get_pri_basic_type(ty).into_int_type();
+        let v = self.builder.build_int_s_extend(v, ty, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_float_cast(&self, v: ValueHandle, ty: &PriType, name: &str) -> ValueHandle {
+        let v = self.get_llvm_value(v).unwrap().into_float_value();
+        let ty = self.get_pri_basic_type(ty).into_float_type();
+        let v = self.builder.build_float_cast(v, ty, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_int_to_float(
+        &self,
+        v: ValueHandle,
+        ty: &PriType,
+        signed: bool,
+        name: &str,
+    ) -> ValueHandle {
+        let v = self.get_llvm_value(v).unwrap().into_int_value();
+        let ty = self.get_pri_basic_type(ty).into_float_type();
+        let v = if signed {
+            self.builder.build_signed_int_to_float(v, ty, name)
+        } else {
+            self.builder.build_unsigned_int_to_float(v, ty, name)
+        };
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_float_to_int(
+        &self,
+        v: ValueHandle,
+        ty: &PriType,
+        signed: bool,
+        name: &str,
+    ) -> ValueHandle {
+        let v = self.get_llvm_value(v).unwrap().into_float_value();
+        let ty = self.get_pri_basic_type(ty).into_int_type();
+        let v = if signed {
+            self.builder.build_float_to_signed_int(v, ty, name)
+        } else {
+            self.builder.build_float_to_unsigned_int(v, ty, name)
+        };
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_ptr_to_int(&self, v: ValueHandle, ty: &PriType, name: &str) -> ValueHandle {
+        let v = self.get_llvm_value(v).unwrap().into_pointer_value();
+        let ty = self.get_pri_basic_type(ty).into_int_type();
+        let v = self.builder.build_ptr_to_int(v, ty, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
+    fn build_int_to_ptr(
+        &self,
+        ctx: &mut Ctx<'a>,
+        v: ValueHandle,
+        ty: &PLType,
+        name: &str,
+    ) -> ValueHandle {
+        let v = self.get_llvm_value(v).unwrap().into_int_value();
+        let ty = self.get_basic_type_op(ty, ctx).unwrap().into_pointer_type();
+        let v = self.builder.build_int_to_ptr(v, ty, name);
+        self.get_llvm_value_handle(&v.as_any_value_enum())
+    }
     fn build_int_add(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str) -> ValueHandle {
         let lhs = self.get_llvm_value(lhs).unwrap().into_int_value();
         let rhs = self.get_llvm_value(rhs).unwrap().into_int_value();
@@ -1650,10 +2087,12 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for LLVMBuilder<'a, 'ctx> {
         let base_type = self.get_basic_type_op(&pltype.borrow(), ctx).unwrap();
         let global = self.module.add_global(base_type, None, name);
         let ditype = self.get_ditype(pltp, ctx);
+        // 调试器中用源码中的变量名，完整的名字作为linkage name
+        let short_name = name.rsplit("..").next().unwrap_or(name);
         let exp = self.dibuilder.create_global_variable_expression(
             self.diunit.as_debug_info_scope(),
+            short_name,
             name,
-            "",
             self.diunit.get_file(),
             line,
             ditype.unwrap(),
@@ -1698,8 +2137,10 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for LLVMBuilder<'a, 'ctx> {
             let visit_complex_f = get_nth_mark_fn(f, 3);
             let visit_trait_f = get_nth_mark_fn(f, 4);
             let f = self.builder.build_struct_gep(st, i, "gep").unwrap();
-            // 指针类型，递归调用visit函数
-            if let PLType::POINTER(_) = field_pltp {
+            // 指针类型与动态数组，递归调用visit函数
+            if matches!(field_pltp, PLType::POINTER(_))
+                || matches!(field_pltp, PLType::ARR(a) if a.is_dynamic())
+            {
                 let ptr = f;
                 let casted = self.builder.build_bitcast(ptr, i8ptrtp, "casted_arg");
                 self.builder
@@ -1712,15 +2153,15 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for LLVMBuilder<'a, 'ctx> {
                 self.builder
                     .build_call(visit_complex_f, &[visitor.into(), casted.into()], "call");
             }
-            // 结构体类型，递归调用visit函数
-            else if let PLType::STRUCT(_) = field_pltp {
+            // 结构体与枚举类型，递归调用visit函数
+            else if let PLType::STRUCT(_) | PLType::ENUM(_) = field_pltp {
                 let ptr = f;
                 let casted = self.builder.build_bitcast(ptr, i8ptrtp, "casted_arg");
                 self.builder
                     .build_call(visit_complex_f, &[visitor.into(), casted.into()], "call");
             }
-            // trait类型，递归调用visit函数
-            else if let PLType::TRAIT(_) = field_pltp {
+            // trait与闭包类型，递归调用visit函数
+            else if let PLType::TRAIT(_) | PLType::CLOSURE(_) = field_pltp {
                 let ptr = f;
                 let casted = self.builder.build_bitcast(ptr, i8ptrtp, "casted_arg");
                 self.builder
@@ -1738,4 +2179,110 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for LLVMBuilder<'a, 'ctx> {
     fn get_stack_root(&self, v: ValueHandle) -> ValueHandle {
         *self.heap_stack_map.borrow().get(&v).unwrap()
     }
+
+    fn get_nth_param(&self, f: ValueHandle, i: u32) -> ValueHandle {
+        let f = self.get_llvm_value(f).unwrap().into_function_value();
+        self.get_llvm_value_handle(&f.get_nth_param(i).unwrap().as_any_value_enum())
+    }
+
+    /// 捕获环境中除vtable外每个字段都是一个`i8*`，
+    /// vtable与指针大小相同，所以可以直接把环境当作`i8**`来索引
+    fn build_load_captured(
+        &self,
+        env: ValueHandle,
+        index: u32,
+        origin: ValueHandle,
+    ) -> ValueHandle {
+        let env = self.get_llvm_value(env).unwrap().into_pointer_value();
+        let origin = self.get_llvm_value(origin).unwrap().into_pointer_value();
+        let i8ptrtp = self.context.i8_type().ptr_type(AddressSpace::default());
+        let slots = self
+            .builder
+            .build_bitcast(env, i8ptrtp.ptr_type(AddressSpace::default()), "env_slots")
+            .into_pointer_value();
+        let slot = unsafe {
+            self.builder.build_in_bounds_gep(
+                slots,
+                &[self.context.i64_type().const_int(index as u64, false)],
+                "captured_slot",
+            )
+        };
+        let ptr = self.builder.build_load(slot, "captured_ptr");
+        let captured = self
+            .builder
+            .build_bitcast(ptr, origin.get_type(), "captured");
+        self.get_llvm_value_handle(&captured.as_any_value_enum())
+    }
+
+    fn alloc_dyn_arr(
+        &self,
+        name: &str,
+        tp: &ARRType,
+        len: ValueHandle,
+        ctx: &mut Ctx<'a>,
+    ) -> ValueHandle {
+        let td = self.targetmachine.get_target_data();
+        let len = self.get_llvm_value(len).unwrap().into_int_value();
+        let objtp = self.dyn_arr_obj_type(tp, ctx);
+        let elmtp = self
+            .get_basic_type_op(&tp.element_type.borrow(), ctx)
+            .unwrap();
+        let i64tp = self.context.i64_type();
+        // 对象头是vtable和长度两个字段，后面紧跟len个元素
+        let header = i64tp.const_int(td.get_abi_size(&objtp), false);
+        let elm_size = i64tp.const_int(td.get_abi_size(&elmtp), false);
+        let size = self.builder.build_int_mul(len, elm_size, "arr_size");
+        let size = self.builder.build_int_add(size, header, "obj_size");
+        let mut root_ctx = &*ctx;
+        while let Some(f) = root_ctx.father {
+            root_ctx = f
+        }
+        let gcmod = root_ctx.plmod.submods.get("gc").unwrap_or(&root_ctx.plmod);
+        let f: FNValue = gcmod
+            .get_type("DioGC__malloc")
+            .unwrap()
+            .borrow()
+            .clone()
+            .try_into()
+            .unwrap();
+        let f = self.get_or_insert_fn(&f, ctx);
+        let obj_type = self
+            .context
+            .i8_type()
+            .const_int(ObjectType::Complex.int_value() as u64, false);
+        let heapptr = self
+            .builder
+            .build_call(
+                f,
+                &[size.into(), obj_type.into()],
+                &format!("heapptr_{}", name),
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        self.builder
+            .build_memset(
+                heapptr,
+                td.get_abi_alignment(&objtp),
+                self.context.i8_type().const_zero(),
+                size,
+            )
+            .unwrap();
+        let obj = self
+            .builder
+            .build_bitcast(heapptr, objtp.ptr_type(AddressSpace::default()), name)
+            .into_pointer_value();
+        let visit_f = self.gen_or_get_dyn_arr_visit_function(ctx, tp);
+        let vtable = self.builder.build_ptr_to_int(
+            visit_f.as_global_value().as_pointer_value(),
+            i64tp,
+            "_vtable",
+        );
+        let vtable_ptr = self.builder.build_struct_gep(obj, 0, "vtable").unwrap();
+        self.builder.build_store(vtable_ptr, vtable);
+        let len_ptr = self.builder.build_struct_gep(obj, 1, "len").unwrap();
+        self.builder.build_store(len_ptr, len);
+        self.get_llvm_value_handle(&obj.as_any_value_enum())
+    }
 }
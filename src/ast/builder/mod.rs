@@ -15,7 +15,7 @@ use super::{
     ctx::Ctx,
     diag::PLDiag,
     node::{types::TypedIdentifierNode, TypeNodeEnum},
-    pltype::{FNValue, Field, PLType, PriType, STType},
+    pltype::{ARRType, FNValue, Field, PLType, PriType, STType},
     range::{Pos, Range},
 };
 
@@ -32,6 +32,8 @@ pub trait IRBuilder<'a, 'ctx> {
     ) -> ValueHandle;
     fn get_global_var_handle(&self, name: &str) -> Option<ValueHandle>;
     fn new_subscope(&self, start: Pos);
+    /// 结束[`IRBuilder::new_subscope`]创建的子作用域，恢复之前的调试信息scope
+    fn end_subscope(&self);
     fn add_global(
         &self,
         name: &str,
@@ -179,6 +181,48 @@ pub trait IRBuilder<'a, 'ctx> {
     fn append_basic_block(&self, func: ValueHandle, name: &str) -> BlockHandle;
     fn build_int_truncate(&self, v: ValueHandle, dest_ty: &PriType, name: &str) -> ValueHandle;
     fn build_int_neg(&self, v: ValueHandle, name: &str) -> ValueHandle;
+    fn build_int_unsigned_div(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str)
+        -> ValueHandle;
+    fn build_int_signed_rem(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str) -> ValueHandle;
+    fn build_int_unsigned_rem(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str)
+        -> ValueHandle;
+    fn build_float_rem(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str) -> ValueHandle;
+    fn build_xor(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str) -> ValueHandle;
+    fn build_int_not(&self, v: ValueHandle, name: &str) -> ValueHandle;
+    fn build_left_shift(&self, lhs: ValueHandle, rhs: ValueHandle, name: &str) -> ValueHandle;
+    /// sign_extend为true时为算术右移，否则为逻辑右移
+    fn build_right_shift(
+        &self,
+        lhs: ValueHandle,
+        rhs: ValueHandle,
+        sign_extend: bool,
+        name: &str,
+    ) -> ValueHandle;
+    fn build_int_s_extend(&self, v: ValueHandle, ty: &PriType, name: &str) -> ValueHandle;
+    /// float之间的转换（fpext或fptrunc）
+    fn build_float_cast(&self, v: ValueHandle, ty: &PriType, name: &str) -> ValueHandle;
+    fn build_int_to_float(
+        &self,
+        v: ValueHandle,
+        ty: &PriType,
+        signed: bool,
+        name: &str,
+    ) -> ValueHandle;
+    fn build_float_to_int(
+        &self,
+        v: ValueHandle,
+        ty: &PriType,
+        signed: bool,
+        name: &str,
+    ) -> ValueHandle;
+    fn build_ptr_to_int(&self, v: ValueHandle, ty: &PriType, name: &str) -> ValueHandle;
+    fn build_int_to_ptr(
+        &self,
+        ctx: &mut Ctx<'a>,
+        v: ValueHandle,
+        ty: &PLType,
+        name: &str,
+    ) -> ValueHandle;
     fn gen_st_visit_function(
         &self,
         ctx: &mut Ctx<'a>,
@@ -186,6 +230,17 @@ pub trait IRBuilder<'a, 'ctx> {
         field_tps: &[Arc<RefCell<PLType>>],
     );
     fn get_stack_root(&self, v: ValueHandle) -> ValueHandle;
+    fn get_nth_param(&self, f: ValueHandle, i: u32) -> ValueHandle;
+    fn build_load_captured(&self, env: ValueHandle, index: u32, origin: ValueHandle)
+        -> ValueHandle;
+    /// 在堆上分配长度为len的动态数组，返回指向数组对象的指针
+    fn alloc_dyn_arr(
+        &self,
+        name: &str,
+        tp: &ARRType,
+        len: ValueHandle,
+        ctx: &mut Ctx<'a>,
+    ) -> ValueHandle;
 }
 
 pub type ValueHandle = usize;
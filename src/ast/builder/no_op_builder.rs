@@ -2,7 +2,7 @@ use std::{cell::RefCell, sync::Arc};
 
 use crate::ast::{
     ctx::Ctx,
-    pltype::{PLType, STType},
+    pltype::{ARRType, PLType, STType},
 };
 
 use super::{IRBuilder, ValueHandle};
@@ -41,6 +41,8 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for NoOpBuilder {
 
     fn new_subscope(&self, _start: crate::ast::range::Pos) {}
 
+    fn end_subscope(&self) {}
+
     fn add_global(
         &self,
         _name: &str,
@@ -405,6 +407,131 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for NoOpBuilder {
         0
     }
 
+    fn build_int_unsigned_div(
+        &self,
+        _lhs: super::ValueHandle,
+        _rhs: super::ValueHandle,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_int_signed_rem(
+        &self,
+        _lhs: super::ValueHandle,
+        _rhs: super::ValueHandle,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_int_unsigned_rem(
+        &self,
+        _lhs: super::ValueHandle,
+        _rhs: super::ValueHandle,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_float_rem(
+        &self,
+        _lhs: super::ValueHandle,
+        _rhs: super::ValueHandle,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_xor(
+        &self,
+        _lhs: super::ValueHandle,
+        _rhs: super::ValueHandle,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_left_shift(
+        &self,
+        _lhs: super::ValueHandle,
+        _rhs: super::ValueHandle,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_int_not(&self, _v: super::ValueHandle, _name: &str) -> super::ValueHandle {
+        0
+    }
+
+    fn build_right_shift(
+        &self,
+        _lhs: super::ValueHandle,
+        _rhs: super::ValueHandle,
+        _sign_extend: bool,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_int_s_extend(
+        &self,
+        _v: super::ValueHandle,
+        _ty: &crate::ast::pltype::PriType,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_float_cast(
+        &self,
+        _v: super::ValueHandle,
+        _ty: &crate::ast::pltype::PriType,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_int_to_float(
+        &self,
+        _v: super::ValueHandle,
+        _ty: &crate::ast::pltype::PriType,
+        _signed: bool,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_float_to_int(
+        &self,
+        _v: super::ValueHandle,
+        _ty: &crate::ast::pltype::PriType,
+        _signed: bool,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_ptr_to_int(
+        &self,
+        _v: super::ValueHandle,
+        _ty: &crate::ast::pltype::PriType,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
+    fn build_int_to_ptr(
+        &self,
+        _ctx: &mut Ctx<'a>,
+        _v: super::ValueHandle,
+        _ty: &PLType,
+        _name: &str,
+    ) -> super::ValueHandle {
+        0
+    }
+
     fn gen_st_visit_function(
         &self,
         _ctx: &mut Ctx<'a>,
@@ -412,4 +539,27 @@ impl<'a, 'ctx> IRBuilder<'a, 'ctx> for NoOpBuilder {
         _field_tps: &[Arc<RefCell<PLType>>],
     ) {
     }
+
+    fn get_nth_param(&self, _f: ValueHandle, _i: u32) -> ValueHandle {
+        0
+    }
+
+    fn build_load_captured(
+        &self,
+        _env: ValueHandle,
+        _index: u32,
+        _origin: ValueHandle,
+    ) -> ValueHandle {
+        0
+    }
+
+    fn alloc_dyn_arr(
+        &self,
+        _name: &str,
+        _tp: &ARRType,
+        _len: ValueHandle,
+        _ctx: &mut Ctx<'a>,
+    ) -> ValueHandle {
+        0
+    }
 }
@@ -0,0 +1,304 @@
+//! # cache
+//! 磁盘上的增量编译缓存
+//!
+//! 分为两级：
+//! - [ModCache]：模块源码、依赖的接口与目标平台都没有变化时，复用上次生成的bitcode，
+//!   只用no-op builder分析模块，得到依赖它的模块需要的[Mod]
+//! - [ObjCache]：模块的bitcode与编译选项没有变化时，复用上次生成的目标文件
+//!
+//! 缓存文件放在target目录中，`plc clean`会删除它们，`--no-cache`会忽略它们
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use lazy_static::lazy_static;
+use log::warn;
+use parking_lot::Mutex;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use super::{compiler::Options, node::NodeEnum, plmod::Mod};
+use crate::utils::get_hash_code;
+
+lazy_static! {
+    pub static ref MOD_CACHE: Mutex<ModCache> = Mutex::new(ModCache::default());
+}
+
+/// 计算模块接口的hash
+///
+/// 接口是模块源码去掉非泛型函数体之后剩下的部分（名字、类型、修饰符、trait约束、宏等），
+/// 以及它依赖的模块的接口。泛型函数和泛型impl中的方法会在使用者的模块中实例化，
+/// 所以它们的函数体也属于接口。只修改非泛型函数体时接口不变，依赖它的模块不需要重新编译
+pub fn interface_hash(src: &[u8], node: &NodeEnum, submods: &FxHashMap<String, Mod>) -> u64 {
+    let mut bodies = vec![];
+    if let NodeEnum::Program(prog) = node {
+        for n in prog.nodes.iter() {
+            match &**n {
+                NodeEnum::FuncDef(f) if f.generics.is_none() => {
+                    bodies.extend(f.body.as_ref().map(|b| b.range))
+                }
+                NodeEnum::ImplNode(im) if im.generics.is_none() => bodies.extend(
+                    im.methods
+                        .iter()
+                        .filter(|m| m.generics.is_none())
+                        .filter_map(|m| m.body.as_ref().map(|b| b.range)),
+                ),
+                _ => (),
+            }
+        }
+    }
+    bodies.sort_by_key(|r| r.start.offset);
+    let mut interface = Vec::with_capacity(src.len());
+    let mut last = 0;
+    for r in bodies {
+        let (start, end) = (r.start.offset, r.end.offset);
+        if start < last || start > end || end > src.len() {
+            continue;
+        }
+        interface.extend_from_slice(&src[last..start]);
+        last = end;
+    }
+    interface.extend_from_slice(&src[last..]);
+    get_hash_code((interface, submod_hashes(submods)))
+}
+
+fn submod_hashes(submods: &FxHashMap<String, Mod>) -> Vec<(&String, u64)> {
+    let mut hashes = submods
+        .iter()
+        .map(|(name, m)| (name, m.interface_hash))
+        .collect::<Vec<_>>();
+    hashes.sort();
+    hashes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModCacheEntry {
+    pub key: u64,
+    pub is_main: bool,
+}
+
+/// # ModCache
+/// 记录每个模块的bitcode是用什么样的输入生成的
+///
+/// 缓存键由模块源码、影响bitcode的编译选项以及所有依赖模块的接口hash组成，
+/// 依赖的接口变化时缓存失效，这样失效会沿着依赖关系传递
+#[derive(Debug, Default)]
+pub struct ModCache {
+    enabled: bool,
+    /// 目标平台、cpu与cpu特性的hash
+    options: u64,
+    path: PathBuf,
+    entries: FxHashMap<String, ModCacheEntry>,
+}
+
+impl ModCache {
+    const FILE_NAME: &'static str = ".modcache.json";
+
+    /// 每次编译开始时调用，`no_cache`时不会使用也不会记录缓存
+    ///
+    /// 优化在生成目标文件时才进行，由[ObjCache]负责，所以不属于这里的缓存键
+    pub fn init(&mut self, dir: &Path, triple: &str, op: &Options) {
+        let enabled = !op.no_cache;
+        self.enabled = enabled;
+        self.options = get_hash_code((triple, &op.target));
+        self.path = dir.join(Self::FILE_NAME);
+        self.entries = if enabled {
+            read_json(&self.path)
+        } else {
+            FxHashMap::default()
+        };
+    }
+
+    pub fn key(&self, src: &[u8], submods: &FxHashMap<String, Mod>) -> u64 {
+        get_hash_code((src, self.options, submod_hashes(submods)))
+    }
+
+    /// bc的缓存仍然有效时返回上次记录的信息
+    pub fn get(&self, bc: &Path, key: u64) -> Option<ModCacheEntry> {
+        if !self.enabled || !bc.exists() {
+            return None;
+        }
+        self.entries
+            .get(&*bc.to_string_lossy())
+            .filter(|e| e.key == key)
+            .copied()
+    }
+
+    pub fn update(&mut self, bc: &Path, entry: ModCacheEntry) {
+        if self.enabled {
+            self.entries.insert(bc.to_string_lossy().to_string(), entry);
+        }
+    }
+
+    pub fn save(&self) {
+        if self.enabled {
+            write_json(&self.path, &self.entries);
+        }
+    }
+}
+
+/// # ObjCache
+/// 记录生成每个目标文件时所用bitcode的hash
+///
+/// 模块的bitcode由`emit_file`生成，它与编译选项、目标平台都没有变化时可以跳过优化和
+/// 目标文件生成，直接复用上次构建留下的目标文件
+pub struct ObjCache {
+    enabled: bool,
+    path: PathBuf,
+    hashes: FxHashMap<String, u64>,
+}
+
+impl ObjCache {
+    const FILE_NAME: &'static str = ".objcache.json";
+
+    pub fn load(dir: &Path, enabled: bool) -> Self {
+        let path = dir.join(Self::FILE_NAME);
+        let hashes = if enabled {
+            read_json(&path)
+        } else {
+            FxHashMap::default()
+        };
+        Self {
+            enabled,
+            path,
+            hashes,
+        }
+    }
+
+    pub fn key(bc: &[u8], op: &Options, triple: &str) -> u64 {
+        get_hash_code((bc, op, triple))
+    }
+
+    pub fn is_fresh(&self, obj: &Path, key: u64) -> bool {
+        self.enabled && obj.exists() && self.hashes.get(&*obj.to_string_lossy()) == Some(&key)
+    }
+
+    pub fn update(&mut self, obj: &Path, key: u64) {
+        if self.enabled {
+            self.hashes.insert(obj.to_string_lossy().to_string(), key);
+        }
+    }
+
+    pub fn save(&self) {
+        if self.enabled {
+            write_json(&self.path, &self.hashes);
+        }
+    }
+}
+
+fn read_json<T: for<'de> Deserialize<'de> + Default>(path: &Path) -> T {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn write_json<T: Serialize>(path: &Path, v: &T) {
+    if let Err(e) = fs::write(path, serde_json::to_string(v).unwrap()) {
+        warn!("failed to save cache {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::compiler::HashOptimizationLevel,
+        nomparser::{program::program, Span},
+    };
+
+    fn hash_of(src: &str) -> u64 {
+        let (_, node) = program(Span::new_extra(src, false)).unwrap();
+        interface_hash(src.as_bytes(), &node, &FxHashMap::default())
+    }
+
+    #[test]
+    fn test_interface_hash() {
+        let base = hash_of("pub fn f() i64 {\n    return 1;\n}\n");
+        // 只修改非泛型函数体不影响接口
+        assert_eq!(base, hash_of("pub fn f() i64 {\n    return 2;\n}\n"));
+        assert_ne!(base, hash_of("pub fn f() i32 {\n    return 1;\n}\n"));
+        assert_ne!(base, hash_of("fn f() i64 {\n    return 1;\n}\n"));
+        // 泛型函数体会在使用者中实例化，属于接口
+        assert_ne!(
+            hash_of("pub fn g<T>(t: T) T {\n    return t;\n}\n"),
+            hash_of("pub fn g<T>(t: T) T {\n    let x = t;\n    return x;\n}\n")
+        );
+        // 泛型impl中的方法同样属于接口
+        let st = "pub struct S<T> {\n    t: T;\n}\n";
+        assert_ne!(
+            hash_of(&format!(
+                "{}impl<T> S<T> {{\n    pub fn get() T {{\n        return self.t;\n    }}\n}}\n",
+                st
+            )),
+            hash_of(&format!(
+                "{}impl<T> S<T> {{\n    pub fn get() T {{\n        let t = self.t;\n        return t;\n    }}\n}}\n",
+                st
+            ))
+        );
+    }
+
+    #[test]
+    fn test_interface_hash_bounds_and_macros() {
+        let tr = "pub trait A {\n    fn a() i64;\n}\npub trait B {\n    fn b() i64;\n}\n";
+        let bound = |b: &str| {
+            hash_of(&format!(
+                "{}pub fn f<T>(t: T) i64\nwhere\n    T: {}\n{{\n    return 1;\n}}\n",
+                tr, b
+            ))
+        };
+        assert_ne!(bound("A"), bound("B"));
+        // 带参数的宏也是接口的一部分
+        let mac = |body: &str| {
+            hash_of(&format!(
+                "macro m {{\n    ($a:@id) => {{\n        {}\n    }};\n}}\n",
+                body
+            ))
+        };
+        assert_ne!(mac("let $a = 1;"), mac("let $a = 2;"));
+    }
+
+    #[test]
+    fn test_mod_cache_options() {
+        let dir = Path::new("target/mod_cache_test");
+        fs::create_dir_all(dir).unwrap();
+        let bc = dir.join("m.bc");
+        fs::write(&bc, "").unwrap();
+        let op = Options::default();
+        let triple = "x86_64-unknown-linux-gnu";
+        let mut cache = ModCache::default();
+        cache.init(dir, triple, &op);
+        let key = cache.key(b"pub fn f() void {}", &FxHashMap::default());
+        let entry = ModCacheEntry {
+            key,
+            is_main: false,
+        };
+        cache.update(&bc, entry);
+        cache.save();
+
+        let key_with = |triple: &str, op: &Options| {
+            let mut cache = ModCache::default();
+            cache.init(dir, triple, op);
+            let key = cache.key(b"pub fn f() void {}", &FxHashMap::default());
+            cache.get(&bc, key)
+        };
+        assert_eq!(key_with(triple, &op), Some(entry));
+        // 目标平台、cpu或cpu特性变化时缓存失效
+        assert_eq!(key_with("aarch64-unknown-linux-gnu", &op), None);
+        let mut cpu = op.clone();
+        cpu.target.cpu = Some("skylake".to_string());
+        assert_eq!(key_with(triple, &cpu), None);
+        let mut features = op.clone();
+        features.target.features = Some("+avx2".to_string());
+        assert_eq!(key_with(triple, &features), None);
+        // 只在生成目标文件时使用的选项不影响bitcode
+        let mut opt = op.clone();
+        opt.optimization = HashOptimizationLevel::Aggressive;
+        assert_eq!(key_with(triple, &opt), Some(entry));
+        // 不使用缓存时总是重新生成
+        let mut no_cache = op;
+        no_cache.no_cache = true;
+        assert_eq!(key_with(triple, &no_cache), None);
+    }
+}
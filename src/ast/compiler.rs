@@ -3,6 +3,7 @@ use crate::{
     ast::{
         accumulators::{Diagnostics, ModBuffer},
         builder::llvmbuilder::get_target_machine,
+        cache::{ObjCache, MOD_CACHE},
         node::program::Program,
         pass::MAP_NAMES,
     },
@@ -20,6 +21,7 @@ use inkwell::{
     passes::{PassManager, PassManagerBuilder},
     OptimizationLevel,
 };
+use kagari::manifest::{find_workspace, MANIFEST};
 use lazy_static::lazy_static;
 use log::{debug, info, trace, warn};
 use pl_linker::{linker::create_with_target, mun_target::spec::Target};
@@ -30,13 +32,41 @@ use std::{
     time::{Duration, Instant},
 };
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Copy)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct Options {
     pub genir: bool,
     pub printast: bool,
     pub flow: bool,
     pub optimization: HashOptimizationLevel,
     pub fmt: bool,
+    /// 输出展开宏之后的代码
+    pub expand_macros: bool,
+    /// 不使用也不记录增量编译缓存
+    pub no_cache: bool,
+    pub target: TargetOptions,
+    /// 编译的是`plc test`生成的测试入口，入口中可以调用其他模块中非pub的测试函数
+    pub test_harness: bool,
+    /// 模块的bitcode与增量编译缓存所在的目录，为None时使用当前目录下的`target`
+    pub target_dir: Option<PathBuf>,
+}
+
+impl Options {
+    pub fn target_dir(&self) -> PathBuf {
+        self.target_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("target"))
+    }
+}
+
+/// # TargetOptions
+/// 编译的目标平台，字段为None时使用主机的配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TargetOptions {
+    /// 目标平台的triple，如`aarch64-unknown-linux-gnu`
+    pub triple: Option<String>,
+    pub cpu: Option<String>,
+    /// llvm格式的cpu特性，如`+neon,-fp-armv8`
+    pub features: Option<String>,
 }
 
 #[repr(u32)]
@@ -72,6 +102,9 @@ impl HashOptimizationLevel {
 pub enum ActionType {
     Completion,
     GotoDef,
+    GotoTypeDef,
+    GotoDecl,
+    Implementation,
     FindReferences,
     SemanticTokensFull,
     Diagnostic,
@@ -84,6 +117,13 @@ pub enum ActionType {
     Hint,
     DocSymbol,
     SignatureHelp,
+    Rename,
+    WorkspaceSymbol,
+    CallHierarchy,
+    TypeHierarchy,
+    DocumentHighlight,
+    ExpandMacro,
+    PrintMacroExpansion,
 }
 
 lazy_static::lazy_static! {
@@ -112,6 +152,15 @@ pub fn run(p: &Path, opt: OptimizationLevel) {
     }
 }
 
+/// 主机平台的链接配置
+fn host_link_target() -> Target {
+    #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
+    let pl_target = Target::search("x86_64-apple-darwin").expect("get target failed");
+    #[cfg(not(all(target_os = "macos", target_arch = "x86_64")))]
+    let pl_target = Target::host_target().expect("get host target failed");
+    pl_target
+}
+
 #[salsa::tracked]
 pub fn compile_dry(db: &dyn Db, docs: MemDocsInput) -> Option<ModWrapper> {
     let path = get_config_path(docs.file(db).to_string());
@@ -125,15 +174,50 @@ pub fn compile_dry(db: &dyn Db, docs: MemDocsInput) -> Option<ModWrapper> {
     let input = input.unwrap();
     let re = compile_dry_file(db, input);
     if let Some(res) = db.get_ref_str() {
+        let mut set = FxHashSet::default();
         if let Some(plmod) = re {
-            plmod
-                .plmod(db)
-                .get_refs(&res, db, &mut FxHashSet::default());
+            plmod.plmod(db).get_refs(&res, db, &mut set);
+        }
+        if docs.action(db) == ActionType::FindReferences {
+            for m in compile_workspace_members(db, docs) {
+                m.plmod(db).get_refs(&res, db, &mut set);
+            }
         }
     }
     re
 }
 
+/// 文件所在的项目属于工作区时，编译工作区中的其他包，返回它们的入口模块
+///
+/// 查找引用和重命名时整个工作区被当作一个项目，其他包中的使用也会被找到
+pub fn compile_workspace_members(db: &dyn Db, docs: MemDocsInput) -> Vec<ModWrapper> {
+    let root = match get_config_path(docs.file(db).to_string())
+        .ok()
+        .and_then(|p| dunce::canonicalize(Path::new(&p).parent()?).ok())
+    {
+        Some(root) => root,
+        None => return vec![],
+    };
+    let (ws_root, ws_config) = match find_workspace(&root) {
+        Some(ws) => ws,
+        None => return vec![],
+    };
+    let mut packages = ws_config.members(&ws_root);
+    if !ws_config.entry.is_empty() {
+        packages.push(ws_root);
+    }
+    packages
+        .into_iter()
+        .filter_map(|dir| dunce::canonicalize(dir).ok())
+        .filter(|dir| dir != &root)
+        .filter_map(|dir| {
+            let manifest = dir.join(MANIFEST).to_string_lossy().to_string();
+            let input = docs.get_file_params(db, manifest, true)?;
+            compile_dry_file(db, input)
+        })
+        .collect()
+}
+
 #[salsa::tracked]
 pub fn compile_dry_file(db: &dyn Db, docs: FileCompileInput) -> Option<ModWrapper> {
     if docs.file(db).ends_with(".toml") {
@@ -208,12 +292,16 @@ pub fn compile(db: &dyn Db, docs: MemDocsInput, out: String, op: Options) {
 
     inkwell::execution_engine::ExecutionEngine::link_in_mc_jit();
     immix::register_llvm_gc_plugins();
-    let targetdir = PathBuf::from("target");
+    let targetdir = op.target_dir();
     if !targetdir.exists() {
-        fs::create_dir(&targetdir).unwrap();
+        fs::create_dir_all(&targetdir).unwrap();
     }
+    let tm = get_target_machine(op.optimization.to_llvm(), &op.target);
+    let triple = tm.get_triple().as_str().to_string_lossy().to_string();
+    MOD_CACHE.lock().init(&targetdir, &triple, &op);
     let now = Instant::now();
     compile_dry(db, docs).unwrap();
+    MOD_CACHE.lock().save();
     pb.finish_with_message("中间代码编译完成");
     let errs = compile_dry::accumulated::<Diagnostics>(db, docs);
     let mut errs_num = 0;
@@ -269,9 +357,22 @@ pub fn compile(db: &dyn Db, docs: MemDocsInput, out: String, op: Options) {
         info!("gen flow done, time: {:?}", time);
         return;
     }
+    if op.expand_macros {
+        let time = now.elapsed();
+        info!("expand macros done, time: {:?}", time);
+        return;
+    }
     let mut objs = vec![];
+    // 目标文件放在输出文件所在的目录，没有指定目录时与bitcode放在一起
+    let obj_dir = Path::new(&out)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(Path::to_path_buf);
+    if let Some(dir) = &obj_dir {
+        fs::create_dir_all(dir).unwrap();
+    }
+    let mut cache = ObjCache::load(obj_dir.as_deref().unwrap_or(&targetdir), !op.no_cache);
     let ctx = Context::create();
-    let tm = get_target_machine(op.optimization.to_llvm());
     let llvmmod = ctx.create_module("main");
     let mut set = FxHashSet::default();
     let pb = ProgressBar::new(mods.len() as u64);
@@ -286,25 +387,35 @@ pub fn compile(db: &dyn Db, docs: MemDocsInput, out: String, op: Options) {
             continue;
         }
         set.insert(m.clone());
-        let o = m.with_extension("o");
+        let o = match &obj_dir {
+            Some(dir) => dir.join(m.file_name().unwrap()).with_extension("o"),
+            None => m.with_extension("o"),
+        };
         // println!("{}", m.clone().to_str().unwrap());
         let module = Module::parse_bitcode_from_path(m.clone(), &ctx)
             .unwrap_or_else(|_| panic!("parse {} failed", m.to_str().unwrap()));
-        pb.set_message(format!(
-            "正在优化模块 {} ",
-            module.get_name().to_str().unwrap().yellow()
-        ));
-        run_pass(&module, op.optimization.to_llvm());
-        pb.set_message(format!(
-            "正在生成模块 {} 的目标文件",
-            module.get_name().to_str().unwrap().yellow()
-        ));
-        module.verify().unwrap();
-        tm.write_to_file(&module, inkwell::targets::FileType::Object, &o)
-            .unwrap();
+        let hash = ObjCache::key(&fs::read(&m).unwrap(), &op, &triple);
+        if cache.is_fresh(&o, hash) {
+            debug!("module {} unchanged, reuse {}", m.display(), o.display());
+        } else {
+            pb.set_message(format!(
+                "正在优化模块 {} ",
+                module.get_name().to_str().unwrap().yellow()
+            ));
+            run_pass(&module, op.optimization.to_llvm());
+            pb.set_message(format!(
+                "正在生成模块 {} 的目标文件",
+                module.get_name().to_str().unwrap().yellow()
+            ));
+            module.verify().unwrap();
+            tm.write_to_file(&module, inkwell::targets::FileType::Object, &o)
+                .unwrap();
+            cache.update(&o, hash);
+        }
         objs.push(o);
         _ = llvmmod.link_in_module(module);
     }
+    cache.save();
     pb.finish_with_message("目标文件编译优化完成");
     let pb = ProgressBar::new(1);
     pb.enable_steady_tick(Duration::from_millis(50));
@@ -320,10 +431,20 @@ pub fn compile(db: &dyn Db, docs: MemDocsInput, out: String, op: Options) {
     }
     let mut fo = out.to_string();
     let mut out = out;
-    #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-    let pl_target = Target::search("x86_64-apple-darwin").expect("get target failed");
-    #[cfg(not(all(target_os = "macos", target_arch = "x86_64")))]
-    let pl_target = Target::host_target().expect("get host target failed");
+    let pl_target = match &op.target.triple {
+        Some(t) => match Target::search(t) {
+            Some(t) => t,
+            None => {
+                pb.abandon_with_message("目标文件链接失败");
+                eprintln!(
+                    "{}",
+                    format!("link failed: unsupported target {}", t).bright_red()
+                );
+                return;
+            }
+        },
+        None => host_link_target(),
+    };
     out.push_str(".bc");
     llvmmod.set_triple(&tm.get_triple());
     llvmmod.set_data_layout(&tm.get_target_data().get_data_layout());
@@ -343,7 +464,35 @@ pub fn compile(db: &dyn Db, docs: MemDocsInput, out: String, op: Options) {
     }
     let root = root.unwrap();
     let vmpath;
-    if cfg!(target_os = "windows") {
+    if let Some(t) = &op.target.triple {
+        // 交叉编译时使用对应平台的libvm，位于`$PL_ROOT/<triple>/`下
+        let mut p = PathBuf::from(&root);
+        p.push(t);
+        p.push(if t.contains("windows") {
+            "vm.lib"
+        } else {
+            "libvm.a"
+        });
+        vmpath = match dunce::canonicalize(&p) {
+            Ok(p) => p.to_str().unwrap().to_string(),
+            Err(_) => {
+                pb.abandon_with_message("目标文件链接失败");
+                eprintln!(
+                    "{}",
+                    format!(
+                        "link failed: failed to find libvm for {} at {}",
+                        t,
+                        p.display()
+                    )
+                    .bright_red()
+                );
+                return;
+            }
+        };
+        if t.contains("windows") {
+            fo.push_str(".exe");
+        }
+    } else if cfg!(target_os = "windows") {
         // cmd = Command::new("clang");
         // f = out.clone();
         fo.push_str(".exe");
@@ -2,14 +2,19 @@ use super::builder::BlockHandle;
 use super::builder::ValueHandle;
 use super::diag::ErrorCode;
 use super::diag::PLDiag;
+use super::fmt::FmtBuilder;
 
 use super::node::macro_nodes::MacroNode;
+use super::node::statement::StatementsNode;
 use super::node::NodeEnum;
 use super::node::NodeResult;
 use super::node::PLValue;
+use super::node::TerminatorEnum;
 use super::node::TypeNode;
+use super::node::TypeNodeEnum;
 use super::plmod::CompletionItemWrapper;
 use super::plmod::GlobalVar;
+use super::plmod::ImplRef;
 use super::plmod::LSPDef;
 use super::plmod::Mod;
 use super::plmod::MutVec;
@@ -26,8 +31,14 @@ use super::tokens::TokenType;
 
 use crate::ast::builder::BuilderEnum;
 use crate::ast::builder::IRBuilder;
+use crate::lsp::completion::CompletionData;
+use crate::lsp::inlay_hints::label_part;
+use crate::lsp::inlay_hints::type_label_part;
+use crate::lsp::inlay_hints::HintData;
+use crate::lsp::inlay_hints::HintKind;
 use crate::lsp::semantic_tokens::type_index;
 
+use crate::format_label;
 use crate::mismatch_err;
 use crate::skip_if_not_modified_by;
 use crate::utils::read_config::Config;
@@ -87,18 +98,113 @@ pub struct Ctx<'a> {
     pub config: Config,                                           // config
     pub db: &'a dyn Db,
     pub rettp: Option<Arc<RefCell<PLType>>>,
-    pub macro_vars: FxHashMap<String, MacroReplaceNode>,
-    pub macro_loop: bool,
-    pub macro_loop_idx: usize,
-    pub macro_loop_len: usize,
+    /// 正在展开的宏的环境，不在宏展开中时为None
+    pub macro_env: Option<Arc<MacroEnv>>,
+    /// 宏展开中`$(...)*`循环当前的下标
+    pub macro_loop: Option<usize>,
     pub temp_source: Option<String>,
     pub in_macro: bool,
+    /// 是否在`plc test`生成的测试入口中，见[Options::test_harness](super::compiler::Options)
+    pub test_harness: bool,
+    pub closure_data: Option<RefCell<ClosureCtxData>>, // 闭包函数体的ctx才有
+    /// 正在生成的表达式期望的类型，用于补全排序
+    pub expect_ty: Option<Arc<RefCell<PLType>>>,
+}
+
+/// # ClosureCtxData
+/// 闭包体中捕获的外部变量
+pub struct ClosureCtxData {
+    /// 闭包函数的第一个参数，指向捕获环境
+    pub env: ValueHandle,
+    /// 闭包函数的alloc块，捕获变量的加载指令生成在这里
+    pub alloca_bb: BlockHandle,
+    /// 变量名 -> (闭包中的指针, 外部的指针, 类型)
+    pub table: IndexMap<String, (ValueHandle, ValueHandle, Arc<RefCell<PLType>>)>,
+}
+
+/// 宏参数匹配到的代码片段
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MacroFragment {
+    Node(NodeEnum),
+    /// `@ty`
+    Type(TypeNodeEnum),
+    /// `@block`，在新的作用域中生成
+    Block(StatementsNode),
+    /// `@tt`，在使用的位置按照需要的语法重新解析
+    Tokens(String, Pos),
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum MacroReplaceNode {
-    NodeEnum(NodeEnum),
-    LoopNodeEnum(Vec<NodeEnum>),
+    Single(MacroFragment),
+    /// `$(...)*`中的参数每次循环匹配到的片段
+    Loop(Vec<MacroFragment>),
+}
+
+/// # MacroEnv
+/// 一次宏展开的环境
+#[derive(Clone, Debug, Default)]
+pub struct MacroEnv {
+    /// 参数名 -> 匹配到的片段
+    pub vars: FxHashMap<String, MacroReplaceNode>,
+    /// 卫生标记，展开中定义的变量会带上它，不会与调用处的变量冲突
+    pub mark: usize,
+    /// 嵌套展开的深度
+    pub depth: usize,
+    /// 定义宏的模块的路径
+    pub module: String,
+    /// 调用宏的位置所在的环境，参数中的片段在这个环境中生成
+    pub caller: Option<Arc<MacroEnv>>,
+    /// 调用宏的位置所在的文件，为None时是当前模块
+    pub caller_src: Option<String>,
+    /// 宏的名字
+    pub name: String,
+    /// 调用宏的位置
+    pub call_site: Range,
+    /// 展开中嵌套调用的宏展开后的代码，key为(调用位置, 循环下标)
+    pub expansions: RefCell<FxHashMap<(Range, Option<usize>), String>>,
+}
+
+impl MacroEnv {
+    /// 宏中定义的变量在符号表中的名字
+    pub fn hygienic_name(&self, name: &str) -> String {
+        format!("{}#{}", name, self.mark)
+    }
+}
+
+/// 标识符在宏展开中对应的名字：宏参数`$name`替换为调用处的标识符，
+/// `hygienic`为true时宏中引入的标识符带上卫生标记
+fn resolve_macro_ident(
+    env: Option<&Arc<MacroEnv>>,
+    idx: Option<usize>,
+    name: &str,
+    hygienic: bool,
+) -> String {
+    let env = match env {
+        Some(env) => env,
+        None => return name.to_string(),
+    };
+    if !name.starts_with('$') {
+        return if hygienic {
+            env.hygienic_name(name)
+        } else {
+            name.to_string()
+        };
+    }
+    let fragment = match (env.vars.get(&name[1..]), idx) {
+        (Some(MacroReplaceNode::Single(f)), _) => Some(f),
+        (Some(MacroReplaceNode::Loop(fs)), Some(i)) => fs.get(i),
+        _ => None,
+    };
+    match fragment {
+        Some(MacroFragment::Node(NodeEnum::Var(v))) => {
+            resolve_macro_ident(env.caller.as_ref(), None, &v.name, hygienic)
+        }
+        Some(MacroFragment::Tokens(t, _)) => {
+            resolve_macro_ident(env.caller.as_ref(), None, t.trim(), hygienic)
+        }
+        _ => name.to_string(),
+    }
 }
 
 impl<'a, 'ctx> Ctx<'a> {
@@ -134,16 +240,18 @@ impl<'a, 'ctx> Ctx<'a> {
             return_block: None,
             roots: RefCell::new(Vec::new()),
             rettp: None,
-            macro_vars: FxHashMap::default(),
-            macro_loop: false,
-            macro_loop_idx: 0,
-            macro_loop_len: 0,
+            macro_env: None,
+            macro_loop: None,
             temp_source: None,
             in_macro: false,
+            test_harness: false,
+            closure_data: None,
+            expect_ty: None,
         };
         add_primitive_types(&mut ctx);
         ctx
     }
+    /// 子ctx对应调试信息中的一个lexical block，使用完后需要调用`builder.end_subscope()`
     pub fn new_child(&'a self, start: Pos, builder: &'a BuilderEnum<'a, 'ctx>) -> Ctx<'a> {
         let mut ctx = Ctx {
             need_highlight: self.need_highlight,
@@ -163,59 +271,129 @@ impl<'a, 'ctx> Ctx<'a> {
             rettp: self.rettp.clone(),
             init_func: self.init_func,
             function: self.function,
-            macro_vars: FxHashMap::default(),
-            macro_loop: false,
-            macro_loop_idx: self.macro_loop_idx,
-            macro_loop_len: self.macro_loop_len,
+            macro_env: self.macro_env.clone(),
+            macro_loop: self.macro_loop,
             temp_source: self.temp_source.clone(),
             in_macro: self.in_macro,
+            test_harness: self.test_harness,
+            closure_data: None,
+            expect_ty: None,
         };
         add_primitive_types(&mut ctx);
         builder.new_subscope(start);
         ctx
     }
 
+    /// 展开`$(...)*`循环，循环体按照下标依次生成`len`次
     pub fn with_macro_loop(
         &mut self,
         mut f: impl FnMut(&mut Self) -> NodeResult,
-        r: Range,
+        len: usize,
     ) -> NodeResult {
         let old_macro_loop = self.macro_loop;
-        let old_macro_loop_idx = self.macro_loop_idx;
-        self.macro_loop_idx = 0;
-        self.macro_loop = true;
-        let mut result;
-        loop {
+        let mut result = Ok((None, None, TerminatorEnum::NONE));
+        for i in 0..len {
+            self.macro_loop = Some(i);
             result = f(self);
-            self.macro_loop_idx += 1;
-            if self.macro_loop_len == 0 {
-                // no loop variable used, return error
-                return Err(r.new_err(ErrorCode::NO_MACRO_LOOP_VAR));
-            }
-            if self.macro_loop_idx == self.macro_loop_len {
-                // loop end
+            if result.is_err() {
                 break;
             }
         }
         self.macro_loop = old_macro_loop;
-        self.macro_loop_idx = old_macro_loop_idx;
         result
     }
-    pub fn with_macro_emit(&mut self, f: impl FnOnce(&mut Self) -> NodeResult) -> NodeResult {
-        let old_in_macro = self.in_macro;
-        self.in_macro = true;
-        let result = f(self);
+    /// `$(...)*`循环的次数，由循环中用到的参数匹配到的次数决定
+    pub fn macro_loop_len(&self, vars: &[String], range: Range) -> Result<usize, PLDiag> {
+        let mut lens = vars
+            .iter()
+            .filter_map(|v| match self.macro_env.as_ref()?.vars.get(v) {
+                Some(MacroReplaceNode::Loop(fs)) => Some(fs.len()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        lens.sort_unstable();
+        lens.dedup();
+        match lens[..] {
+            [] => Err(range.new_err(ErrorCode::NO_MACRO_LOOP_VAR)),
+            [len] => Ok(len),
+            _ => Err(range
+                .new_err(ErrorCode::MACRO_LOOP_VAR_LEN_MISMATCH)
+                .add_help("all macro vars used in a macro loop must repeat the same times")
+                .clone()),
+        }
+    }
+    /// 宏参数`$name`匹配到的片段，在循环中时取当前下标对应的片段
+    pub fn get_macro_fragment(&self, name: &str, range: Range) -> Result<MacroFragment, PLDiag> {
+        let var = self
+            .macro_env
+            .as_ref()
+            .and_then(|env| env.vars.get(&name[1..]));
+        match (var, self.macro_loop) {
+            (Some(MacroReplaceNode::Single(f)), _) => Ok(f.clone()),
+            (Some(MacroReplaceNode::Loop(fs)), Some(i)) if i < fs.len() => Ok(fs[i].clone()),
+            (Some(MacroReplaceNode::Loop(_)), _) => Err(range
+                .new_err(ErrorCode::MACRO_LOOP_VAR_USED_OUT_OF_LOOP)
+                .add_help("add a `macro loop` surrounding the macro body like $($var)*")
+                .clone()),
+            (None, _) => Err(range
+                .new_err(ErrorCode::MACRO_VAR_NOT_FOUND)
+                .add_help(&format!(
+                    "add a macro var named `{}` in the macro definition",
+                    name
+                ))
+                .clone()),
+        }
+    }
+    /// 标识符在当前宏展开中对应的名字，见[resolve_macro_ident]
+    pub fn macro_ident(&self, name: &str, hygienic: bool) -> String {
+        resolve_macro_ident(self.macro_env.as_ref(), self.macro_loop, name, hygienic)
+    }
+    /// 在宏展开的环境中生成宏的内容，诊断信息属于定义宏的文件
+    pub fn with_macro_env<T>(&mut self, env: Arc<MacroEnv>, f: impl FnOnce(&mut Self) -> T) -> T {
+        let src = env.module.clone();
+        let old_env = self.macro_env.replace(env);
+        let old_macro_loop = self.macro_loop.take();
+        let old_in_macro = std::mem::replace(&mut self.in_macro, true);
+        let result = self.with_diag_src(&src, f);
+        self.macro_env = old_env;
+        self.macro_loop = old_macro_loop;
         self.in_macro = old_in_macro;
         result
     }
-    pub fn with_macro_loop_parse<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
-        let old_macro_loop = self.macro_loop;
-        let old_macro_loop_idx = self.macro_loop_idx;
-        self.macro_loop_idx = 0;
-        self.macro_loop = true;
+    /// 宏展开中的诊断信息加上每一层宏调用的位置，相同的位置只标记一次
+    fn add_macro_call_labels(&self, dia: &mut PLDiag) {
+        let mut env = self.macro_env.as_ref();
+        let mut sites: Vec<(Range, String)> = vec![];
+        while let Some(e) = env {
+            let file = e
+                .caller_src
+                .clone()
+                .unwrap_or_else(|| self.plmod.path.clone());
+            if !sites.iter().any(|(r, f)| *r == e.call_site && *f == file) {
+                dia.add_label(
+                    e.call_site,
+                    file.clone(),
+                    format_label!("in this expansion of `{}!`", &e.name),
+                );
+                sites.push((e.call_site, file));
+            }
+            env = e.caller.as_ref();
+        }
+    }
+    /// 在调用宏的位置的环境中执行`f`，用来生成宏参数匹配到的片段
+    pub fn with_macro_caller<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let env = self.macro_env.clone();
+        let caller = env.as_ref().and_then(|e| e.caller.clone());
+        let caller_src = env.as_ref().and_then(|e| e.caller_src.clone());
+        let old_in_macro = std::mem::replace(&mut self.in_macro, caller.is_some());
+        let old_env = std::mem::replace(&mut self.macro_env, caller);
+        let old_src = std::mem::replace(&mut self.temp_source, caller_src);
+        let old_macro_loop = self.macro_loop.take();
         let result = f(self);
+        self.macro_env = old_env;
+        self.temp_source = old_src;
         self.macro_loop = old_macro_loop;
-        self.macro_loop_idx = old_macro_loop_idx;
+        self.in_macro = old_in_macro;
         result
     }
     pub fn up_cast<'b>(
@@ -227,7 +405,7 @@ impl<'a, 'ctx> Ctx<'a> {
         st_value: usize,
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> Result<usize, PLDiag> {
-        let (st_pltype, st_value) = self.auto_deref(st_pltype, st_value, builder);
+        let (st_pltype, st_value) = self.auto_deref(st_pltype, st_value, st_range, builder);
         if let (PLType::TRAIT(t), PLType::STRUCT(st)) =
             (&*trait_pltype.borrow(), &*st_pltype.borrow())
         {
@@ -327,7 +505,19 @@ impl<'a, 'ctx> Ctx<'a> {
             return Some((*h, pltype.clone(), *range, Some(refs.clone()), false));
         }
         if let Some(father) = self.father {
-            return father.get_symbol(name, builder);
+            let re = father.get_symbol(name, builder);
+            if let Some(data) = &self.closure_data {
+                if let Some((v, pltype, range, refs, false)) = re {
+                    return Some((
+                        self.capture_symbol(data, name, v, pltype.clone(), builder),
+                        pltype,
+                        range,
+                        refs,
+                        false,
+                    ));
+                }
+            }
+            return re;
         }
         if let Some(GlobalVar { tp: pltype, range }) = self.plmod.get_global_symbol(name) {
             return Some((
@@ -343,6 +533,31 @@ impl<'a, 'ctx> Ctx<'a> {
         None
     }
 
+    /// 闭包体中使用了外部的局部变量，将其加入捕获环境
+    ///
+    /// 返回该变量在闭包函数中的指针
+    fn capture_symbol(
+        &self,
+        data: &RefCell<ClosureCtxData>,
+        name: &str,
+        origin: ValueHandle,
+        pltype: Arc<RefCell<PLType>>,
+        builder: &BuilderEnum<'a, 'ctx>,
+    ) -> ValueHandle {
+        let mut data = data.borrow_mut();
+        if let Some((v, _, _)) = data.table.get(name) {
+            return *v;
+        }
+        // 环境的第0个字段是vtable
+        let index = data.table.len() as u32 + 1;
+        let cur = builder.get_cur_basic_block();
+        builder.position_at_end_block(data.alloca_bb);
+        let v = builder.build_load_captured(data.env, index, origin);
+        builder.position_at_end_block(cur);
+        data.table.insert(name.to_string(), (v, origin, pltype));
+        v
+    }
+
     pub fn add_symbol(
         &mut self,
         name: String,
@@ -359,6 +574,7 @@ impl<'a, 'ctx> Ctx<'a> {
             self.plmod.add_global_symbol(name, pltype, range)?;
         } else {
             let refs = Arc::new(RefCell::new(vec![]));
+            self.send_if_type_def(range, &pltype);
             self.table.insert(name, (pv, pltype, range, refs.clone()));
             self.set_if_refs(refs, range);
         }
@@ -476,6 +692,7 @@ impl<'a, 'ctx> Ctx<'a> {
                 .doc_symbols
                 .borrow_mut()
                 .push(st.get_doc_symbol()),
+            PLType::ENUM(e) => self.plmod.doc_symbols.borrow_mut().push(e.get_doc_symbol()),
             _ => {}
         }
     }
@@ -492,6 +709,7 @@ impl<'a, 'ctx> Ctx<'a> {
         if let Some(src) = &self.temp_source {
             dia.set_source(src);
         }
+        self.add_macro_call_labels(&mut dia);
         let dia2 = dia.clone();
         self.errs.borrow_mut().insert(dia);
         dia2
@@ -663,6 +881,29 @@ impl<'a, 'ctx> Ctx<'a> {
         }
     }
 
+    /// 记录函数调用点，用于call hierarchy
+    pub fn add_call(&self, range: Range, callee: &FNValue) {
+        self.plmod
+            .calls
+            .borrow_mut()
+            .insert(range, callee.get_fn_ref());
+    }
+
+    /// 记录变量类型的定义位置，用于go to type definition
+    pub fn send_if_type_def(&self, range: Range, pltype: &Arc<RefCell<PLType>>) {
+        if let Some((path, def)) = pltype.borrow().get_type_def() {
+            self.plmod.type_defs.borrow_mut().insert(
+                range,
+                Location::new(Url::from_file_path(path).unwrap(), def.to_diag_range()),
+            );
+        }
+    }
+
+    /// 记录实现trait的impl块，用于go to implementation
+    pub fn add_impl_ref(&self, impl_ref: ImplRef) {
+        self.plmod.trait_impls.borrow_mut().push(impl_ref);
+    }
+
     pub fn set_if_sig(&self, range: Range, name: String, params: &[String], n: u32) {
         self.plmod.sig_helps.borrow_mut().insert(
             range,
@@ -709,11 +950,62 @@ impl<'a, 'ctx> Ctx<'a> {
         self.get_var_completions(&mut m);
         self.get_keyword_completions(&mut m);
         self.get_macro_completions(&mut m);
+        self.rank_completions(&mut m);
 
         let cm = m.values().cloned().collect();
         cm
     }
 
+    /// 类型和光标处期望的类型一致的补全项排在前面
+    fn rank_completions(&self, m: &mut FxHashMap<String, CompletionItem>) {
+        let expect = match &self.expect_ty {
+            Some(tp) => tp.borrow().get_name(),
+            None => return,
+        };
+        let mut tps = FxHashMap::default();
+        self.get_completion_tp_names(&mut tps);
+        for (k, item) in m.iter_mut() {
+            let matched = tps.get(k).map_or(false, |tp| {
+                tp.rsplit("::").next() == expect.rsplit("::").next()
+            });
+            item.sort_text = Some(format!("{}{}", if matched { 0 } else { 1 }, item.label));
+        }
+    }
+
+    /// 补全项对应的值的类型名：变量的类型，函数的返回值类型，结构体字面量的类型
+    fn get_completion_tp_names(&self, tps: &mut FxHashMap<String, String>) {
+        for (k, f) in self.plmod.types.iter() {
+            match &*f.borrow() {
+                PLType::FN(f) => {
+                    tps.insert(k.clone(), FmtBuilder::generate_node(&f.fntype.ret_pltype));
+                }
+                PLType::STRUCT(_) => {
+                    tps.insert(format!("{}{{}}", k), k.clone());
+                }
+                _ => (),
+            }
+        }
+        if let Some(father) = self.father {
+            father.get_completion_tp_names(tps);
+        }
+        // 内层作用域的变量遮蔽外层的同名符号
+        for (k, (_, tp, _, _)) in self.table.iter() {
+            tps.insert(k.clone(), tp.borrow().get_name());
+        }
+    }
+
+    /// 在期望类型为`tp`的上下文中生成表达式
+    pub fn with_expect_ty<T>(
+        &mut self,
+        tp: Arc<RefCell<PLType>>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let old = self.expect_ty.replace(tp);
+        let re = f(self);
+        self.expect_ty = old;
+        re
+    }
+
     pub fn get_completions_in_ns(&self, ns: &str) -> Vec<CompletionItem> {
         let mut m = FxHashMap::default();
         self.get_const_completions_in_ns(ns, &mut m);
@@ -755,6 +1047,10 @@ impl<'a, 'ctx> Ctx<'a> {
                         skip_if_not_modified_by!(s.modifier, TokenType::PUB);
                         CompletionItemKind::STRUCT
                     }
+                    PLType::ENUM(e) => {
+                        skip_if_not_modified_by!(e.modifier, TokenType::PUB);
+                        CompletionItemKind::ENUM
+                    }
                     PLType::FN(fnvalue) => {
                         skip_if_not_modified_by!(fnvalue.fntype.modifier, TokenType::PUB);
                         insert_text = Some(fnvalue.gen_snippet());
@@ -803,9 +1099,11 @@ impl<'a, 'ctx> Ctx<'a> {
                 PLType::GENERIC(_) => CompletionItemKind::TYPE_PARAMETER,
                 PLType::STRUCT(_) => CompletionItemKind::STRUCT,
                 PLType::TRAIT(_) => CompletionItemKind::INTERFACE,
+                PLType::ENUM(_) => CompletionItemKind::ENUM,
                 PLType::PRIMITIVE(_) => CompletionItemKind::KEYWORD,
                 PLType::VOID => CompletionItemKind::KEYWORD,
                 PLType::POINTER(_) => todo!(),
+                PLType::CLOSURE(_) => continue,
             };
             m.insert(
                 k.to_string(),
@@ -822,12 +1120,13 @@ impl<'a, 'ctx> Ctx<'a> {
     }
 
     fn get_var_completions(&self, vmap: &mut FxHashMap<String, CompletionItem>) {
-        for (k, _) in self.table.iter() {
+        for (k, (_, tp, _, _)) in self.table.iter() {
             vmap.insert(
                 k.to_string(),
                 CompletionItem {
                     label: k.to_string(),
                     kind: Some(CompletionItemKind::VARIABLE),
+                    detail: Some(tp.borrow().get_name()),
                     ..Default::default()
                 },
             );
@@ -848,6 +1147,7 @@ impl<'a, 'ctx> Ctx<'a> {
         for (k, f) in self.plmod.types.iter() {
             let mut insert_text = None;
             let mut command = None;
+            let mut data = None;
             let tp = match &*f.clone().borrow() {
                 PLType::FN(f) => {
                     insert_text = Some(f.gen_snippet());
@@ -856,16 +1156,42 @@ impl<'a, 'ctx> Ctx<'a> {
                         "editor.action.triggerParameterHints".to_string(),
                         None,
                     ));
+                    data = CompletionData::new(&f.path, k).to_value();
                     CompletionItemKind::FUNCTION
                 }
-                PLType::STRUCT(_) => CompletionItemKind::STRUCT,
-                PLType::TRAIT(_) => CompletionItemKind::INTERFACE,
+                PLType::STRUCT(st) => {
+                    data = CompletionData::new(&st.path, k).to_value();
+                    // 结构体字面量，所有字段都已填好
+                    vmap.insert(
+                        format!("{}{{}}", k),
+                        CompletionItem {
+                            label: format!("{}{{…}}", k),
+                            kind: Some(CompletionItemKind::SNIPPET),
+                            detail: Some("struct literal".to_string()),
+                            filter_text: Some(k.to_string()),
+                            insert_text: Some(st.gen_literal_snippet()),
+                            insert_text_format: Some(InsertTextFormat::SNIPPET),
+                            data: data.clone(),
+                            ..Default::default()
+                        },
+                    );
+                    CompletionItemKind::STRUCT
+                }
+                PLType::TRAIT(st) => {
+                    data = CompletionData::new(&st.path, k).to_value();
+                    CompletionItemKind::INTERFACE
+                }
+                PLType::ENUM(e) => {
+                    data = CompletionData::new(&e.path, k).to_value();
+                    CompletionItemKind::ENUM
+                }
                 PLType::ARR(_) => CompletionItemKind::KEYWORD,
                 PLType::PRIMITIVE(_) => CompletionItemKind::KEYWORD,
                 PLType::GENERIC(_) => CompletionItemKind::STRUCT,
                 PLType::VOID => CompletionItemKind::KEYWORD,
                 PLType::POINTER(_) => todo!(),
                 PLType::PLACEHOLDER(_) => CompletionItemKind::STRUCT,
+                PLType::CLOSURE(_) => continue,
             };
             if k.starts_with('|') {
                 // skip method
@@ -879,6 +1205,7 @@ impl<'a, 'ctx> Ctx<'a> {
                     insert_text,
                     insert_text_format: Some(InsertTextFormat::SNIPPET),
                     command,
+                    data,
                     ..Default::default()
                 },
             );
@@ -898,39 +1225,104 @@ impl<'a, 'ctx> Ctx<'a> {
             modifiers,
         )
     }
-    pub fn push_type_hints(&self, range: Range, pltype: Arc<RefCell<PLType>>) {
+    fn push_hint(
+        &self,
+        position: lsp_types::Position,
+        label: String,
+        kind: Option<InlayHintKind>,
+        data: HintData,
+    ) {
         if self.need_highlight != 0 || self.in_macro {
             return;
         }
         let hint = InlayHint {
-            position: range.to_diag_range().end,
-            label: lsp_types::InlayHintLabel::String(
-                ": ".to_string() + &pltype.borrow().get_name(),
-            ),
-            kind: Some(InlayHintKind::TYPE),
+            position,
+            label: lsp_types::InlayHintLabel::String(label),
+            kind,
             text_edits: None,
             tooltip: None,
             padding_left: None,
             padding_right: None,
-            data: None,
+            data: data.to_value(),
         };
         self.plmod.hints.borrow_mut().push(hint);
     }
-    pub fn push_param_hint(&self, range: Range, name: String) {
-        if self.need_highlight != 0 || self.in_macro {
+    pub fn push_type_hints(&self, range: Range, pltype: Arc<RefCell<PLType>>) {
+        self.push_type_hint_of(range, pltype, HintKind::Type);
+    }
+    /// 换行的链式调用，在行尾显示前面一段的类型
+    pub fn push_chaining_hint(&self, range: Range, pltype: Arc<RefCell<PLType>>) {
+        self.push_type_hint_of(range, pltype, HintKind::Chaining);
+    }
+    fn push_type_hint_of(&self, range: Range, pltype: Arc<RefCell<PLType>>, kind: HintKind) {
+        let name = pltype.borrow().get_name();
+        let parts = vec![
+            label_part(": ".to_string(), None, None),
+            type_label_part(name.clone(), &pltype.borrow()),
+        ];
+        self.push_hint(
+            range.to_diag_range().end,
+            ": ".to_string() + &name,
+            Some(InlayHintKind::TYPE),
+            HintData::new(kind, parts),
+        );
+    }
+    pub fn push_param_hint(&self, range: Range, fnvalue: &FNValue, i: usize) {
+        let name = fnvalue.param_names[i].clone();
+        let def = fnvalue.node.as_ref().and_then(|n| {
+            let para = n.paralist.iter().find(|p| p.id.name == name)?;
+            Some(Location::new(
+                Url::from_file_path(&fnvalue.path).ok()?,
+                para.id.range.to_diag_range(),
+            ))
+        });
+        let tooltip = format!(
+            "```pivot-lang\n{}: {}\n```",
+            name,
+            FmtBuilder::generate_node(&fnvalue.fntype.param_pltypes[i])
+        );
+        let parts = vec![
+            label_part(name.clone(), Some(tooltip), def),
+            label_part(": ".to_string(), None, None),
+        ];
+        self.push_hint(
+            range.to_diag_range().start,
+            name + ": ",
+            Some(InlayHintKind::PARAMETER),
+            HintData::new(HintKind::Parameter, parts),
+        );
+    }
+    /// 调用泛型函数时没有写出泛型参数，在函数名后显示推导出的泛型参数
+    pub fn push_generic_hint(&self, range: Range, tps: &[Arc<RefCell<PLType>>]) {
+        if tps.is_empty() {
             return;
         }
-        let hint = InlayHint {
-            position: range.to_diag_range().start,
-            label: lsp_types::InlayHintLabel::String(name + ": "),
-            kind: Some(InlayHintKind::TYPE),
-            text_edits: None,
-            tooltip: None,
-            padding_left: None,
-            padding_right: None,
-            data: None,
-        };
-        self.plmod.hints.borrow_mut().push(hint);
+        let mut parts = vec![label_part("<".to_string(), None, None)];
+        for (i, tp) in tps.iter().enumerate() {
+            if i > 0 {
+                parts.push(label_part(", ".to_string(), None, None));
+            }
+            parts.push(type_label_part(tp.borrow().get_name(), &tp.borrow()));
+        }
+        parts.push(label_part(">".to_string(), None, None));
+        let label = parts.iter().map(|p| p.value.as_str()).collect::<String>();
+        self.push_hint(
+            range.to_diag_range().end,
+            label,
+            Some(InlayHintKind::TYPE),
+            HintData::new(HintKind::Generic, parts),
+        );
+    }
+    /// 较长的代码块，在右花括号后显示代码块的名字
+    pub fn push_closing_brace_hint(&self, range: Range, label: String) {
+        let mut data = HintData::new(HintKind::ClosingBrace, vec![]);
+        data.lines = Some((range.end.line - range.start.line) as u32 + 1);
+        self.push_hint(
+            range.to_diag_range().end,
+            format!(" // {}", label),
+            None,
+            data,
+        );
     }
     pub fn position_at_end<'b>(
         &'b mut self,
@@ -942,32 +1334,50 @@ impl<'a, 'ctx> Ctx<'a> {
     }
     fn get_keyword_completions(&self, vmap: &mut FxHashMap<String, CompletionItem>) {
         let keywords = vec![
-            "if", "else", "while", "for", "return", "struct", "let", "true", "false",
+            "if", "else", "while", "for", "return", "struct", "let", "true", "false", "match",
+            "move", "as",
         ];
         let loopkeys = vec!["break", "continue"];
-        let toplevel = vec!["fn", "struct", "const", "use", "impl", "trait", "pub"];
-        if self.father.is_none() {
-            for k in toplevel {
-                vmap.insert(
-                    k.to_string(),
-                    CompletionItem {
-                        label: k.to_string(),
-                        kind: Some(CompletionItemKind::KEYWORD),
-                        ..Default::default()
-                    },
-                );
-            }
+        let toplevel = vec![
+            "fn", "struct", "const", "use", "impl", "trait", "pub", "enum",
+        ];
+        // 语句模板
+        let snippets = vec![
+            ("if", "if ${1:cond} {\n\t$0\n}"),
+            ("while", "while ${1:cond} {\n\t$0\n}"),
+            (
+                "for",
+                "for let ${1:i} = 0; ${1:i} < ${2:n}; ${1:i} += 1 {\n\t$0\n}",
+            ),
+        ];
+        let toplevel_snippets =
+            vec![("impl Trait for", "impl ${1:Trait} for ${2:Type} {\n\t$0\n}")];
+        let (keys, snippets) = if self.father.is_none() {
+            (toplevel, toplevel_snippets)
         } else {
-            for k in keywords {
-                vmap.insert(
-                    k.to_string(),
-                    CompletionItem {
-                        label: k.to_string(),
-                        kind: Some(CompletionItemKind::KEYWORD),
-                        ..Default::default()
-                    },
-                );
-            }
+            (keywords, snippets)
+        };
+        for k in keys {
+            vmap.insert(
+                k.to_string(),
+                CompletionItem {
+                    label: k.to_string(),
+                    kind: Some(CompletionItemKind::KEYWORD),
+                    ..Default::default()
+                },
+            );
+        }
+        for (k, snippet) in snippets {
+            vmap.insert(
+                k.to_string(),
+                CompletionItem {
+                    label: k.to_string(),
+                    kind: Some(CompletionItemKind::SNIPPET),
+                    insert_text: Some(snippet.to_string()),
+                    insert_text_format: Some(InsertTextFormat::SNIPPET),
+                    ..Default::default()
+                },
+            );
         }
         if self.break_block.is_some() && self.continue_block.is_some() {
             for k in loopkeys {
@@ -1019,19 +1429,31 @@ impl<'a, 'ctx> Ctx<'a> {
         &'b self,
         tp: Arc<RefCell<PLType>>,
         value: ValueHandle,
+        range: Range,
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> (Arc<RefCell<PLType>>, ValueHandle) {
         let mut tp = tp;
         let mut value = value;
+        let mut levels = 0;
         loop {
             match &*RefCell::borrow(&tp.clone()) {
                 PLType::POINTER(p) => {
                     tp = p.clone();
                     value = builder.build_load(value, "load");
+                    levels += 1;
                 }
                 _ => break,
             }
         }
+        if levels > 0 {
+            // 在表达式前显示隐式的解引用
+            self.push_hint(
+                range.to_diag_range().start,
+                "*".repeat(levels),
+                None,
+                HintData::new(HintKind::Deref, vec![]),
+            );
+        }
         (tp, value)
     }
 
@@ -1105,7 +1527,16 @@ impl<'a, 'ctx> Ctx<'a> {
                     self.eq(l.get_elem_type(), r.get_elem_type()).eq && l.size == r.size
                 }
                 (PLType::STRUCT(l), PLType::STRUCT(r)) => l.name == r.name && l.path == r.path,
+                (PLType::ENUM(l), PLType::ENUM(r)) => l.name == r.name && l.path == r.path,
                 (PLType::FN(l), PLType::FN(r)) => l == r,
+                (PLType::CLOSURE(l), PLType::CLOSURE(r)) => {
+                    l.arg_types.len() == r.arg_types.len()
+                        && l.arg_types
+                            .iter()
+                            .zip(r.arg_types.iter())
+                            .all(|(l, r)| self.eq(l.clone(), r.clone()).eq)
+                        && self.eq(l.ret_type.clone(), r.ret_type.clone()).eq
+                }
                 (PLType::PLACEHOLDER(l), PLType::PLACEHOLDER(r)) => l == r,
                 _ => {
                     if l != r {
@@ -109,6 +109,23 @@ define_error!(
     NO_MACRO_LOOP_VAR = "no macro loop var used in macro loop block",
     MACRO_LOOP_VAR_USED_OUT_OF_LOOP = "macro loop var used out of loop",
     MACRO_VAR_NOT_FOUND = "macro var not found",
+    EXPECT_ENUM_TYPE = "expect enum type",
+    EXPECT_PUBLIC_ENUM = "expect public enum",
+    ENUM_VARIANT_NOT_FOUND = "enum variant not found",
+    DUPLICATE_ENUM_VARIANT = "duplicate enum variant",
+    ENUM_VARIANT_FIELD_LEN_MISMATCH = "enum variant field length mismatch",
+    NON_EXHAUSTIVE_MATCH = "non exhaustive match",
+    UNREACHABLE_MATCH_ARM = "unreachable match arm",
+    MATCH_ARM_TYPE_MISMATCH = "match arm type mismatch",
+    INVALID_CAST = "invalid type cast",
+    BIT_OP_NOT_INT = "bit operation parameters must be int",
+    INVALID_TEST_FUNCTION = "test function must have no parameter, no generic and return void",
+    EXPECT_PUBLIC_MACRO = "expect public macro",
+    EXPECT_BLOCK = "expect block",
+    EXPECT_LITERAL = "expect literal",
+    EXPECT_TOKEN_TREE = "expect token tree",
+    MACRO_RECURSION_LIMIT = "macro expansion exceeds the recursion limit",
+    MACRO_LOOP_VAR_LEN_MISMATCH = "macro loop vars repeat different times",
 );
 macro_rules! define_warn {
     ($(
@@ -161,7 +178,10 @@ impl Display for DiagCode {
     }
 }
 
-use lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag, Url};
+use lsp_types::{
+    CodeAction, CodeActionKind, Diagnostic, DiagnosticSeverity, DiagnosticTag, TextEdit, Url,
+    WorkspaceEdit,
+};
 
 use crate::Db;
 
@@ -170,6 +190,54 @@ use super::{
     range::{Pos, Range},
 };
 
+/// # QuickFix
+/// 编译器在生成诊断时就能确定的修复，lsp会把它作为code action提供给用户
+///
+/// 需要源码文本的修复（例如删除不可达语句）由lsp根据诊断的code生成，不需要放在这里
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct QuickFix {
+    pub title: String,
+    /// (文件, 范围, 新文本)
+    pub edits: Vec<(String, Range, String)>,
+}
+
+impl QuickFix {
+    pub fn new(title: String) -> Self {
+        QuickFix {
+            title,
+            edits: vec![],
+        }
+    }
+    pub fn replace(mut self, file: &str, range: Range, text: &str) -> Self {
+        self.edits.push((file.to_string(), range, text.to_string()));
+        self
+    }
+    pub fn insert(self, file: &str, pos: Pos, text: &str) -> Self {
+        self.replace(file, pos.to(pos), text)
+    }
+    pub fn to_code_action(&self) -> CodeAction {
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        for (file, range, text) in &self.edits {
+            changes
+                .entry(Url::from_file_path(file).unwrap())
+                .or_default()
+                .push(TextEdit {
+                    range: range.to_diag_range(),
+                    new_text: text.clone(),
+                });
+        }
+        CodeAction {
+            title: self.title.clone(),
+            kind: Some(CodeActionKind::QUICKFIX),
+            edit: Some(WorkspaceEdit {
+                changes: Some(changes),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
 /// # PLDiag
 /// Diagnostic for pivot-lang
 #[range]
@@ -178,6 +246,7 @@ pub struct PLDiag {
     code: DiagCode,
     help: Option<Box<String>>,
     labels: Vec<(Range, String, Option<(String, Vec<String>)>)>,
+    fixes: Vec<QuickFix>,
     pub source: Option<String>,
 }
 
@@ -302,6 +371,15 @@ impl PLDiag {
             labels.push(lab);
         });
         d.related_information = Some(labels);
+        if !self.fixes.is_empty() {
+            // 客户端请求code action时会带上诊断，修复从`data`中取出
+            let actions = self
+                .fixes
+                .iter()
+                .map(|f| f.to_code_action())
+                .collect::<Vec<_>>();
+            d.data = Some(serde_json::to_value(actions).unwrap());
+        }
         let p = if let Some(source) = &self.source {
             source.clone()
         } else {
@@ -324,6 +402,10 @@ impl PLDiag {
         self.help = Some(Box::new(help.to_string()));
         self
     }
+    pub fn add_fix(&mut self, fix: QuickFix) -> &mut Self {
+        self.fixes.push(fix);
+        self
+    }
     pub fn add_to_ctx(&self, ctx: &Ctx) -> PLDiag {
         ctx.add_diag(self.clone())
     }
@@ -1,16 +1,23 @@
-use crate::{ast::node::Num, utils::read_config::enter};
+use std::sync::Arc;
+
+use crate::{ast::node::Num, nomparser::expression, utils::read_config::enter};
 
 use super::{
+    ctx::{MacroEnv, MacroFragment, MacroReplaceNode},
+    diag::ErrorCode,
     node::{
         comment::CommentNode,
-        control::{BreakNode, ContinueNode, ForNode, IfNode, WhileNode},
+        control::{BreakNode, ContinueNode, ForNode, IfNode, MatchArmNode, MatchNode, WhileNode},
+        enums::{EnumDefNode, EnumVariantNode},
         error::{ErrorNode, StErrorNode},
-        function::{FuncCallNode, FuncDefNode},
+        function::{ClosureNode, FuncCallNode, FuncDefNode},
         global::GlobalNode,
         implement::ImplNode,
         interface::{TraitBoundNode, TraitDefNode},
-        macro_nodes::{MacroCallNode, MacroLoopStatementNode, MacroNode, MacroRuleNode},
-        operator::{BinOpNode, TakeOpNode, UnaryOpNode},
+        macro_nodes::{
+            parse_tokens, MacroCallNode, MacroLoopStatementNode, MacroNode, MacroRuleNode,
+        },
+        operator::{AsNode, BinOpNode, TakeOpNode, UnaryOpNode},
         pkg::{ExternIdNode, UseNode},
         pointer::{PointerOpEnum, PointerOpNode},
         primary::{
@@ -22,11 +29,14 @@ use super::{
         statement::{AssignNode, DefNode, EmptyNode, StatementsNode},
         string_literal::StringNode,
         types::{
-            ArrayInitNode, ArrayTypeNameNode, GenericDefNode, GenericParamNode, PointerTypeNode,
-            StructDefNode, StructInitFieldNode, StructInitNode, TypeNameNode, TypedIdentifierNode,
+            ArrayInitNode, ArrayTypeNameNode, ClosureTypeNode, DynArrayInitNode, GenericDefNode,
+            GenericParamNode, PointerTypeNode, StructDefNode, StructInitFieldNode, StructInitNode,
+            TypeNameNode, TypedIdentifierNode,
         },
         FmtTrait, NodeEnum, TypeNodeEnum,
     },
+    plmod::LSPRangeMap,
+    range::Range,
     tokens::TokenType,
 };
 
@@ -35,6 +45,11 @@ pub struct FmtBuilder {
     buf: String,
     tabs: usize,
     prefix: &'static str,
+    /// 正在输出的宏展开的环境，宏参数会被替换为匹配到的代码
+    macro_env: Option<Arc<MacroEnv>>,
+    macro_loop: Option<usize>,
+    /// 不为None时，宏调用会被替换为展开后的代码
+    expansions: Option<LSPRangeMap<Range, (String, String)>>,
 }
 
 impl FmtBuilder {
@@ -43,7 +58,31 @@ impl FmtBuilder {
             buf: String::new(),
             tabs: 0,
             prefix: "    ",
+            macro_env: None,
+            macro_loop: None,
+            expansions: None,
+        }
+    }
+    /// 输出代码时把模块中的宏调用替换为展开后的代码，见[Mod::macro_expansions](super::plmod::Mod)
+    pub fn with_expansions(expansions: LSPRangeMap<Range, (String, String)>) -> Self {
+        FmtBuilder {
+            expansions: Some(expansions),
+            ..FmtBuilder::new()
+        }
+    }
+    /// 宏在`env`中展开后的代码，`expansions`是调用宏的模块中记录的宏展开
+    pub fn expand_macro(
+        body: &NodeEnum,
+        env: Arc<MacroEnv>,
+        expansions: LSPRangeMap<Range, (String, String)>,
+    ) -> String {
+        let mut b = FmtBuilder::with_expansions(expansions);
+        b.macro_env = Some(env);
+        match body {
+            NodeEnum::STS(sts) => b.parse_statements(&sts.statements),
+            _ => body.format(&mut b),
         }
+        b.generate()
     }
     pub fn generate_node(node: &Box<TypeNodeEnum>) -> String {
         let mut b = FmtBuilder::new();
@@ -128,11 +167,79 @@ impl FmtBuilder {
     // parse nodes
     pub fn parse_program_node(&mut self, node: &ProgramNode) {
         for statement in &node.nodes {
+            // 展开后的代码中不再需要宏定义
+            if let (NodeEnum::MacroNode(_), Some(_)) = (&**statement, &self.expansions) {
+                continue;
+            }
             statement.format(self);
         }
     }
     pub fn parse_var_node(&mut self, node: &VarNode) {
-        self.token(&node.name);
+        match self.macro_fragment(&node.name) {
+            Some(fragment) => self.with_macro_caller(|b| b.parse_macro_fragment(&fragment)),
+            None => self.token(&node.name),
+        }
+    }
+    /// 宏参数`$name`在当前展开中匹配到的片段
+    fn macro_fragment(&self, name: &str) -> Option<MacroFragment> {
+        let name = name.strip_prefix('$')?;
+        match (self.macro_env.as_ref()?.vars.get(name)?, self.macro_loop) {
+            (MacroReplaceNode::Single(f), _) => Some(f.clone()),
+            (MacroReplaceNode::Loop(fs), Some(i)) => fs.get(i).cloned(),
+            (MacroReplaceNode::Loop(_), None) => None,
+        }
+    }
+    fn parse_macro_fragment(&mut self, fragment: &MacroFragment) {
+        match fragment {
+            MacroFragment::Node(node) => node.format(self),
+            MacroFragment::Type(tp) => tp.format(self),
+            MacroFragment::Block(block) => {
+                self.l_brace();
+                self.add_tab();
+                block.format(self);
+                self.sub_tab();
+                self.prefix();
+                self.r_brace();
+            }
+            // 外层宏的参数原样传入时，在外层宏的环境中替换
+            MacroFragment::Tokens(tokens, pos) if tokens.contains('$') => {
+                match parse_tokens(
+                    tokens,
+                    *pos,
+                    expression::logic_exp,
+                    ErrorCode::EXPECT_EXPRESSION,
+                ) {
+                    Ok(node) => node.format(self),
+                    Err(_) => self.token(tokens.trim()),
+                }
+            }
+            MacroFragment::Tokens(tokens, _) => self.token(tokens.trim()),
+        }
+    }
+    /// 在调用宏的位置的环境中输出，宏参数匹配到的片段属于调用处
+    fn with_macro_caller(&mut self, f: impl FnOnce(&mut Self)) {
+        let caller = self.macro_env.as_ref().and_then(|env| env.caller.clone());
+        let old_env = std::mem::replace(&mut self.macro_env, caller);
+        let old_macro_loop = self.macro_loop.take();
+        f(self);
+        self.macro_env = old_env;
+        self.macro_loop = old_macro_loop;
+    }
+    /// 宏调用展开后的代码，不需要展开时返回None
+    fn macro_expansion(&self, node: &MacroCallNode) -> Option<String> {
+        match &self.macro_env {
+            Some(env) => env
+                .expansions
+                .borrow()
+                .get(&(node.range, self.macro_loop))
+                .cloned(),
+            None => self
+                .expansions
+                .as_ref()?
+                .borrow()
+                .get(&node.range)
+                .map(|(_, code)| code.clone()),
+        }
     }
     pub fn parse_use_node(&mut self, node: &UseNode) {
         self.token("use");
@@ -158,10 +265,12 @@ impl FmtBuilder {
     pub fn parse_array_type_name_node(&mut self, node: &ArrayTypeNameNode) {
         self.l_bracket();
         node.id.format(self);
-        self.space();
-        self.asterisk();
-        self.space();
-        node.size.format(self);
+        if let Some(size) = &node.size {
+            self.space();
+            self.asterisk();
+            self.space();
+            size.format(self);
+        }
         self.r_bracket();
     }
     pub fn parse_type_name_node(&mut self, node: &TypeNameNode) {
@@ -212,10 +321,72 @@ impl FmtBuilder {
         // 顶层节点加空格
         self.enter();
     }
+    pub fn parse_enum_variant_node(&mut self, node: &EnumVariantNode) {
+        for d in node.doc.iter() {
+            self.prefix();
+            d.format(self);
+        }
+        self.prefix();
+        self.token(node.id.name.as_str());
+        if !node.fields.is_empty() {
+            self.l_paren();
+            for (i, field) in node.fields.iter().enumerate() {
+                field.format(self);
+                if i != node.fields.len() - 1 {
+                    self.comma();
+                    self.space();
+                }
+            }
+            self.r_paren();
+        }
+    }
+    pub fn parse_enum_def_node(&mut self, node: &EnumDefNode) {
+        for c in node.precom.iter() {
+            c.format(self);
+        }
+        self.prefix();
+        if let Some((modi, _)) = node.modifier {
+            self.token(modi.get_str());
+            self.space();
+        }
+        self.token("enum");
+        self.space();
+        self.token(node.id.name.as_str());
+        self.space();
+        self.l_brace();
+        self.add_tab();
+        for variant in &node.variants {
+            self.enter();
+            variant.format(self);
+            self.comma();
+        }
+        self.enter();
+        self.sub_tab();
+        self.prefix();
+        self.r_brace();
+        self.enter();
+        // 顶层节点加空格
+        self.enter();
+    }
     pub fn parse_pointer_type_node(&mut self, node: &PointerTypeNode) {
         self.asterisk();
         node.elm.format(self);
     }
+    pub fn parse_closure_type_node(&mut self, node: &ClosureTypeNode) {
+        self.parallel();
+        for (i, arg) in node.arg_types.iter().enumerate() {
+            arg.format(self);
+            if i != node.arg_types.len() - 1 {
+                self.comma();
+                self.space();
+            }
+        }
+        self.parallel();
+        self.space();
+        self.token("=>");
+        self.space();
+        node.ret_type.format(self);
+    }
     pub fn parse_struct_init_field_node(&mut self, node: &StructInitFieldNode) {
         self.prefix();
         self.token(&node.id.name);
@@ -255,6 +426,16 @@ impl FmtBuilder {
         }
         self.r_bracket();
     }
+    pub fn parse_dyn_array_init_node(&mut self, node: &DynArrayInitNode) {
+        self.l_bracket();
+        node.tp.format(self);
+        self.space();
+        self.asterisk();
+        self.space();
+        node.len.format(self);
+        self.semicolon();
+        self.r_bracket();
+    }
     pub fn parse_generic_param_node(&mut self, node: &GenericParamNode) {
         self.l_angle_bracket();
 
@@ -288,7 +469,7 @@ impl FmtBuilder {
     pub fn parse_assign_node(&mut self, node: &AssignNode) {
         node.var.format(self);
         self.space();
-        self.equal();
+        self.token(node.op.0.get_str());
         self.space();
         node.exp.format(self);
     }
@@ -296,15 +477,41 @@ impl FmtBuilder {
     pub fn parse_empty_node(&mut self, _node: &EmptyNode) {}
     pub fn parse_statements_node(&mut self, node: &StatementsNode) {
         self.enter();
-        for statement in &node.statements {
+        self.parse_statements(&node.statements);
+    }
+    fn parse_statements(&mut self, statements: &[Box<NodeEnum>]) {
+        for statement in statements {
             match &**statement {
                 NodeEnum::Empty(_) => continue,
+                NodeEnum::MacroLoopStatementNode(node) if self.macro_env.is_some() => {
+                    self.expand_macro_loop(node);
+                    continue;
+                }
+                NodeEnum::Primary(p) => {
+                    // 作为语句的宏调用直接替换为展开后的语句
+                    if let NodeEnum::MacroCallNode(call) = &*p.value {
+                        if let Some(code) = self.macro_expansion(call) {
+                            for line in code.lines() {
+                                if !line.is_empty() {
+                                    self.prefix();
+                                    self.token(line);
+                                }
+                                self.enter();
+                            }
+                            continue;
+                        }
+                    }
+                }
                 _ => {}
             }
             self.prefix();
             statement.format(self);
             match &**statement {
-                NodeEnum::For(_) | NodeEnum::While(_) | NodeEnum::If(_) | NodeEnum::Comment(_) => {}
+                NodeEnum::For(_)
+                | NodeEnum::While(_)
+                | NodeEnum::If(_)
+                | NodeEnum::Match(_)
+                | NodeEnum::Comment(_) => {}
                 _ => {
                     self.semicolon();
                 }
@@ -360,6 +567,13 @@ impl FmtBuilder {
         self.space();
         node.right.format(self);
     }
+    pub fn parse_as_node(&mut self, node: &AsNode) {
+        node.expr.format(self);
+        self.space();
+        self.token("as");
+        self.space();
+        node.ty.format(self);
+    }
     pub fn parse_take_op_node(&mut self, node: &TakeOpNode) {
         node.head.format(self);
         for id in &node.field {
@@ -429,6 +643,11 @@ impl FmtBuilder {
             self.prefix();
             c.format(self);
         }
+        if node.is_test {
+            self.prefix();
+            self.token("#[test]");
+            self.enter();
+        }
         self.prefix();
         self.token("fn");
         self.space();
@@ -459,6 +678,28 @@ impl FmtBuilder {
         // 顶层节点加空格
         self.enter();
     }
+    pub fn parse_closure_node(&mut self, node: &ClosureNode) {
+        if node.is_move {
+            self.token("move");
+            self.space();
+        }
+        self.parallel();
+        self.token(&print_params(&node.paralist));
+        self.parallel();
+        self.space();
+        if let Some(ret) = &node.ret {
+            self.token("=>");
+            self.space();
+            ret.format(self);
+            self.space();
+        }
+        self.l_brace();
+        self.add_tab();
+        node.body.format(self);
+        self.sub_tab();
+        self.prefix();
+        self.r_brace();
+    }
     pub fn parse_st_error_node(&mut self, node: &StErrorNode) {
         node.st.format(self);
     }
@@ -576,6 +817,63 @@ impl FmtBuilder {
     pub fn parse_string_node(&mut self, node: &StringNode) {
         self.token(&format!("{:?}", node.content));
     }
+    pub fn parse_match_arm_node(&mut self, node: &MatchArmNode) {
+        for c in node.comments[0].iter() {
+            self.prefix();
+            c.format(self);
+        }
+        self.prefix();
+        if let Some(pattern) = &node.pattern {
+            pattern.format(self);
+        } else {
+            self.underline();
+        }
+        if let Some(bindings) = &node.bindings {
+            self.l_paren();
+            for (i, b) in bindings.iter().enumerate() {
+                b.format(self);
+                if i != bindings.len() - 1 {
+                    self.comma();
+                    self.space();
+                }
+            }
+            self.r_paren();
+        }
+        self.space();
+        self.token("=>");
+        self.space();
+        if let NodeEnum::STS(sts) = &*node.body {
+            self.l_brace();
+            self.add_tab();
+            sts.format(self);
+            self.sub_tab();
+            self.prefix();
+            self.r_brace();
+        } else {
+            node.body.format(self);
+            self.comma();
+        }
+        self.enter();
+    }
+    pub fn parse_match_node(&mut self, node: &MatchNode) {
+        self.token("match");
+        self.space();
+        node.value.format(self);
+        self.space();
+        self.l_brace();
+        self.enter();
+        self.add_tab();
+        for arm in &node.arms {
+            arm.format(self);
+        }
+        for c in node.comments[0].iter() {
+            self.prefix();
+            c.format(self);
+        }
+        self.sub_tab();
+        self.prefix();
+        self.r_brace();
+    }
     pub fn parse_trait_def_node(&mut self, node: &TraitDefNode) {
         // for c in node.precom.iter() {
         //     c.format(self);
@@ -600,6 +898,10 @@ impl FmtBuilder {
     }
     pub fn parse_macro_node(&mut self, node: &MacroNode) {
         self.prefix();
+        if let Some((modi, _)) = node.modifier {
+            self.token(modi.get_str());
+            self.space();
+        }
         self.token("macro");
         self.space();
         self.token(node.id.name.as_str());
@@ -619,6 +921,27 @@ impl FmtBuilder {
         node.statements.format(self);
         self.token(")*");
     }
+    /// 按照循环中的参数匹配到的次数展开`$(...)*`
+    fn expand_macro_loop(&mut self, node: &MacroLoopStatementNode) {
+        let len = node
+            .vars
+            .iter()
+            .filter_map(|v| match self.macro_env.as_ref()?.vars.get(v) {
+                Some(MacroReplaceNode::Loop(fs)) => Some(fs.len()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+        let old_macro_loop = self.macro_loop;
+        for i in 0..len {
+            self.macro_loop = Some(i);
+            match &*node.statements {
+                NodeEnum::STS(sts) => self.parse_statements(&sts.statements),
+                st => st.format(self),
+            }
+        }
+        self.macro_loop = old_macro_loop;
+    }
     pub fn parse_macro_rule_node(&mut self, node: &MacroRuleNode) {
         self.prefix();
         self.l_paren();
@@ -633,6 +956,23 @@ impl FmtBuilder {
         self.enter();
     }
     pub fn parse_macro_call_node(&mut self, node: &MacroCallNode) {
+        // 不在语句位置的宏调用展开为代码块
+        if let Some(code) = self.macro_expansion(node) {
+            self.l_brace();
+            self.add_tab();
+            for line in code.lines() {
+                self.enter();
+                if !line.is_empty() {
+                    self.prefix();
+                    self.token(line);
+                }
+            }
+            self.sub_tab();
+            self.enter();
+            self.prefix();
+            self.r_brace();
+            return;
+        }
         node.callee.format(self);
         self.token("!");
         self.l_paren();
@@ -1,5 +1,6 @@
 pub mod accumulators;
 pub mod builder;
+pub mod cache;
 pub mod compiler;
 pub mod ctx;
 pub mod diag;
@@ -1,8 +1,13 @@
+use super::pkg::ExternIdNode;
+use super::primary::VarNode;
 use super::statement::StatementsNode;
 use super::*;
 use crate::ast::ctx::Ctx;
 use crate::ast::diag::ErrorCode;
-use crate::ast::pltype::PriType;
+use crate::ast::pltype::{EnumType, EnumVariant, PriType};
+use crate::ast::tokens::TokenType;
+use crate::plv;
+use inkwell::IntPredicate;
 use internal_macro::node;
 
 #[node(comment)]
@@ -64,7 +69,9 @@ impl Node for IfNode {
         ctx.position_at_end(else_block, builder);
         let terminator = if let Some(el) = &mut self.els {
             let mut child = ctx.new_child(el.range().start, builder);
-            let (_, _, else_terminator) = el.emit(&mut child, builder)?;
+            let re = el.emit(&mut child, builder);
+            builder.end_subscope();
+            let (_, _, else_terminator) = re?;
             if else_terminator.is_none() {
                 builder.build_unconditional_branch(after_block);
             }
@@ -109,7 +116,19 @@ impl Node for WhileNode {
         ctx: &'b mut Ctx<'a>,
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> NodeResult {
-        let ctx = &mut ctx.new_child(self.range.start, builder);
+        let child = &mut ctx.new_child(self.range.start, builder);
+        let re = self.emit_loop(child, builder);
+        builder.end_subscope();
+        re
+    }
+}
+
+impl WhileNode {
+    fn emit_loop<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> NodeResult {
         let cond_block = builder.append_basic_block(ctx.function.unwrap(), "while.cond");
         let body_block = builder.append_basic_block(ctx.function.unwrap(), "while.body");
         let after_block = builder.append_basic_block(ctx.function.unwrap(), "while.after");
@@ -174,7 +193,19 @@ impl Node for ForNode {
         ctx: &'b mut Ctx<'a>,
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> NodeResult {
-        let ctx = &mut ctx.new_child(self.range.start, builder);
+        let child = &mut ctx.new_child(self.range.start, builder);
+        let re = self.emit_loop(child, builder);
+        builder.end_subscope();
+        re
+    }
+}
+
+impl ForNode {
+    fn emit_loop<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> NodeResult {
         let pre_block = builder.append_basic_block(ctx.function.unwrap(), "for.pre");
         let cond_block = builder.append_basic_block(ctx.function.unwrap(), "for.cond");
         let opt_block = builder.append_basic_block(ctx.function.unwrap(), "for.opt");
@@ -281,3 +312,335 @@ impl Node for ContinueNode {
         Ok((None, None, TerminatorEnum::CONTINUE))
     }
 }
+
+#[node(comment)]
+pub struct MatchArmNode {
+    /// None表示通配符`_`
+    pub pattern: Option<Box<ExternIdNode>>,
+    pub bindings: Option<Vec<Box<VarNode>>>,
+    pub body: Box<NodeEnum>,
+}
+
+impl PrintTrait for MatchArmNode {
+    fn print(&self, tabs: usize, end: bool, mut line: Vec<bool>) {
+        deal_line(tabs, &mut line, end);
+        tab(tabs, line.clone(), end);
+        println!("MatchArmNode");
+        if let Some(pattern) = &self.pattern {
+            pattern.print(tabs + 1, false, line.clone());
+        } else {
+            tab(tabs + 1, line.clone(), false);
+            println!("pattern: _");
+        }
+        if let Some(bindings) = &self.bindings {
+            for b in bindings {
+                b.print(tabs + 1, false, line.clone());
+            }
+        }
+        self.body.print(tabs + 1, true, line.clone());
+    }
+}
+
+impl MatchArmNode {
+    /// 解析该分支对应的变体，通配符返回None
+    fn get_variant<'a>(
+        &self,
+        ctx: &Ctx<'a>,
+        pltype: &Arc<RefCell<PLType>>,
+        e: &EnumType,
+    ) -> Result<Option<EnumVariant>, PLDiag> {
+        let pattern = match &self.pattern {
+            Some(pattern) => pattern,
+            None => {
+                if let Some(bindings) = &self.bindings {
+                    let range = bindings[0]
+                        .range
+                        .start
+                        .to(bindings.last().unwrap().range.end);
+                    return Err(
+                        ctx.add_diag(range.new_err(ErrorCode::ENUM_VARIANT_FIELD_LEN_MISMATCH))
+                    );
+                }
+                return Ok(None);
+            }
+        };
+        let tp = pattern.get_enum_type(ctx);
+        if tp.is_none() || tp.unwrap().borrow().get_full_elm_name() != e.get_enum_full_name() {
+            return Err(ctx.add_diag(
+                pattern
+                    .range
+                    .new_err(ErrorCode::ENUM_VARIANT_NOT_FOUND)
+                    .add_help(&format!("expect a variant of enum `{}`", e.name))
+                    .clone(),
+            ));
+        }
+        let (enum_id, mods) = pattern.ns.split_last().unwrap();
+        for ns in mods {
+            ctx.push_semantic_token(ns.range, SemanticTokenType::NAMESPACE, 0);
+        }
+        ctx.push_semantic_token(enum_id.range, SemanticTokenType::ENUM, 0);
+        ctx.set_if_refs_tp(pltype.clone(), enum_id.range);
+        ctx.send_if_go_to_def(enum_id.range, e.range, e.path.clone());
+        let variant = match e.find_variant(&pattern.id.get_name(ctx)) {
+            Some(variant) => variant.clone(),
+            None => {
+                ctx.if_completion(pattern.range, || e.get_variant_completions());
+                return Err(
+                    ctx.add_diag(pattern.id.range.new_err(ErrorCode::ENUM_VARIANT_NOT_FOUND))
+                );
+            }
+        };
+        ctx.push_semantic_token(pattern.id.range, SemanticTokenType::ENUM_MEMBER, 0);
+        ctx.send_if_go_to_def(pattern.id.range, variant.range, e.path.clone());
+        ctx.save_if_comment_doc_hover(pattern.id.range, Some(variant.doc.clone()));
+        let len = self.bindings.as_ref().map_or(0, |b| b.len());
+        if len != variant.fields.len() {
+            return Err(ctx.add_diag(
+                self.range
+                    .new_err(ErrorCode::ENUM_VARIANT_FIELD_LEN_MISMATCH)
+                    .add_help(&format!(
+                        "variant `{}` has {} field(s)",
+                        variant.name,
+                        variant.fields.len()
+                    ))
+                    .clone(),
+            ));
+        }
+        Ok(Some(variant))
+    }
+
+    /// 生成分支体，如果分支体是表达式，返回它的值
+    fn emit_arm<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+        variant: Option<&EnumVariant>,
+        enum_ptr: ValueHandle,
+    ) -> Result<(Option<(ValueHandle, Arc<RefCell<PLType>>)>, TerminatorEnum), PLDiag> {
+        let child = &mut ctx.new_child(self.range.start, builder);
+        let re = self.emit_arm_body(child, builder, variant, enum_ptr);
+        builder.end_subscope();
+        re
+    }
+
+    fn emit_arm_body<'a, 'ctx, 'b>(
+        &mut self,
+        child: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+        variant: Option<&EnumVariant>,
+        enum_ptr: ValueHandle,
+    ) -> Result<(Option<(ValueHandle, Arc<RefCell<PLType>>)>, TerminatorEnum), PLDiag> {
+        if let Some(variant) = variant {
+            self.emit_bindings(child, builder, variant, enum_ptr)?;
+        }
+        let body_range = self.body.range();
+        let is_block = matches!(*self.body, NodeEnum::STS(_));
+        let (value, pltype, terminator) = self.body.emit(child, builder)?;
+        if is_block || !terminator.is_none() {
+            return Ok((None, terminator));
+        }
+        match (value, pltype) {
+            (Some(value), Some(pltype)) => {
+                let value = child.try_load2var(body_range, value, builder)?;
+                Ok((Some((value, pltype)), terminator))
+            }
+            _ => Ok((None, terminator)),
+        }
+    }
+
+    /// 将变体的字段绑定到分支的局部变量上
+    fn emit_bindings<'a, 'ctx, 'b>(
+        &self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+        variant: &EnumVariant,
+        enum_ptr: ValueHandle,
+    ) -> Result<(), PLDiag> {
+        let (payload, bindings) = match (&variant.payload, &self.bindings) {
+            (Some(payload), Some(bindings)) => (payload, bindings),
+            _ => return Ok(()),
+        };
+        let payload_field = builder.build_struct_gep(enum_ptr, 2, "payload").unwrap();
+        let payload_ptr = builder.build_load(payload_field, "payload");
+        let payload_pltype =
+            PLType::POINTER(Arc::new(RefCell::new(PLType::STRUCT(payload.clone()))));
+        let payload_ptr = builder.bitcast(ctx, payload_ptr, &payload_pltype, "payload_casted");
+        let field_tps = ctx.run_in_st_mod(payload, |ctx, _| {
+            variant
+                .fields
+                .iter()
+                .map(|f| f.get_type(ctx, builder))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        for (i, (binding, tp)) in bindings.iter().zip(field_tps).enumerate() {
+            if binding.name == TokenType::INGNORE.get_str() {
+                continue;
+            }
+            ctx.push_semantic_token(binding.range, SemanticTokenType::VARIABLE, 0);
+            ctx.push_type_hints(binding.range, tp.clone());
+            let field_ptr = builder
+                .build_struct_gep(payload_ptr, i as u32 + 1, "payload_field")
+                .unwrap();
+            let value = builder.build_load(field_ptr, "payload_field_value");
+            let ptr2value =
+                builder.alloc(&binding.name, &tp.borrow(), ctx, Some(binding.range.start));
+            builder.build_store(ptr2value, value);
+            ctx.add_symbol(binding.name.clone(), ptr2value, tp, binding.range, false)?;
+        }
+        Ok(())
+    }
+}
+
+#[node(comment)]
+pub struct MatchNode {
+    pub value: Box<NodeEnum>,
+    pub arms: Vec<Box<MatchArmNode>>,
+}
+
+impl PrintTrait for MatchNode {
+    fn print(&self, tabs: usize, end: bool, mut line: Vec<bool>) {
+        deal_line(tabs, &mut line, end);
+        tab(tabs, line.clone(), end);
+        println!("MatchNode");
+        self.value
+            .print(tabs + 1, self.arms.is_empty(), line.clone());
+        let mut i = self.arms.len();
+        for arm in &self.arms {
+            i -= 1;
+            arm.print(tabs + 1, i == 0, line.clone());
+        }
+    }
+}
+
+impl Node for MatchNode {
+    fn emit<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> NodeResult {
+        let value_range = self.value.range();
+        let (value, pltype, _) = self.value.emit(ctx, builder)?;
+        if value.is_none() || pltype.is_none() {
+            return Err(ctx.add_diag(value_range.new_err(ErrorCode::EXPECT_VALUE)));
+        }
+        let (pltype, enum_ptr) =
+            ctx.auto_deref(pltype.unwrap(), value.unwrap().value, value_range, builder);
+        let e = match &*pltype.borrow() {
+            PLType::ENUM(e) => e.clone(),
+            _ => {
+                return Err(ctx.add_diag(value_range.new_err(ErrorCode::EXPECT_ENUM_TYPE)));
+            }
+        };
+        // 先检查所有分支，得到每个分支对应的变体
+        let mut arm_variants = vec![];
+        let mut covered = vec![];
+        let mut has_wildcard = false;
+        let mut has_unreachable = false;
+        for arm in self.arms.iter() {
+            ctx.emit_comment_highlight(&arm.comments[0]);
+            let variant = arm.get_variant(ctx, &pltype, &e)?;
+            if has_wildcard || matches!(&variant, Some(v) if covered.contains(&v.name)) {
+                has_unreachable = true;
+                ctx.add_diag(arm.range.new_err(ErrorCode::UNREACHABLE_MATCH_ARM));
+            }
+            match &variant {
+                Some(v) => covered.push(v.name.clone()),
+                None => has_wildcard = true,
+            }
+            arm_variants.push(variant);
+        }
+        let missing = e
+            .variants
+            .iter()
+            .filter(|v| !covered.contains(&v.name))
+            .map(|v| format!("`{}::{}`", e.name, v.name))
+            .collect::<Vec<_>>();
+        let exhaustive = has_wildcard || missing.is_empty();
+        let tag_ptr = builder.build_struct_gep(enum_ptr, 1, "tag").unwrap();
+        let tag = builder.build_load(tag_ptr, "tag");
+        let after_block = builder.append_basic_block(ctx.function.unwrap(), "match.after");
+        let mut incoming = vec![];
+        let mut value_pltype: Option<Arc<RefCell<PLType>>> = None;
+        let mut has_value = !self.arms.is_empty();
+        let mut all_return = !self.arms.is_empty();
+        let mut fallthrough = true;
+        let arm_len = self.arms.len();
+        for (i, (arm, variant)) in self.arms.iter_mut().zip(arm_variants).enumerate() {
+            let last = i == arm_len - 1;
+            let arm_block = builder.append_basic_block(ctx.function.unwrap(), "match.arm");
+            let next_block = match &variant {
+                // 穷尽的match的最后一个分支不需要再比较tag
+                Some(v) if !(exhaustive && last) => {
+                    let next_block =
+                        builder.append_basic_block(ctx.function.unwrap(), "match.next");
+                    let expect = builder.int_value(&PriType::U64, v.tag, false);
+                    let cond = builder.build_int_compare(IntPredicate::EQ, tag, expect, "tag_eq");
+                    builder.build_conditional_branch(cond, arm_block, next_block);
+                    Some(next_block)
+                }
+                _ => {
+                    builder.build_unconditional_branch(arm_block);
+                    if last {
+                        None
+                    } else {
+                        Some(builder.append_basic_block(ctx.function.unwrap(), "match.next"))
+                    }
+                }
+            };
+            ctx.position_at_end(arm_block, builder);
+            let body_range = arm.body.range();
+            let (arm_value, terminator) = arm.emit_arm(ctx, builder, variant.as_ref(), enum_ptr)?;
+            if terminator.is_none() {
+                all_return = false;
+                if let Some((v, tp)) = arm_value {
+                    if let Some(pre) = &value_pltype {
+                        if !ctx.eq(pre.clone(), tp.clone()).eq {
+                            return Err(ctx
+                                .add_diag(body_range.new_err(ErrorCode::MATCH_ARM_TYPE_MISMATCH)));
+                        }
+                    } else {
+                        value_pltype = Some(tp);
+                    }
+                    incoming.push((v, builder.get_cur_basic_block()));
+                } else {
+                    has_value = false;
+                }
+                builder.build_unconditional_branch(after_block);
+            } else if !terminator.is_return() {
+                all_return = false;
+            }
+            fallthrough = next_block.is_some();
+            if let Some(next_block) = next_block {
+                ctx.position_at_end(next_block, builder);
+            }
+        }
+        if fallthrough {
+            builder.build_unconditional_branch(after_block);
+        }
+        ctx.position_at_end(after_block, builder);
+        ctx.emit_comment_highlight(&self.comments[0]);
+        if !exhaustive {
+            return Err(ctx.add_diag(
+                self.range
+                    .new_err(ErrorCode::NON_EXHAUSTIVE_MATCH)
+                    .add_help(&format!("{} not covered", missing.join(", ")))
+                    .clone(),
+            ));
+        }
+        if has_unreachable {
+            return Ok((None, None, TerminatorEnum::NONE));
+        }
+        if all_return {
+            builder.build_unconditional_branch(after_block);
+            return Ok((None, None, TerminatorEnum::RETURN));
+        }
+        if has_value && !incoming.is_empty() {
+            let value_pltype = value_pltype.unwrap();
+            let phi = builder.build_phi(&value_pltype.borrow(), ctx, &incoming);
+            let ptr = builder.alloc("match_value", &value_pltype.borrow(), ctx, None);
+            builder.build_store(ptr, phi);
+            return Ok((Some(plv!(ptr)), Some(value_pltype), TerminatorEnum::NONE));
+        }
+        Ok((None, None, TerminatorEnum::NONE))
+    }
+}
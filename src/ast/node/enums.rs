@@ -0,0 +1,323 @@
+use super::pkg::ExternIdNode;
+use super::primary::VarNode;
+use super::*;
+use crate::{
+    ast::{
+        ctx::Ctx,
+        pltype::{EnumType, EnumVariant, Field, STType},
+        tokens::TokenType,
+    },
+    format_label, plv,
+};
+use indexmap::IndexMap;
+use internal_macro::node;
+use rustc_hash::FxHashMap;
+
+#[node]
+pub struct EnumVariantNode {
+    pub id: Box<VarNode>,
+    pub fields: Vec<Box<TypeNodeEnum>>,
+    pub doc: Vec<Box<NodeEnum>>,
+}
+
+impl PrintTrait for EnumVariantNode {
+    fn print(&self, tabs: usize, end: bool, mut line: Vec<bool>) {
+        deal_line(tabs, &mut line, end);
+        tab(tabs, line.clone(), end);
+        println!("EnumVariantNode");
+        tab(tabs + 1, line.clone(), self.fields.is_empty());
+        println!("id: {}", self.id.name);
+        let mut i = self.fields.len();
+        for field in &self.fields {
+            i -= 1;
+            field.print(tabs + 1, i == 0, line.clone());
+        }
+    }
+}
+
+#[node]
+pub struct EnumDefNode {
+    pub precom: Vec<Box<NodeEnum>>,
+    pub doc: Vec<Box<NodeEnum>>,
+    pub id: Box<VarNode>,
+    pub variants: Vec<Box<EnumVariantNode>>,
+    pub modifier: Option<(TokenType, Range)>,
+}
+
+impl PrintTrait for EnumDefNode {
+    fn print(&self, tabs: usize, end: bool, mut line: Vec<bool>) {
+        deal_line(tabs, &mut line, end);
+        tab(tabs, line.clone(), end);
+        println!("EnumDefNode");
+        tab(tabs + 1, line.clone(), false);
+        println!("id: {}", self.id.name);
+        for c in self.precom.iter() {
+            c.print(tabs + 1, false, line.clone());
+        }
+        let mut i = self.variants.len();
+        for variant in &self.variants {
+            i -= 1;
+            variant.print(tabs + 1, i == 0, line.clone());
+        }
+    }
+}
+
+impl Node for EnumDefNode {
+    fn emit<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        _builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> NodeResult {
+        ctx.emit_comment_highlight(&self.precom);
+        ctx.push_semantic_token(self.id.range, SemanticTokenType::ENUM, 0);
+        for variant in self.variants.iter() {
+            for doc in variant.doc.iter() {
+                ctx.push_semantic_token(doc.range(), SemanticTokenType::COMMENT, 0);
+            }
+            ctx.push_semantic_token(variant.id.range, SemanticTokenType::ENUM_MEMBER, 0);
+            for field in variant.fields.iter() {
+                field.emit_highlight(ctx);
+            }
+        }
+        Ok((None, None, TerminatorEnum::NONE))
+    }
+}
+
+impl EnumDefNode {
+    pub fn add_to_symbols<'a, 'ctx, 'b>(
+        &self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) {
+        let e = EnumType {
+            name: self.id.name.clone(),
+            path: ctx.plmod.path.clone(),
+            variants: vec![],
+            range: self.range(),
+            doc: vec![],
+            modifier: self.modifier,
+        };
+        let full_name = ctx.plmod.get_full_name(&self.id.name);
+        builder.opaque_struct_type(&full_name);
+        // 枚举的布局与变体无关，提前生成，结构体中可以直接包含枚举
+        builder.add_body_to_struct_type(&full_name, &e.get_layout().ordered_fields, ctx);
+        _ = ctx.add_type(
+            self.id.name.clone(),
+            Arc::new(RefCell::new(PLType::ENUM(e))),
+            self.id.range,
+        );
+    }
+
+    pub fn emit_enum_def<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> Result<(), PLDiag> {
+        let pltype = ctx.get_type(self.id.name.as_str(), self.range)?;
+        let layout = match &*pltype.borrow() {
+            PLType::ENUM(e) => e.get_layout(),
+            _ => unreachable!(),
+        };
+        let tag_tp = Arc::new(RefCell::new(PLType::PRIMITIVE(PriType::U64)));
+        let payload_tp = Arc::new(RefCell::new(PLType::POINTER(Arc::new(RefCell::new(
+            PLType::PRIMITIVE(PriType::U8),
+        )))));
+        builder.gen_st_visit_function(ctx, &layout, &[tag_tp, payload_tp]);
+        let mut variants: Vec<EnumVariant> = vec![];
+        'variants: for (tag, variant) in self.variants.iter().enumerate() {
+            if let Some(pre) = variants.iter().find(|v| v.name == variant.id.name) {
+                ctx.add_diag(
+                    variant
+                        .id
+                        .range
+                        .new_err(ErrorCode::DUPLICATE_ENUM_VARIANT)
+                        .add_label(
+                            pre.range,
+                            ctx.get_file(),
+                            format_label!("variant `{}` first defined here", pre.name),
+                        )
+                        .clone(),
+                );
+                continue;
+            }
+            let mut payload = None;
+            if !variant.fields.is_empty() {
+                // 变体的字段存放在单独的结构体中，第一个字段为gcrtti
+                let vtable_field = Field {
+                    index: 0,
+                    typenode: Box::new(TypeNameNode::new_from_str("u64").into()),
+                    name: "_vtable".to_string(),
+                    range: Default::default(),
+                    modifier: None,
+                };
+                let mut fields = FxHashMap::<String, Field>::default();
+                fields.insert(vtable_field.name.clone(), vtable_field.clone());
+                let mut order_fields = vec![vtable_field];
+                let mut field_pltps = vec![];
+                for (i, field) in variant.fields.iter().enumerate() {
+                    let tp = match field.get_type(ctx, builder) {
+                        Ok(tp) => tp,
+                        Err(_) => continue 'variants,
+                    };
+                    match &*tp.borrow() {
+                        PLType::STRUCT(sttp) => {
+                            ctx.send_if_go_to_def(field.range(), sttp.range, sttp.path.clone());
+                        }
+                        PLType::ENUM(e) => {
+                            ctx.send_if_go_to_def(field.range(), e.range, e.path.clone());
+                        }
+                        _ => {}
+                    }
+                    ctx.set_if_refs_tp(tp.clone(), field.range());
+                    field_pltps.push(tp);
+                    let f = Field {
+                        index: i as u32 + 1,
+                        typenode: field.clone(),
+                        name: i.to_string(),
+                        range: field.range(),
+                        modifier: None,
+                    };
+                    fields.insert(f.name.clone(), f.clone());
+                    order_fields.push(f);
+                }
+                let st = STType {
+                    name: format!("{}::{}", self.id.name, variant.id.name),
+                    path: ctx.plmod.path.clone(),
+                    fields,
+                    ordered_fields: order_fields,
+                    range: variant.range,
+                    doc: vec![],
+                    generic_map: IndexMap::default(),
+                    derives: vec![],
+                    modifier: self.modifier,
+                };
+                builder.gen_st_visit_function(ctx, &st, &field_pltps);
+                payload = Some(st);
+            }
+            ctx.send_if_go_to_def(variant.id.range, variant.range, ctx.plmod.path.clone());
+            ctx.save_if_comment_doc_hover(variant.id.range, Some(variant.doc.clone()));
+            variants.push(EnumVariant {
+                name: variant.id.name.clone(),
+                tag: tag as u64,
+                fields: variant.fields.clone(),
+                range: variant.range,
+                doc: variant.doc.clone(),
+                payload,
+            });
+        }
+        if let PLType::ENUM(e) = &mut *pltype.borrow_mut() {
+            e.variants = variants;
+            e.doc = self.doc.clone();
+        }
+        ctx.set_if_refs_tp(pltype.clone(), self.id.range);
+        ctx.add_doc_symbols(pltype);
+        ctx.save_if_comment_doc_hover(self.range, Some(self.doc.clone()));
+        Ok(())
+    }
+}
+
+/// 在堆上构造一个枚举值，返回指向它的指针
+pub fn build_enum_value<'a, 'ctx, 'b>(
+    ctx: &'b mut Ctx<'a>,
+    builder: &'b BuilderEnum<'a, 'ctx>,
+    pltype: &PLType,
+    variant: &EnumVariant,
+    values: &[ValueHandle],
+) -> ValueHandle {
+    let enum_ptr = builder.alloc("enum_value", pltype, ctx, None);
+    let tag_ptr = builder.build_struct_gep(enum_ptr, 1, "tag").unwrap();
+    let tag = builder.int_value(&PriType::U64, variant.tag, false);
+    builder.build_store(tag_ptr, tag);
+    if let Some(payload) = &variant.payload {
+        let payload_ptr =
+            builder.alloc("enum_payload", &PLType::STRUCT(payload.clone()), ctx, None);
+        for (i, value) in values.iter().enumerate() {
+            let field_ptr = builder
+                .build_struct_gep(payload_ptr, i as u32 + 1, "payload_field")
+                .unwrap();
+            builder.build_store(field_ptr, *value);
+        }
+        let i8ptr = PLType::POINTER(Arc::new(RefCell::new(PLType::PRIMITIVE(PriType::U8))));
+        let casted = builder.bitcast(ctx, payload_ptr, &i8ptr, "payload_casted");
+        let payload_field = builder.build_struct_gep(enum_ptr, 2, "payload").unwrap();
+        builder.build_store(payload_field, casted);
+    }
+    enum_ptr
+}
+
+/// 生成形如`Shape::Circle(1.0)`或`Shape::Empty`的枚举值
+///
+/// `args`为None表示没有参数列表
+pub fn emit_enum_variant<'a, 'ctx, 'b>(
+    ctx: &'b mut Ctx<'a>,
+    builder: &'b BuilderEnum<'a, 'ctx>,
+    id: &ExternIdNode,
+    pltype: Arc<RefCell<PLType>>,
+    args: Option<&mut Vec<Box<NodeEnum>>>,
+    range: Range,
+) -> NodeResult {
+    let (enum_id, mods) = id.ns.split_last().unwrap();
+    for ns in mods {
+        ctx.push_semantic_token(ns.range, SemanticTokenType::NAMESPACE, 0);
+    }
+    ctx.push_semantic_token(enum_id.range, SemanticTokenType::ENUM, 0);
+    let e = match &*pltype.borrow() {
+        PLType::ENUM(e) => e.clone(),
+        _ => unreachable!(),
+    };
+    _ = pltype.borrow().expect_pub(ctx, enum_id.range);
+    ctx.set_if_refs_tp(pltype.clone(), enum_id.range);
+    ctx.send_if_go_to_def(enum_id.range, e.range, e.path.clone());
+    ctx.save_if_comment_doc_hover(enum_id.range, Some(e.doc.clone()));
+    let variant = match e.find_variant(&id.id.get_name(ctx)) {
+        Some(variant) => variant.clone(),
+        None => {
+            ctx.if_completion(id.range, || e.get_variant_completions());
+            return Err(ctx.add_diag(id.id.range.new_err(ErrorCode::ENUM_VARIANT_NOT_FOUND)));
+        }
+    };
+    ctx.push_semantic_token(id.id.range, SemanticTokenType::ENUM_MEMBER, 0);
+    ctx.send_if_go_to_def(id.id.range, variant.range, e.path.clone());
+    ctx.save_if_comment_doc_hover(id.id.range, Some(variant.doc.clone()));
+    let args = args.map_or(vec![], |args| args.iter_mut().collect::<Vec<_>>());
+    if args.len() != variant.fields.len() {
+        return Err(ctx.add_diag(
+            range
+                .new_err(ErrorCode::ENUM_VARIANT_FIELD_LEN_MISMATCH)
+                .add_help(&format!(
+                    "variant `{}` has {} field(s)",
+                    variant.name,
+                    variant.fields.len()
+                ))
+                .clone(),
+        ));
+    }
+    let mut values = vec![];
+    let mut value_pltypes = vec![];
+    for arg in args {
+        let arg_range = arg.range();
+        let (value, value_pltype, _) = arg.emit(ctx, builder)?;
+        if value.is_none() || value_pltype.is_none() {
+            return Err(ctx.add_diag(arg_range.new_err(ErrorCode::EXPECT_VALUE)));
+        }
+        values.push(ctx.try_load2var(arg_range, value.unwrap(), builder)?);
+        value_pltypes.push((value_pltype.unwrap(), arg_range));
+    }
+    if let Some(payload) = &variant.payload {
+        ctx.run_in_st_mod(payload, |ctx, _| {
+            for (i, (value_pltype, arg_range)) in value_pltypes.iter().enumerate() {
+                if !variant.fields[i]
+                    .eq_or_infer(ctx, value_pltype.clone(), builder)?
+                    .eq
+                {
+                    return Err(
+                        ctx.add_diag(arg_range.new_err(ErrorCode::PARAMETER_TYPE_NOT_MATCH))
+                    );
+                }
+            }
+            Ok(())
+        })?;
+    }
+    let v = build_enum_value(ctx, builder, &pltype.borrow(), &variant, &values);
+    Ok((Some(plv!(v)), Some(pltype.clone()), TerminatorEnum::NONE))
+}
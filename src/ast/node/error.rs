@@ -1,5 +1,8 @@
 use super::*;
-use crate::ast::{ctx::Ctx, diag::ErrorCode};
+use crate::ast::{
+    ctx::Ctx,
+    diag::{ErrorCode, QuickFix},
+};
 
 use colored::Colorize;
 use internal_macro::node;
@@ -59,6 +62,12 @@ impl Node for StErrorNode {
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> NodeResult {
         _ = self.st.emit(ctx, builder);
-        self.err.emit(ctx, builder)
+        let mut err = self.err.range.new_err(self.err.code);
+        if self.err.code == ErrorCode::MISSING_SEMI {
+            let end = self.st.range().end;
+            err.add_fix(QuickFix::new("insert `;`".to_string()).insert(&ctx.get_file(), end, ";"));
+        }
+        ctx.if_completion(self.err.range, || ctx.get_completions());
+        Err(ctx.add_diag(err))
     }
 }
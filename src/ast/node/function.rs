@@ -1,24 +1,34 @@
+use super::enums::emit_enum_variant;
 use super::interface::TraitBoundNode;
 use super::statement::StatementsNode;
 use super::*;
 use super::{types::TypedIdentifierNode, Node, TypeNode};
+use crate::ast::ctx::ClosureCtxData;
 use crate::ast::diag::ErrorCode;
 use crate::ast::node::{deal_line, tab};
 
-use crate::ast::pltype::{get_type_deep, FNType, FNValue, PLType};
+use crate::ast::pltype::{
+    get_type_deep, ClosureType, FNType, FNValue, Field, PLType, PriType, STType,
+};
 use crate::ast::tokens::TokenType;
 use crate::plv;
 use indexmap::IndexMap;
 use internal_macro::node;
 use lsp_types::SemanticTokenType;
+use rustc_hash::FxHashMap;
 use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use std::vec;
+
+static CLOSURE_ID: AtomicUsize = AtomicUsize::new(0);
 #[node(comment)]
 pub struct FuncCallNode {
     pub generic_params: Option<Box<GenericParamNode>>,
     pub callee: Box<NodeEnum>,
     pub paralist: Vec<Box<NodeEnum>>,
+    /// 包括括号在内的参数列表的范围
+    pub params_range: Range,
 }
 
 impl PrintTrait for FuncCallNode {
@@ -41,12 +51,42 @@ impl Node for FuncCallNode {
         ctx: &'b mut Ctx<'a>,
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> NodeResult {
+        if let NodeEnum::Primary(p) = &*self.callee {
+            if let NodeEnum::ExternIdNode(id) = &*p.value {
+                if let Some(tp) = id.get_enum_type(ctx) {
+                    // 形如`Shape::Circle(1.0)`的枚举值
+                    let id = id.clone();
+                    let res = emit_enum_variant(
+                        ctx,
+                        builder,
+                        &id,
+                        tp,
+                        Some(&mut self.paralist),
+                        self.range,
+                    );
+                    ctx.emit_comment_highlight(&self.comments[0]);
+                    return res;
+                }
+            }
+        }
         let id_range = self.callee.range();
         let (plvalue, pltype, _) = self.callee.emit(ctx, builder)?;
         if pltype.is_none() {
             return Err(ctx.add_diag(self.range.new_err(ErrorCode::FUNCTION_NOT_FOUND)));
         }
         let pltype = pltype.unwrap();
+        let closure = match &*pltype.borrow() {
+            PLType::CLOSURE(c) => Some(c.clone()),
+            _ => None,
+        };
+        if let Some(closure) = closure {
+            let res = match plvalue {
+                Some(v) => self.emit_closure_call(ctx, builder, v.value, closure),
+                None => Err(ctx.add_diag(self.range.new_err(ErrorCode::EXPECT_VALUE))),
+            };
+            ctx.emit_comment_highlight(&self.comments[0]);
+            return res;
+        }
         let mut fnvalue = match &*pltype.borrow() {
             PLType::FN(f) => {
                 let mut res = f.clone();
@@ -55,6 +95,7 @@ impl Node for FuncCallNode {
             }
             _ => return Err(ctx.add_diag(self.range.new_err(ErrorCode::FUNCTION_NOT_FOUND))),
         };
+        ctx.add_call(id_range, &fnvalue);
 
         if let Some(generic_params) = &self.generic_params {
             let generic_params_range = generic_params.range;
@@ -90,7 +131,7 @@ impl Node for FuncCallNode {
         }
         for (i, para) in self.paralist.iter_mut().enumerate() {
             let pararange = para.range();
-            ctx.push_param_hint(pararange, fnvalue.param_names[i + skip as usize].clone());
+            ctx.push_param_hint(pararange, &fnvalue, i + skip as usize);
             ctx.set_if_sig(
                 para.range(),
                 fnvalue.name.clone().split("::").last().unwrap().to_string()
@@ -173,6 +214,19 @@ impl Node for FuncCallNode {
             })?;
             if !fnvalue.fntype.generic_map.is_empty() {
                 if fnvalue.fntype.need_gen_code() {
+                    if self.generic_params.is_none() {
+                        let inferred = fnvalue
+                            .fntype
+                            .generic_map
+                            .values()
+                            .take(fnvalue.fntype.generics_size)
+                            .filter_map(|g| match &*g.borrow() {
+                                PLType::GENERIC(g) => g.curpltype.clone(),
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>();
+                        ctx.push_generic_hint(id_range, &inferred);
+                    }
                     fnvalue = ctx.run_in_fn_mod_mut(&mut fnvalue, |ctx, fnvalue| {
                         fnvalue.generic_infer_pltype(ctx, builder)
                     })?;
@@ -212,6 +266,57 @@ impl Node for FuncCallNode {
         res
     }
 }
+impl FuncCallNode {
+    /// 通过闭包值调用，捕获环境作为第一个参数传入
+    fn emit_closure_call<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+        closure_v: ValueHandle,
+        closure: ClosureType,
+    ) -> NodeResult {
+        if let Some(generic_params) = &self.generic_params {
+            return Err(ctx.add_diag(
+                generic_params
+                    .range
+                    .new_err(ErrorCode::GENERIC_PARAM_LEN_MISMATCH),
+            ));
+        }
+        if closure.arg_types.len() != self.paralist.len() {
+            return Err(ctx.add_diag(self.range.new_err(ErrorCode::PARAMETER_LENGTH_NOT_MATCH)));
+        }
+        let mut para_values = vec![];
+        for (i, para) in self.paralist.iter_mut().enumerate() {
+            let pararange = para.range();
+            let (value, value_pltype, _) = para.emit(ctx, builder)?;
+            if value.is_none() || value_pltype.is_none() {
+                return Err(ctx.add_diag(pararange.new_err(ErrorCode::EXPECT_VALUE)));
+            }
+            let value_pltype = get_type_deep(value_pltype.unwrap());
+            if !ctx.eq(closure.arg_types[i].clone(), value_pltype).eq {
+                return Err(ctx.add_diag(pararange.new_err(ErrorCode::PARAMETER_TYPE_NOT_MATCH)));
+            }
+            para_values.push(ctx.try_load2var(pararange, value.unwrap(), builder)?);
+        }
+        let f = builder.build_struct_gep(closure_v, 0, "closure_f").unwrap();
+        let f = builder.build_load(f, "closure_fnptr");
+        let env = builder
+            .build_struct_gep(closure_v, 1, "closure_env")
+            .unwrap();
+        let env = builder.build_load(env, "closure_env");
+        para_values.insert(0, env);
+        if let Some(function) = ctx.function {
+            builder.try_set_fn_dbg(self.range.start, function);
+        }
+        let ret = builder.build_call(f, &para_values, &closure.ret_type.borrow(), ctx);
+        Ok((
+            ret.map(|v| plv!(v)),
+            Some(closure.ret_type),
+            TerminatorEnum::NONE,
+        ))
+    }
+}
+
 #[node]
 pub struct FuncDefNode {
     pub id: Box<VarNode>,
@@ -225,6 +330,8 @@ pub struct FuncDefNode {
     pub modifier: Option<(TokenType, Range)>,
     pub generics_size: usize, // the size of generics except the generics from impl node
     pub trait_bounds: Option<Vec<Box<TraitBoundNode>>>,
+    /// 是否被`#[test]`标记为测试函数
+    pub is_test: bool,
 }
 
 impl TypeNode for FuncDefNode {
@@ -240,10 +347,13 @@ impl TypeNode for FuncDefNode {
             .map_or(IndexMap::default(), |generics| generics.gen_generic_type());
         if let Some(trait_bounds) = &self.trait_bounds {
             for trait_bound in trait_bounds.iter() {
-                trait_bound.set_traits(child, builder, &generic_map)?;
+                if let Err(e) = trait_bound.set_traits(child, builder, &generic_map) {
+                    builder.end_subscope();
+                    return Err(e);
+                }
             }
         }
-        let (pltype, flater) = child.protect_generic_context(&generic_map, |child| {
+        let re = child.protect_generic_context(&generic_map, |child| {
             let mut flater = None;
             let mut param_pltypes = Vec::new();
             let mut param_name = Vec::new();
@@ -314,7 +424,9 @@ impl TypeNode for FuncDefNode {
                 };
             }
             Ok((pltype, flater))
-        })?;
+        });
+        builder.end_subscope();
+        let (pltype, flater) = re?;
         if let Some(flater) = flater {
             flater(ctx);
         }
@@ -366,6 +478,14 @@ impl FuncDefNode {
         if let Ok(_) = ctx.get_type(self.id.name.as_str(), self.id.range) {
             return Err(ctx.add_diag(self.range.new_err(ErrorCode::REDEFINE_SYMBOL)));
         }
+        if self.is_test
+            && (!self.paralist.is_empty()
+                || self.generics.is_some()
+                || self.declare
+                || !matches!(&*self.ret.get_type(ctx, builder)?.borrow(), PLType::VOID))
+        {
+            return Err(ctx.add_diag(self.range.new_err(ErrorCode::INVALID_TEST_FUNCTION)));
+        }
         let pltype = self.get_type(ctx, builder)?;
         ctx.add_type(self.id.name.clone(), pltype, self.id.range)?;
         Ok(())
@@ -378,7 +498,7 @@ impl FuncDefNode {
         fnvalue: FNValue,
     ) -> Result<(), PLDiag> {
         let child = &mut ctx.new_child(self.range.start, builder);
-        return child.protect_generic_context(&fnvalue.fntype.generic_map, |child| {
+        let re = child.protect_generic_context(&fnvalue.fntype.generic_map, |child| {
             if first && fnvalue.fntype.generic {
                 fnvalue.fntype.generic_map.iter().for_each(|(_, pltype)| {
                     match &mut *pltype.borrow_mut() {
@@ -484,6 +604,8 @@ impl FuncDefNode {
             builder.build_unconditional_branch(entry);
             return Ok(());
         });
+        builder.end_subscope();
+        re
     }
 }
 
@@ -545,8 +667,282 @@ impl Node for FuncDefNode {
                 }
                 _ => return Ok((None, None, TerminatorEnum::NONE)),
             };
+            ctx.push_closing_brace_hint(
+                self.range,
+                format!("fn {}", self.id.name.split("::").last().unwrap()),
+            );
             self.gen_fntype(ctx, true, builder, fntype)?;
         }
         Ok((None, Some(pltype), TerminatorEnum::NONE))
     }
 }
+
+/// 匿名函数，可以捕获外部的局部变量
+///
+/// 默认按引用捕获，使用`move`修饰时按值捕获
+#[node]
+pub struct ClosureNode {
+    pub paralist: Vec<Box<TypedIdentifierNode>>,
+    pub ret: Option<Box<TypeNodeEnum>>,
+    pub body: StatementsNode,
+    pub is_move: bool,
+}
+
+impl PrintTrait for ClosureNode {
+    fn print(&self, tabs: usize, end: bool, mut line: Vec<bool>) {
+        deal_line(tabs, &mut line, end);
+        tab(tabs, line.clone(), end);
+        println!("ClosureNode");
+        tab(tabs + 1, line.clone(), false);
+        println!("move: {}", self.is_move);
+        for p in self.paralist.iter() {
+            p.print(tabs + 1, false, line.clone());
+        }
+        if let Some(ret) = &self.ret {
+            ret.print(tabs + 1, false, line.clone());
+        }
+        self.body.print(tabs + 1, true, line.clone());
+    }
+}
+
+impl Node for ClosureNode {
+    fn emit<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> NodeResult {
+        for para in self.paralist.iter() {
+            ctx.push_semantic_token(para.id.range, SemanticTokenType::PARAMETER, 0);
+            para.typenode.emit_highlight(ctx);
+        }
+        if let Some(ret) = &self.ret {
+            ret.emit_highlight(ctx);
+        }
+        let ret = self
+            .ret
+            .clone()
+            .unwrap_or_else(|| Box::new(TypeNameNode::new_from_str("void").into()));
+        let mut arg_types = vec![];
+        for para in self.paralist.iter() {
+            let tp = get_type_deep(para.typenode.get_type(ctx, builder)?);
+            if let PLType::VOID = &*tp.borrow() {
+                return Err(
+                    ctx.add_diag(para.range.new_err(ErrorCode::VOID_TYPE_CANNOT_BE_PARAMETER))
+                );
+            }
+            ctx.set_if_refs_tp(tp.clone(), para.typenode.range());
+            arg_types.push(tp);
+        }
+        let closure_tp = ClosureType {
+            arg_types,
+            ret_type: get_type_deep(ret.get_type(ctx, builder)?),
+            range: self.range,
+        };
+        // 闭包函数的第一个参数是捕获环境
+        let mut paralist = vec![Box::new(TypedIdentifierNode {
+            id: VarNode {
+                name: "__env".to_string(),
+                range: Default::default(),
+            },
+            typenode: Box::new(TypeNodeEnum::PointerTypeNode(PointerTypeNode {
+                elm: Box::new(TypeNameNode::new_from_str("u8").into()),
+                range: Default::default(),
+            })),
+            doc: None,
+            range: Default::default(),
+        })];
+        paralist.extend(self.paralist.iter().cloned());
+        let name = format!(
+            "__closure_{}_{}",
+            self.range.start.line,
+            CLOSURE_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let fnvalue = FNValue {
+            name: name.clone(),
+            param_names: paralist.iter().map(|p| p.id.name.clone()).collect(),
+            range: self.range,
+            doc: vec![],
+            llvmname: ctx.plmod.get_full_name(&name),
+            path: ctx.plmod.path.clone(),
+            fntype: FNType {
+                ret_pltype: ret,
+                param_pltypes: paralist.iter().map(|p| p.typenode.clone()).collect(),
+                method: false,
+                generic_map: IndexMap::default(),
+                generic: false,
+                modifier: None,
+                generics_size: 0,
+            },
+            generic_infer: Arc::new(RefCell::new(IndexMap::default())),
+            node: None,
+        };
+        let block = ctx.block;
+        let re = self.emit_closure_fn(ctx, builder, paralist, &fnvalue, &closure_tp);
+        if let Some(block) = block {
+            ctx.position_at_end(block, builder);
+        }
+        if let Some(function) = ctx.function {
+            builder.try_set_fn_dbg(self.range.start, function);
+        }
+        // 回到闭包外的作用域
+        builder.end_subscope();
+        let (f, captures) = re?;
+
+        // 构造捕获环境，每个字段都是指向被捕获变量的指针
+        let u8ptr = Arc::new(RefCell::new(PLType::POINTER(Arc::new(RefCell::new(
+            PLType::PRIMITIVE(PriType::U8),
+        )))));
+        let vtable_field = Field {
+            index: 0,
+            typenode: Box::new(TypeNameNode::new_from_str("u64").into()),
+            name: "_vtable".to_string(),
+            range: Default::default(),
+            modifier: None,
+        };
+        let mut fields = FxHashMap::<String, Field>::default();
+        fields.insert(vtable_field.name.clone(), vtable_field.clone());
+        let mut order_fields = vec![vtable_field];
+        for (i, captured) in captures.keys().enumerate() {
+            let field = Field {
+                index: i as u32 + 1,
+                typenode: u8ptr.borrow().get_typenode(ctx),
+                name: captured.clone(),
+                range: Default::default(),
+                modifier: None,
+            };
+            fields.insert(field.name.clone(), field.clone());
+            order_fields.push(field);
+        }
+        let env_st = STType {
+            name: format!("{}__env", name),
+            path: ctx.plmod.path.clone(),
+            fields,
+            ordered_fields: order_fields,
+            range: self.range,
+            doc: vec![],
+            generic_map: IndexMap::default(),
+            derives: vec![],
+            modifier: None,
+        };
+        builder.gen_st_visit_function(ctx, &env_st, &vec![u8ptr.clone(); captures.len()]);
+        let env = builder.alloc("closure_env", &PLType::STRUCT(env_st), ctx, None);
+        for (i, (_, origin, tp)) in captures.values().enumerate() {
+            let mut v = *origin;
+            if self.is_move {
+                // 按值捕获，复制一份
+                let copied = builder.alloc("captured", &tp.borrow(), ctx, None);
+                let value = builder.build_load(v, "captured_value");
+                builder.build_store(copied, value);
+                v = copied;
+            }
+            let casted = builder.bitcast(ctx, v, &u8ptr.borrow(), "captured_casted");
+            let field = builder
+                .build_struct_gep(env, i as u32 + 1, "env_field")
+                .unwrap();
+            builder.build_store(field, casted);
+        }
+        let closure_pltype = Arc::new(RefCell::new(PLType::CLOSURE(closure_tp)));
+        let closure_v = builder.alloc("closure", &closure_pltype.borrow(), ctx, None);
+        let f_field = builder.build_struct_gep(closure_v, 0, "closure_f").unwrap();
+        let fnptr = builder.bitcast(ctx, f, &PLType::FN(fnvalue), "closure_fnptr");
+        builder.build_store(f_field, fnptr);
+        let env_field = builder
+            .build_struct_gep(closure_v, 1, "closure_env")
+            .unwrap();
+        let env = builder.bitcast(ctx, env, &u8ptr.borrow(), "env_casted");
+        builder.build_store(env_field, env);
+        Ok((
+            Some(plv!(closure_v)),
+            Some(closure_pltype),
+            TerminatorEnum::NONE,
+        ))
+    }
+}
+
+impl ClosureNode {
+    /// 生成闭包对应的函数，返回函数和捕获的变量
+    ///
+    /// 这里创建的子ctx对应的调试信息作用域由调用者在恢复外层函数的调试位置后结束，
+    /// 出错时也一样
+    #[allow(clippy::type_complexity)]
+    fn emit_closure_fn<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+        paralist: Vec<Box<TypedIdentifierNode>>,
+        fnvalue: &FNValue,
+        closure_tp: &ClosureType,
+    ) -> Result<
+        (
+            ValueHandle,
+            IndexMap<String, (ValueHandle, ValueHandle, Arc<RefCell<PLType>>)>,
+        ),
+        PLDiag,
+    > {
+        let child = &mut ctx.new_child(self.range.start, builder);
+        let funcvalue = builder.get_or_insert_fn_handle(fnvalue, child);
+        child.function = Some(funcvalue);
+        // 闭包内不能break或continue到外部的循环
+        child.break_block = None;
+        child.continue_block = None;
+        builder.build_sub_program(
+            paralist,
+            fnvalue.fntype.ret_pltype.clone(),
+            fnvalue,
+            funcvalue,
+            child,
+        )?;
+        let allocab = builder.append_basic_block(funcvalue, "alloc");
+        let entry = builder.append_basic_block(funcvalue, "entry");
+        let return_block = builder.append_basic_block(funcvalue, "return");
+        child.position_at_end(entry, builder);
+        let ret_value_ptr = match &*closure_tp.ret_type.borrow() {
+            PLType::VOID => None,
+            other => {
+                builder.rm_curr_debug_location();
+                let retv = builder.alloc("retvalue", other, child, None);
+                // 返回值不能在函数结束时从root表移除
+                child.roots.borrow_mut().pop();
+                Some(retv)
+            }
+        };
+        child.position_at_end(return_block, builder);
+        child.return_block = Some((return_block, ret_value_ptr));
+        if let Some(ptr) = ret_value_ptr {
+            let value = builder.build_load(ptr, "load_ret_tmp");
+            builder.build_return(Some(value));
+        } else {
+            builder.build_return(None);
+        };
+        child.position_at_end(entry, builder);
+        child.closure_data = Some(RefCell::new(ClosureCtxData {
+            env: builder.get_nth_param(funcvalue, 0),
+            alloca_bb: allocab,
+            table: IndexMap::default(),
+        }));
+        for (i, para) in self.paralist.iter().enumerate() {
+            let tp = closure_tp.arg_types[i].clone();
+            let alloca = builder.alloc(&para.id.name, &tp.borrow(), child, None);
+            builder.create_parameter_variable(
+                fnvalue,
+                para.range.start,
+                i + 1,
+                child,
+                funcvalue,
+                alloca,
+                allocab,
+            );
+            child.add_symbol(para.id.name.clone(), alloca, tp, para.id.range, false)?;
+        }
+        builder.rm_curr_debug_location();
+        child.rettp = Some(closure_tp.ret_type.clone());
+        let (_, _, terminator) = self.body.emit(child, builder)?;
+        if !terminator.is_return() {
+            return Err(child.add_diag(self.range.new_err(ErrorCode::FUNCTION_MUST_HAVE_RETURN)));
+        }
+        child.position_at_end(allocab, builder);
+        builder.build_unconditional_branch(entry);
+        let captures = child.closure_data.take().unwrap().into_inner().table;
+        Ok((funcvalue, captures))
+    }
+}
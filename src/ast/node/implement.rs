@@ -1,6 +1,6 @@
 use super::*;
 use crate::{
-    ast::{ctx::Ctx, tokens::TokenType},
+    ast::{ctx::Ctx, diag::QuickFix, plmod::ImplRef, tokens::TokenType},
     format_label,
 };
 use internal_macro::node;
@@ -159,6 +159,20 @@ impl Node for ImplNode {
         }
         for f in traitfns {
             let (tp, r) = traittpandrange.clone().unwrap();
+            let mut fix = QuickFix::new(format!("implement missing method {}", f));
+            if let PLType::TRAIT(st) = &*tp.borrow() {
+                if let TypeNodeEnum::FuncTypeNode(fd) = &*st.fields.get(&f).unwrap().typenode {
+                    fix = fix.insert(
+                        &ctx.get_file(),
+                        Pos {
+                            line: self.range.end.line,
+                            column: self.range.end.column - 1,
+                            offset: self.range.end.offset - 1,
+                        },
+                        &format!("    {} {{\n    }}\n", method_stub_sig(fd)),
+                    );
+                }
+            }
             r.new_err(ErrorCode::METHOD_NOT_IN_IMPL)
                 .add_label(
                     r,
@@ -175,12 +189,31 @@ impl Node for ImplNode {
                     format_label!("trait {} def here", tp.borrow().get_name()),
                 )
                 .add_help("add the method to current impl block")
+                .add_fix(fix)
                 .add_to_ctx(ctx);
         }
+        if let Some((tp, _)) = &traittpandrange {
+            if let PLType::TRAIT(st) = &*tp.borrow() {
+                ctx.add_impl_ref(ImplRef {
+                    trait_name: st.get_st_full_name(),
+                    range: self.range,
+                    methods: self
+                        .methods
+                        .iter()
+                        .map(|m| {
+                            let name = m.id.name.split("::").last().unwrap().to_string();
+                            (name, m.id.range)
+                        })
+                        .collect(),
+                });
+            }
+        }
         ctx.emit_comment_highlight(&self.comments[0]);
+        let name = format!("impl {}", FmtBuilder::generate_node(&self.target));
+        ctx.push_closing_brace_hint(self.range, name.clone());
         #[allow(deprecated)]
         let docsymbol = DocumentSymbol {
-            name: format!("impl {}", FmtBuilder::generate_node(&self.target)),
+            name,
             detail: None,
             kind: SymbolKind::OBJECT,
             tags: None,
@@ -193,3 +226,16 @@ impl Node for ImplNode {
         Ok((None, None, TerminatorEnum::NONE))
     }
 }
+
+/// 生成trait方法在impl块中的签名（去掉隐式的`self`参数）
+fn method_stub_sig(fd: &FuncDefNode) -> String {
+    let mut stub = fd.clone();
+    if !stub.paralist.is_empty() {
+        stub.paralist.remove(0);
+    }
+    stub.body = None;
+    stub.precom = vec![];
+    stub.is_test = false;
+    let sig = FmtBuilder::generate_node(&Box::new(TypeNodeEnum::FuncTypeNode(stub)));
+    sig.trim().trim_end_matches(';').to_string()
+}
@@ -1,22 +1,67 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
 use internal_macro::node;
+use lsp_types::{HoverContents, LanguageString, MarkedString};
+use nom::branch::alt;
+use nom::combinator::map;
 use nom::multi::many0;
 use nom::IResult;
+use rustc_hash::FxHashMap;
 
 use super::*;
 use super::{primary::VarNode, NodeEnum};
-use crate::ast::ctx::MacroReplaceNode;
+use crate::ast::ctx::{MacroEnv, MacroFragment, MacroReplaceNode};
+use crate::ast::fmt::FmtBuilder;
+use crate::ast::pltype::expect_pub_err;
 use crate::ast::{range::Range, tokens::TokenType};
 use crate::nomparser::helper::tag_token_symbol_ex;
 use crate::nomparser::identifier::identifier;
-use crate::nomparser::{expression, string_literal, Span};
-use crate::{del_newline_or_space, format_label};
+use crate::nomparser::macro_parse::token_tree;
+use crate::nomparser::{constval, expression, statement, string_literal, types, Span};
+use crate::{del_newline_or_space, format_label, if_not_modified_by};
 use nom::bytes::complete::tag;
 
+/// 宏展开的最大嵌套深度，超过时认为宏无限递归
+pub const MACRO_RECURSION_LIMIT: usize = 32;
+
+/// 每次宏展开使用不同的卫生标记
+static MACRO_MARK: AtomicUsize = AtomicUsize::new(0);
+
+type MacroVars = FxHashMap<String, MacroReplaceNode>;
+
 #[node]
 pub struct MacroNode {
     pub id: VarNode,
     pub rules: Vec<MacroRuleNode>,
     pub file: String,
+    pub modifier: Option<(TokenType, Range)>,
+}
+
+impl MacroNode {
+    /// 其他模块中只能使用`pub`的宏
+    pub fn expect_pub(&self, ctx: &Ctx, range: Range) -> Result<(), PLDiag> {
+        // 宏展开中调用定义在同一个模块中的宏
+        let in_def_mod = ctx
+            .macro_env
+            .as_ref()
+            .map_or(false, |env| env.module == self.file);
+        if self.file == ctx.plmod.path || in_def_mod {
+            return Ok(());
+        }
+        if_not_modified_by!(
+            self.modifier,
+            TokenType::PUB,
+            return expect_pub_err(
+                ErrorCode::EXPECT_PUBLIC_MACRO,
+                ctx,
+                range,
+                self.id.name.clone(),
+                (&self.file, self.range.start),
+            )
+        );
+        Ok(())
+    }
 }
 
 impl PrintTrait for MacroNode {
@@ -56,11 +101,13 @@ pub enum MacroMatchExp {
 impl MacroMatchExp {
     pub fn parse<'a, 'b: 'a>(
         &'b self,
-        ctx: &mut Ctx,
+        ctx: &Ctx,
         args: Span<'a>,
+        vars: &mut MacroVars,
+        in_loop: bool,
     ) -> IResult<Span<'a>, (), PLDiag> {
         match self {
-            MacroMatchExp::Parameter(p) => p.parse(ctx, args),
+            MacroMatchExp::Parameter(p) => p.parse(args, vars, in_loop),
             MacroMatchExp::RawTokens((t, r)) => {
                 let re: (Span, Span) =
                     del_newline_or_space!(tag(t.as_str()))(args).map_err(|_: nom::Err<()>| {
@@ -80,23 +127,61 @@ impl MacroMatchExp {
                 let (mut new, _) = tag_token_symbol_ex(TokenType::LPAREN)(args)
                     .map_err(|_| nom::Err::Error(r.new_err(ErrorCode::UNEXPECTED_TOKEN)))?;
                 for t in ts {
-                    (new, _) = t.parse(ctx, new)?;
+                    (new, _) = t.parse(ctx, new, vars, in_loop)?;
                 }
-                let (new, _) = tag_token_symbol_ex(TokenType::RPAREN)(args)
+                let (new, _) = tag_token_symbol_ex(TokenType::RPAREN)(new)
                     .map_err(|_| nom::Err::Error(r.new_err(ErrorCode::UNEXPECTED_TOKEN)))?;
                 Ok((new, ()))
             }
-            MacroMatchExp::Looper((ts, _r, _m)) => {
+            MacroMatchExp::Looper((ts, r, looper)) => {
+                // 一次也没有匹配到的参数在展开时循环0次
+                for name in self.params() {
+                    vars.entry(name)
+                        .or_insert_with(|| MacroReplaceNode::Loop(vec![]));
+                }
                 let mut new = args;
-                loop {
-                    for t in ts {
-                        (new, _) = ctx.with_macro_loop_parse(|ctx| t.parse(ctx, new))?;
+                let mut times = 0;
+                let mut last_err = None;
+                while !new.fragment().trim().is_empty() {
+                    // 匹配失败时回退到这次循环之前，剩下的参数交给后面的规则
+                    let mut try_vars = vars.clone();
+                    let mut cur = new;
+                    let re = ts.iter().try_for_each(|t| {
+                        cur = t.parse(ctx, cur, &mut try_vars, true)?.0;
+                        Ok::<_, nom::Err<PLDiag>>(())
+                    });
+                    if let Err(e) = re {
+                        last_err = Some(e);
+                        break;
                     }
-                    if new.len() == 0 {
+                    if cur.location_offset() == new.location_offset() {
                         break;
                     }
+                    *vars = try_vars;
+                    new = cur;
+                    times += 1;
+                }
+                if times == 0 && *looper == TokenType::PLUS {
+                    return Err(last_err.unwrap_or_else(|| {
+                        nom::Err::Error(
+                            r.new_err(ErrorCode::UNEXPECTED_TOKEN)
+                                .add_help("`$(...)+` should match at least once")
+                                .clone(),
+                        )
+                    }));
                 }
-                return Ok((new, ()));
+                Ok((new, ()))
+            }
+        }
+    }
+
+    /// 匹配表达式中声明的参数名
+    fn params(&self) -> Vec<String> {
+        match self {
+            MacroMatchExp::Parameter(p) => vec![p.id.name.clone()],
+            MacroMatchExp::RawTokens(_) => vec![],
+            MacroMatchExp::Parantheses((ts, _)) | MacroMatchExp::Looper((ts, _, _)) => {
+                ts.iter().flat_map(|t| t.params()).collect()
             }
         }
     }
@@ -110,76 +195,99 @@ pub struct MacroMatchParameter {
 }
 
 impl MacroMatchParameter {
-    pub fn parse<'a, 'b: 'a>(
-        &'b self,
-        ctx: &mut Ctx,
+    pub fn parse<'a>(
+        &self,
         args: Span<'a>,
+        vars: &mut MacroVars,
+        in_loop: bool,
     ) -> IResult<Span<'a>, (), PLDiag> {
-        match self.tp.0 {
+        let re = match self.tp.0 {
             TokenType::MACRO_TYPE_ID => {
-                let (new, node) = identifier(args).map_err(|_| {
-                    nom::Err::Error(self.range.new_err(ErrorCode::EXPECT_IDENTIFIER))
-                })?;
-                self.add_to_macro_var(ctx, NodeEnum::Var(*node));
-                Ok((new, ()))
-            }
-            TokenType::MACRO_TYPE_STR => {
-                let (new, node) = del_newline_or_space!(string_literal::string_literal)(args)
-                    .map_err(|_| nom::Err::Error(self.range.new_err(ErrorCode::EXPECT_STRING)))?;
-                self.add_to_macro_var(ctx, *node);
-                Ok((new, ()))
+                map(identifier, |id| MacroFragment::Node(NodeEnum::Var(*id)))(args)
             }
+            TokenType::MACRO_TYPE_STR => map(
+                del_newline_or_space!(string_literal::string_literal),
+                |node| MacroFragment::Node(*node),
+            )(args),
             TokenType::MACRO_TYPE_EXPR => {
-                let (new, node) = expression::logic_exp(args).map_err(|_| {
-                    nom::Err::Error(self.range.new_err(ErrorCode::EXPECT_EXPRESSION))
-                })?;
-                self.add_to_macro_var(ctx, *node);
-                Ok((new, ()))
+                map(expression::logic_exp, |node| MacroFragment::Node(*node))(args)
             }
             TokenType::MACRO_TYPE_STMT => {
-                let (new, node) = del_newline_or_space!(crate::nomparser::statement::statement)(
-                    args,
-                )
-                .map_err(|_| nom::Err::Error(self.range.new_err(ErrorCode::EXPECT_STATEMENT)))?;
-                self.add_to_macro_var(ctx, *node);
-                Ok((new, ()))
-            }
-            TokenType::MACRO_TYPE_STMTS => {
-                let (new, nodes) = many0(del_newline_or_space!(
-                    crate::nomparser::statement::statement
-                ))(args)
-                .map_err(|_| nom::Err::Error(self.range.new_err(ErrorCode::EXPECT_STATEMENTS)))?;
-                let sts = StatementsNode {
-                    statements: nodes,
-                    range: Default::default(),
-                };
-                self.add_to_macro_var(ctx, sts.into());
-                Ok((new, ()))
+                map(del_newline_or_space!(statement::statement), |node| {
+                    MacroFragment::Node(*node)
+                })(args)
             }
+            TokenType::MACRO_TYPE_STMTS => map(
+                many0(del_newline_or_space!(statement::statement)),
+                |statements| {
+                    MacroFragment::Node(
+                        StatementsNode {
+                            statements,
+                            range: Default::default(),
+                        }
+                        .into(),
+                    )
+                },
+            )(args),
+            TokenType::MACRO_TYPE_TY => map(types::type_name, |tp| MacroFragment::Type(*tp))(args),
+            TokenType::MACRO_TYPE_BLOCK => map(
+                del_newline_or_space!(statement::statement_block),
+                MacroFragment::Block,
+            )(args),
+            TokenType::MACRO_TYPE_LIT => map(
+                del_newline_or_space!(alt((
+                    constval::number,
+                    constval::bool_const,
+                    string_literal::string_literal,
+                ))),
+                |node| MacroFragment::Node(*node),
+            )(args),
+            TokenType::MACRO_TYPE_TT => map(token_tree, |tt| {
+                MacroFragment::Tokens(tt.to_string(), Pos::from_span(&tt))
+            })(args),
+            _ => unreachable!(),
+        };
+        let (new, fragment) = match re {
+            Ok(re) => re,
+            // 在宏中调用宏时，外层宏的参数`$name`原样传入，生成时在外层宏的环境中替换
+            Err(_) => match identifier(args) {
+                Ok((new, id)) if id.name.starts_with('$') => {
+                    (new, MacroFragment::Node(NodeEnum::Var(*id)))
+                }
+                _ => return Err(nom::Err::Error(self.range.new_err(self.expect_err()))),
+            },
+        };
+        self.add_to_macro_var(vars, in_loop, fragment);
+        Ok((new, ()))
+    }
+
+    fn expect_err(&self) -> ErrorCode {
+        match self.tp.0 {
+            TokenType::MACRO_TYPE_ID => ErrorCode::EXPECT_IDENTIFIER,
+            TokenType::MACRO_TYPE_STR => ErrorCode::EXPECT_STRING,
+            TokenType::MACRO_TYPE_EXPR => ErrorCode::EXPECT_EXPRESSION,
+            TokenType::MACRO_TYPE_STMT => ErrorCode::EXPECT_STATEMENT,
+            TokenType::MACRO_TYPE_STMTS => ErrorCode::EXPECT_STATEMENTS,
+            TokenType::MACRO_TYPE_TY => ErrorCode::EXPECT_TYPE,
+            TokenType::MACRO_TYPE_BLOCK => ErrorCode::EXPECT_BLOCK,
+            TokenType::MACRO_TYPE_LIT => ErrorCode::EXPECT_LITERAL,
+            TokenType::MACRO_TYPE_TT => ErrorCode::EXPECT_TOKEN_TREE,
             _ => unreachable!(),
         }
     }
 
-    fn add_to_macro_var(&self, ctx: &mut Ctx, node: NodeEnum) {
-        if ctx.macro_loop {
-            let mut default = MacroReplaceNode::LoopNodeEnum(vec![]);
-            let n = ctx
-                .macro_vars
-                .get_mut(&self.id.name)
-                .unwrap_or(&mut default);
-            match n {
-                MacroReplaceNode::LoopNodeEnum(v) => {
-                    v.push(node);
-                    if v.len() == 1 {
-                        ctx.macro_vars.insert(self.id.name.clone(), default);
-                    }
-                }
-                _ => unreachable!(),
-            }
+    fn add_to_macro_var(&self, vars: &mut MacroVars, in_loop: bool, fragment: MacroFragment) {
+        if !in_loop {
+            vars.insert(self.id.name.clone(), MacroReplaceNode::Single(fragment));
             return;
         }
-        ctx.macro_vars
-            .insert(self.id.name.clone(), MacroReplaceNode::NodeEnum(node));
+        let var = vars
+            .entry(self.id.name.clone())
+            .or_insert_with(|| MacroReplaceNode::Loop(vec![]));
+        match var {
+            MacroReplaceNode::Loop(fragments) => fragments.push(fragment),
+            MacroReplaceNode::Single(_) => *var = MacroReplaceNode::Loop(vec![fragment]),
+        }
     }
 }
 
@@ -189,9 +297,35 @@ pub struct MacroRuleNode {
     pub body: NodeEnum,
 }
 
+impl MacroRuleNode {
+    /// 用这条规则匹配宏调用的参数，成功时返回每个参数匹配到的片段
+    fn match_args(&self, ctx: &Ctx, args: Span) -> Result<MacroVars, PLDiag> {
+        let mut vars = MacroVars::default();
+        let mut input = args;
+        for e in &self.match_exp {
+            input = match e.parse(ctx, input, &mut vars, false) {
+                Ok((new, _)) => new,
+                Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => return Err(e),
+                Err(nom::Err::Incomplete(_)) => unreachable!(),
+            };
+        }
+        let rest = input.fragment().trim();
+        if !rest.is_empty() {
+            return Err(self
+                .range
+                .new_err(ErrorCode::UNEXPECTED_TOKEN)
+                .add_help(&format!("unexpected `{}` after the macro arguments", rest))
+                .clone());
+        }
+        Ok(vars)
+    }
+}
+
 #[node]
 pub struct MacroLoopStatementNode {
     pub statements: Box<NodeEnum>,
+    /// 循环中用到的宏参数，决定循环的次数
+    pub vars: Vec<String>,
 }
 
 impl PrintTrait for MacroLoopStatementNode {
@@ -209,7 +343,152 @@ impl Node for MacroLoopStatementNode {
         ctx: &'b mut Ctx<'a>,
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> NodeResult {
-        ctx.with_macro_loop(|ctx| self.statements.emit(ctx, builder), self.range)
+        let len = ctx
+            .macro_loop_len(&self.vars, self.range)
+            .map_err(|e| e.add_to_ctx(ctx))?;
+        ctx.with_macro_loop(|ctx| self.statements.emit(ctx, builder), len)
+    }
+}
+
+/// 在调用宏的位置生成宏参数匹配到的片段
+pub fn emit_macro_fragment<'a, 'ctx, 'b>(
+    fragment: MacroFragment,
+    range: Range,
+    ctx: &'b mut Ctx<'a>,
+    builder: &'b BuilderEnum<'a, 'ctx>,
+) -> NodeResult {
+    match fragment {
+        MacroFragment::Node(mut node) => ctx.with_macro_caller(|ctx| node.emit(ctx, builder)),
+        MacroFragment::Block(mut block) => {
+            ctx.with_macro_caller(|ctx| block.emit_child(ctx, builder))
+        }
+        MacroFragment::Tokens(tokens, pos) => {
+            let mut node = parse_tokens(
+                &tokens,
+                pos,
+                expression::logic_exp,
+                ErrorCode::EXPECT_EXPRESSION,
+            )
+            .map_err(|e| ctx.add_diag(e))?;
+            ctx.with_macro_caller(|ctx| node.emit(ctx, builder))
+        }
+        MacroFragment::Type(_) => Err(range
+            .new_err(ErrorCode::EXPECT_EXPRESSION)
+            .add_help("a macro var matched by `@ty` can only be used as a type")
+            .add_to_ctx(ctx)),
+    }
+}
+
+/// 用`parser`解析`@tt`匹配到的代码，代码需要被完整解析
+pub fn parse_tokens<T>(
+    tokens: &str,
+    pos: Pos,
+    mut parser: impl FnMut(Span) -> IResult<Span, Box<T>>,
+    err: ErrorCode,
+) -> Result<Box<T>, PLDiag> {
+    let span = unsafe { Span::new_from_raw_offset(pos.offset, pos.line as u32, tokens, false) };
+    match parser(span) {
+        Ok((rest, node)) if rest.fragment().trim().is_empty() => Ok(node),
+        _ => {
+            let mut end = pos;
+            for c in tokens.chars() {
+                end.offset += c.len_utf8();
+                if c == '\n' {
+                    end.line += 1;
+                    end.column = 1;
+                } else {
+                    end.column += 1;
+                }
+            }
+            Err(pos.to(end).new_err(err))
+        }
+    }
+}
+
+/// 把代码中的宏参数`$name`替换为`f`返回的参数名，`f`返回None时不替换
+fn replace_macro_vars(code: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut chars = code.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        let mut end = i + 1;
+        while let Some(&(j, c)) = chars.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+            end = j + 1;
+            chars.next();
+        }
+        let name = &code[i + 1..end];
+        match Some(name).filter(|n| !n.is_empty()).and_then(&mut f) {
+            Some(new) => {
+                out.push('$');
+                out.push_str(&new);
+            }
+            None => out.push_str(&code[i..end]),
+        }
+    }
+    out
+}
+
+/// 代码中用到的宏参数名（不含`$`）
+pub fn macro_vars_in(code: &str) -> Vec<String> {
+    let mut vars: Vec<String> = vec![];
+    replace_macro_vars(code, |name| {
+        if !vars.iter().any(|v| v == name) {
+            vars.push(name.to_string());
+        }
+        None
+    });
+    vars
+}
+
+/// `code`以`(`之后的内容开始，返回与之匹配的`)`的位置
+fn find_rparen(code: &str) -> Option<usize> {
+    let mut depth = 1;
+    let mut in_str = false;
+    let mut escaped = false;
+    for (i, c) in code.char_indices() {
+        if in_str {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_str = false,
+                _ => (),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_str = true,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// `$(...)`之后的分隔符与`*`或`+`，返回分隔符与剩下的代码
+fn split_looper(code: &str) -> Option<(Option<char>, &str)> {
+    let code = code.trim_start();
+    let mut chars = code.chars();
+    match chars.next()? {
+        '*' | '+' => Some((None, &code[1..])),
+        sep => {
+            let rest = code[sep.len_utf8()..].trim_start();
+            match rest.chars().next()? {
+                '*' | '+' => Some((Some(sep), &rest[1..])),
+                _ => None,
+            }
+        }
     }
 }
 
@@ -220,6 +499,84 @@ pub struct MacroCallNode {
     pub inner_start: Pos,
 }
 
+impl MacroCallNode {
+    /// 在宏展开中调用宏时，展开参数中的`$(...)*`：循环中用到的参数按照下标换成新的参数名，
+    /// 返回展开后的参数，以及加入了这些参数的调用处环境
+    fn expand_args(&self, ctx: &Ctx) -> Result<(String, Option<Arc<MacroEnv>>), PLDiag> {
+        let env = match &ctx.macro_env {
+            Some(env) if self.args.contains("$(") => env,
+            env => return Ok((self.args.clone(), env.clone())),
+        };
+        let mut new_env = (**env).clone();
+        let mut out = String::new();
+        let mut rest = self.args.as_str();
+        while let Some(i) = rest.find("$(") {
+            out.push_str(&rest[..i]);
+            let body = &rest[i + 2..];
+            let looper =
+                find_rparen(body).and_then(|end| split_looper(&body[end + 1..]).map(|l| (end, l)));
+            let (end, (sep, tail)) = match looper {
+                Some(l) => l,
+                None => {
+                    out.push_str("$(");
+                    rest = body;
+                    continue;
+                }
+            };
+            let body = &body[..end];
+            let len = ctx.macro_loop_len(&macro_vars_in(body), self.range)?;
+            for idx in 0..len {
+                if idx > 0 {
+                    out.extend(sep);
+                }
+                out.push_str(&replace_macro_vars(body, |name| {
+                    if let Some(MacroReplaceNode::Loop(fragments)) = env.vars.get(name) {
+                        let new = format!("{}__{}", name, idx);
+                        new_env.vars.insert(
+                            new.clone(),
+                            MacroReplaceNode::Single(fragments[idx].clone()),
+                        );
+                        return Some(new);
+                    }
+                    None
+                }));
+            }
+            rest = tail;
+        }
+        out.push_str(rest);
+        Ok((out, Some(Arc::new(new_env))))
+    }
+}
+
+impl MacroCallNode {
+    /// 记录宏展开后的代码，不在宏展开中的调用还会在hover中显示
+    fn save_expansion(&self, ctx: &Ctx, body: &NodeEnum, env: Arc<MacroEnv>) {
+        let name = env.name.clone();
+        let code = FmtBuilder::expand_macro(body, env, ctx.plmod.macro_expansions.clone());
+        let code = code.trim().to_string();
+        match &ctx.macro_env {
+            Some(env) => {
+                env.expansions
+                    .borrow_mut()
+                    .insert((self.range, ctx.macro_loop), code);
+            }
+            None => {
+                ctx.save_if_hover(
+                    self.range,
+                    HoverContents::Scalar(MarkedString::LanguageString(LanguageString {
+                        language: "pivot-lang".to_string(),
+                        value: code.clone(),
+                    })),
+                );
+                ctx.plmod
+                    .macro_expansions
+                    .borrow_mut()
+                    .insert(self.range, (name, code));
+            }
+        }
+    }
+}
+
 impl PrintTrait for MacroCallNode {
     fn print(&self, tabs: usize, end: bool, mut line: Vec<bool>) {
         deal_line(tabs, &mut line, end);
@@ -235,76 +592,77 @@ impl Node for MacroCallNode {
         ctx: &'b mut Ctx<'a>,
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> NodeResult {
-        match &*self.callee {
-            NodeEnum::ExternIdNode(ex_node) => {
-                for ns in &ex_node.ns {
-                    ctx.push_semantic_token(ns.range(), SemanticTokenType::NAMESPACE, 0);
-                }
-                ctx.push_semantic_token(ex_node.id.range(), SemanticTokenType::MACRO, 0);
-                let m = ex_node.get_macro(ctx)?;
-                ctx.send_if_go_to_def(self.range, m.range, m.file.clone());
-                ctx.set_glob_refs(&format!("{}..{}", &m.file, &m.id.name), self.range);
-                let src = m.file.clone();
-                let mut span = unsafe {
-                    Span::new_from_raw_offset(
-                        self.inner_start.offset,
-                        self.inner_start.line as u32,
-                        &self.args,
-                        false,
-                    )
-                };
-                let mut last_err: Option<PLDiag> = None;
-                for rule in &m.rules {
-                    let mut next = false;
-                    for e in rule.match_exp.iter() {
-                        let re = e.parse(ctx, span).map_err(|e| {
-                            if let nom::Err::Error(e) = e {
-                                let mut e = e.clone();
-                                e.add_label(
-                                    self.range,
-                                    ctx.get_file(),
-                                    format_label!("the macro is called here"),
-                                );
-                                e
-                            } else {
-                                unreachable!()
-                            }
-                        });
-                        match re {
-                            Ok((new, _)) => {
-                                span = new;
-                            }
-                            Err(e) => {
-                                last_err = Some(e);
-                                next = true;
-                                continue;
-                            }
-                        }
-                    }
-                    if next {
-                        continue;
-                    }
-                    let mut b = rule.clone().body;
-                    let re = ctx
-                        .with_diag_src(&src, |ctx| ctx.with_macro_emit(|ctx| b.emit(ctx, builder)));
-                    match re {
-                        Ok(_) => {
-                            return Ok((None, None, TerminatorEnum::NONE));
-                        }
-                        Err(e) => {
-                            last_err = Some(e);
-                            continue;
-                        }
-                    }
-                }
-                if let Some(e) = last_err {
-                    ctx.with_diag_src(&src, |ctx| e.add_to_ctx(ctx));
-                    return Err(e);
-                }
-            }
+        let ex_node = match &*self.callee {
+            NodeEnum::ExternIdNode(ex_node) => ex_node,
             _ => unreachable!(),
+        };
+        for ns in &ex_node.ns {
+            ctx.push_semantic_token(ns.range(), SemanticTokenType::NAMESPACE, 0);
         }
-
-        Ok((None, None, TerminatorEnum::NONE))
+        ctx.push_semantic_token(ex_node.id.range(), SemanticTokenType::MACRO, 0);
+        let m = ex_node.get_macro(ctx)?;
+        ctx.send_if_go_to_def(self.range, m.range, m.file.clone());
+        ctx.set_glob_refs(&format!("{}..{}", &m.file, &m.id.name), self.range);
+        m.expect_pub(ctx, ex_node.range)?;
+        // 在宏展开中调用时，调用处位于定义外层宏的文件中
+        let file = ctx.temp_source.clone().unwrap_or_else(|| ctx.get_file());
+        let depth = ctx.macro_env.as_ref().map_or(0, |env| env.depth) + 1;
+        if depth > MACRO_RECURSION_LIMIT {
+            return Err(self
+                .range
+                .new_err(ErrorCode::MACRO_RECURSION_LIMIT)
+                .add_label(
+                    self.range,
+                    file,
+                    format_label!(
+                        "macro `{}` is expanded {} times",
+                        &m.id.name,
+                        depth.to_string()
+                    ),
+                )
+                .add_help("check whether the macro calls itself endlessly")
+                .add_to_ctx(ctx));
+        }
+        let (args, caller) = self.expand_args(ctx).map_err(|e| e.add_to_ctx(ctx))?;
+        let span = unsafe {
+            Span::new_from_raw_offset(
+                self.inner_start.offset,
+                self.inner_start.line as u32,
+                &args,
+                false,
+            )
+        };
+        let mut last_err = None;
+        for rule in &m.rules {
+            let vars = match rule.match_args(ctx, span) {
+                Ok(vars) => vars,
+                Err(e) => {
+                    last_err = Some(e);
+                    continue;
+                }
+            };
+            // 使用第一条匹配的规则展开
+            let env = Arc::new(MacroEnv {
+                vars,
+                mark: MACRO_MARK.fetch_add(1, Ordering::Relaxed),
+                depth,
+                module: m.file.clone(),
+                caller,
+                caller_src: ctx.temp_source.clone(),
+                name: m.id.name.clone(),
+                call_site: self.range,
+                expansions: Default::default(),
+            });
+            let mut body = rule.body.clone();
+            let re = ctx.with_macro_env(env.clone(), |ctx| body.emit(ctx, builder));
+            // 展开出错时也记录展开后的代码，方便查看错误
+            self.save_expansion(ctx, &body, env);
+            re?;
+            return Ok((None, None, TerminatorEnum::NONE));
+        }
+        let mut e = last_err.unwrap_or_else(|| m.range.new_err(ErrorCode::UNEXPECTED_TOKEN));
+        e.add_label(self.range, file, format_label!("the macro is called here"));
+        ctx.with_diag_src(&m.file, |ctx| ctx.add_diag(e.clone()));
+        Err(e)
     }
 }
@@ -12,6 +12,7 @@ use lsp_types::SemanticTokenType;
 
 use self::comment::CommentNode;
 use self::control::*;
+use self::enums::*;
 use self::error::*;
 use self::function::*;
 use self::global::*;
@@ -39,6 +40,7 @@ use super::range::{Pos, Range};
 
 pub mod comment;
 pub mod control;
+pub mod enums;
 pub mod error;
 pub mod function;
 pub mod global;
@@ -87,6 +89,7 @@ pub enum TypeNodeEnum {
     ArrayTypeNode(ArrayTypeNameNode),
     PointerTypeNode(PointerTypeNode),
     FuncTypeNode(FuncDefNode),
+    ClosureTypeNode(ClosureTypeNode),
 }
 #[enum_dispatch]
 pub trait TypeNode: RangeTrait + FmtTrait + PrintTrait {
@@ -139,6 +142,7 @@ pub enum NodeEnum {
     UseNode(UseNode),
     ExternIdNode(ExternIdNode),
     ArrayInitNode(ArrayInitNode),
+    DynArrayInitNode(DynArrayInitNode),
     ArrayElementNode(ArrayElementNode),
     PointerOpNode(PointerOpNode),
     ParanthesesNode(ParanthesesNode),
@@ -148,6 +152,10 @@ pub enum NodeEnum {
     MacroLoopStatementNode(MacroLoopStatementNode),
     MacroNode(MacroNode),
     MacroCallNode(MacroCallNode),
+    EnumDef(EnumDefNode),
+    Match(MatchNode),
+    Closure(ClosureNode),
+    As(AsNode),
 }
 // ANCHOR: range
 #[enum_dispatch]
@@ -328,7 +336,8 @@ impl<'a, 'ctx> Ctx<'a> {
                 ));
             }
         }
-        let (value, ty, term) = node.emit(self, builder)?;
+        let (value, ty, term) =
+            self.with_expect_ty(expect.clone(), |ctx| node.emit(ctx, builder))?;
         if let (Some(value), Some(ty)) = (&value, &ty) {
             if *ty != expect {
                 let handle = self.up_cast(
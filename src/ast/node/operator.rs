@@ -5,8 +5,10 @@ use super::*;
 
 use crate::ast::builder::BuilderEnum;
 use crate::ast::builder::IRBuilder;
+use crate::ast::builder::ValueHandle;
 use crate::ast::ctx::Ctx;
 use crate::ast::diag::ErrorCode;
+use crate::ast::fmt::FmtBuilder;
 use crate::ast::pltype::get_type_deep;
 use crate::ast::pltype::PLType;
 use crate::ast::pltype::PriType;
@@ -14,9 +16,15 @@ use crate::ast::tokens::TokenType;
 use crate::format_label;
 use crate::handle_calc;
 use crate::plv;
+use inkwell::FloatPredicate;
 use inkwell::IntPredicate;
 use internal_macro::node;
+use lsp_types::CompletionItem;
+use lsp_types::CompletionItemKind;
+use lsp_types::CompletionTextEdit;
+use lsp_types::InsertTextFormat;
 use lsp_types::SemanticTokenType;
+use lsp_types::TextEdit;
 #[node]
 pub struct UnaryOpNode {
     pub op: (TokenType, Range),
@@ -59,6 +67,11 @@ impl Node for UnaryOpNode {
                 Some(pltype.clone()),
                 TerminatorEnum::NONE,
             ),
+            (PLType::PRIMITIVE(p), TokenType::BIT_NOT) if p.is_int() => (
+                Some(plv!(builder.build_int_not(exp, "nottmp"))),
+                Some(pltype.clone()),
+                TerminatorEnum::NONE,
+            ),
             (PLType::PRIMITIVE(PriType::F64 | PriType::F32), TokenType::MINUS) => (
                 Some(plv!(builder.build_float_neg(exp, "negtmp"))),
                 Some(pltype.clone()),
@@ -190,77 +203,159 @@ impl Node for BinOpNode {
             return Err(ctx.add_diag(self.range.new_err(ErrorCode::EXPECT_VALUE)));
         }
         let right = ctx.try_load2var(rrange, rv.unwrap(), builder)?;
-        Ok(match self.op.0 {
-            TokenType::PLUS => {
-                handle_calc!(ctx, add, float_add, lpltype, left, right, self.range, builder)
-            }
-            TokenType::MINUS => {
-                handle_calc!(ctx, sub, float_sub, lpltype, left, right, self.range, builder)
-            }
-            TokenType::MUL => {
-                handle_calc!(ctx, mul, float_mul, lpltype, left, right, self.range, builder)
+        emit_bin_op(
+            ctx, builder, self.op, lpltype, left, right, self.range, lrange,
+        )
+    }
+}
+
+/// 对已经求值的左右操作数进行二元运算，复合赋值也会复用这个函数
+#[allow(clippy::too_many_arguments)]
+pub fn emit_bin_op<'a, 'ctx, 'b>(
+    ctx: &'b mut Ctx<'a>,
+    builder: &'b BuilderEnum<'a, 'ctx>,
+    op: (TokenType, Range),
+    lpltype: Option<Arc<RefCell<PLType>>>,
+    left: ValueHandle,
+    right: ValueHandle,
+    range: Range,
+    lrange: Range,
+) -> NodeResult {
+    let unsigned = matches!(
+        &*lpltype.clone().unwrap().borrow(),
+        PLType::PRIMITIVE(p) if p.is_int() && !p.is_signed_int()
+    );
+    Ok(match op.0 {
+        TokenType::PLUS => {
+            handle_calc!(ctx, add, float_add, lpltype, left, right, range, builder)
+        }
+        TokenType::MINUS => {
+            handle_calc!(ctx, sub, float_sub, lpltype, left, right, range, builder)
+        }
+        TokenType::MUL => {
+            handle_calc!(ctx, mul, float_mul, lpltype, left, right, range, builder)
+        }
+        TokenType::DIV => {
+            if unsigned {
+                handle_calc!(
+                    ctx,
+                    unsigned_div,
+                    float_div,
+                    lpltype,
+                    left,
+                    right,
+                    range,
+                    builder
+                )
             }
-            TokenType::DIV => {
-                // TODO: 无符号触发
-                handle_calc!(ctx, signed_div, float_div, lpltype, left, right, self.range, builder)
+            handle_calc!(ctx, signed_div, float_div, lpltype, left, right, range, builder)
+        }
+        TokenType::MOD => {
+            if unsigned {
+                handle_calc!(
+                    ctx,
+                    unsigned_rem,
+                    float_rem,
+                    lpltype,
+                    left,
+                    right,
+                    range,
+                    builder
+                )
             }
-            TokenType::EQ
-            | TokenType::NE
-            | TokenType::LEQ
-            | TokenType::GEQ
-            | TokenType::GREATER
-            | TokenType::LESS => match *lpltype.unwrap().borrow() {
-                PLType::PRIMITIVE(
-                    PriType::I128
-                    | PriType::I64
-                    | PriType::I32
-                    | PriType::I16
-                    | PriType::I8
-                    | PriType::U128
-                    | PriType::U64
-                    | PriType::U32
-                    | PriType::U16
-                    | PriType::U8,
-                ) => (
-                    {
-                        let bool_origin =
-                            builder.build_int_compare(self.op.0.get_op(), left, right, "cmptmp");
-                        // Some(plv!(builder.build_int_z_extend(
-                        //     bool_origin,
-                        //     &PriType::BOOL,
-                        //     "zexttemp"
-                        // )))
-                        Some(plv!(bool_origin))
-                    },
-                    Some(Arc::new(RefCell::new(PLType::PRIMITIVE(PriType::BOOL)))),
-                    TerminatorEnum::NONE,
-                ),
-                PLType::PRIMITIVE(PriType::F64 | PriType::F32) => (
-                    {
-                        let bool_origin =
-                            builder.build_float_compare(self.op.0.get_fop(), left, right, "cmptmp");
-                        // Some(plv!(builder.build_int_z_extend(
-                        //     bool_origin,
-                        //     &PriType::BOOL,
-                        //     "zexttemp"
-                        // )))
-                        Some(plv!(bool_origin))
-                    },
-                    Some(Arc::new(RefCell::new(PLType::PRIMITIVE(PriType::BOOL)))),
-                    TerminatorEnum::NONE,
-                ),
-                _ => return Err(ctx.add_diag(self.range.new_err(ErrorCode::VALUE_NOT_COMPARABLE))),
-            },
-            _ => {
-                return Err(ctx.add_diag(
-                    self.range
-                        .new_err(ErrorCode::UNRECOGNIZED_BIN_OPERATOR)
-                        .add_label(self.op.1, ctx.get_file(), None)
-                        .clone(),
-                ))
+            handle_calc!(ctx, signed_rem, float_rem, lpltype, left, right, range, builder)
+        }
+        TokenType::BIT_AND
+        | TokenType::BIT_OR
+        | TokenType::BIT_XOR
+        | TokenType::LEFT_SHIFT
+        | TokenType::RIGHT_SHIFT => {
+            // 位运算只能用于整数，与或异或额外允许bool
+            let valid = match &*lpltype.clone().unwrap().borrow() {
+                PLType::PRIMITIVE(PriType::BOOL) => {
+                    !matches!(op.0, TokenType::LEFT_SHIFT | TokenType::RIGHT_SHIFT)
+                }
+                PLType::PRIMITIVE(p) => p.is_int(),
+                _ => false,
+            };
+            if !valid {
+                return Err(ctx
+                    .add_diag(range.new_err(ErrorCode::BIT_OP_NOT_INT))
+                    .add_label(lrange, ctx.get_file(), format_label!("expect int here"))
+                    .clone());
             }
-        })
-    }
+            let v = match op.0 {
+                TokenType::BIT_AND => builder.build_and(left, right, "andtmp"),
+                TokenType::BIT_OR => builder.build_or(left, right, "ortmp"),
+                TokenType::BIT_XOR => builder.build_xor(left, right, "xortmp"),
+                TokenType::LEFT_SHIFT => builder.build_left_shift(left, right, "shltmp"),
+                TokenType::RIGHT_SHIFT => {
+                    builder.build_right_shift(left, right, !unsigned, "shrtmp")
+                }
+                _ => unreachable!(),
+            };
+            (Some(plv!(v)), lpltype, TerminatorEnum::NONE)
+        }
+        TokenType::EQ
+        | TokenType::NE
+        | TokenType::LEQ
+        | TokenType::GEQ
+        | TokenType::GREATER
+        | TokenType::LESS => match *lpltype.unwrap().borrow() {
+            PLType::PRIMITIVE(
+                PriType::I128
+                | PriType::I64
+                | PriType::I32
+                | PriType::I16
+                | PriType::I8
+                | PriType::U128
+                | PriType::U64
+                | PriType::U32
+                | PriType::U16
+                | PriType::U8,
+            ) => (
+                {
+                    let op = if unsigned {
+                        op.0.get_uop()
+                    } else {
+                        op.0.get_op()
+                    };
+                    let bool_origin = builder.build_int_compare(op, left, right, "cmptmp");
+                    // Some(plv!(builder.build_int_z_extend(
+                    //     bool_origin,
+                    //     &PriType::BOOL,
+                    //     "zexttemp"
+                    // )))
+                    Some(plv!(bool_origin))
+                },
+                Some(Arc::new(RefCell::new(PLType::PRIMITIVE(PriType::BOOL)))),
+                TerminatorEnum::NONE,
+            ),
+            PLType::PRIMITIVE(PriType::F64 | PriType::F32) => (
+                {
+                    let bool_origin =
+                        builder.build_float_compare(op.0.get_fop(), left, right, "cmptmp");
+                    // Some(plv!(builder.build_int_z_extend(
+                    //     bool_origin,
+                    //     &PriType::BOOL,
+                    //     "zexttemp"
+                    // )))
+                    Some(plv!(bool_origin))
+                },
+                Some(Arc::new(RefCell::new(PLType::PRIMITIVE(PriType::BOOL)))),
+                TerminatorEnum::NONE,
+            ),
+            _ => return Err(ctx.add_diag(range.new_err(ErrorCode::VALUE_NOT_COMPARABLE))),
+        },
+        _ => {
+            return Err(ctx.add_diag(
+                range
+                    .new_err(ErrorCode::UNRECOGNIZED_BIN_OPERATOR)
+                    .add_label(op.1, ctx.get_file(), None)
+                    .clone(),
+            ))
+        }
+    })
 }
 
 #[node(comment)]
@@ -282,6 +377,48 @@ impl PrintTrait for TakeOpNode {
     }
 }
 
+impl TakeOpNode {
+    /// 后缀模板，例如`a.let`补全为`let name = a;`
+    fn get_postfix_completions(&self, head_pltype: &PLType) -> Vec<CompletionItem> {
+        // 替换的范围必须在同一行中
+        if self.head.range().start.line != self.range.end.line {
+            return vec![];
+        }
+        let mut builder = FmtBuilder::new();
+        self.head.format(&mut builder);
+        let head = builder.generate();
+        let escaped = head
+            .replace('\\', "\\\\")
+            .replace('$', "\\$")
+            .replace('}', "\\}");
+        let mut templates = vec![
+            ("let", format!("let ${{1:name}} = {};$0", escaped)),
+            ("ref", format!("&{}", escaped)),
+            ("return", format!("return {};", escaped)),
+        ];
+        if let PLType::PRIMITIVE(PriType::BOOL) = head_pltype {
+            templates.push(("if", format!("if {} {{\n\t$0\n}}", escaped)));
+            templates.push(("while", format!("while {} {{\n\t$0\n}}", escaped)));
+            templates.push(("not", format!("!{}", escaped)));
+        }
+        templates
+            .into_iter()
+            .map(|(label, snippet)| CompletionItem {
+                label: label.to_string(),
+                kind: Some(CompletionItemKind::SNIPPET),
+                detail: Some("postfix".to_string()),
+                filter_text: Some(format!("{}.{}", head, label)),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    self.range.to_diag_range(),
+                    snippet,
+                ))),
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
 impl Node for TakeOpNode {
     fn emit<'a, 'ctx, 'b>(
         &mut self,
@@ -293,10 +430,27 @@ impl Node for TakeOpNode {
             return Err(ctx.add_diag(self.range.new_err(ErrorCode::INVALID_GET_FIELD)));
         }
         let head_pltype = get_type_deep(pltype.unwrap());
-        if !matches!(
+        let has_field = matches!(
             &*head_pltype.clone().borrow(),
             PLType::STRUCT(_) | PLType::POINTER(_) | PLType::TRAIT(_)
-        ) {
+        );
+        if self.field.is_none() {
+            // end with ".", gen completions
+            ctx.if_completion(self.range, || {
+                let mut comps = if has_field {
+                    match &*ctx.auto_deref_tp(head_pltype.clone()).clone().borrow() {
+                        PLType::STRUCT(s) => s.get_completions(ctx),
+                        PLType::TRAIT(s) => s.get_trait_completions(ctx),
+                        _ => vec![],
+                    }
+                } else {
+                    vec![]
+                };
+                comps.extend(self.get_postfix_completions(&head_pltype.borrow()));
+                comps
+            });
+        }
+        if !has_field {
             return Err(ctx.add_diag(
                 self.head
                     .range()
@@ -304,19 +458,19 @@ impl Node for TakeOpNode {
             ));
         }
         if self.field.is_none() {
-            // end with ".", gen completions
-            ctx.if_completion(self.range, || {
-                match &*ctx.auto_deref_tp(head_pltype).clone().borrow() {
-                    PLType::STRUCT(s) => s.get_completions(ctx),
-                    PLType::TRAIT(s) => s.get_trait_completions(ctx),
-                    _ => vec![],
-                }
-            });
             return Err(ctx.add_diag(self.range.new_err(crate::ast::diag::ErrorCode::COMPLETION)));
         }
         let id = self.field.as_ref().unwrap();
         let id_range = id.range();
-        let (head_pltype, headptr) = ctx.auto_deref(head_pltype, plvalue.unwrap().value, builder);
+        let head_range = self.head.range();
+        if let NodeEnum::FuncCall(call) = &*self.head {
+            if call.params_range.end.line < id_range.start.line {
+                // 链式调用换行了，在行尾显示前一段的类型
+                ctx.push_chaining_hint(call.params_range, head_pltype.clone());
+            }
+        }
+        let (head_pltype, headptr) =
+            ctx.auto_deref(head_pltype, plvalue.unwrap().value, head_range, builder);
         match &*head_pltype.clone().borrow() {
             PLType::TRAIT(s) => {
                 let field = s.fields.get(&id.name);
@@ -392,3 +546,140 @@ impl Node for TakeOpNode {
         }
     }
 }
+
+#[node]
+pub struct AsNode {
+    pub expr: Box<NodeEnum>,
+    pub ty: Box<TypeNodeEnum>,
+}
+
+impl PrintTrait for AsNode {
+    fn print(&self, tabs: usize, end: bool, mut line: Vec<bool>) {
+        deal_line(tabs, &mut line, end);
+        tab(tabs, line.clone(), end);
+        println!("AsNode");
+        self.expr.print(tabs + 1, false, line.clone());
+        self.ty.print(tabs + 1, true, line.clone());
+    }
+}
+
+impl Node for AsNode {
+    fn emit<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> NodeResult {
+        let exp_range = self.expr.range();
+        let (v, tp, _) = self.expr.emit(ctx, builder)?;
+        self.ty.emit_highlight(ctx);
+        let target = self.ty.get_type(ctx, builder)?;
+        if v.is_none() || tp.is_none() {
+            return Err(ctx.add_diag(exp_range.new_err(ErrorCode::EXPECT_VALUE)));
+        }
+        let src = get_type_deep(tp.unwrap());
+        let target = get_type_deep(target);
+        if let (PLType::TRAIT(_), PLType::POINTER(p)) = (&*src.borrow(), &*target.borrow()) {
+            if let PLType::STRUCT(st) = &*p.borrow() {
+                let re = emit_downcast(ctx, builder, v.unwrap().value, st.get_type_code());
+                let re = builder.bitcast(ctx, re, &target.borrow(), "downcast");
+                return Ok((Some(plv!(re)), Some(target.clone()), TerminatorEnum::NONE));
+            }
+        }
+        let v = ctx.try_load2var(exp_range, v.unwrap(), builder)?;
+        let re = match (&*src.borrow(), &*target.borrow()) {
+            (PLType::PRIMITIVE(from), PLType::PRIMITIVE(to)) => {
+                cast_primitive(builder, v, from, to)
+            }
+            (PLType::POINTER(_), PLType::PRIMITIVE(to)) if to.is_int() => {
+                builder.build_ptr_to_int(v, to, "ptrtoint")
+            }
+            (PLType::PRIMITIVE(from), tp @ PLType::POINTER(_)) if from.is_int() => {
+                builder.build_int_to_ptr(ctx, v, tp, "inttoptr")
+            }
+            (PLType::POINTER(_), tp @ PLType::POINTER(_)) => builder.bitcast(ctx, v, tp, "ptrcast"),
+            (from, to) => {
+                return Err(ctx
+                    .add_diag(self.range.new_err(ErrorCode::INVALID_CAST))
+                    .add_label(
+                        exp_range,
+                        ctx.get_file(),
+                        format_label!("type {}", from.get_name()),
+                    )
+                    .add_label(
+                        self.ty.range(),
+                        ctx.get_file(),
+                        format_label!("cannot cast to {}", to.get_name()),
+                    )
+                    .clone())
+            }
+        };
+        Ok((Some(plv!(re)), Some(target), TerminatorEnum::NONE))
+    }
+}
+
+/// trait对象向下转换为实际类型的指针，返回指向实际值的指针
+///
+/// trait对象的第一个字段是实际类型的type hash，第二个字段是指向实际值的指针，
+/// type hash与`type_code`不同时调用`pl_panic`
+fn emit_downcast<'a, 'ctx, 'b>(
+    ctx: &'b mut Ctx<'a>,
+    builder: &'b BuilderEnum<'a, 'ctx>,
+    trait_v: ValueHandle,
+    type_code: u64,
+) -> ValueHandle {
+    let hash = builder.build_struct_gep(trait_v, 0, "tp_hash").unwrap();
+    let hash = builder.build_load(hash, "tp_hash");
+    let expect = builder.int_value(&PriType::U64, type_code, false);
+    let cond = builder.build_int_compare(IntPredicate::EQ, hash, expect, "downcast_check");
+    let ok_block = builder.append_basic_block(ctx.function.unwrap(), "downcast.ok");
+    let panic_block = builder.append_basic_block(ctx.function.unwrap(), "downcast.panic");
+    builder.build_conditional_branch(cond, ok_block, panic_block);
+    ctx.position_at_end(panic_block, builder);
+    let pl_panic = builder
+        .get_function("pl_panic")
+        .unwrap_or_else(|| builder.add_function("pl_panic", &[], PLType::VOID, ctx));
+    builder.build_call(pl_panic, &[], &PLType::VOID, ctx);
+    builder.build_unconditional_branch(ok_block);
+    ctx.position_at_end(ok_block, builder);
+    let data = builder.build_struct_gep(trait_v, 1, "trait_data").unwrap();
+    builder.build_load(data, "trait_data")
+}
+
+/// 基础类型之间的转换，整数扩展时根据源类型的符号选择sext或zext
+fn cast_primitive<'a, 'ctx>(
+    builder: &BuilderEnum<'a, 'ctx>,
+    v: ValueHandle,
+    from: &PriType,
+    to: &PriType,
+) -> ValueHandle {
+    if from == to {
+        return v;
+    }
+    match (from, to) {
+        (_, PriType::BOOL) if from.is_float() => builder.build_float_compare(
+            FloatPredicate::ONE,
+            v,
+            builder.float_value(from, 0.0),
+            "fptobool",
+        ),
+        (_, PriType::BOOL) => builder.build_int_compare(
+            IntPredicate::NE,
+            v,
+            builder.int_value(from, 0, false),
+            "inttobool",
+        ),
+        _ if from.is_float() && to.is_float() => builder.build_float_cast(v, to, "fpcast"),
+        _ if from.is_float() => builder.build_float_to_int(v, to, to.is_signed_int(), "fptoint"),
+        _ if to.is_float() => builder.build_int_to_float(v, to, from.is_signed_int(), "inttofp"),
+        _ if from.bits() < to.bits() => {
+            if from.is_signed_int() {
+                builder.build_int_s_extend(v, to, "sexttmp")
+            } else {
+                builder.build_int_z_extend(v, to, "zexttmp")
+            }
+        }
+        _ if from.bits() > to.bits() => builder.build_int_truncate(v, to, "trunctmp"),
+        // 位宽相同的整数只是解释方式不同
+        _ => v,
+    }
+}
@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use crate::ast::builder::BuilderEnum;
@@ -16,6 +17,7 @@ use crate::{
 use internal_macro::node;
 use lsp_types::SemanticTokenType;
 
+use super::enums::emit_enum_variant;
 use super::macro_nodes::MacroNode;
 use super::PrintTrait;
 use super::{primary::VarNode, Node, NodeResult, PLValue, TerminatorEnum};
@@ -66,7 +68,7 @@ impl Node for UseNode {
         for v in self.ids.iter() {
             ctx.push_semantic_token(v.range, SemanticTokenType::NAMESPACE, 0);
         }
-        if !path.with_extension("pi").exists() {
+        if !path.with_extension("pi").exists() && !self.import_macro(ctx, &path)? {
             ctx.add_diag(self.range.new_err(ErrorCode::UNRESOLVED_MODULE));
         }
         ctx.if_completion(self.range, || {
@@ -100,6 +102,38 @@ impl Node for UseNode {
     }
 }
 
+impl UseNode {
+    /// `use a::b::mac`中`a/b.pi`存在时从模块`b`中导入宏`mac`，成功时返回true
+    fn import_macro(&self, ctx: &mut Ctx, path: &Path) -> Result<bool, PLDiag> {
+        if self.ids.len() <= 2 || !self.complete {
+            return Ok(false);
+        }
+        let modpath = path.parent().unwrap().with_extension("pi");
+        let modpath = match dunce::canonicalize(modpath) {
+            Ok(p) => p.to_str().unwrap().to_string(),
+            Err(_) => return Ok(false),
+        };
+        let id = self.ids.last().unwrap();
+        let m = ctx
+            .plmod
+            .submods
+            .get(&self.ids[self.ids.len() - 2].get_name(ctx))
+            .filter(|m| m.path == modpath)
+            .and_then(|m| m.macros.get(&id.get_name(ctx)))
+            .filter(|m| m.file == modpath)
+            .cloned();
+        let m = match m {
+            Some(m) => m,
+            None => return Ok(false),
+        };
+        m.expect_pub(ctx, id.range)?;
+        ctx.send_if_go_to_def(id.range, m.range, m.file.clone());
+        ctx.set_glob_refs(&format!("{}..{}", &m.file, &m.id.name), id.range);
+        ctx.plmod.macros.insert(m.id.name.clone(), m);
+        Ok(true)
+    }
+}
+
 /// # ExternIdNode
 /// 外部符号节点，可能会退化为内部符号节点（VarNode）
 ///
@@ -149,6 +183,10 @@ impl Node for ExternIdNode {
             });
             return Err(ctx.add_diag(self.range.new_err(ErrorCode::COMPLETION)));
         }
+        if let Some(tp) = self.get_enum_type(ctx) {
+            // 形如`Shape::Empty`的枚举值
+            return emit_enum_variant(ctx, builder, self, tp, None, self.range);
+        }
         for id in &self.ns {
             ctx.push_semantic_token(id.range, SemanticTokenType::NAMESPACE, 0);
         }
@@ -234,7 +272,9 @@ impl ExternIdNode {
             // 必须是public的
             _ = tp.borrow().expect_pub(ctx, self.range);
             let re = match *tp.clone().borrow() {
-                PLType::STRUCT(_) | PLType::TRAIT(_) => Ok((None, Some(tp), TerminatorEnum::NONE)),
+                PLType::STRUCT(_) | PLType::TRAIT(_) | PLType::ENUM(_) => {
+                    Ok((None, Some(tp), TerminatorEnum::NONE))
+                }
                 _ => unreachable!(),
             };
             return re;
@@ -242,15 +282,48 @@ impl ExternIdNode {
         Err(ctx.add_diag(self.range.new_err(ErrorCode::SYMBOL_NOT_FOUND)))
     }
 
+    /// 如果该节点形如`Enum::Variant`或`m::Enum::Variant`，返回对应的枚举类型
+    pub fn get_enum_type(&self, ctx: &Ctx) -> Option<Arc<RefCell<PLType>>> {
+        let (enum_id, mods) = self.ns.split_last()?;
+        let mut plmod = &ctx.plmod;
+        for ns in mods {
+            plmod = plmod.submods.get(&ns.get_name(ctx))?;
+        }
+        let name = enum_id.get_name(ctx);
+        if plmod.submods.contains_key(&name) {
+            return None;
+        }
+        let tp = if mods.is_empty() {
+            ctx.get_type(&name, enum_id.range).ok()?
+        } else {
+            plmod.get_type(&name)?
+        };
+        let is_enum = matches!(&*tp.borrow(), PLType::ENUM(_));
+        if is_enum {
+            Some(tp)
+        } else {
+            None
+        }
+    }
+
     pub fn get_macro<'a, 'ctx>(&'a self, ctx: &Ctx<'a>) -> Result<Arc<MacroNode>, PLDiag> {
+        // 宏展开中调用的宏先在定义外层宏的模块中查找
+        let def_mod = ctx
+            .macro_env
+            .as_ref()
+            .and_then(|env| ctx.plmod.find_mod(&env.module));
         if self.ns.is_empty() {
             // 如果该节点只有一个id，且完整，那么就是一个普通的包内符号，直接调用idnode
-            if let Some(m) = ctx.get_macro(&self.id.get_name(ctx)) {
+            let name = self.id.get_name(ctx);
+            if let Some(m) = def_mod.and_then(|m| m.macros.get(&name)) {
                 return Ok(m.clone());
             }
+            if let Some(m) = ctx.get_macro(&name) {
+                return Ok(m);
+            }
             return Err(ctx.add_diag(self.range.new_err(ErrorCode::MACRO_NOT_FOUND)));
         }
-        let mut plmod = &ctx.plmod;
+        let mut plmod = def_mod.unwrap_or(&ctx.plmod);
         for ns in self.ns.iter() {
             let re = plmod.submods.get(&ns.get_name(ctx));
             if let Some(re) = re {
@@ -259,8 +332,11 @@ impl ExternIdNode {
                 return Err(ctx.add_diag(ns.range.new_err(ErrorCode::UNRESOLVED_MODULE)));
             }
         }
+        // 通过`use`导入到模块中的宏不能再通过这个模块访问
         if let Some(m) = plmod.macros.get(&self.id.get_name(ctx)) {
-            return Ok(m.clone());
+            if m.file == plmod.path {
+                return Ok(m.clone());
+            }
         }
         Err(ctx.add_diag(self.range.new_err(ErrorCode::MACRO_NOT_FOUND)))
     }
@@ -4,11 +4,13 @@ use super::*;
 
 use crate::ast::builder::BuilderEnum;
 use crate::ast::builder::IRBuilder;
+use crate::ast::builder::ValueHandle;
 use crate::ast::ctx::Ctx;
-use crate::ast::ctx::MacroReplaceNode;
 use crate::ast::diag::ErrorCode;
+use crate::ast::node::macro_nodes::emit_macro_fragment;
 use crate::ast::pltype::{PLType, PriType};
 use crate::plv;
+use inkwell::IntPredicate;
 use internal_macro::node;
 use lsp_types::SemanticTokenType;
 
@@ -118,41 +120,21 @@ impl Node for VarNode {
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> NodeResult {
         if self.is_macro_var() {
-            let re = ctx
-                .macro_vars
-                .get(&self.name[1..])
-                .ok_or_else(|| {
-                    self.range
-                        .new_err(ErrorCode::MACRO_VAR_NOT_FOUND)
-                        .add_help(&format!(
-                            "add a macro var named `{}` in the macro definition",
-                            self.name
-                        ))
-                        .add_to_ctx(ctx)
-                })?
-                .clone();
-            match re {
-                MacroReplaceNode::NodeEnum(mut n) => {
-                    return n.emit(ctx, builder);
-                }
-                MacroReplaceNode::LoopNodeEnum(mut loop_var) => {
-                    if !ctx.macro_loop {
-                        return Err(self
-                            .range
-                            .new_err(ErrorCode::MACRO_LOOP_VAR_USED_OUT_OF_LOOP)
-                            .add_help("add a `macro loop` surrounding the macro body like $($var)*")
-                            .add_to_ctx(ctx));
-                    }
-                    let re = loop_var[ctx.macro_loop_idx].emit(ctx, builder);
-                    ctx.macro_loop_len = loop_var.len();
-                    return re;
-                }
-            }
+            let fragment = ctx
+                .get_macro_fragment(&self.name, self.range)
+                .map_err(|e| e.add_to_ctx(ctx))?;
+            return emit_macro_fragment(fragment, self.range, ctx, builder);
         }
         ctx.if_completion(self.range, || ctx.get_completions());
-        let v = ctx.get_symbol(&self.name, builder);
+        // 宏中定义的变量带有卫生标记，优先查找
+        let v = ctx
+            .macro_env
+            .as_ref()
+            .and_then(|env| ctx.get_symbol(&env.hygienic_name(&self.name), builder))
+            .or_else(|| ctx.get_symbol(&self.name, builder));
         if let Some((v, pltype, dst, refs, is_const)) = v {
             ctx.push_semantic_token(self.range, SemanticTokenType::VARIABLE, 0);
+            ctx.send_if_type_def(self.range, &pltype);
             let o = Ok((
                 Some({
                     let mut res: PLValue = plv!(v);
@@ -199,33 +181,18 @@ impl VarNode {
         self.name.starts_with("$")
     }
     pub fn get_name(&self, ctx: &Ctx) -> String {
-        if self.is_macro_var() {
-            let re = ctx.macro_vars.get(&self.name[1..]).unwrap();
-            if let MacroReplaceNode::NodeEnum(NodeEnum::Var(v)) = re {
-                return v.name.clone();
-            } else {
-                todo!()
-            }
-        } else {
-            self.name.clone()
-        }
+        ctx.macro_ident(&self.name, false)
     }
 
     pub fn get_type<'a, 'ctx>(&'a self, ctx: &Ctx<'a>) -> NodeResult {
-        if self.is_macro_var() {
-            let re = ctx.macro_vars.get(&self.name[1..]).unwrap();
-            if let MacroReplaceNode::NodeEnum(NodeEnum::Var(v)) = re {
-                return v.get_type(ctx);
-            } else {
-                todo!()
-            }
-        }
+        let name = self.get_name(ctx);
         ctx.if_completion(self.range, || ctx.get_completions());
 
-        if let Ok(tp) = ctx.get_type(&self.name, self.range) {
+        if let Ok(tp) = ctx.get_type(&name, self.range) {
             match *tp.borrow() {
                 PLType::STRUCT(_)
                 | PLType::TRAIT(_)
+                | PLType::ENUM(_)
                 | PLType::PRIMITIVE(_)
                 | PLType::VOID
                 | PLType::GENERIC(_)
@@ -234,6 +201,9 @@ impl VarNode {
                         ctx.send_if_go_to_def(self.range, st.range, ctx.plmod.path.clone());
                         // ctx.set_if_refs(st.refs.clone(), self.range);
                     }
+                    if let PLType::ENUM(e) = &*tp.clone().borrow() {
+                        ctx.send_if_go_to_def(self.range, e.range, ctx.plmod.path.clone());
+                    }
                     return Ok((None, Some(tp.clone()), TerminatorEnum::NONE));
                 }
                 _ => return Err(ctx.add_diag(self.range.new_err(ErrorCode::UNDEFINED_TYPE))),
@@ -268,7 +238,6 @@ impl Node for ArrayElementNode {
         let (arr, pltype, _) = self.arr.emit(ctx, builder)?;
         if let PLType::ARR(arrtp) = &*pltype.unwrap().borrow() {
             let arr = arr.unwrap();
-            // TODO: check if index is out of bounds
             let index_range = self.index.range();
             let (index, index_pltype, _) = self.index.emit(ctx, builder)?;
             let index = ctx.try_load2var(index_range, index.unwrap(), builder)?;
@@ -276,8 +245,15 @@ impl Node for ArrayElementNode {
                 return Err(ctx.add_diag(self.range.new_err(ErrorCode::ARRAY_INDEX_MUST_BE_INT)));
             }
             let elemptr = {
+                let real_arr = if arrtp.is_dynamic() {
+                    // 动态数组的值是指向堆上数组对象的指针，第二个字段是长度，第三个字段才是数组
+                    let obj = builder.build_load(arr.value, "arr_obj");
+                    emit_bounds_check(ctx, builder, obj, index);
+                    builder.build_struct_gep(obj, 2, "real_arr").unwrap()
+                } else {
+                    builder.build_struct_gep(arr.value, 1, "real_arr").unwrap()
+                };
                 let index = &[builder.int_value(&PriType::I64, 0, false), index];
-                let real_arr = builder.build_struct_gep(arr.value, 1, "real_arr").unwrap();
                 builder.build_in_bounds_gep(real_arr, index, "element_ptr")
             };
             ctx.emit_comment_highlight(&self.comments[0]);
@@ -291,6 +267,31 @@ impl Node for ArrayElementNode {
     }
 }
 
+/// 动态数组的越界检查，`index < 0 || index >= len`时调用`pl_panic`
+fn emit_bounds_check<'a, 'ctx, 'b>(
+    ctx: &'b mut Ctx<'a>,
+    builder: &'b BuilderEnum<'a, 'ctx>,
+    obj: ValueHandle,
+    index: ValueHandle,
+) {
+    let len = builder.build_struct_gep(obj, 1, "arr_len").unwrap();
+    let len = builder.build_load(len, "arr_len");
+    let zero = builder.int_value(&PriType::I64, 0, false);
+    let neg = builder.build_int_compare(IntPredicate::SLT, index, zero, "index_neg");
+    let over = builder.build_int_compare(IntPredicate::SGE, index, len, "index_over");
+    let cond = builder.build_or(neg, over, "out_of_bounds");
+    let panic_block = builder.append_basic_block(ctx.function.unwrap(), "bounds.panic");
+    let ok_block = builder.append_basic_block(ctx.function.unwrap(), "bounds.ok");
+    builder.build_conditional_branch(cond, panic_block, ok_block);
+    ctx.position_at_end(panic_block, builder);
+    let pl_panic = builder
+        .get_function("pl_panic")
+        .unwrap_or_else(|| builder.add_function("pl_panic", &[], PLType::VOID, ctx));
+    builder.build_call(pl_panic, &[], &PLType::VOID, ctx);
+    builder.build_unconditional_branch(ok_block);
+    ctx.position_at_end(ok_block, builder);
+}
+
 #[node]
 pub struct ParanthesesNode {
     pub node: Box<NodeEnum>,
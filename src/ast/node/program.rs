@@ -1,3 +1,4 @@
+use super::enums::EnumDefNode;
 use super::function::FuncDefNode;
 use super::types::StructDefNode;
 use super::*;
@@ -7,8 +8,9 @@ use crate::ast::builder::llvmbuilder::LLVMBuilder;
 use crate::ast::builder::no_op_builder::NoOpBuilder;
 use crate::ast::builder::BuilderEnum;
 use crate::ast::builder::IRBuilder;
+use crate::ast::cache::{interface_hash, ModCacheEntry, MOD_CACHE};
 use crate::ast::compiler::COMPILE_PROGRESS;
-use crate::ast::compiler::{compile_dry_file, ActionType};
+use crate::ast::compiler::{compile_dry_file, ActionType, TargetOptions};
 use crate::ast::ctx::{self, Ctx};
 use crate::ast::plmod::LSPDef;
 use crate::ast::plmod::Mod;
@@ -23,6 +25,8 @@ use inkwell::context::Context;
 
 use internal_macro::node;
 use lsp_types::GotoDefinitionResponse;
+use lsp_types::Location;
+use lsp_types::Url;
 use rustc_hash::FxHashMap;
 use rustc_hash::FxHashSet;
 use std::cell::RefCell;
@@ -45,6 +49,7 @@ pub struct ProgramNode {
     pub uses: Vec<Box<NodeEnum>>,
     pub traits: Vec<TraitDefNode>,
     pub trait_impls: Vec<(String, String)>,
+    pub enums: Vec<EnumDefNode>,
 }
 
 impl PrintTrait for ProgramNode {
@@ -74,9 +79,15 @@ impl Node for ProgramNode {
             // 提前加入占位符号，解决自引用问题
             def.add_to_symbols(ctx, builder);
         }
+        for def in self.enums.iter() {
+            def.add_to_symbols(ctx, builder);
+        }
         for def in self.structs.iter_mut() {
             _ = def.emit_struct_def(ctx, builder);
         }
+        for def in self.enums.iter_mut() {
+            _ = def.emit_enum_def(ctx, builder);
+        }
         for def in self.traits.iter_mut() {
             _ = def.emit_trait_def(ctx, builder);
         }
@@ -180,6 +191,11 @@ impl Program {
             for p in u.ids[1..].iter() {
                 path = path.join(p.name.clone());
             }
+            // `use a::b::mac`导入模块b中的宏mac，此时加载模块b
+            let import_macro = !path.with_extension("pi").exists() && u.ids.len() > 2;
+            if import_macro {
+                path.pop();
+            }
             path = path.with_extension("pi");
             let f = path.to_str().unwrap().to_string();
             // eprintln!("use {}", f.clone());
@@ -193,6 +209,11 @@ impl Program {
                 continue;
             }
             let m = m.unwrap();
+            if import_macro {
+                let modname = u.ids[u.ids.len() - 2].name.clone();
+                modmap.entry(modname).or_insert_with(|| m.plmod(db));
+                continue;
+            }
             modmap.insert(u.ids.last().unwrap().name.clone(), m.plmod(db));
         }
         let filepath = Path::new(self.params(db).file(db));
@@ -221,6 +242,9 @@ impl Program {
                 pos,
                 params.config(db),
                 params.action(db) == ActionType::Compile,
+                is_active_file && self.docs(db).op(db).test_harness,
+                self.docs(db).op(db).target_dir(),
+                self.docs(db).op(db).target,
             ),
             modmap,
             self.docs(db)
@@ -348,6 +372,38 @@ impl Program {
                     }
                 }
             }
+            ActionType::GotoTypeDef => {
+                let (pos, _) = params.params(db).unwrap();
+                let range = pos.to(pos);
+                let res = plmod.type_defs.borrow();
+                let re = res.range((Unbounded, Included(&range))).last();
+                if let Some((range, def)) = re {
+                    if pos.is_in(*range) {
+                        GotoDef::push(db, GotoDefinitionResponse::Scalar(def.clone()));
+                    }
+                }
+            }
+            ActionType::GotoDecl => {
+                let (pos, _) = params.params(db).unwrap();
+                // 光标在函数调用处或者函数声明的函数名上
+                let callee = plmod
+                    .calls
+                    .borrow()
+                    .iter()
+                    .find(|(r, _)| pos.is_in(**r))
+                    .map(|(_, f)| f.clone());
+                let f =
+                    callee.or_else(|| plmod.fn_defs().into_iter().find(|f| pos.is_in(f.id_range)));
+                if let Some(f) = f.filter(|f| f.declare) {
+                    GotoDef::push(
+                        db,
+                        GotoDefinitionResponse::Scalar(Location::new(
+                            Url::from_file_path(&f.path).unwrap(),
+                            f.range.to_diag_range(),
+                        )),
+                    );
+                }
+            }
             ActionType::SignatureHelp => {
                 let (pos, _) = params.params(db).unwrap();
                 let range = pos.to(pos);
@@ -374,6 +430,12 @@ impl Program {
                 let b = plmod.semantic_tokens_builder.borrow().build();
                 PLSemanticTokens::push(db, b);
             }
+            ActionType::PrintMacroExpansion if is_active_file => {
+                // 宏展开记录在生成代码时，所以在emit之后输出
+                let mut builder = FmtBuilder::with_expansions(plmod.macro_expansions.clone());
+                p.node(db).node(db).format(&mut builder);
+                println!("{}", builder.generate());
+            }
             _ => {}
         }
         m
@@ -403,13 +465,25 @@ pub struct LspParams {
     pub params: Option<Pos>,
     pub config: Config,
     pub is_compile: bool,
+    /// 是否是`plc test`生成的测试入口
+    pub test_harness: bool,
+    /// 模块的bitcode所在的目录
+    #[return_ref]
+    pub target_dir: PathBuf,
+    #[return_ref]
+    pub target: TargetOptions,
 }
 
 #[salsa::tracked(lru = 32)]
 pub fn emit_file(db: &dyn Db, params: ProgramEmitParam) -> ModWrapper {
     log::info!("emit_file: {}", params.fullpath(db),);
     let context = &Context::create();
-    let (a, b, c, d, e) = create_llvm_deps(context, params.dir(db), params.file(db));
+    let (a, b, c, d, e) = create_llvm_deps(
+        context,
+        params.dir(db),
+        params.file(db),
+        params.params(db).target(db),
+    );
     let v = RefCell::new(FxHashSet::default());
     let builder = LLVMBuilder::new(context, &a, &b, &c, &d, &e);
     let mut ctx = ctx::Ctx::new(
@@ -420,17 +494,47 @@ pub fn emit_file(db: &dyn Db, params: ProgramEmitParam) -> ModWrapper {
         db,
     );
     ctx.plmod.submods = params.submods(db);
+    ctx.test_harness = params.params(db).test_harness(db);
     let m = &mut ctx;
     let node = params.node(db);
     let mut nn = node.node(db);
     let mut builder = &builder.into();
     let noop = NoOpBuilder::new();
     let noop = noop.into();
-    if !params.params(db).is_compile(db) {
+    let is_compile = params.params(db).is_compile(db);
+    if !is_compile {
         log::info!("not in compile mode, using no-op builder");
         builder = &noop;
     }
+    let mut hasher = DefaultHasher::new();
+    params.fullpath(db).hash(&mut hasher);
+    let hashed = params.params(db).target_dir(db).join(format!(
+        "{}_{:x}",
+        Path::new(&params.file(db))
+            .with_extension("")
+            .to_str()
+            .unwrap(),
+        hasher.finish()
+    ));
+    let pp = hashed.with_extension("bc");
+    let p = pp.as_path();
+    let mut cache_key = None;
+    let mut cached = None;
+    if is_compile {
+        let src = fs::read(params.fullpath(db)).unwrap_or_default();
+        m.plmod.interface_hash = interface_hash(&src, &nn, &m.plmod.submods);
+        let cache = MOD_CACHE.lock();
+        let key = cache.key(&src, &m.plmod.submods);
+        cached = cache.get(p, key);
+        cache_key = Some(key);
+        if cached.is_some() {
+            // bitcode可以复用，只需要分析出模块的信息
+            log::info!("{} unchanged, reuse {}", params.fullpath(db), p.display());
+            builder = &noop;
+        }
+    }
     let _ = nn.emit(m, builder);
+    let has_err = v.borrow().iter().any(|d| d.is_err());
     Diagnostics::push(
         db,
         (
@@ -438,28 +542,34 @@ pub fn emit_file(db: &dyn Db, params: ProgramEmitParam) -> ModWrapper {
             v.borrow().iter().cloned().collect(),
         ),
     );
-    if params.params(db).is_compile(db) {
-        builder.finalize_debug();
-        let mut hasher = DefaultHasher::new();
-        params.fullpath(db).hash(&mut hasher);
-        let hashed = format!(
-            "target/{}_{:x}",
-            Path::new(&params.file(db))
-                .with_extension("")
-                .to_str()
-                .unwrap(),
-            hasher.finish()
+    if let Some(entry) = cached {
+        ModBuffer::push(
+            db,
+            PLModBuffer {
+                path: p.to_path_buf(),
+                is_main: entry.is_main,
+            },
         );
-        let pp = Path::new(&hashed).with_extension("bc");
-        let ll = Path::new(&hashed).with_extension("ll");
-        let p = pp.as_path();
+    } else if is_compile {
+        builder.finalize_debug();
+        let ll = hashed.with_extension("ll");
         builder.write_bitcode_to_path(p);
         builder.print_to_file(&ll).unwrap();
+        let is_main = builder.get_function("main").is_some();
+        if !has_err {
+            MOD_CACHE.lock().update(
+                p,
+                ModCacheEntry {
+                    key: cache_key.unwrap(),
+                    is_main,
+                },
+            );
+        }
         ModBuffer::push(
             db,
             PLModBuffer {
-                path: p.clone().to_path_buf(),
-                is_main: builder.get_function("main").is_some(),
+                path: p.to_path_buf(),
+                is_main,
             },
         );
     }
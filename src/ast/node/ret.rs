@@ -30,7 +30,8 @@ impl Node for RetNode {
     ) -> NodeResult {
         let ret_pltype = ctx.rettp.as_ref().unwrap().clone();
         if let Some(ret_node) = &mut self.value {
-            let (value, value_pltype, _) = ret_node.emit(ctx, builder)?;
+            let (value, value_pltype, _) =
+                ctx.with_expect_ty(ret_pltype.clone(), |ctx| ret_node.emit(ctx, builder))?;
             ctx.emit_comment_highlight(&self.comments[0]);
             let value_pltype = value_pltype.unwrap();
             let mut value = ctx.try_load2var(self.range, value.unwrap(), builder)?;
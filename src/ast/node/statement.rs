@@ -3,7 +3,8 @@ use super::*;
 use crate::ast::builder::BuilderEnum;
 use crate::ast::builder::IRBuilder;
 use crate::ast::ctx::Ctx;
-use crate::ast::diag::{ErrorCode, WarnCode};
+use crate::ast::diag::{ErrorCode, QuickFix, WarnCode};
+use crate::ast::tokens::TokenType;
 use crate::format_label;
 
 use internal_macro::node;
@@ -68,13 +69,13 @@ impl Node for DefNode {
         }
         let pltype = pltype.unwrap();
         let ptr2value = builder.alloc(
-            &self.var.name,
+            &self.var.get_name(ctx),
             &pltype.borrow(),
             ctx,
             Some(self.var.range.start),
         );
         ctx.add_symbol(
-            self.var.name.clone(),
+            ctx.macro_ident(&self.var.name, true),
             ptr2value,
             pltype,
             self.var.range,
@@ -90,6 +91,8 @@ impl Node for DefNode {
 #[node]
 pub struct AssignNode {
     pub var: Box<NodeEnum>,
+    /// `=`或者`+=`等复合赋值运算符
+    pub op: (TokenType, Range),
     pub exp: Box<NodeEnum>,
 }
 
@@ -99,6 +102,8 @@ impl PrintTrait for AssignNode {
         tab(tabs, line.clone(), end);
         println!("AssignNode");
         self.var.print(tabs + 1, false, line.clone());
+        tab(tabs + 1, line.clone(), false);
+        println!("{:?}", self.op);
         self.exp.print(tabs + 1, true, line.clone());
     }
 }
@@ -110,17 +115,40 @@ impl Node for AssignNode {
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> NodeResult {
         let exp_range = self.exp.range();
+        let var_range = self.var.range();
         let (ptr, lpltype, _) = self.var.emit(ctx, builder)?;
         if lpltype.is_none() {
-            return Err(ctx.add_diag(self.var.range().new_err(ErrorCode::NOT_ASSIGNABLE)));
+            return Err(ctx.add_diag(var_range.new_err(ErrorCode::NOT_ASSIGNABLE)));
         }
         let (value, _, _) =
-            ctx.emit_with_expectation(&mut self.exp, lpltype, self.var.range(), builder)?;
+            ctx.emit_with_expectation(&mut self.exp, lpltype.clone(), var_range, builder)?;
         if ptr.as_ref().unwrap().is_const {
             return Err(ctx.add_diag(self.range.new_err(ErrorCode::ASSIGN_CONST)));
         }
+        if value.is_none() {
+            return Err(ctx.add_diag(exp_range.new_err(ErrorCode::EXPECT_VALUE)));
+        }
+        let ptr = ptr.unwrap().value;
         let load = ctx.try_load2var(exp_range, value.unwrap(), builder)?;
-        builder.build_store(ptr.unwrap().value, load);
+        let load = match self.op.0.get_compound_op() {
+            // 复合赋值：左值地址只求值一次，load-op-store
+            Some(op) => {
+                let left = builder.build_load(ptr, "compound_load");
+                let (v, _, _) = emit_bin_op(
+                    ctx,
+                    builder,
+                    (op, self.op.1),
+                    lpltype,
+                    left,
+                    load,
+                    self.range,
+                    var_range,
+                )?;
+                v.unwrap().value
+            }
+            None => load,
+        };
+        builder.build_store(ptr, load);
         Ok((None, None, TerminatorEnum::NONE))
     }
 }
@@ -202,7 +230,7 @@ impl Node for StatementsNode {
             terminator = terminator_res;
         }
         for (v, (_, _, range, refs)) in &ctx.table {
-            if refs.borrow().len() <= 1 && v != "self" {
+            if refs.borrow().len() <= 1 && v != "self" && !v.starts_with('_') {
                 range
                     .new_warn(WarnCode::UNUSED_VARIABLE)
                     .add_label(
@@ -210,6 +238,11 @@ impl Node for StatementsNode {
                         ctx.get_file(),
                         format_label!("Unused variable `{}`", v),
                     )
+                    .add_fix(QuickFix::new(format!("rename to `_{}`", v)).insert(
+                        &ctx.get_file(),
+                        range.start,
+                        "_",
+                    ))
                     .add_to_ctx(ctx);
             }
         }
@@ -224,6 +257,8 @@ impl StatementsNode {
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> NodeResult {
         let child = &mut ctx.new_child(self.range.start, builder);
-        self.emit(child, builder)
+        let re = self.emit(child, builder);
+        builder.end_subscope();
+        re
     }
 }
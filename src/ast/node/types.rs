@@ -2,6 +2,7 @@ use std::cell::RefCell;
 
 use std::sync::Arc;
 
+use super::macro_nodes::parse_tokens;
 use super::primary::VarNode;
 use super::*;
 
@@ -9,11 +10,13 @@ use crate::ast::builder::BuilderEnum;
 use crate::ast::builder::IRBuilder;
 use crate::ast::ctx::Ctx;
 use crate::ast::ctx::EqRes;
+use crate::ast::ctx::MacroFragment;
 use crate::ast::diag::ErrorCode;
 
 use crate::ast::pltype::get_type_deep;
-use crate::ast::pltype::{ARRType, Field, GenericType, PLType, STType};
+use crate::ast::pltype::{ARRType, ClosureType, Field, GenericType, PLType, PriType, STType};
 use crate::ast::tokens::TokenType;
+use crate::nomparser::types::type_name;
 use crate::plv;
 use indexmap::IndexMap;
 
@@ -53,7 +56,22 @@ impl TypeNameNode {
             ctx.if_completion(self.range, || ctx.get_type_completions());
             return Err(ctx.add_diag(self.range.new_err(ErrorCode::EXPECT_TYPE)));
         }
-        let (_, pltype, _) = self.id.as_ref().unwrap().get_type(ctx)?;
+        let id = self.id.as_ref().unwrap();
+        if id.ns.is_empty() && id.id.name.starts_with('$') && self.generic_params.is_none() {
+            // 宏参数`$tp`匹配到的类型在调用宏的位置解析
+            match ctx.get_macro_fragment(&id.id.name, id.range) {
+                Ok(MacroFragment::Type(tp)) => {
+                    return ctx.with_macro_caller(|ctx| tp.get_type(ctx, builder));
+                }
+                Ok(MacroFragment::Tokens(tokens, pos)) => {
+                    let tp = parse_tokens(&tokens, pos, type_name, ErrorCode::EXPECT_TYPE)
+                        .map_err(|e| ctx.add_diag(e))?;
+                    return ctx.with_macro_caller(|ctx| tp.get_type(ctx, builder));
+                }
+                _ => (),
+            }
+        }
+        let (_, pltype, _) = id.get_type(ctx)?;
         ctx.if_completion(self.range, || ctx.get_type_completions());
         let pltype = pltype.unwrap();
         if let PLType::STRUCT(sttype) = &*pltype.clone().borrow() {
@@ -213,7 +231,8 @@ impl TypeNode for TypeNameNode {
 #[node]
 pub struct ArrayTypeNameNode {
     pub id: Box<TypeNodeEnum>,
-    pub size: Box<NodeEnum>,
+    /// 为None时是动态数组`[T]`
+    pub size: Option<Box<NodeEnum>>,
 }
 
 impl PrintTrait for ArrayTypeNameNode {
@@ -221,8 +240,10 @@ impl PrintTrait for ArrayTypeNameNode {
         deal_line(tabs, &mut line, end);
         tab(tabs, line.clone(), end);
         println!("ArrayTypeNameNode");
-        self.id.print(tabs + 1, false, line.clone());
-        self.size.print(tabs + 1, true, line.clone());
+        self.id.print(tabs + 1, self.size.is_none(), line.clone());
+        if let Some(size) = &self.size {
+            size.print(tabs + 1, true, line.clone());
+        }
     }
 }
 
@@ -232,18 +253,20 @@ impl TypeNode for ArrayTypeNameNode {
         ctx: &'b mut Ctx<'a>,
         builder: &'b BuilderEnum<'a, 'ctx>,
     ) -> TypeNodeResult {
-        if let NodeEnum::Num(num) = *self.size {
-            if let Num::INT(sz) = num.value {
-                let pltype = self.id.get_type(ctx, builder)?;
-                let arrtype = ARRType {
-                    element_type: pltype,
-                    size: sz as u32,
-                };
-                let arrtype = Arc::new(RefCell::new(PLType::ARR(arrtype)));
-                return Ok(arrtype);
-            }
-        }
-        Err(ctx.add_diag(self.range.new_err(ErrorCode::SIZE_MUST_BE_INT)))
+        let size = match self.size.as_deref() {
+            None => None,
+            Some(NodeEnum::Num(NumNode {
+                value: Num::INT(sz),
+                ..
+            })) => Some(*sz as u32),
+            Some(_) => return Err(ctx.add_diag(self.range.new_err(ErrorCode::SIZE_MUST_BE_INT))),
+        };
+        let pltype = self.id.get_type(ctx, builder)?;
+        let arrtype = ARRType {
+            element_type: pltype,
+            size,
+        };
+        Ok(Arc::new(RefCell::new(PLType::ARR(arrtype))))
     }
 
     fn emit_highlight<'a, 'ctx>(&self, ctx: &mut Ctx<'a>) {
@@ -258,18 +281,23 @@ impl TypeNode for ArrayTypeNameNode {
     ) -> Result<EqRes, PLDiag> {
         match &*pltype.borrow() {
             PLType::ARR(a) => {
-                if let NodeEnum::Num(num) = *self.size {
-                    if let Num::INT(size) = num.value {
-                        if a.size as u64 != size {
-                            return Ok(EqRes {
-                                eq: false,
-                                need_up_cast: false,
-                            });
-                        }
-                        return self.id.eq_or_infer(ctx, a.element_type.clone(), builder);
+                let size = match self.size.as_deref() {
+                    None => None,
+                    Some(NodeEnum::Num(NumNode {
+                        value: Num::INT(sz),
+                        ..
+                    })) => Some(*sz as u32),
+                    Some(_) => {
+                        return Err(ctx.add_diag(self.range.new_err(ErrorCode::SIZE_MUST_BE_INT)))
                     }
+                };
+                if a.size != size {
+                    return Ok(EqRes {
+                        eq: false,
+                        need_up_cast: false,
+                    });
                 }
-                Err(ctx.add_diag(self.range.new_err(ErrorCode::SIZE_MUST_BE_INT)))
+                self.id.eq_or_infer(ctx, a.element_type.clone(), builder)
             }
             _ => Ok(EqRes {
                 eq: false,
@@ -324,6 +352,88 @@ impl TypeNode for PointerTypeNode {
     }
 }
 
+#[node]
+pub struct ClosureTypeNode {
+    pub arg_types: Vec<Box<TypeNodeEnum>>,
+    pub ret_type: Box<TypeNodeEnum>,
+}
+
+impl PrintTrait for ClosureTypeNode {
+    fn print(&self, tabs: usize, end: bool, mut line: Vec<bool>) {
+        deal_line(tabs, &mut line, end);
+        tab(tabs, line.clone(), end);
+        println!("ClosureTypeNode");
+        for arg in &self.arg_types {
+            arg.print(tabs + 1, false, line.clone());
+        }
+        self.ret_type.print(tabs + 1, true, line.clone());
+    }
+}
+
+impl TypeNode for ClosureTypeNode {
+    fn get_type<'a, 'ctx, 'b>(
+        &self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> TypeNodeResult {
+        let mut arg_types = vec![];
+        for arg in self.arg_types.iter() {
+            arg_types.push(get_type_deep(arg.get_type(ctx, builder)?));
+        }
+        let ret_type = get_type_deep(self.ret_type.get_type(ctx, builder)?);
+        Ok(Arc::new(RefCell::new(PLType::CLOSURE(ClosureType {
+            arg_types,
+            ret_type,
+            range: self.range,
+        }))))
+    }
+
+    fn emit_highlight<'a, 'ctx>(&self, ctx: &mut Ctx<'a>) {
+        for arg in self.arg_types.iter() {
+            arg.emit_highlight(ctx);
+        }
+        self.ret_type.emit_highlight(ctx);
+    }
+
+    fn eq_or_infer<'a, 'ctx, 'b>(
+        &self,
+        ctx: &'b mut Ctx<'a>,
+        pltype: Arc<RefCell<PLType>>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> Result<EqRes, PLDiag> {
+        let closure = match &*pltype.borrow() {
+            PLType::CLOSURE(c) => c.clone(),
+            _ => {
+                return Ok(EqRes {
+                    eq: false,
+                    need_up_cast: false,
+                })
+            }
+        };
+        if closure.arg_types.len() != self.arg_types.len() {
+            return Ok(EqRes {
+                eq: false,
+                need_up_cast: false,
+            });
+        }
+        for (arg, tp) in self.arg_types.iter().zip(closure.arg_types.iter()) {
+            if !arg.eq_or_infer(ctx, tp.clone(), builder)?.eq {
+                return Ok(EqRes {
+                    eq: false,
+                    need_up_cast: false,
+                });
+            }
+        }
+        Ok(EqRes {
+            eq: self
+                .ret_type
+                .eq_or_infer(ctx, closure.ret_type, builder)?
+                .eq,
+            need_up_cast: false,
+        })
+    }
+}
+
 #[node]
 pub struct TypedIdentifierNode {
     pub id: VarNode,
@@ -699,7 +809,7 @@ impl Node for ArrayInitNode {
             "array_alloca",
             &PLType::ARR(ARRType {
                 element_type: tp,
-                size: exps.len() as u32,
+                size: Some(exps.len() as u32),
             }),
             ctx,
             None,
@@ -714,13 +824,63 @@ impl Node for ArrayInitNode {
             Some(plv!(arr)),
             Some(Arc::new(RefCell::new(PLType::ARR(ARRType {
                 element_type: tp0.unwrap(),
-                size: sz,
+                size: Some(sz),
             })))),
             TerminatorEnum::NONE,
         ))
     }
 }
 
+/// # DynArrayInitNode
+/// 形如`[T * len;]`，在堆上创建长度为len的动态数组，元素全部为零值
+#[node]
+pub struct DynArrayInitNode {
+    pub tp: Box<TypeNodeEnum>,
+    pub len: Box<NodeEnum>,
+}
+
+impl PrintTrait for DynArrayInitNode {
+    fn print(&self, tabs: usize, end: bool, mut line: Vec<bool>) {
+        deal_line(tabs, &mut line, end);
+        tab(tabs, line.clone(), end);
+        println!("DynArrayInitNode");
+        self.tp.print(tabs + 1, false, line.clone());
+        self.len.print(tabs + 1, true, line);
+    }
+}
+
+impl Node for DynArrayInitNode {
+    fn emit<'a, 'ctx, 'b>(
+        &mut self,
+        ctx: &'b mut Ctx<'a>,
+        builder: &'b BuilderEnum<'a, 'ctx>,
+    ) -> NodeResult {
+        self.tp.emit_highlight(ctx);
+        let elm = self.tp.get_type(ctx, builder)?;
+        let len_range = self.len.range();
+        let (len, len_tp, _) = self.len.emit(ctx, builder)?;
+        if len.is_none() || len_tp.is_none() || !len_tp.unwrap().borrow().is(&PriType::I64) {
+            return Err(ctx.add_diag(len_range.new_err(ErrorCode::SIZE_MUST_BE_INT)));
+        }
+        let len = ctx.try_load2var(len_range, len.unwrap(), builder)?;
+        let arrtp = ARRType {
+            element_type: elm,
+            size: None,
+        };
+        // 动态数组的值是堆指针，存放它的位置要先于数组分配，
+        // 否则分配位置时触发的gc可能会回收还未被引用的数组
+        let pltype = PLType::ARR(arrtp.clone());
+        let arr = builder.alloc("dyn_arr_ptr", &pltype, ctx, None);
+        let heap = builder.alloc_dyn_arr("dyn_arr", &arrtp, len, ctx);
+        builder.build_store(arr, heap);
+        Ok((
+            Some(plv!(arr)),
+            Some(Arc::new(RefCell::new(pltype))),
+            TerminatorEnum::NONE,
+        ))
+    }
+}
+
 #[node]
 pub struct GenericDefNode {
     pub generics: Vec<Box<VarNode>>,
@@ -71,11 +71,50 @@ pub struct Mod {
     pub hints: Arc<RefCell<Box<Vec<InlayHint>>>>,
     pub doc_symbols: Arc<RefCell<Box<Vec<DocumentSymbol>>>>,
     pub impls: FxHashMap<String, FxHashSet<String>>,
+    /// 函数调用点 -> 被调用的函数，用于call hierarchy
+    pub calls: LSPRangeMap<Range, FnRef>,
+    /// 变量 -> 变量类型的定义位置，用于go to type definition
+    pub type_defs: LSPRangeMap<Range, Location>,
+    /// 模块中实现trait的impl块，用于go to implementation
+    pub trait_impls: Arc<RefCell<Vec<ImplRef>>>,
     pub macros: FxHashMap<String, Arc<MacroNode>>,
+    /// 宏调用的位置 -> (宏的名字, 展开后的代码)，用于hover与`pivot/expandMacro`
+    pub macro_expansions: LSPRangeMap<Range, (String, String)>,
+    /// 模块接口的hash，只在编译时计算，用于增量编译
+    pub interface_hash: u64,
 }
 
 pub type MutVec<T> = RefCell<Vec<T>>;
 
+/// # FnRef
+/// 函数定义的位置信息，用于call hierarchy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FnRef {
+    /// 函数名，方法形如`A::name`
+    pub name: String,
+    /// 定义所在的文件
+    pub path: String,
+    /// 整个函数定义的范围
+    pub range: Range,
+    /// 函数名的范围
+    pub id_range: Range,
+    pub method: bool,
+    /// 没有函数体的外部函数声明
+    pub declare: bool,
+}
+
+/// # ImplRef
+/// 实现trait的impl块的位置信息，用于go to implementation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImplRef {
+    /// trait的全名
+    pub trait_name: String,
+    /// impl块的范围
+    pub range: Range,
+    /// 方法名以及方法名的范围
+    pub methods: Vec<(String, Range)>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct CompletionItemWrapper(pub CompletionItem);
 
@@ -98,7 +137,7 @@ impl Gened {
     }
 }
 
-type LSPRangeMap<T, V> = Arc<RefCell<BTreeMap<T, V>>>;
+pub type LSPRangeMap<T, V> = Arc<RefCell<BTreeMap<T, V>>>;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LSPDef {
@@ -146,7 +185,12 @@ impl Mod {
             glob_refs: Arc::new(RefCell::new(BTreeMap::new())),
             refs_map: Arc::new(RefCell::new(BTreeMap::new())),
             impls: FxHashMap::default(),
+            calls: Arc::new(RefCell::new(BTreeMap::new())),
+            type_defs: Arc::new(RefCell::new(BTreeMap::new())),
+            trait_impls: Arc::new(RefCell::new(vec![])),
             macros: FxHashMap::default(),
+            macro_expansions: Arc::new(RefCell::new(BTreeMap::new())),
+            interface_hash: 0,
         }
     }
     pub fn new_child(&self) -> Self {
@@ -171,7 +215,12 @@ impl Mod {
             glob_refs: self.glob_refs.clone(),
             refs_map: self.refs_map.clone(),
             impls: self.impls.clone(),
+            calls: self.calls.clone(),
+            type_defs: self.type_defs.clone(),
+            trait_impls: self.trait_impls.clone(),
             macros: FxHashMap::default(),
+            macro_expansions: self.macro_expansions.clone(),
+            interface_hash: self.interface_hash,
         }
     }
     pub fn get_refs(&self, name: &str, db: &dyn Db, set: &mut FxHashSet<String>) {
@@ -335,6 +384,41 @@ impl Mod {
         cm
     }
 
+    /// 当前模块以及它直接或间接依赖的所有模块
+    pub fn all_mods(&self) -> Vec<Mod> {
+        let mut mods = vec![];
+        let mut set = FxHashSet::default();
+        collect_mods(self, &mut set, &mut mods);
+        mods
+    }
+
+    /// 在当前模块以及它直接或间接依赖的模块中查找路径为`path`的模块
+    pub fn find_mod(&self, path: &str) -> Option<&Mod> {
+        find_mod(self, path, &mut FxHashSet::default())
+    }
+
+    /// 模块中定义的所有函数与方法（不包括trait中的方法声明）
+    pub fn fn_defs(&self) -> Vec<FnRef> {
+        let mut fns = vec![];
+        for tp in self.types.values() {
+            if let PLType::FN(f) = &*tp.borrow() {
+                if f.path == self.path {
+                    fns.push(f.get_fn_ref());
+                }
+            }
+        }
+        for mthds in self.methods.values() {
+            for f in mthds.values() {
+                if f.path == self.path {
+                    fns.push(f.get_fn_ref());
+                }
+            }
+        }
+        fns.sort_by_key(|f| f.range.start);
+        fns.dedup();
+        fns
+    }
+
     pub fn add_impl(&mut self, stname: &str, trait_tp_name: &str) {
         let full_name = format!("{}..{}", self.path, stname);
         if let Some(m) = self.impls.get_mut(&full_name) {
@@ -347,6 +431,26 @@ impl Mod {
     }
 }
 
+fn find_mod<'a>(m: &'a Mod, path: &str, set: &mut FxHashSet<String>) -> Option<&'a Mod> {
+    if m.path == path {
+        return Some(m);
+    }
+    if !set.insert(m.path.clone()) {
+        return None;
+    }
+    m.submods.values().find_map(|sub| find_mod(sub, path, set))
+}
+
+fn collect_mods(m: &Mod, set: &mut FxHashSet<String>, mods: &mut Vec<Mod>) {
+    if !set.insert(m.path.clone()) {
+        return;
+    }
+    for sub in m.submods.values() {
+        collect_mods(sub, set, mods);
+    }
+    mods.push(m.clone());
+}
+
 fn get_ns_path_completions_pri(path: &str, vmap: &mut FxHashMap<String, CompletionItem>) {
     let dirs = PathBuf::from(path).read_dir();
     if dirs.is_err() {
@@ -1,6 +1,6 @@
 use super::ctx::Ctx;
-use super::diag::ErrorCode;
-use super::plmod::Mod;
+use super::diag::{ErrorCode, QuickFix};
+use super::plmod::{FnRef, Mod};
 use super::tokens::TokenType;
 use crate::add_basic_types;
 use crate::ast::builder::IRBuilder;
@@ -20,13 +20,14 @@ use super::node::pkg::ExternIdNode;
 use super::node::primary::NumNode;
 use super::node::primary::VarNode;
 use super::node::types::ArrayTypeNameNode;
+use super::node::types::ClosureTypeNode;
 use super::node::types::PointerTypeNode;
 use super::node::types::TypeNameNode;
 use super::node::NodeEnum;
 use super::node::Num;
 use super::node::TypeNode;
 use super::node::TypeNodeEnum;
-use super::range::Range;
+use super::range::{Pos, Range};
 use immix::ObjectType;
 use indexmap::IndexMap;
 
@@ -51,7 +52,7 @@ use std::sync::Arc;
 
 /// # PLType
 /// Type for pivot-lang
-/// including primitive type, struct type, function type, void type
+/// including primitive type, struct type, function type, void type, enum type, closure type
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PLType {
     FN(FNValue),
@@ -63,6 +64,8 @@ pub enum PLType {
     GENERIC(GenericType),
     PLACEHOLDER(PlaceHolderType),
     TRAIT(STType),
+    ENUM(EnumType),
+    CLOSURE(ClosureType),
 }
 /// # PriType
 /// Primitive type for pivot-lang
@@ -100,6 +103,35 @@ impl PriType {
             PriType::BOOL => String::from("bool"),
         }
     }
+    /// 是否为有符号整数
+    pub fn is_signed_int(&self) -> bool {
+        matches!(
+            self,
+            PriType::I8 | PriType::I16 | PriType::I32 | PriType::I64 | PriType::I128
+        )
+    }
+    /// 是否为整数（包括有符号和无符号）
+    pub fn is_int(&self) -> bool {
+        self.is_signed_int()
+            || matches!(
+                self,
+                PriType::U8 | PriType::U16 | PriType::U32 | PriType::U64 | PriType::U128
+            )
+    }
+    pub fn is_float(&self) -> bool {
+        matches!(self, PriType::F32 | PriType::F64)
+    }
+    /// 类型的位宽，bool为1
+    pub fn bits(&self) -> u32 {
+        match self {
+            PriType::BOOL => 1,
+            PriType::I8 | PriType::U8 => 8,
+            PriType::I16 | PriType::U16 => 16,
+            PriType::I32 | PriType::U32 | PriType::F32 => 32,
+            PriType::I64 | PriType::U64 | PriType::F64 => 64,
+            PriType::I128 | PriType::U128 => 128,
+        }
+    }
     pub fn try_from_str(str: &str) -> Option<Self> {
         match str {
             "i8" => Some(PriType::I8),
@@ -135,13 +167,15 @@ fn new_typename_node(name: &str, range: Range) -> Box<TypeNodeEnum> {
         range,
     }))
 }
-fn new_arrtype_node(typenode: Box<TypeNodeEnum>, size: u64) -> Box<TypeNodeEnum> {
+fn new_arrtype_node(typenode: Box<TypeNodeEnum>, size: Option<u32>) -> Box<TypeNodeEnum> {
     Box::new(TypeNodeEnum::ArrayTypeNode(ArrayTypeNameNode {
         id: typenode,
-        size: Box::new(NodeEnum::Num(NumNode {
-            value: Num::INT(size),
-            range: Default::default(),
-        })),
+        size: size.map(|size| {
+            Box::new(NodeEnum::Num(NumNode {
+                value: Num::INT(size as u64),
+                range: Default::default(),
+            }))
+        }),
         range: Default::default(),
     }))
 }
@@ -151,6 +185,16 @@ fn new_ptrtype_node(typenode: Box<TypeNodeEnum>) -> Box<TypeNodeEnum> {
         range: Default::default(),
     }))
 }
+fn new_closure_type_node(
+    arg_types: Vec<Box<TypeNodeEnum>>,
+    ret_type: Box<TypeNodeEnum>,
+) -> Box<TypeNodeEnum> {
+    Box::new(TypeNodeEnum::ClosureTypeNode(ClosureTypeNode {
+        arg_types,
+        ret_type,
+        range: Default::default(),
+    }))
+}
 pub fn get_type_deep(pltype: Arc<RefCell<PLType>>) -> Arc<RefCell<PLType>> {
     match &*pltype.borrow() {
         PLType::GENERIC(g) => {
@@ -164,7 +208,14 @@ pub fn get_type_deep(pltype: Arc<RefCell<PLType>>) -> Arc<RefCell<PLType>> {
     }
 }
 
-fn expect_pub_err(err: ErrorCode, ctx: &Ctx, range: Range, name: String) -> Result<(), PLDiag> {
+/// `def`是符号定义所在的文件与定义开始的位置，quick fix会在那里插入`pub`
+pub fn expect_pub_err(
+    err: ErrorCode,
+    ctx: &Ctx,
+    range: Range,
+    name: String,
+    def: (&str, Pos),
+) -> Result<(), PLDiag> {
     Err(PLDiag::new_error(range, err)
         .add_label(
             range,
@@ -172,14 +223,17 @@ fn expect_pub_err(err: ErrorCode, ctx: &Ctx, range: Range, name: String) -> Resu
             format_label!("{} is not public", name),
         )
         .add_help("try add `pub` modifier before it".into())
+        .add_fix(QuickFix::new(format!("make `{}` public", name)).insert(def.0, def.1, "pub "))
         .add_to_ctx(ctx))
 }
 impl PLType {
     pub fn get_immix_type(&self) -> ObjectType {
         match self {
-            PLType::STRUCT(_) | PLType::ARR(_) => ObjectType::Complex,
+            PLType::ARR(arr) if arr.is_dynamic() => ObjectType::Pointer,
+            PLType::STRUCT(_) | PLType::ARR(_) | PLType::ENUM(_) => ObjectType::Complex,
             PLType::POINTER(_) => ObjectType::Pointer,
-            PLType::TRAIT(_) => ObjectType::Trait,
+            // 闭包的第二个字段是指向环境的堆指针
+            PLType::TRAIT(_) | PLType::CLOSURE(_) => ObjectType::Trait,
             _ => ObjectType::Atomic,
         }
     }
@@ -194,15 +248,17 @@ impl PLType {
             PLType::PLACEHOLDER(_) => "placeholder".to_string(),
             PLType::GENERIC(_) => "generic".to_string(),
             PLType::TRAIT(_) => "trait".to_string(),
+            PLType::ENUM(_) => "enum".to_string(),
+            PLType::CLOSURE(_) => "closure".to_string(),
         }
     }
     pub fn get_typenode(&self, ctx: &Ctx) -> Box<TypeNodeEnum> {
         match self {
             PLType::STRUCT(st) => new_typename_node(&st.name, st.range),
-            PLType::ARR(arr) => new_arrtype_node(
-                arr.get_elem_type().borrow().get_typenode(ctx),
-                arr.size as u64,
-            ),
+            PLType::ENUM(e) => new_typename_node(&e.name, e.range),
+            PLType::ARR(arr) => {
+                new_arrtype_node(arr.get_elem_type().borrow().get_typenode(ctx), arr.size)
+            }
             PLType::PRIMITIVE(p) => new_typename_node(&p.get_name(), Default::default()),
             PLType::VOID => new_typename_node("void", Default::default()),
             PLType::POINTER(p) => new_ptrtype_node(p.borrow().get_typenode(ctx)),
@@ -216,6 +272,13 @@ impl PLType {
             PLType::PLACEHOLDER(p) => {
                 new_typename_node(&p.get_place_holder_name(), Default::default())
             }
+            PLType::CLOSURE(c) => new_closure_type_node(
+                c.arg_types
+                    .iter()
+                    .map(|t| t.borrow().get_typenode(ctx))
+                    .collect(),
+                c.ret_type.borrow().get_typenode(ctx),
+            ),
             _ => unreachable!(),
         }
     }
@@ -230,13 +293,14 @@ impl PLType {
     /// if support find refs
     pub fn if_refs(&self, f: impl FnOnce(&PLType)) {
         match self {
-            PLType::FN(_) | PLType::STRUCT(_) | PLType::TRAIT(_) => f(self),
+            PLType::FN(_) | PLType::STRUCT(_) | PLType::TRAIT(_) | PLType::ENUM(_) => f(self),
             PLType::ARR(_) => (),
             PLType::PRIMITIVE(_) => (),
             PLType::VOID => (),
             PLType::POINTER(_) => (),
             PLType::GENERIC(_) => (),
             PLType::PLACEHOLDER(_) => (),
+            PLType::CLOSURE(_) => (),
         }
     }
 
@@ -245,9 +309,7 @@ impl PLType {
             PLType::FN(fu) => fu.name.clone(),
             PLType::STRUCT(st) => st.name.clone(),
             PLType::PRIMITIVE(pri) => pri.get_name(),
-            PLType::ARR(arr) => {
-                format!("[{} * {}]", arr.element_type.borrow().get_name(), arr.size)
-            }
+            PLType::ARR(arr) => arr.get_name(&arr.element_type.borrow().get_name()),
             PLType::VOID => "void".to_string(),
             PLType::POINTER(p) => "*".to_string() + &p.borrow().get_name(),
             PLType::GENERIC(g) => {
@@ -259,6 +321,8 @@ impl PLType {
             }
             PLType::PLACEHOLDER(p) => p.name.clone(),
             PLType::TRAIT(t) => t.name.clone(),
+            PLType::ENUM(e) => e.name.clone(),
+            PLType::CLOSURE(c) => c.get_name(),
         }
     }
     pub fn get_llvm_name(&self) -> String {
@@ -266,10 +330,9 @@ impl PLType {
             PLType::FN(fu) => fu.name.clone(),
             PLType::STRUCT(st) => st.name.clone(),
             PLType::TRAIT(t) => t.name.clone(),
+            PLType::ENUM(e) => e.name.clone(),
             PLType::PRIMITIVE(pri) => pri.get_name(),
-            PLType::ARR(arr) => {
-                format!("[{} * {}]", arr.element_type.borrow().get_name(), arr.size)
-            }
+            PLType::ARR(arr) => arr.get_name(&arr.element_type.borrow().get_name()),
             PLType::VOID => "void".to_string(),
             PLType::POINTER(p) => "*".to_string() + &p.borrow().get_name(),
             PLType::GENERIC(g) => {
@@ -280,6 +343,7 @@ impl PLType {
                 }
             }
             PLType::PLACEHOLDER(p) => p.get_place_holder_name(),
+            PLType::CLOSURE(c) => c.get_name(),
         }
     }
 
@@ -289,17 +353,13 @@ impl PLType {
             PLType::FN(fu) => fu.llvmname.clone(),
             PLType::STRUCT(st) => st.get_st_full_name(),
             PLType::TRAIT(st) => st.get_st_full_name(),
+            PLType::ENUM(e) => e.get_enum_full_name(),
             PLType::PRIMITIVE(pri) => pri.get_name(),
-            PLType::ARR(arr) => {
-                format!(
-                    "[{} * {}]",
-                    arr.element_type.borrow().get_full_elm_name(),
-                    arr.size
-                )
-            }
+            PLType::ARR(arr) => arr.get_name(&arr.element_type.borrow().get_full_elm_name()),
             PLType::VOID => "void".to_string(),
             PLType::POINTER(p) => p.borrow().get_full_elm_name(),
             PLType::PLACEHOLDER(p) => p.name.clone(),
+            PLType::CLOSURE(c) => c.get_name(),
         }
     }
     pub fn get_ptr_depth(&self) -> usize {
@@ -323,7 +383,8 @@ impl PLType {
                         super::diag::ErrorCode::EXPECT_PUBLIC_STRUCT,
                         ctx,
                         range,
-                        s.name.clone()
+                        s.name.clone(),
+                        (&s.path, s.range.start)
                     )
                 );
                 Ok(())
@@ -339,7 +400,25 @@ impl PLType {
                         super::diag::ErrorCode::EXPECT_PUBLIC_TRAIT,
                         ctx,
                         range,
-                        st.name.clone()
+                        st.name.clone(),
+                        (&st.path, st.range.start)
+                    )
+                );
+                Ok(())
+            }
+            PLType::ENUM(e) => {
+                if e.path == ctx.plmod.path {
+                    return Ok(());
+                }
+                if_not_modified_by!(
+                    e.modifier,
+                    TokenType::PUB,
+                    return expect_pub_err(
+                        super::diag::ErrorCode::EXPECT_PUBLIC_ENUM,
+                        ctx,
+                        range,
+                        e.name.clone(),
+                        (&e.path, e.range.start)
                     )
                 );
                 Ok(())
@@ -361,6 +440,25 @@ impl PLType {
             PLType::POINTER(_) => None,
             PLType::PLACEHOLDER(p) => Some(p.range),
             PLType::TRAIT(t) => Some(t.range),
+            PLType::ENUM(e) => Some(e.range),
+            PLType::CLOSURE(_) => None,
+        }
+    }
+
+    /// 类型定义所在的文件与范围，指针、数组与泛型会被解开，直到结构体、trait或enum
+    pub fn get_type_def(&self) -> Option<(String, Range)> {
+        match self {
+            PLType::STRUCT(st) | PLType::TRAIT(st) => Some((st.path.clone(), st.range)),
+            PLType::ENUM(e) => Some((e.path.clone(), e.range)),
+            PLType::POINTER(elm) => elm.borrow().get_type_def(),
+            PLType::ARR(arr) => arr.element_type.borrow().get_type_def(),
+            PLType::GENERIC(g) => g
+                .curpltype
+                .as_ref()
+                .or(g.trait_impl.as_ref())?
+                .borrow()
+                .get_type_def(),
+            _ => None,
         }
     }
 
@@ -460,6 +558,10 @@ impl FNValue {
         if ctx.plmod.path == self.path {
             return Ok(());
         }
+        // 测试函数需要能被`plc test`生成的测试入口调用
+        if ctx.test_harness && self.node.as_ref().map_or(false, |n| n.is_test) {
+            return Ok(());
+        }
         if_not_modified_by!(
             self.fntype.modifier,
             TokenType::PUB,
@@ -467,7 +569,8 @@ impl FNValue {
                 super::diag::ErrorCode::EXPECT_PUBLIC_FUNCTION,
                 ctx,
                 range,
-                self.name.clone()
+                self.name.clone(),
+                (&self.path, self.range.start)
             )
         );
         Ok(())
@@ -609,6 +712,16 @@ impl FNValue {
             children: None,
         }
     }
+    pub fn get_fn_ref(&self) -> FnRef {
+        FnRef {
+            name: self.name.trim_start_matches('|').to_string(),
+            path: self.path.clone(),
+            range: self.range,
+            id_range: self.node.as_ref().map_or(self.range, |n| n.id.range),
+            method: self.fntype.method,
+            declare: self.node.as_ref().map_or(false, |n| n.declare),
+        }
+    }
     pub fn get_signature(&self) -> String {
         let mut params = String::new();
         if !self.param_names.is_empty() {
@@ -638,13 +751,49 @@ impl FNValue {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ARRType {
     pub element_type: Arc<RefCell<PLType>>,
-    pub size: u32,
+    /// 为None时是运行时确定长度的数组`[T]`
+    pub size: Option<u32>,
 }
 
 impl ARRType {
     pub fn get_elem_type<'a, 'ctx>(&'a self) -> Arc<RefCell<PLType>> {
         self.element_type.clone()
     }
+    /// 动态数组的值是指向堆上`{ vtable, len, [0 x T] }`的指针
+    pub fn is_dynamic(&self) -> bool {
+        self.size.is_none()
+    }
+    fn get_name(&self, elm: &str) -> String {
+        match self.size {
+            Some(size) => format!("[{} * {}]", elm, size),
+            None => format!("[{}]", elm),
+        }
+    }
+}
+
+/// # ClosureType
+/// 闭包类型，在llvm中是`{ fnptr, env }`两个字段的结构体
+///
+/// fnptr的第一个参数是指向捕获环境的`*u8`，env由gc分配
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosureType {
+    pub arg_types: Vec<Arc<RefCell<PLType>>>,
+    pub ret_type: Arc<RefCell<PLType>>,
+    pub range: Range,
+}
+
+impl ClosureType {
+    pub fn get_name(&self) -> String {
+        format!(
+            "|{}| => {}",
+            self.arg_types
+                .iter()
+                .map(|t| t.borrow().get_name())
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.ret_type.borrow().get_name()
+        )
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -691,7 +840,8 @@ impl STType {
                 super::diag::ErrorCode::EXPECT_PUBLIC_FIELD,
                 ctx,
                 range,
-                f.name.clone()
+                f.name.clone(),
+                (&self.path, f.range.start)
             )
         );
         Ok(())
@@ -796,6 +946,20 @@ impl STType {
             .get_methods_completions(&self.get_st_full_name(), self.path != ctx.plmod.path)
     }
 
+    /// 结构体字面量的snippet，每个字段一个占位符
+    pub fn gen_literal_snippet(&self) -> String {
+        if self.ordered_fields.is_empty() {
+            return format!("{}{{}}$0", self.name);
+        }
+        let fields = self
+            .ordered_fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| format!("\t{}: ${{{}}}", f.name, i + 1))
+            .collect::<Vec<_>>()
+            .join(",\n");
+        format!("{}{{\n{}\n}}$0", self.name, fields)
+    }
     pub fn get_completions<'a, 'ctx>(&self, ctx: &Ctx<'a>) -> Vec<CompletionItem> {
         let mut coms = self.get_field_completions(self.path != ctx.plmod.path);
         coms.extend(self.get_mthd_completions(ctx));
@@ -858,6 +1022,146 @@ impl STType {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumVariant {
+    pub name: String,
+    pub tag: u64,
+    pub fields: Vec<Box<TypeNodeEnum>>,
+    pub range: Range,
+    pub doc: Vec<Box<NodeEnum>>,
+    /// 存放变体字段的结构体，没有字段的变体为None
+    pub payload: Option<STType>,
+}
+
+impl EnumVariant {
+    pub fn get_doc_symbol(&self) -> DocumentSymbol {
+        let detail = if self.fields.is_empty() {
+            None
+        } else {
+            Some(format!(
+                "({})",
+                self.fields
+                    .iter()
+                    .map(FmtBuilder::generate_node)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        };
+        #[allow(deprecated)]
+        DocumentSymbol {
+            name: self.name.clone(),
+            detail,
+            kind: SymbolKind::ENUM_MEMBER,
+            tags: None,
+            deprecated: None,
+            range: self.range.to_diag_range(),
+            selection_range: self.range.to_diag_range(),
+            children: None,
+        }
+    }
+}
+
+/// # EnumType
+/// 枚举类型，llvm中的布局为`{ _vtable, tag, payload }`
+///
+/// payload为指向对应变体结构体的指针，由gc管理
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumType {
+    pub name: String,
+    pub path: String,
+    pub variants: Vec<EnumVariant>,
+    pub range: Range,
+    pub doc: Vec<Box<NodeEnum>>,
+    pub modifier: Option<(TokenType, Range)>,
+}
+
+impl EnumType {
+    pub fn get_enum_full_name(&self) -> String {
+        format!("{}..{}", self.path, self.name)
+    }
+    pub fn find_variant(&self, name: &str) -> Option<&EnumVariant> {
+        self.variants.iter().find(|v| v.name == name)
+    }
+    /// 枚举在内存中对应的结构体
+    pub fn get_layout(&self) -> STType {
+        let mut fields = FxHashMap::default();
+        let mut ordered_fields = vec![];
+        for (i, (name, tp)) in [("_vtable", "u64"), ("tag", "u64")].iter().enumerate() {
+            let f = Field {
+                index: i as u32,
+                typenode: new_typename_node(tp, Default::default()),
+                name: name.to_string(),
+                range: Default::default(),
+                modifier: None,
+            };
+            fields.insert(f.name.clone(), f.clone());
+            ordered_fields.push(f);
+        }
+        let payload = Field {
+            index: 2,
+            typenode: new_ptrtype_node(new_typename_node("u8", Default::default())),
+            name: "payload".to_string(),
+            range: Default::default(),
+            modifier: None,
+        };
+        fields.insert(payload.name.clone(), payload.clone());
+        ordered_fields.push(payload);
+        STType {
+            name: self.name.clone(),
+            path: self.path.clone(),
+            fields,
+            ordered_fields,
+            range: self.range,
+            doc: vec![],
+            generic_map: IndexMap::default(),
+            derives: vec![],
+            modifier: self.modifier,
+        }
+    }
+    pub fn get_variant_completions(&self) -> Vec<CompletionItem> {
+        self.variants
+            .iter()
+            .map(|v| {
+                let (insert_text, insert_text_format) = if v.fields.is_empty() {
+                    (v.name.clone(), InsertTextFormat::PLAIN_TEXT)
+                } else {
+                    (
+                        v.name.clone()
+                            + "("
+                            + &(1..=v.fields.len())
+                                .map(|i| format!("${{{}}}", i))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                            + ")$0",
+                        InsertTextFormat::SNIPPET,
+                    )
+                };
+                CompletionItem {
+                    kind: Some(CompletionItemKind::ENUM_MEMBER),
+                    label: v.name.clone(),
+                    detail: Some("variant".to_string()),
+                    insert_text: Some(insert_text),
+                    insert_text_format: Some(insert_text_format),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+    pub fn get_doc_symbol(&self) -> DocumentSymbol {
+        #[allow(deprecated)]
+        DocumentSymbol {
+            name: self.name.clone(),
+            detail: None,
+            kind: SymbolKind::ENUM,
+            tags: None,
+            deprecated: None,
+            range: self.range.to_diag_range(),
+            selection_range: self.range.to_diag_range(),
+            children: Some(self.variants.iter().map(|v| v.get_doc_symbol()).collect()),
+        }
+    }
+}
+
 pub fn add_primitive_types<'a, 'ctx>(ctx: &mut Ctx<'a>) {
     add_basic_types!(
         ctx.plmod.types,
@@ -2,25 +2,45 @@
 mod test {
     use std::{
         cell::RefCell,
-        fs::remove_file,
+        fs::{read_to_string, remove_file},
         sync::{Arc, Mutex},
     };
 
     use lsp_types::{
-        CompletionItemKind, GotoDefinitionResponse, HoverContents, InlayHintLabel, MarkedString,
+        CompletionItemKind, CompletionTextEdit, GotoDefinitionResponse, HoverContents,
+        InlayHintKind, InlayHintLabel, InsertTextFormat, MarkedString, TextEdit,
     };
+    use rustc_hash::FxHashMap;
     use salsa::{accumulator::Accumulator, storage::HasJar};
 
     use crate::{
         ast::{
             accumulators::{
-                Completions, DocSymbols, GotoDef, Hints, PLHover, PLReferences, PLSignatureHelp,
+                Completions, Diagnostics, DocSymbols, GotoDef, Hints, PLHover, PLReferences,
+                PLSignatureHelp,
             },
             compiler::{compile_dry, ActionType},
+            plmod::Mod,
             range::Pos,
         },
         db::Database,
-        lsp::mem_docs::{MemDocs, MemDocsInput},
+        lsp::{
+            code_action::{code_actions, ImportScope},
+            completion::{
+                auto_import_completions, config_root, resolve_completion, CompletionData,
+            },
+            expand_macro::expand_macro,
+            hierarchy::{
+                incoming_calls, outgoing_calls, prepare_call_hierarchy, prepare_type_hierarchy,
+                subtypes, supertypes,
+            },
+            implementation::implementations,
+            inlay_hints::{resolve_hint, HintData, HintKind, InlayHintConfig},
+            mem_docs::{MemDocs, MemDocsInput},
+            rename::rename,
+            syntax::{document_highlights, folding_ranges, parse_program, selection_ranges},
+            workspace_symbol::workspace_symbols,
+        },
         Db,
     };
 
@@ -128,11 +148,169 @@ mod test {
             "test/lsp/test_completion.pi",
         );
         assert!(!comps.is_empty());
-        assert_eq!(comps[0].len(), 3);
+        let fields = comps[0]
+            .iter()
+            .filter(|c| c.kind == Some(CompletionItemKind::FIELD))
+            .collect::<Vec<_>>();
+        assert_eq!(fields.len(), 3);
         let compstr = vec!["a", "b", "c"];
-        for comp in comps[0].iter() {
+        for comp in fields {
             assert!(compstr.contains(&comp.label.as_str()));
         }
+        // 后缀模板替换整个表达式
+        let let_tmpl = comps[0].iter().find(|c| c.label == "let").unwrap();
+        match &let_tmpl.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => {
+                assert_eq!(edit.range.start, lsp_types::Position::new(8, 4));
+                assert_eq!(edit.new_text, "let ${1:name} = args;$0");
+            }
+            _ => panic!("expect text edit"),
+        }
+        assert!(!comps[0].iter().any(|c| c.label == "if"));
+    }
+
+    #[test]
+    fn test_postfix_completion() {
+        let comps = test_lsp::<Completions>(
+            &Database::default(),
+            Some((
+                Pos {
+                    line: 97,
+                    column: 10,
+                    offset: 0,
+                },
+                Some(".".to_string()),
+            )),
+            ActionType::Completion,
+            "test/lsp/test_completion.pi",
+        );
+        assert!(!comps.is_empty());
+        let labels = comps[0]
+            .iter()
+            .map(|c| c.label.as_str())
+            .collect::<Vec<_>>();
+        for l in ["let", "ref", "return", "if", "while", "not"] {
+            assert!(labels.contains(&l), "postfix `{}` not found", l);
+        }
+        let if_tmpl = comps[0].iter().find(|c| c.label == "if").unwrap();
+        assert_eq!(if_tmpl.filter_text.as_deref(), Some("flag.if"));
+    }
+
+    #[test]
+    fn test_completion_snippets() {
+        let comps = test_lsp::<Completions>(
+            &Database::default(),
+            Some((
+                Pos {
+                    line: 10,
+                    column: 6,
+                    offset: 0,
+                },
+                None,
+            )),
+            ActionType::Completion,
+            "test/lsp/test_completion.pi",
+        );
+        assert!(!comps.is_empty());
+        let find = |label: &str| comps[0].iter().find(|c| c.label == label).cloned();
+        let literal = find("test1{…}").expect("struct literal not found");
+        assert_eq!(
+            literal.insert_text.as_deref(),
+            Some("test1{\n\ta: ${1},\n\tb: ${2},\n\tc: ${3}\n}$0")
+        );
+        assert_eq!(literal.filter_text.as_deref(), Some("test1"));
+        let if_snippet = find("if").unwrap();
+        assert_eq!(
+            if_snippet.insert_text_format,
+            Some(InsertTextFormat::SNIPPET)
+        );
+        assert!(if_snippet.insert_text.unwrap().starts_with("if ${1:cond}"));
+        assert!(find("for").is_some());
+        // 函数补全带有用于resolve的data
+        assert!(find("name1").unwrap().data.is_some());
+    }
+
+    #[test]
+    fn test_expect_ty_completion() {
+        let comps = test_lsp::<Completions>(
+            &Database::default(),
+            Some((
+                Pos {
+                    line: 91,
+                    column: 19,
+                    offset: 0,
+                },
+                None,
+            )),
+            ActionType::Completion,
+            "test/lsp/test_completion.pi",
+        );
+        assert!(!comps.is_empty());
+        let sort_text = |label: &str| {
+            comps[0]
+                .iter()
+                .find(|c| c.label == label)
+                .and_then(|c| c.sort_text.clone())
+                .unwrap()
+        };
+        // 期望类型为i64
+        assert!(sort_text("num").starts_with('0'));
+        assert!(sort_text("flag").starts_with('1'));
+        assert!(sort_text("num") < sort_text("flag"));
+    }
+
+    #[test]
+    fn test_auto_import_completion() {
+        let db = Database::default();
+        let file = "test/lsp/trait1.pi";
+        let input = MemDocsInput::new(
+            &db,
+            Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+            file.to_string(),
+            Default::default(),
+            ActionType::Completion,
+            None,
+            None,
+        );
+        let m = compile_dry(&db, input).unwrap();
+        let path = abs_path(file);
+        let prog = parse_program(&db, input, &path).unwrap();
+        let config = input
+            .get_file_params(&db, path.clone(), false)
+            .unwrap()
+            .config(&db);
+        let root = config_root(&path).unwrap();
+        let items = auto_import_completions(&m.plmod(&db), &prog, &path, &config, &root);
+        // mod中的公开结构体，trait1没有导入mod
+        let item = items
+            .iter()
+            .find(|c| c.label == "pubname")
+            .expect("auto import item not found");
+        assert_eq!(item.detail.as_deref(), Some("use test::mod"));
+        assert_eq!(item.insert_text.as_deref(), Some("mod::pubname"));
+        assert_eq!(
+            item.additional_text_edits,
+            Some(vec![TextEdit::new(
+                new_range(0, 0, 0, 0),
+                "use test::mod;\n".to_string()
+            )])
+        );
+        // 不是pub的结构体不会被自动导入
+        assert!(!items.iter().any(|c| c.label == "name"));
+
+        // resolve时加上文档注释
+        let mut item = lsp_types::CompletionItem {
+            label: "name".to_string(),
+            data: CompletionData::new(&abs_path("test/lsp/mod.pi"), "name").to_value(),
+            ..Default::default()
+        };
+        resolve_completion(&m.plmod(&db), &mut item);
+        match item.documentation {
+            Some(lsp_types::Documentation::MarkupContent(c)) => {
+                assert!(c.value.contains("# content"))
+            }
+            _ => panic!("expect documentation"),
+        }
     }
 
     #[test]
@@ -229,6 +407,33 @@ mod test {
         );
     }
     #[test]
+    fn test_enum_variant_completion() {
+        let comps = test_lsp::<Completions>(
+            &Database::default(),
+            Some((
+                Pos {
+                    line: 59,
+                    column: 21,
+                    offset: 0,
+                },
+                None,
+            )),
+            ActionType::Completion,
+            "test/lsp/test_completion.pi",
+        );
+        assert!(!comps.is_empty());
+        let lables = comps[0].to_vec();
+        assert_eq!(lables.len(), 2);
+        assert!(
+            lables
+                .iter()
+                .all(|c| c.kind == Some(CompletionItemKind::ENUM_MEMBER)),
+            "expect all completions to be enum members"
+        );
+        assert!(lables.iter().any(|c| c.label == "Red"));
+        assert!(lables.iter().any(|c| c.label == "Rgb"));
+    }
+    #[test]
     fn test_hint() {
         let hints = test_lsp::<Hints>(
             &Database::default(),
@@ -237,11 +442,80 @@ mod test {
             "test/lsp/test_completion.pi",
         );
         assert!(!hints.is_empty());
-        assert!(!hints[0].is_empty());
+        // 默认配置下不显示解引用和短代码块的右花括号提示
+        let hints = InlayHintConfig::default().filter(hints[0].clone());
+        assert!(!hints.is_empty());
+        assert_eq!(hints[0].label, InlayHintLabel::String(": i64".to_string()));
+    }
+    #[test]
+    fn test_configurable_hints() {
+        let hints = test_lsp::<Hints>(
+            &Database::default(),
+            None,
+            ActionType::Hint,
+            "test/lsp/test_completion.pi",
+        );
+        assert!(!hints.is_empty());
+        let find = |kind: HintKind, line: u32| {
+            hints[0]
+                .iter()
+                .find(|h| {
+                    h.position.line == line
+                        && HintData::from_hint(h).map_or(false, |d| d.kind == kind)
+                })
+                .cloned()
+        };
+        // 推导出的泛型参数
+        let generic = find(HintKind::Generic, 116).expect("generic hint not found");
+        assert_eq!(generic.label, InlayHintLabel::String("<i64>".to_string()));
+        assert_eq!(generic.position.character, 24);
+        // 参数名，resolve后可以跳转到参数的定义
+        let mut param = find(HintKind::Parameter, 116).expect("param hint not found");
+        assert_eq!(param.kind, Some(InlayHintKind::PARAMETER));
+        assert_eq!(param.label, InlayHintLabel::String("t: ".to_string()));
+        resolve_hint(&mut param);
+        match &param.label {
+            InlayHintLabel::LabelParts(parts) => {
+                assert_eq!(parts[0].value, "t");
+                assert!(parts[0].tooltip.is_some());
+                let loc = parts[0].location.as_ref().expect("expect param location");
+                assert!(loc.uri.path().ends_with("test_completion.pi"));
+                assert_eq!(loc.range.start.line, 111);
+            }
+            _ => panic!("expect label parts"),
+        }
+        // 自动解引用
+        let deref = find(HintKind::Deref, 119).expect("deref hint not found");
+        assert_eq!(deref.label, InlayHintLabel::String("*".to_string()));
+        assert_eq!(deref.position.character, 12);
+        // 换行的链式调用
+        let chaining = find(HintKind::Chaining, 120).expect("chaining hint not found");
+        match &chaining.label {
+            InlayHintLabel::String(s) => assert!(s.starts_with(": ") && s.contains("HintSt")),
+            _ => panic!("expect string label"),
+        }
+        // impl块的右花括号
+        let brace = find(HintKind::ClosingBrace, 109).expect("closing brace hint not found");
         assert_eq!(
-            hints[0][0].label,
-            InlayHintLabel::String(": i64".to_string())
+            brace.label,
+            InlayHintLabel::String(" // impl HintSt".to_string())
         );
+        assert_eq!(HintData::from_hint(&brace).unwrap().lines, Some(5));
+
+        let config = InlayHintConfig {
+            deref_hints: true,
+            generic_hints: false,
+            closing_brace_min_lines: 5,
+            ..Default::default()
+        };
+        let filtered = config.filter(hints[0].clone());
+        assert!(filtered.contains(&deref));
+        assert!(filtered.contains(&brace));
+        assert!(!filtered.contains(&generic));
+        let filtered = InlayHintConfig::default().filter(hints[0].clone());
+        assert!(!filtered.contains(&deref));
+        assert!(!filtered.contains(&brace));
+        assert!(filtered.contains(&chaining));
     }
     fn new_range(sl: u32, sc: u32, el: u32, ec: u32) -> lsp_types::Range {
         lsp_types::Range {
@@ -279,6 +553,67 @@ mod test {
         }
     }
     #[test]
+    fn test_goto_type_def() {
+        let type_def = |line, column| {
+            let def = test_lsp::<GotoDef>(
+                &Database::default(),
+                Some((
+                    Pos {
+                        line,
+                        column,
+                        offset: 0,
+                    },
+                    None,
+                )),
+                ActionType::GotoTypeDef,
+                "test/lsp/test_completion.pi",
+            );
+            match def.last() {
+                Some(GotoDefinitionResponse::Scalar(sc)) => sc.clone(),
+                _ => panic!("expect type def to be scalar, found {:?}", def),
+            }
+        };
+        // 变量定义
+        let def = type_def(80, 9);
+        assert!(def.uri.to_string().contains("test/lsp/trait1.pi"));
+        assert_eq!(def.range.start, new_range(17, 0, 17, 0).start);
+        // 指针
+        assert_eq!(type_def(81, 9), def);
+        // 变量的使用
+        assert_eq!(type_def(82, 31), def);
+        // trait
+        let def = type_def(82, 9);
+        assert!(def.uri.to_string().contains("test/lsp/trait1.pi"));
+        assert_eq!(def.range.start, new_range(2, 0, 2, 0).start);
+    }
+    #[test]
+    fn test_goto_decl() {
+        let decl = |line, column| {
+            test_lsp::<GotoDef>(
+                &Database::default(),
+                Some((
+                    Pos {
+                        line,
+                        column,
+                        offset: 0,
+                    },
+                    None,
+                )),
+                ActionType::GotoDecl,
+                "test/lsp/test_completion.pi",
+            )
+        };
+        let def = decl(84, 6);
+        if let Some(GotoDefinitionResponse::Scalar(sc)) = def.last() {
+            assert!(sc.uri.to_string().contains("test/lsp/test_completion.pi"));
+            assert_eq!(sc.range.start, new_range(76, 0, 76, 0).start);
+        } else {
+            panic!("expect declaration to be scalar, found {:?}", def)
+        }
+        // 有函数体的函数没有单独的声明
+        assert!(decl(49, 14).is_empty());
+    }
+    #[test]
     fn test_hover_struct() {
         let hovers = test_lsp::<PLHover>(
             &Database::default(),
@@ -389,6 +724,279 @@ mod test {
             .is_some());
     }
 
+    fn rename_at(
+        file: &str,
+        line: usize,
+        column: usize,
+        new_name: &str,
+    ) -> Result<lsp_types::WorkspaceEdit, String> {
+        let db = Database::default();
+        let pos = Pos {
+            line,
+            column,
+            offset: 0,
+        };
+        let input = MemDocsInput::new(
+            &db,
+            Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+            file.to_string(),
+            Default::default(),
+            ActionType::Rename,
+            Some((pos, None)),
+            None,
+        );
+        let m = compile_dry(&db, input).unwrap();
+        rename(&db, input, m.plmod(&db), file, pos, new_name)
+    }
+
+    fn edit_ranges(edit: lsp_types::WorkspaceEdit, file: &str) -> Vec<lsp_types::Range> {
+        let mut ranges = edit
+            .changes
+            .unwrap()
+            .into_iter()
+            .filter(|(uri, _)| uri.to_string().contains(file))
+            .flat_map(|(_, edits)| edits.into_iter().map(|e| e.range))
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|r| (r.start.line, r.start.character));
+        ranges
+    }
+
+    #[test]
+    fn test_rename() {
+        let edit = rename_at("test/lsp/mod.pi", 2, 8, "name2").unwrap();
+        assert!(edit
+            .changes
+            .as_ref()
+            .unwrap()
+            .values()
+            .flatten()
+            .all(|e| e.new_text == "name2"));
+        assert_eq!(
+            edit_ranges(edit.clone(), "test/lsp/mod.pi"),
+            vec![new_range(1, 7, 1, 11)]
+        );
+        assert_eq!(
+            edit_ranges(edit.clone(), "test/lsp/test_completion.pi"),
+            vec![new_range(38, 11, 38, 15)]
+        );
+        assert_eq!(
+            edit_ranges(edit, "test/lsp/mod2.pi"),
+            vec![new_range(3, 17, 3, 21)]
+        );
+        // 与同一模块中的结构体重名
+        assert!(rename_at("test/lsp/mod.pi", 2, 8, "pubname").is_err());
+        assert!(rename_at("test/lsp/mod.pi", 2, 8, "let").is_err());
+
+        let edit = rename_at("test/lsp/test_completion.pi", 64, 9, "z").unwrap();
+        assert_eq!(
+            edit_ranges(edit, "test/lsp/test_completion.pi"),
+            vec![new_range(63, 8, 63, 9), new_range(65, 11, 65, 12)]
+        );
+        // 与作用域中的其他局部变量重名
+        assert!(rename_at("test/lsp/test_completion.pi", 64, 9, "y").is_err());
+    }
+
+    #[test]
+    fn test_workspace() {
+        // lib中的函数在同一个工作区的app中被使用
+        let refs = test_lsp::<PLReferences>(
+            &Database::default(),
+            Some((
+                Pos {
+                    line: 1,
+                    column: 8,
+                    offset: 0,
+                },
+                None,
+            )),
+            ActionType::FindReferences,
+            "test/workspace/lib/lib.pi",
+        );
+        let locs = refs.iter().flat_map(|r| r.iter()).collect::<Vec<_>>();
+        assert!(locs
+            .iter()
+            .any(|l| l.uri.to_string().contains("test/workspace/app/main.pi")
+                && l.range.start.line == 3));
+
+        let edit = rename_at("test/workspace/lib/lib.pi", 1, 8, "add2").unwrap();
+        assert_eq!(
+            edit_ranges(edit.clone(), "test/workspace/lib/lib.pi"),
+            vec![new_range(0, 7, 0, 10)]
+        );
+        assert_eq!(
+            edit_ranges(edit, "test/workspace/app/main.pi"),
+            vec![new_range(3, 17, 3, 20)]
+        );
+    }
+
+    #[test]
+    fn test_macro_diag() {
+        let diags = test_lsp::<Diagnostics>(
+            &Database::default(),
+            None,
+            ActionType::Diagnostic,
+            "test/macro/main.pi",
+        );
+        let mut m = FxHashMap::<String, Vec<lsp_types::Diagnostic>>::default();
+        for (p, diags) in &diags {
+            diags.iter().for_each(|d| d.get_diagnostic(p, &mut m));
+        }
+        let has = |file: &str, msg: &str| {
+            m.iter()
+                .any(|(p, diags)| p.ends_with(file) && diags.iter().any(|d| d.message == msg))
+        };
+        // 其他模块中的宏需要是pub的
+        assert!(has("main.pi", "expect public macro"), "{:?}", m);
+        // 无限递归的宏在定义宏的文件中报错
+        assert!(
+            has("mac.pi", "macro expansion exceeds the recursion limit"),
+            "{:?}",
+            m
+        );
+        // 宏展开中的诊断同时标出调用宏的位置
+        let call_site = m.iter().flat_map(|(_, diags)| diags).any(|d| {
+            d.related_information.iter().flatten().any(|r| {
+                r.location.uri.path().ends_with("main.pi")
+                    && r.message == "in this expansion of `forever!`"
+            })
+        });
+        assert!(call_site, "{:?}", m);
+    }
+
+    #[test]
+    fn test_expand_macro() {
+        let root = compile_mod("test/test/macros.pi", ActionType::ExpandMacro);
+        let file = abs_path("test/test/macros.pi");
+        let expand = |line, column| {
+            expand_macro(
+                &root,
+                &file,
+                Pos {
+                    line,
+                    column,
+                    offset: 0,
+                },
+            )
+        };
+        let swap = expand(54, 6).unwrap();
+        assert_eq!(swap.name, "swap");
+        assert_eq!(
+            swap.expansion.lines().collect::<Vec<_>>(),
+            vec!["let tmp = tmp;", "tmp = x;", "x = tmp;"]
+        );
+        let new_var = expand(57, 6).unwrap();
+        assert_eq!(new_var.expansion, "let y: i64 = 10;");
+        // 递归的宏完全展开
+        let add_all = expand(65, 6).unwrap();
+        assert_eq!(
+            add_all.expansion.lines().collect::<Vec<_>>(),
+            vec![
+                "total = total + 1;",
+                "total = total + 2;",
+                "total = total + (3 + 4);"
+            ]
+        );
+        assert!(expand(53, 6).is_none());
+        // 嵌套调用时返回包含光标的最内层调用
+        assert_eq!(expand(69, 9).unwrap().name, "swap");
+        assert_eq!(expand(70, 5).unwrap().name, "twice");
+
+        let hovers = test_lsp::<PLHover>(
+            &Database::default(),
+            Some((
+                Pos {
+                    line: 54,
+                    column: 6,
+                    offset: 0,
+                },
+                None,
+            )),
+            ActionType::Hover,
+            "test/test/macros.pi",
+        );
+        match hovers.last().map(|h| h.contents.clone()) {
+            Some(HoverContents::Scalar(MarkedString::LanguageString(code))) => {
+                assert_eq!(code.value, swap.expansion)
+            }
+            h => panic!("expect hover to be the macro expansion, found {:?}", h),
+        }
+    }
+
+    #[test]
+    fn test_code_action() {
+        let db = Database::default();
+        let input = MemDocsInput::new(
+            &db,
+            Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+            "test/lsp/test_completion.pi".to_string(),
+            Default::default(),
+            ActionType::Diagnostic,
+            None,
+            None,
+        );
+        let root = compile_dry(&db, input).unwrap().plmod(&db);
+        let diags = compile_dry::accumulated::<Diagnostics>(&db, input);
+        let mut m = FxHashMap::<String, Vec<lsp_types::Diagnostic>>::default();
+        for (p, diags) in &diags {
+            diags.iter().for_each(|d| d.get_diagnostic(p, &mut m));
+        }
+        let mut actions = vec![];
+        for (p, diags) in &m {
+            let src = read_to_string(p).unwrap_or_default();
+            let uri = lsp_types::Url::from_file_path(dunce::canonicalize(p).unwrap()).unwrap();
+            let path = abs_path(p);
+            let prog = parse_program(&db, input, &path).unwrap();
+            let config = input
+                .get_file_params(&db, path.clone(), false)
+                .unwrap()
+                .config(&db);
+            let config_root = config_root(&path).unwrap();
+            let scope = ImportScope {
+                root: &root,
+                prog: &prog,
+                file: &path,
+                config: &config,
+                config_root: &config_root,
+            };
+            for a in code_actions(&src, &uri, diags, Some(&scope)) {
+                if let lsp_types::CodeActionOrCommand::CodeAction(a) = a {
+                    actions.push(a);
+                }
+            }
+        }
+        let edits = |title: &str, file: &str| {
+            let action = actions
+                .iter()
+                .find(|a| a.title == title)
+                .unwrap_or_else(|| panic!("expect code action `{}`", title));
+            action
+                .edit
+                .clone()
+                .unwrap()
+                .changes
+                .unwrap()
+                .into_iter()
+                .filter(|(uri, _)| uri.to_string().contains(file))
+                .flat_map(|(_, edits)| edits)
+                .map(|e| (e.range, e.new_text))
+                .collect::<Vec<_>>()
+        };
+        // `mod::name`不是pub的，在定义处加上`pub`
+        assert_eq!(
+            edits("make `name` public", "test/lsp/mod.pi"),
+            vec![(new_range(1, 0, 1, 0), "pub ".to_string())]
+        );
+        // impl块缺少trait方法`name`，生成方法桩
+        let stub = edits("implement missing method name", "test/lsp/trait1.pi");
+        assert_eq!(stub.len(), 1);
+        assert_eq!(stub[0].1, "    fn name() void {\n    }\n");
+        // trait1中使用了没有导入的模块`mod`，在文件开头加上`use`
+        assert_eq!(
+            edits("import `test::mod`", "test/lsp/trait1.pi"),
+            vec![(new_range(0, 0, 0, 0), "use test::mod;\n".to_string())]
+        );
+    }
+
     #[test]
     fn test_doc_symbol() {
         let symbols = test_lsp::<DocSymbols>(
@@ -432,6 +1040,400 @@ mod test {
         );
     }
 
+    fn compile_mod(file: &str, action: ActionType) -> Mod {
+        let db = Database::default();
+        let input = MemDocsInput::new(
+            &db,
+            Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+            file.to_string(),
+            Default::default(),
+            action,
+            None,
+            None,
+        );
+        compile_dry(&db, input).unwrap().plmod(&db)
+    }
+
+    fn abs_path(file: &str) -> String {
+        dunce::canonicalize(file)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_workspace_symbol() {
+        let root = compile_mod("test/lsp/test_completion.pi", ActionType::WorkspaceSymbol);
+        let symbols = workspace_symbols(&root, "testst");
+        let names = symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        // 前缀匹配的结果排在前面，名字短的优先
+        assert_eq!(names[..2], ["testSt", "testSt2"]);
+        assert!(symbols[..2].iter().all(|s| s
+            .location
+            .uri
+            .to_string()
+            .contains("test/lsp/trait1.pi")));
+        // 模糊匹配
+        let symbols = workspace_symbols(&root, "rnmlcl");
+        assert!(symbols.iter().any(|s| s.name == "rename_local"));
+        // impl块中的方法以实现的类型作为容器
+        let symbols = workspace_symbols(&root, "name");
+        assert!(symbols
+            .iter()
+            .any(|s| s.name == "name" && s.container_name.as_deref() == Some("testSt2")));
+    }
+
+    #[test]
+    fn test_call_hierarchy() {
+        let file = abs_path("test/lsp/test_completion.pi");
+        let root = compile_mod(&file, ActionType::CallHierarchy);
+        let pos = |line, column| Pos {
+            line,
+            column,
+            offset: 0,
+        };
+        // 光标在调用处
+        let items = prepare_call_hierarchy(&root, &file, pos(49, 14)).unwrap();
+        assert_eq!(items[0].name, "new");
+        assert!(items[0].uri.to_string().contains("test/lsp/trait1.pi"));
+        let incoming = incoming_calls(&root, &items[0]);
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(incoming[0].from.name, "traitname");
+        assert_eq!(incoming[0].from_ranges, vec![new_range(48, 4, 48, 15)]);
+        // 光标在函数定义的函数名上
+        let items = prepare_call_hierarchy(&root, &file, pos(44, 6)).unwrap();
+        assert_eq!(items[0].name, "traitname");
+        assert_eq!(items[0].selection_range, new_range(43, 3, 43, 12));
+        let outgoing = outgoing_calls(&root, &items[0]);
+        assert!(outgoing.iter().any(|c| c.to.name == "new"));
+        assert!(prepare_call_hierarchy(&root, &file, pos(32, 1)).is_none());
+    }
+
+    #[test]
+    fn test_implementation() {
+        let file = abs_path("test/lsp/trait1.pi");
+        let root = compile_mod("test/lsp/test_completion.pi", ActionType::Implementation);
+        let pos = |line, column| Pos {
+            line,
+            column,
+            offset: 0,
+        };
+        // 光标在trait定义上
+        let mut lines = implementations(&root, &file, pos(3, 9))
+            .unwrap()
+            .iter()
+            .map(|l| l.range.start.line)
+            .collect::<Vec<_>>();
+        lines.sort();
+        assert_eq!(lines, vec![11, 22]);
+        // 光标在trait方法上，只有testSt2实现了`name`
+        let locs = implementations(&root, &file, pos(4, 9)).unwrap();
+        assert_eq!(locs.len(), 1);
+        assert_eq!(locs[0].range, new_range(23, 7, 23, 11));
+        // 光标在其他文件中对trait方法的调用上
+        let other = abs_path("test/lsp/test_completion.pi");
+        assert_eq!(implementations(&root, &other, pos(83, 7)).unwrap(), locs);
+        assert!(implementations(&root, &file, pos(30, 1)).is_none());
+    }
+
+    #[test]
+    fn test_type_hierarchy() {
+        let file = abs_path("test/lsp/trait1.pi");
+        let root = compile_mod("test/lsp/test_completion.pi", ActionType::TypeHierarchy);
+        let pos = |line, column| Pos {
+            line,
+            column,
+            offset: 0,
+        };
+        let items = prepare_type_hierarchy(&root, &file, pos(3, 9)).unwrap();
+        assert_eq!(items[0].name, "TestTrait");
+        assert_eq!(items[0].kind, lsp_types::SymbolKind::INTERFACE);
+        let mut subs = subtypes(&root, &items[0])
+            .into_iter()
+            .map(|i| i.name)
+            .collect::<Vec<_>>();
+        subs.sort();
+        assert_eq!(subs, vec!["testSt", "testSt2"]);
+        // 光标在结构体定义上
+        let items = prepare_type_hierarchy(&root, &file, pos(18, 10)).unwrap();
+        assert_eq!(items[0].name, "testSt2");
+        // 光标在其他文件中对结构体的引用上
+        let other = abs_path("test/lsp/test_completion.pi");
+        assert_eq!(
+            prepare_type_hierarchy(&root, &other, pos(45, 24)).unwrap(),
+            items
+        );
+        let supers = supertypes(&root, &items[0]);
+        assert_eq!(supers.len(), 1);
+        assert_eq!(supers[0].name, "TestTrait");
+        assert!(subtypes(&root, &items[0]).is_empty());
+    }
+
+    #[test]
+    fn test_test_fn_visibility() {
+        use crate::ast::compiler::Options;
+
+        let diags = |test_harness: bool| {
+            let db = Database::default();
+            let input = MemDocsInput::new(
+                &db,
+                Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+                "test/harness/main.pi".to_string(),
+                Options {
+                    test_harness,
+                    ..Default::default()
+                },
+                ActionType::Diagnostic,
+                None,
+                None,
+            );
+            compile_dry(&db, input).unwrap();
+            compile_dry::accumulated::<Diagnostics>(&db, input)
+                .iter()
+                .flat_map(|(_, diags)| diags.iter().filter(|d| d.is_err()).map(|d| d.get_msg()))
+                .collect::<Vec<_>>()
+        };
+        // 非pub的测试函数只有`plc test`生成的测试入口可以调用
+        assert!(diags(false).contains(&"expect public function".to_string()));
+        assert!(diags(true).is_empty(), "{:?}", diags(true));
+    }
+
+    #[test]
+    fn test_folding_range() {
+        let db = Database::default();
+        let input = MemDocsInput::new(
+            &db,
+            Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+            "test/lsp/test_completion.pi".to_string(),
+            Default::default(),
+            ActionType::Diagnostic,
+            None,
+            None,
+        );
+        let prog = parse_program(&db, input, "test/lsp/test_completion.pi").unwrap();
+        let folds = folding_ranges(&prog)
+            .into_iter()
+            .map(|f| (f.start_line, f.end_line, f.kind.unwrap()))
+            .collect::<Vec<_>>();
+        use lsp_types::FoldingRangeKind::{Comment, Imports, Region};
+        // 结构体
+        assert!(folds.contains(&(0, 4, Region)));
+        // 函数体，保留结尾的`}`
+        assert!(folds.contains(&(43, 49, Region)));
+        // enum
+        assert!(folds.contains(&(52, 54, Region)));
+        // 连续的use
+        assert!(folds.contains(&(40, 41, Imports)));
+        // 多行注释
+        assert!(folds.contains(&(68, 69, Comment)));
+        // 单行的注释和use不折叠
+        assert!(!folds.iter().any(|f| f.0 == 31 || f.0 == 33));
+    }
+
+    #[test]
+    fn test_selection_range() {
+        let db = Database::default();
+        let input = MemDocsInput::new(
+            &db,
+            Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+            "test/lsp/test_completion.pi".to_string(),
+            Default::default(),
+            ActionType::Diagnostic,
+            None,
+            None,
+        );
+        let prog = parse_program(&db, input, "test/lsp/test_completion.pi").unwrap();
+        let pos = Pos {
+            line: 74,
+            column: 12,
+            offset: 0,
+        };
+        let sels = selection_ranges(&prog, &[pos]);
+        assert_eq!(sels.len(), 1);
+        let mut sel = &sels[0];
+        assert_eq!(sel.range, new_range(73, 11, 73, 12));
+        let mut ranges = vec![sel.range];
+        while let Some(parent) = &sel.parent {
+            // 父节点包含子节点
+            assert!(parent.range.start <= sel.range.start && sel.range.end <= parent.range.end);
+            assert_ne!(parent.range, sel.range);
+            ranges.push(parent.range);
+            sel = parent;
+        }
+        // 函数体
+        assert!(ranges
+            .iter()
+            .any(|r| r.start.line == 70 && r.end == lsp_types::Position::new(74, 1)));
+        // 最外层是整个文件
+        assert_eq!(ranges.last().unwrap().start, lsp_types::Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_document_highlight() {
+        let file = abs_path("test/lsp/test_completion.pi");
+        let db = Database::default();
+        let input = MemDocsInput::new(
+            &db,
+            Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+            file.clone(),
+            Default::default(),
+            ActionType::DocumentHighlight,
+            None,
+            None,
+        );
+        let root = compile_dry(&db, input).unwrap().plmod(&db);
+        let prog = parse_program(&db, input, &file).unwrap();
+        let pos = Pos {
+            line: 72,
+            column: 9,
+            offset: 0,
+        };
+        let highlights = document_highlights(&root, &prog, &file, pos)
+            .unwrap()
+            .into_iter()
+            .map(|h| (h.range, h.kind.unwrap()))
+            .collect::<Vec<_>>();
+        use lsp_types::DocumentHighlightKind as Kind;
+        assert_eq!(
+            highlights,
+            vec![
+                (new_range(71, 8, 71, 9), Kind::WRITE),
+                (new_range(72, 4, 72, 5), Kind::WRITE),
+                (new_range(72, 8, 72, 9), Kind::READ),
+                (new_range(73, 11, 73, 12), Kind::READ),
+            ]
+        );
+        // 光标不在符号上
+        let pos = Pos {
+            line: 68,
+            column: 1,
+            offset: 0,
+        };
+        assert!(document_highlights(&root, &prog, &file, pos).is_none());
+        // `hs[i] = 1`只写入了`hs`，下标`i`只被读取
+        let kinds = |line, column| {
+            let pos = Pos {
+                line,
+                column,
+                offset: 0,
+            };
+            document_highlights(&root, &prog, &file, pos)
+                .unwrap()
+                .into_iter()
+                .map(|h| (h.range, h.kind.unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds(129, 5),
+            vec![
+                (new_range(126, 8, 126, 10), Kind::WRITE),
+                (new_range(128, 4, 128, 6), Kind::WRITE),
+            ]
+        );
+        assert_eq!(
+            kinds(129, 8),
+            vec![
+                (new_range(127, 8, 127, 9), Kind::WRITE),
+                (new_range(128, 7, 128, 8), Kind::READ),
+            ]
+        );
+    }
+
+    /// 把ir中的调试信息元数据整理成类似`llvm-dwarfdump`输出的格式，每个节点一行
+    fn dwarf_dump(ir: &str) -> Vec<String> {
+        let nodes = ir
+            .lines()
+            .filter_map(|l| l.split_once(" = "))
+            .filter(|(id, _)| id.starts_with('!'))
+            .map(|(id, node)| (id, node.trim_start_matches("distinct ")))
+            .collect::<FxHashMap<_, _>>();
+        let attr = |node: &str, key: &str| -> Option<String> {
+            let v = node
+                .split(&format!("({}: ", key))
+                .nth(1)
+                .or_else(|| node.split(&format!(", {}: ", key)).nth(1))?;
+            let v = match v.strip_prefix('"') {
+                Some(v) => v.split('"').next()?,
+                None => v.split(|c: char| c == ',' || c == ')').next()?,
+            };
+            Some(v.to_string())
+        };
+        let dw_tag = |node: &str| -> Option<String> {
+            Some(match node.strip_prefix('!')?.split('(').next()? {
+                "DICompositeType" | "DIDerivedType" => attr(node, "tag")?,
+                "DIBasicType" => "DW_TAG_base_type".to_string(),
+                "DISubroutineType" => "DW_TAG_subroutine_type".to_string(),
+                "DISubprogram" => "DW_TAG_subprogram".to_string(),
+                "DILexicalBlock" => "DW_TAG_lexical_block".to_string(),
+                "DILocalVariable" if attr(node, "arg").is_some() => {
+                    "DW_TAG_formal_parameter".to_string()
+                }
+                "DIGlobalVariable" | "DILocalVariable" => "DW_TAG_variable".to_string(),
+                _ => return None,
+            })
+        };
+        let mut res = nodes
+            .values()
+            .filter_map(|node| {
+                let tag = dw_tag(node)?;
+                let mut line = tag.clone();
+                if let Some(name) = attr(node, "name") {
+                    line += &format!(" DW_AT_name (\"{}\")", name);
+                }
+                if tag == "DW_TAG_member" {
+                    // 偏移为0时ir中省略了offset
+                    let offset = attr(node, "offset").map_or(0, |o| o.parse::<u64>().unwrap());
+                    line += &format!(" DW_AT_data_member_location (0x{:02x})", offset / 8);
+                }
+                if tag == "DW_TAG_variable" || tag == "DW_TAG_lexical_block" {
+                    if let Some(parent) = attr(node, "scope")
+                        .and_then(|scope| nodes.get(scope.as_str()))
+                        .and_then(|parent| dw_tag(parent))
+                    {
+                        line += &format!(" DW_AT_parent ({})", parent);
+                    }
+                }
+                Some(line)
+            })
+            .collect::<Vec<_>>();
+        res.sort();
+        res.dedup();
+        res
+    }
+
+    /// 检查test/test/debuginfo.pi生成的调试信息
+    fn check_debug_info(ir: &str) {
+        let dump = dwarf_dump(ir);
+        let expect = [
+            r#"DW_TAG_structure_type DW_AT_name ("DbgPoint")"#,
+            r#"DW_TAG_member DW_AT_name ("y") DW_AT_data_member_location (0x08)"#,
+            // 泛型结构体的实例带有泛型参数
+            r#"DW_TAG_structure_type DW_AT_name ("DbgBox<DbgPoint>")"#,
+            r#"DW_TAG_subprogram DW_AT_name ("dbg_id<i64>")"#,
+            r#"DW_TAG_structure_type DW_AT_name ("arr_wrapper")"#,
+            r#"DW_TAG_structure_type DW_AT_name ("dyn_arr")"#,
+            r#"DW_TAG_member DW_AT_name ("len") DW_AT_data_member_location (0x08)"#,
+            r#"DW_TAG_member DW_AT_name ("array") DW_AT_data_member_location (0x10)"#,
+            r#"DW_TAG_structure_type DW_AT_name ("DbgShow")"#,
+            r#"DW_TAG_member DW_AT_name ("__ptr") DW_AT_data_member_location (0x08)"#,
+            r#"DW_TAG_member DW_AT_name ("show") DW_AT_data_member_location (0x10)"#,
+            r#"DW_TAG_variable DW_AT_name ("dbg_global")"#,
+            // 子作用域结束后回到函数的作用域
+            r#"DW_TAG_variable DW_AT_name ("inner") DW_AT_parent (DW_TAG_lexical_block)"#,
+            r#"DW_TAG_variable DW_AT_name ("after") DW_AT_parent (DW_TAG_subprogram)"#,
+            "DW_TAG_lexical_block DW_AT_parent (DW_TAG_subprogram)",
+        ];
+        for e in expect {
+            assert!(
+                dump.iter().any(|l| l == e),
+                "`{}` not found in debug info:\n{}",
+                e,
+                dump.join("\n")
+            );
+        }
+    }
+
     #[test]
     fn test_compile() {
         _ = remove_file("testout");
@@ -466,6 +1468,11 @@ mod test {
                 printast: false,
                 flow: false,
                 fmt: false,
+                expand_macros: false,
+                no_cache: false,
+                target: Default::default(),
+                test_harness: false,
+                target_dir: None,
             },
         );
         // #[cfg(feature = "jit")]
@@ -488,6 +1495,7 @@ mod test {
             String::from_utf8_lossy(&o.stdout),
             String::from_utf8_lossy(&o.stderr)
         );
+        check_debug_info(&read_to_string("testout.ll").unwrap());
         input.set_action(&mut db).to(ActionType::PrintAst);
         compile(
             &db,
@@ -499,6 +1507,11 @@ mod test {
                 printast: true,
                 flow: false,
                 fmt: false,
+                expand_macros: false,
+                no_cache: false,
+                target: Default::default(),
+                test_harness: false,
+                target_dir: None,
             },
         );
         test_lsp::<Completions>(
@@ -515,4 +1528,130 @@ mod test {
             "test/main.pi",
         );
     }
+
+    #[test]
+    fn test_compile_cache() {
+        let _l = crate::utils::plc_new::tests::TEST_COMPILE_MUTEX
+            .lock()
+            .unwrap();
+        use std::{
+            collections::hash_map::DefaultHasher,
+            fs::metadata,
+            hash::{Hash, Hasher},
+            path::Path,
+        };
+
+        use crate::ast::compiler::{compile, Options};
+
+        let file = "test/test/macros.pi";
+        let build = || {
+            let db = Database::default();
+            let input = MemDocsInput::new(
+                &db,
+                Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+                file.to_string(),
+                Default::default(),
+                ActionType::Compile,
+                None,
+                None,
+            );
+            compile(
+                &db,
+                input,
+                "target/cache_test/macros".to_string(),
+                Options::default(),
+            );
+        };
+        let mut hasher = DefaultHasher::new();
+        dunce::canonicalize(file)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .hash(&mut hasher);
+        let bc = format!("target/macros_{:x}.bc", hasher.finish());
+        // 含有带参数的宏的模块也能计算接口hash
+        build();
+        let modified = metadata(&bc).unwrap().modified().unwrap();
+        // 没有修改时复用上次生成的bitcode，不会重新写入
+        build();
+        assert!(Path::new(&bc).exists());
+        assert_eq!(metadata(&bc).unwrap().modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn test_cross_target() {
+        let _l = crate::utils::plc_new::tests::TEST_COMPILE_MUTEX
+            .lock()
+            .unwrap();
+        use std::fs::{read, read_dir, remove_dir_all};
+
+        use crate::ast::compiler::{compile, Options, TargetOptions};
+
+        let out_dir = "target/cross_test";
+        _ = remove_dir_all(out_dir);
+        let db = Database::default();
+        let input = MemDocsInput::new(
+            &db,
+            Arc::new(Mutex::new(RefCell::new(MemDocs::new()))),
+            "test/test/ops.pi".to_string(),
+            Default::default(),
+            ActionType::Compile,
+            None,
+            None,
+        );
+        compile(
+            &db,
+            input,
+            format!("{}/ops", out_dir),
+            Options {
+                target: TargetOptions {
+                    triple: Some("aarch64-unknown-linux-gnu".to_string()),
+                    cpu: Some("cortex-a72".to_string()),
+                    features: Some("+neon".to_string()),
+                },
+                ..Default::default()
+            },
+        );
+        // 没有aarch64的libvm时链接会失败，但每个模块的目标文件都已经生成
+        let objs = read_dir(out_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().map_or(false, |e| e == "o"))
+            .collect::<Vec<_>>();
+        assert!(!objs.is_empty());
+        for o in objs {
+            let obj = read(&o).unwrap();
+            // ELF文件，e_machine为EM_AARCH64
+            assert_eq!(&obj[..4], b"\x7fELF", "{}", o.display());
+            assert_eq!(
+                u16::from_le_bytes([obj[18], obj[19]]),
+                183,
+                "{}",
+                o.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_target() {
+        use crate::ast::{builder::llvmbuilder::check_target, compiler::TargetOptions};
+
+        assert!(check_target(&TargetOptions::default()).is_ok());
+        assert!(check_target(&TargetOptions {
+            triple: Some("aarch64-unknown-linux-gnu".to_string()),
+            cpu: Some("cortex-a72".to_string()),
+            features: Some("+neon".to_string()),
+        })
+        .is_ok());
+        let e = check_target(&TargetOptions {
+            triple: Some("nope-unknown-none".to_string()),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(
+            e.starts_with("unsupported target nope-unknown-none"),
+            "{}",
+            e
+        );
+    }
 }
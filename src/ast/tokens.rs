@@ -85,9 +85,35 @@ define_tokens!(
     MACRO_TYPE_EXPR = "@expr",
     MACRO_TYPE_STMT = "@stmt",
     MACRO_TYPE_STMTS = "@stmts",
+    MACRO_TYPE_TY = "@ty",
+    MACRO_TYPE_BLOCK = "@block",
+    MACRO_TYPE_LIT = "@lit",
+    MACRO_TYPE_TT = "@tt",
     MACRO = "macro",
     ARROW = "=>",
-    WHERE = "where"
+    WHERE = "where",
+    ENUM = "enum",
+    MATCH = "match",
+    MOVE = "move",
+    MOD = "%",
+    BIT_AND = "&",
+    BIT_OR = "|",
+    BIT_XOR = "^",
+    BIT_NOT = "~",
+    LEFT_SHIFT = "<<",
+    RIGHT_SHIFT = ">>",
+    AS = "as",
+    PLUS_ASSIGN = "+=",
+    MINUS_ASSIGN = "-=",
+    MUL_ASSIGN = "*=",
+    DIV_ASSIGN = "/=",
+    MOD_ASSIGN = "%=",
+    BIT_AND_ASSIGN = "&=",
+    BIT_OR_ASSIGN = "|=",
+    BIT_XOR_ASSIGN = "^=",
+    LEFT_SHIFT_ASSIGN = "<<=",
+    RIGHT_SHIFT_ASSIGN = ">>=",
+    HASH = "#"
 );
 
 impl TokenType {
@@ -105,6 +131,33 @@ impl TokenType {
             _ => panic!("expected logic op token,found {:?}", self),
         }
     }
+    /// 复合赋值运算符对应的二元运算符，普通赋值返回None
+    pub fn get_compound_op(&self) -> Option<TokenType> {
+        match self {
+            TokenType::PLUS_ASSIGN => Some(TokenType::PLUS),
+            TokenType::MINUS_ASSIGN => Some(TokenType::MINUS),
+            TokenType::MUL_ASSIGN => Some(TokenType::MUL),
+            TokenType::DIV_ASSIGN => Some(TokenType::DIV),
+            TokenType::MOD_ASSIGN => Some(TokenType::MOD),
+            TokenType::BIT_AND_ASSIGN => Some(TokenType::BIT_AND),
+            TokenType::BIT_OR_ASSIGN => Some(TokenType::BIT_OR),
+            TokenType::BIT_XOR_ASSIGN => Some(TokenType::BIT_XOR),
+            TokenType::LEFT_SHIFT_ASSIGN => Some(TokenType::LEFT_SHIFT),
+            TokenType::RIGHT_SHIFT_ASSIGN => Some(TokenType::RIGHT_SHIFT),
+            _ => None,
+        }
+    }
+    pub fn get_uop(&self) -> IntPredicate {
+        match self {
+            TokenType::GREATER => IntPredicate::UGT,
+            TokenType::LESS => IntPredicate::ULT,
+            TokenType::LEQ => IntPredicate::ULE,
+            TokenType::GEQ => IntPredicate::UGE,
+            TokenType::EQ => IntPredicate::EQ,
+            TokenType::NE => IntPredicate::NE,
+            _ => panic!("expected logic op token,found {:?}", self),
+        }
+    }
     pub fn get_fop(&self) -> FloatPredicate {
         match self {
             TokenType::GREATER => FloatPredicate::OGT,
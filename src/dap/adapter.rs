@@ -0,0 +1,842 @@
+//! # adapter
+//! 把DAP请求翻译成调试器的mi命令，把调试器的异步通知翻译成DAP事件
+use std::io::Write;
+
+use crossbeam_channel::{never, Receiver};
+use rustc_hash::FxHashMap;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use super::{
+    mi::{get, get_str, quote, AsyncKind, MiRecord, MiResults, MiSession, MiValue, StreamKind},
+    protocol::{
+        write_message, Breakpoint, EvaluateArguments, Event, LaunchArguments, OutputEventBody,
+        ProtocolMessage, Request, Response, Scope, ScopesArguments, SetBreakpointsArguments,
+        Source, StackFrame, StackTraceArguments, StoppedEventBody, Thread, ThreadArguments,
+        Variable, VariablesArguments,
+    },
+    values::{
+        decode_string, display_array, display_pointer, display_type, is_null, value_kind, ValueKind,
+    },
+};
+
+/// 字符串最多读取的字节数
+const MAX_STRING_LEN: u64 = 4096;
+/// 动态数组最多显示的元素个数
+const MAX_ARRAY_LEN: u64 = 1000;
+
+/// `variablesReference`对应的内容
+enum VarHandle {
+    /// 栈帧中的局部变量
+    Locals { thread: i64, level: i64 },
+    /// 调试器中的varobj
+    Object { name: String, kind: ValueKind },
+}
+
+/// 调试器中的varobj
+struct VarObj {
+    name: String,
+    numchild: u64,
+    value: String,
+    ty: String,
+}
+
+impl VarObj {
+    fn from_mi(v: &MiValue) -> Option<Self> {
+        Some(Self {
+            name: v.get_str("name")?.to_string(),
+            numchild: v
+                .get_str("numchild")
+                .and_then(|n| n.parse().ok())
+                .unwrap_or_default(),
+            value: v.get_str("value").unwrap_or_default().to_string(),
+            ty: v.get_str("type").unwrap_or_default().to_string(),
+        })
+    }
+}
+
+pub struct Adapter<W: Write> {
+    out: W,
+    seq: i64,
+    session: Option<MiSession>,
+    launch: Option<LaunchArguments>,
+    /// 每个源文件中的断点在调试器中的编号
+    breakpoints: FxHashMap<String, Vec<String>>,
+    /// 栈帧的id是它在这个列表中的下标，元素是线程id和帧的层数
+    frames: Vec<(i64, i64)>,
+    /// `variablesReference`减一是这个列表中的下标
+    handles: Vec<VarHandle>,
+    /// 创建过的varobj，程序继续运行后删除
+    varobjs: Vec<String>,
+}
+
+impl<W: Write> Adapter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            seq: 0,
+            session: None,
+            launch: None,
+            breakpoints: FxHashMap::default(),
+            frames: vec![],
+            handles: vec![],
+            varobjs: vec![],
+        }
+    }
+
+    /// 调试器的输出，还没有启动调试器时永远不会收到消息
+    pub fn records(&self) -> Receiver<MiRecord> {
+        self.session
+            .as_ref()
+            .map_or_else(never, |s| s.records().clone())
+    }
+
+    /// 处理请求，返回`false`时结束调试
+    pub fn handle_request(&mut self, req: Request) -> bool {
+        let args = req.arguments.clone();
+        let res = match req.command.as_str() {
+            "initialize" => Ok(capabilities()),
+            "launch" => parse(args).and_then(|a| self.launch(a)),
+            "setBreakpoints" => parse(args).and_then(|a| self.set_breakpoints(a)),
+            "setExceptionBreakpoints" | "setFunctionBreakpoints" => {
+                Ok(json!({ "breakpoints": [] }))
+            }
+            "configurationDone" => self.run(),
+            "threads" => self.threads(),
+            "stackTrace" => parse(args).and_then(|a| self.stack_trace(a)),
+            "scopes" => parse(args).and_then(|a| self.scopes(a)),
+            "variables" => parse(args).and_then(|a| self.variables(a)),
+            "evaluate" => parse(args).and_then(|a| self.evaluate(a)),
+            "continue" => self
+                .resume("-exec-continue", None)
+                .map(|_| json!({ "allThreadsContinued": true })),
+            "next" => parse::<ThreadArguments>(args)
+                .and_then(|a| self.resume("-exec-next", Some(a.thread_id))),
+            "stepIn" => parse::<ThreadArguments>(args)
+                .and_then(|a| self.resume("-exec-step", Some(a.thread_id))),
+            "stepOut" => parse::<ThreadArguments>(args)
+                .and_then(|a| self.resume("-exec-finish", Some(a.thread_id))),
+            "pause" => self
+                .session()
+                .and_then(|s| s.exec("-exec-interrupt"))
+                .map(|_| Value::Null),
+            "disconnect" | "terminate" => {
+                self.shutdown();
+                self.send_response(&req, Ok(Value::Null));
+                if req.command == "terminate" {
+                    self.send_event("terminated", Value::Null);
+                }
+                return req.command != "disconnect";
+            }
+            _ => Err(format!("unsupported request `{}`", req.command)),
+        };
+        let launched = req.command == "launch" && res.is_ok();
+        self.send_response(&req, res);
+        if launched {
+            // 调试器准备好了，客户端可以开始设置断点
+            self.send_event("initialized", Value::Null);
+        }
+        self.drain_pending();
+        true
+    }
+
+    /// 执行命令时收到的异步记录
+    pub fn drain_pending(&mut self) {
+        while let Some(record) = self.session.as_mut().and_then(|s| s.take_pending()) {
+            self.handle_record(record);
+        }
+    }
+
+    pub fn handle_record(&mut self, record: MiRecord) {
+        match record {
+            MiRecord::Async {
+                kind: AsyncKind::Exec,
+                class,
+                results,
+                ..
+            } if class == "stopped" => self.stopped(&results),
+            MiRecord::Async {
+                kind: AsyncKind::Notify,
+                class,
+                results,
+                ..
+            } if class == "thread-created" || class == "thread-exited" => {
+                if let Some(id) = get_str(&results, "id").and_then(|id| id.parse::<i64>().ok()) {
+                    let reason = if class == "thread-created" {
+                        "started"
+                    } else {
+                        "exited"
+                    };
+                    self.send_event("thread", json!({ "reason": reason, "threadId": id }));
+                }
+            }
+            MiRecord::Stream { kind, text } => {
+                let category = match kind {
+                    StreamKind::Target => "stdout",
+                    _ => "console",
+                };
+                self.send_output(category, text);
+            }
+            MiRecord::Output(line) => self.send_output("stdout", line + "\n"),
+            _ => {}
+        }
+    }
+
+    /// 调试器退出了
+    pub fn debugger_exited(&mut self) {
+        if self.session.take().is_some() {
+            self.send_event("terminated", Value::Null);
+        }
+    }
+
+    pub fn shutdown(&mut self) {
+        if let Some(mut session) = self.session.take() {
+            session.kill();
+        }
+    }
+
+    fn session(&mut self) -> Result<&mut MiSession, String> {
+        self.session
+            .as_mut()
+            .ok_or_else(|| "the program is not launched".to_string())
+    }
+
+    fn launch(&mut self, args: LaunchArguments) -> Result<Value, String> {
+        let mut session = MiSession::start(&args.debugger, args.cwd.as_deref())
+            .map_err(|e| format!("failed to start debugger `{}`: {}", args.debugger, e))?;
+        session.exec(&format!("-file-exec-and-symbols {}", quote(&args.program)))?;
+        if !args.args.is_empty() {
+            let program_args = args.args.iter().map(|a| quote(a)).collect::<Vec<_>>();
+            session.exec(&format!("-exec-arguments {}", program_args.join(" ")))?;
+        }
+        if let Some(cwd) = &args.cwd {
+            session.exec(&format!("-environment-cd {}", quote(cwd)))?;
+        }
+        self.session = Some(session);
+        self.launch = Some(args);
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, args: SetBreakpointsArguments) -> Result<Value, String> {
+        let path = args
+            .source
+            .path
+            .clone()
+            .ok_or_else(|| "breakpoint source has no path".to_string())?;
+        let session = self
+            .session
+            .as_mut()
+            .ok_or_else(|| "the program is not launched".to_string())?;
+        // 每次请求都包含文件中所有的断点，先删除原有的
+        if let Some(old) = self.breakpoints.remove(&path) {
+            if !old.is_empty() {
+                session.exec(&format!("-break-delete {}", old.join(" ")))?;
+            }
+        }
+        let mut ids = vec![];
+        let mut breakpoints = vec![];
+        for bp in &args.breakpoints {
+            let mut cmd = "-break-insert -f".to_string();
+            if let Some(cond) = &bp.condition {
+                cmd.push_str(&format!(" -c {}", quote(cond)));
+            }
+            cmd.push_str(&format!(" {}", quote(&format!("{}:{}", path, bp.line))));
+            let b = match session.exec(&cmd) {
+                Ok(res) => match get(&res, "bkpt") {
+                    Some(bkpt) => {
+                        if let Some(id) = bkpt.get_str("number") {
+                            ids.push(id.to_string());
+                        }
+                        breakpoint_from_mi(bkpt, &args.source, bp.line)
+                    }
+                    None => unverified(&args.source, bp.line, "no breakpoint inserted".into()),
+                },
+                Err(msg) => unverified(&args.source, bp.line, msg),
+            };
+            breakpoints.push(b);
+        }
+        self.breakpoints.insert(path, ids);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn run(&mut self) -> Result<Value, String> {
+        let stop_on_entry = self.launch.as_ref().map_or(false, |l| l.stop_on_entry);
+        let cmd = if stop_on_entry {
+            "-exec-run --start"
+        } else {
+            "-exec-run"
+        };
+        self.session()?.exec(cmd)?;
+        Ok(Value::Null)
+    }
+
+    fn threads(&mut self) -> Result<Value, String> {
+        let session = match self.session.as_mut() {
+            Some(s) => s,
+            None => return Ok(json!({ "threads": [] })),
+        };
+        let res = session.exec("-thread-info")?;
+        let threads = get(&res, "threads")
+            .map(|t| t.as_list())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|t| {
+                let id = t.get_str("id")?.parse().ok()?;
+                let name = t
+                    .get_str("name")
+                    .or_else(|| t.get_str("target-id"))
+                    .unwrap_or("thread");
+                Some(Thread {
+                    id,
+                    name: name.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({ "threads": threads }))
+    }
+
+    fn stack_trace(&mut self, args: StackTraceArguments) -> Result<Value, String> {
+        let mut cmd = format!("-stack-list-frames --thread {}", args.thread_id);
+        let start = args.start_frame.unwrap_or_default();
+        if let Some(levels) = args.levels.filter(|l| *l > 0) {
+            cmd.push_str(&format!(" {} {}", start, start + levels - 1));
+        }
+        let res = self.session()?.exec(&cmd)?;
+        let mut frames = vec![];
+        for f in get(&res, "stack").map(|s| s.as_list()).unwrap_or_default() {
+            let level = f
+                .get_str("level")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or_default();
+            let id = self.frames.len() as i64;
+            self.frames.push((args.thread_id, level));
+            frames.push(frame_from_mi(f, id));
+        }
+        let total = self
+            .session()?
+            .exec(&format!("-stack-info-depth --thread {}", args.thread_id))
+            .ok()
+            .and_then(|r| get_str(&r, "depth").and_then(|d| d.parse::<i64>().ok()))
+            .unwrap_or(start + frames.len() as i64);
+        Ok(json!({ "stackFrames": frames, "totalFrames": total }))
+    }
+
+    fn scopes(&mut self, args: ScopesArguments) -> Result<Value, String> {
+        let (thread, level) = *self
+            .frames
+            .get(args.frame_id as usize)
+            .ok_or_else(|| format!("unknown frame {}", args.frame_id))?;
+        let reference = self.new_handle(VarHandle::Locals { thread, level });
+        Ok(json!({
+            "scopes": [Scope {
+                name: "Locals".to_string(),
+                variables_reference: reference,
+                expensive: false,
+            }]
+        }))
+    }
+
+    fn variables(&mut self, args: VariablesArguments) -> Result<Value, String> {
+        let handle = (args.variables_reference as usize)
+            .checked_sub(1)
+            .and_then(|i| self.handles.get(i))
+            .ok_or_else(|| format!("unknown variables {}", args.variables_reference))?;
+        let mut vars = vec![];
+        match handle {
+            VarHandle::Locals { thread, level } => {
+                let (thread, level) = (*thread, *level);
+                let res = self.session()?.exec(&format!(
+                    "-stack-list-variables --thread {} --frame {} --no-values",
+                    thread, level
+                ))?;
+                let names = get(&res, "variables")
+                    .map(|v| v.as_list())
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|v| v.get_str("name").map(|n| n.to_string()))
+                    .collect::<Vec<_>>();
+                for name in names {
+                    if let Ok(obj) = self.create_varobj(&name, thread, level) {
+                        vars.push(self.variable(name.clone(), Some(name), obj));
+                    }
+                }
+            }
+            VarHandle::Object { name, kind } => {
+                let (mut parent, kind) = (name.clone(), *kind);
+                if kind == ValueKind::Array {
+                    // 只显示被包装的数组中的元素
+                    parent = format!("{}.array", parent);
+                }
+                let res = self
+                    .session()?
+                    .exec(&format!("-var-list-children --all-values {}", parent))?;
+                let children = get(&res, "children")
+                    .map(|c| c.as_list().to_vec())
+                    .unwrap_or_default();
+                for child in children {
+                    let obj = match VarObj::from_mi(&child) {
+                        Some(obj) => obj,
+                        None => continue,
+                    };
+                    let exp = child.get_str("exp").unwrap_or_default();
+                    let label = if matches!(kind, ValueKind::Array | ValueKind::DynArray) {
+                        format!("[{}]", exp)
+                    } else {
+                        exp.to_string()
+                    };
+                    vars.push(self.variable(label, None, obj));
+                }
+            }
+        }
+        Ok(json!({ "variables": vars }))
+    }
+
+    fn evaluate(&mut self, args: EvaluateArguments) -> Result<Value, String> {
+        // 在调试控制台中以`开头时作为调试器的命令执行
+        if let Some(cmd) = args.expression.strip_prefix('`') {
+            self.session()?
+                .exec(&format!("-interpreter-exec console {}", quote(cmd)))?;
+            return Ok(json!({ "result": "", "variablesReference": 0 }));
+        }
+        let (thread, level) = match args.frame_id {
+            Some(id) => *self
+                .frames
+                .get(id as usize)
+                .ok_or_else(|| format!("unknown frame {}", id))?,
+            None => (1, 0),
+        };
+        let obj = self.create_varobj(&args.expression, thread, level)?;
+        let var = self.variable(args.expression.clone(), Some(args.expression), obj);
+        Ok(json!({
+            "result": var.value,
+            "type": var.ty,
+            "variablesReference": var.variables_reference,
+        }))
+    }
+
+    fn create_varobj(&mut self, expr: &str, thread: i64, level: i64) -> Result<VarObj, String> {
+        let res = self.session()?.exec(&format!(
+            "-var-create --thread {} --frame {} - * {}",
+            thread,
+            level,
+            quote(expr)
+        ))?;
+        let obj = VarObj::from_mi(&MiValue::Tuple(res))
+            .ok_or_else(|| format!("cannot evaluate `{}`", expr))?;
+        self.varobjs.push(obj.name.clone());
+        Ok(obj)
+    }
+
+    /// 按pivot-lang的类型显示varobj
+    fn variable(&mut self, name: String, evaluate_name: Option<String>, obj: VarObj) -> Variable {
+        let kind = value_kind(&obj.ty);
+        let mut ty = display_type(&obj.ty);
+        let mut elements = None;
+        let value = match kind {
+            ValueKind::String => self
+                .string_value(&obj.name)
+                .unwrap_or_else(|| obj.value.clone()),
+            ValueKind::Array => {
+                ty = self
+                    .session()
+                    .and_then(|s| s.exec(&format!("-var-info-type {}.array", obj.name)))
+                    .ok()
+                    .and_then(|r| get_str(&r, "type").map(display_type))
+                    .unwrap_or(ty);
+                self.path_value(&obj.name, ".array")
+                    .map(|v| display_array(&v))
+                    .unwrap_or_else(|| obj.value.clone())
+            }
+            ValueKind::DynArray if !is_null(&obj.value) => match self.dyn_arr_value(&obj.name) {
+                Some((arr_ty, value, elms)) => {
+                    ty = arr_ty;
+                    elements = elms;
+                    value
+                }
+                None => display_pointer(&obj.value),
+            },
+            ValueKind::Pointer | ValueKind::DynArray => display_pointer(&obj.value),
+            ValueKind::Closure => "closure".to_string(),
+            ValueKind::Other if obj.numchild > 0 => format!("{}{{...}}", ty),
+            ValueKind::Other => obj.value.clone(),
+        };
+        let expandable = obj.numchild > 0 && !(kind == ValueKind::Pointer && is_null(&obj.value));
+        let variables_reference = if kind == ValueKind::DynArray {
+            // 展开后直接显示数组中的元素
+            elements.map_or(0, |elms: VarObj| {
+                self.new_handle(VarHandle::Object {
+                    name: elms.name,
+                    kind,
+                })
+            })
+        } else if expandable {
+            self.new_handle(VarHandle::Object {
+                name: obj.name.clone(),
+                kind,
+            })
+        } else {
+            0
+        };
+        Variable {
+            name,
+            value,
+            ty: Some(ty),
+            variables_reference,
+            evaluate_name,
+        }
+    }
+
+    /// 在varobj对应的表达式后加上`suffix`求值
+    fn path_value(&mut self, varobj: &str, suffix: &str) -> Option<String> {
+        let path = self.path_expr(varobj)?;
+        self.eval(&format!("({}){}", path, suffix))
+    }
+
+    fn path_expr(&mut self, varobj: &str) -> Option<String> {
+        let res = self
+            .session()
+            .ok()?
+            .exec(&format!("-var-info-path-expression {}", varobj))
+            .ok()?;
+        get_str(&res, "path_expr").map(|p| p.to_string())
+    }
+
+    fn eval(&mut self, expr: &str) -> Option<String> {
+        let res = self
+            .session()
+            .ok()?
+            .exec(&format!("-data-evaluate-expression {}", quote(expr)))
+            .ok()?;
+        get_str(&res, "value").map(|v| v.to_string())
+    }
+
+    /// 在当前选中的栈帧中创建varobj
+    fn current_varobj(&mut self, expr: &str) -> Option<VarObj> {
+        let res = self
+            .session()
+            .ok()?
+            .exec(&format!("-var-create - * {}", quote(expr)))
+            .ok()?;
+        let obj = VarObj::from_mi(&MiValue::Tuple(res))?;
+        self.varobjs.push(obj.name.clone());
+        Some(obj)
+    }
+
+    /// 动态数组的类型、内容和包含所有元素的varobj，数组为空时没有varobj
+    fn dyn_arr_value(&mut self, varobj: &str) -> Option<(String, String, Option<VarObj>)> {
+        let path = self.path_expr(varobj)?;
+        let elm = self.current_varobj(&format!("({})->array[0]", path))?;
+        let ty = format!("[{}]", display_type(&elm.ty));
+        let len = self
+            .eval(&format!("({})->len", path))?
+            .parse::<u64>()
+            .ok()?
+            .min(MAX_ARRAY_LEN);
+        if len == 0 {
+            return Some((ty, "[]".to_string(), None));
+        }
+        // gdb中`a[0]@n`是从a[0]开始的n个元素组成的数组
+        let expr = format!("({})->array[0]@{}", path, len);
+        let value = display_array(&self.eval(&expr)?);
+        let elms = self.current_varobj(&expr)?;
+        Some((ty, value, Some(elms)))
+    }
+
+    /// 读取`gc::string`的内容
+    fn string_value(&mut self, varobj: &str) -> Option<String> {
+        let len = self
+            .path_value(varobj, ".byte_len")?
+            .parse::<u64>()
+            .ok()?
+            .min(MAX_STRING_LEN);
+        if len == 0 {
+            return Some("\"\"".to_string());
+        }
+        let data = display_pointer(&self.path_value(varobj, ".data")?);
+        let data = data.split_whitespace().next()?.to_string();
+        let res = self
+            .session()
+            .ok()?
+            .exec(&format!("-data-read-memory-bytes {} {}", data, len))
+            .ok()?;
+        let contents = get(&res, "memory")?
+            .as_list()
+            .first()?
+            .get_str("contents")?;
+        decode_string(contents)
+    }
+
+    fn new_handle(&mut self, handle: VarHandle) -> i64 {
+        self.handles.push(handle);
+        self.handles.len() as i64
+    }
+
+    /// 程序继续运行，之前的栈帧和变量都失效了
+    fn invalidate(&mut self) {
+        self.frames.clear();
+        self.handles.clear();
+        let varobjs = std::mem::take(&mut self.varobjs);
+        if let Some(session) = self.session.as_mut() {
+            for name in varobjs {
+                _ = session.exec(&format!("-var-delete {}", name));
+            }
+        }
+    }
+
+    fn resume(&mut self, cmd: &str, thread: Option<i64>) -> Result<Value, String> {
+        self.invalidate();
+        let cmd = match thread {
+            Some(t) => format!("{} --thread {}", cmd, t),
+            None => cmd.to_string(),
+        };
+        self.session()?.exec(&cmd)?;
+        Ok(Value::Null)
+    }
+
+    fn stopped(&mut self, results: &MiResults) {
+        self.invalidate();
+        let reason = get_str(results, "reason").unwrap_or_default();
+        if reason.starts_with("exited") {
+            // gdb中的退出码是八进制
+            let code = get_str(results, "exit-code")
+                .and_then(|c| i64::from_str_radix(c, 8).ok())
+                .unwrap_or_default();
+            self.send_event("exited", json!({ "exitCode": code }));
+            self.send_event("terminated", Value::Null);
+            return;
+        }
+        let body = StoppedEventBody {
+            reason: stop_reason(results).to_string(),
+            description: get_str(results, "signal-meaning").map(|s| s.to_string()),
+            thread_id: get_str(results, "thread-id").and_then(|t| t.parse().ok()),
+            all_threads_stopped: true,
+        };
+        self.send_event("stopped", serde_json::to_value(body).unwrap());
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn send(&mut self, msg: ProtocolMessage) {
+        if let Err(e) = write_message(&mut self.out, &msg) {
+            log::error!("failed to send dap message: {}", e);
+        }
+    }
+
+    fn send_response(&mut self, req: &Request, res: Result<Value, String>) {
+        let seq = self.next_seq();
+        let (success, message, body) = match res {
+            Ok(body) => (true, None, body),
+            Err(msg) => (false, Some(msg), Value::Null),
+        };
+        self.send(ProtocolMessage::Response(Response {
+            seq,
+            request_seq: req.seq,
+            success,
+            command: req.command.clone(),
+            message,
+            body,
+        }));
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) {
+        let seq = self.next_seq();
+        self.send(ProtocolMessage::Event(Event {
+            seq,
+            event: event.to_string(),
+            body,
+        }));
+    }
+
+    fn send_output(&mut self, category: &str, output: String) {
+        let body = OutputEventBody {
+            category: category.to_string(),
+            output,
+        };
+        self.send_event("output", serde_json::to_value(body).unwrap());
+    }
+}
+
+fn parse<T: DeserializeOwned>(args: Value) -> Result<T, String> {
+    serde_json::from_value(args).map_err(|e| format!("invalid arguments: {}", e))
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsConditionalBreakpoints": true,
+        "supportsEvaluateForHovers": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+fn unverified(source: &Source, line: i64, message: String) -> Breakpoint {
+    Breakpoint {
+        id: None,
+        verified: false,
+        message: Some(message),
+        source: Some(source.clone()),
+        line: Some(line),
+    }
+}
+
+/// 调试器中的断点，程序还没有加载对应的代码时断点是pending的
+fn breakpoint_from_mi(bkpt: &MiValue, source: &Source, line: i64) -> Breakpoint {
+    let pending = bkpt.get("pending").is_some() || bkpt.get_str("addr") == Some("<PENDING>");
+    Breakpoint {
+        id: bkpt.get_str("number").and_then(|n| n.parse().ok()),
+        verified: !pending,
+        message: None,
+        source: Some(source.clone()),
+        // 调试器可能把断点移动到最近的有代码的行
+        line: Some(
+            bkpt.get_str("line")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or(line),
+        ),
+    }
+}
+
+fn frame_from_mi(f: &MiValue, id: i64) -> StackFrame {
+    let source = f.get_str("fullname").map(|path| Source {
+        name: f.get_str("file").map(|file| file.to_string()),
+        path: Some(path.to_string()),
+    });
+    StackFrame {
+        id,
+        name: f.get_str("func").unwrap_or("??").to_string(),
+        presentation_hint: if source.is_none() {
+            Some("subtle".to_string())
+        } else {
+            None
+        },
+        source,
+        line: f
+            .get_str("line")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or_default(),
+        column: 1,
+    }
+}
+
+fn stop_reason(results: &MiResults) -> &'static str {
+    match get_str(results, "reason").unwrap_or_default() {
+        // `-exec-run --start`在入口设置的临时断点
+        "breakpoint-hit" if get_str(results, "disp") == Some("del") => "entry",
+        "breakpoint-hit" => "breakpoint",
+        "end-stepping-range" | "function-finished" | "location-reached" => "step",
+        "signal-received" if get_str(results, "signal-name") != Some("SIGINT") => "exception",
+        _ => "pause",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dap::{mi::parse_record, protocol::read_message};
+
+    use super::*;
+
+    fn request(seq: i64, command: &str, arguments: Value) -> Request {
+        Request {
+            seq,
+            command: command.to_string(),
+            arguments,
+        }
+    }
+
+    fn results(line: &str) -> MiResults {
+        match parse_record(line) {
+            MiRecord::Result { results, .. } | MiRecord::Async { results, .. } => results,
+            r => panic!("unexpected record {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_adapter_without_debugger() {
+        let mut adapter = Adapter::new(vec![]);
+        assert!(adapter.handle_request(request(1, "initialize", json!({}))));
+        assert!(adapter.handle_request(request(2, "threads", Value::Null)));
+        assert!(adapter.handle_request(request(
+            3,
+            "setBreakpoints",
+            json!({"source": {"path": "/a/main.pi"}, "breakpoints": [{"line": 3}]})
+        )));
+        assert!(adapter.handle_request(request(4, "unknown", Value::Null)));
+        adapter.handle_record(parse_record("hello"));
+        assert!(!adapter.handle_request(request(5, "disconnect", Value::Null)));
+
+        let mut r = std::io::Cursor::new(adapter.out);
+        let mut msgs = vec![];
+        while let Some(msg) = read_message(&mut r).unwrap() {
+            msgs.push(msg);
+        }
+        let resp = |i: usize| match &msgs[i] {
+            ProtocolMessage::Response(r) => r.clone(),
+            m => panic!("expect response, got {:?}", m),
+        };
+        assert!(resp(0).success);
+        assert_eq!(resp(0).body["supportsConfigurationDoneRequest"], true);
+        assert_eq!(resp(1).body, json!({ "threads": [] }));
+        // 还没有启动调试器
+        assert!(!resp(2).success);
+        assert!(!resp(3).success);
+        assert!(matches!(
+            &msgs[4],
+            ProtocolMessage::Event(Event { event, body, .. })
+                if event == "output" && body["output"] == "hello\n"
+        ));
+        assert!(resp(5).success);
+        assert_eq!(resp(5).request_seq, 5);
+    }
+
+    #[test]
+    fn test_mi_conversion() {
+        let source = Source {
+            name: None,
+            path: Some("/a/main.pi".to_string()),
+        };
+        let res = results(
+            r#"^done,bkpt={number="2",type="breakpoint",addr="0x1139",file="main.pi",fullname="/a/main.pi",line="5"}"#,
+        );
+        let bp = breakpoint_from_mi(get(&res, "bkpt").unwrap(), &source, 4);
+        assert_eq!(bp.id, Some(2));
+        assert!(bp.verified);
+        assert_eq!(bp.line, Some(5));
+        let res = results(r#"^done,bkpt={number="3",addr="<PENDING>",pending="/a/main.pi:9"}"#);
+        let bp = breakpoint_from_mi(get(&res, "bkpt").unwrap(), &source, 9);
+        assert!(!bp.verified);
+        assert_eq!(bp.line, Some(9));
+
+        let res = results(
+            r#"^done,stack=[frame={level="0",func="add",file="main.pi",fullname="/a/main.pi",line="3"},frame={level="1",addr="0x1",func="__libc_start_main"}]"#,
+        );
+        let stack = get(&res, "stack").unwrap().as_list();
+        let f = frame_from_mi(&stack[0], 7);
+        assert_eq!((f.id, f.name.as_str(), f.line), (7, "add", 3));
+        assert_eq!(f.source.unwrap().path.unwrap(), "/a/main.pi");
+        let f = frame_from_mi(&stack[1], 8);
+        assert!(f.source.is_none());
+        assert_eq!(f.presentation_hint.unwrap(), "subtle");
+
+        let reason = |line| stop_reason(&results(line));
+        assert_eq!(
+            reason(r#"*stopped,reason="breakpoint-hit",disp="keep",bkptno="1""#),
+            "breakpoint"
+        );
+        assert_eq!(
+            reason(r#"*stopped,reason="breakpoint-hit",disp="del",bkptno="1""#),
+            "entry"
+        );
+        assert_eq!(reason(r#"*stopped,reason="end-stepping-range""#), "step");
+        assert_eq!(
+            reason(r#"*stopped,reason="signal-received",signal-name="SIGSEGV""#),
+            "exception"
+        );
+        assert_eq!(
+            reason(r#"*stopped,reason="signal-received",signal-name="SIGINT""#),
+            "pause"
+        );
+    }
+}
@@ -0,0 +1,499 @@
+//! # mi
+//! gdb与lldb-mi的机器接口(GDB/MI)
+//!
+//! 调试器的每一行输出是一条记录：
+//! - `^`开头的是命令的结果，前面可以带上命令的编号
+//! - `*`、`+`、`=`开头的是异步通知，比如程序停在了断点上
+//! - `~`、`@`、`&`开头的是调试器输出的文本
+//!
+//! 被调试的程序和调试器共用标准输出，无法解析为记录的行是程序的输出
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    thread,
+};
+
+use crossbeam_channel::{unbounded, Receiver};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MiValue {
+    Const(String),
+    Tuple(Vec<(String, MiValue)>),
+    /// 列表中的元素可以是`name=value`的形式，此时丢弃名字，同一个列表中的名字总是相同的
+    List(Vec<MiValue>),
+}
+
+impl MiValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MiValue::Const(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&MiValue> {
+        match self {
+            MiValue::Tuple(results) => get(results, key),
+            _ => None,
+        }
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key)?.as_str()
+    }
+
+    pub fn as_list(&self) -> &[MiValue] {
+        match self {
+            MiValue::List(l) => l,
+            _ => &[],
+        }
+    }
+}
+
+pub type MiResults = Vec<(String, MiValue)>;
+
+pub fn get<'a>(results: &'a [(String, MiValue)], key: &str) -> Option<&'a MiValue> {
+    results.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+pub fn get_str<'a>(results: &'a [(String, MiValue)], key: &str) -> Option<&'a str> {
+    get(results, key)?.as_str()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsyncKind {
+    /// `*`，程序运行状态的变化
+    Exec,
+    /// `+`
+    Status,
+    /// `=`，断点、线程等的变化
+    Notify,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    /// `~`，调试器的命令行输出
+    Console,
+    /// `@`，被调试程序的输出
+    Target,
+    /// `&`，调试器的日志
+    Log,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MiRecord {
+    Result {
+        token: Option<u64>,
+        class: String,
+        results: MiResults,
+    },
+    Async {
+        token: Option<u64>,
+        kind: AsyncKind,
+        class: String,
+        results: MiResults,
+    },
+    Stream {
+        kind: StreamKind,
+        text: String,
+    },
+    /// `(gdb)`，一组输出结束
+    Prompt,
+    /// 被调试程序的输出
+    Output(String),
+}
+
+/// 解析一行输出
+pub fn parse_record(line: &str) -> MiRecord {
+    let trimmed = line.trim_end_matches(['\r', '\n']);
+    if trimmed.trim_end() == "(gdb)" {
+        return MiRecord::Prompt;
+    }
+    parse_record_inner(trimmed).unwrap_or_else(|| MiRecord::Output(line.to_string()))
+}
+
+fn parse_record_inner(line: &str) -> Option<MiRecord> {
+    let digits = line.bytes().take_while(|b| b.is_ascii_digit()).count();
+    let token = if digits > 0 {
+        Some(line[..digits].parse().ok()?)
+    } else {
+        None
+    };
+    let rest = &line[digits..];
+    let mut chars = rest.chars();
+    let first = chars.next()?;
+    let body = chars.as_str();
+    let stream = |kind| {
+        if token.is_some() {
+            return None;
+        }
+        let mut p = Parser::new(body);
+        let text = p.c_string()?;
+        p.eof().then_some(MiRecord::Stream { kind, text })
+    };
+    match first {
+        '^' => {
+            let (class, results) = parse_class_results(body)?;
+            Some(MiRecord::Result {
+                token,
+                class,
+                results,
+            })
+        }
+        '*' | '+' | '=' => {
+            let kind = match first {
+                '*' => AsyncKind::Exec,
+                '+' => AsyncKind::Status,
+                _ => AsyncKind::Notify,
+            };
+            let (class, results) = parse_class_results(body)?;
+            Some(MiRecord::Async {
+                token,
+                kind,
+                class,
+                results,
+            })
+        }
+        '~' => stream(StreamKind::Console),
+        '@' => stream(StreamKind::Target),
+        '&' => stream(StreamKind::Log),
+        _ => None,
+    }
+}
+
+fn parse_class_results(body: &str) -> Option<(String, MiResults)> {
+    let mut p = Parser::new(body);
+    let class = p.ident()?;
+    if class.is_empty() {
+        return None;
+    }
+    let mut results = vec![];
+    while p.eat(',') {
+        results.push(p.result()?);
+    }
+    p.eof().then_some((class, results))
+}
+
+struct Parser<'a> {
+    s: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn new(s: &'a str) -> Self {
+        Self { s }
+    }
+
+    fn eof(&self) -> bool {
+        self.s.is_empty()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s.chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.s = &self.s[c.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        let len = self
+            .s
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(self.s.len());
+        let id = self.s[..len].to_string();
+        self.s = &self.s[len..];
+        Some(id)
+    }
+
+    fn result(&mut self) -> Option<(String, MiValue)> {
+        let name = self.ident()?;
+        if name.is_empty() || !self.eat('=') {
+            return None;
+        }
+        Some((name, self.value()?))
+    }
+
+    fn value(&mut self) -> Option<MiValue> {
+        match self.peek()? {
+            '"' => Some(MiValue::Const(self.c_string()?)),
+            '{' => {
+                self.eat('{');
+                let mut results = vec![];
+                if !self.eat('}') {
+                    loop {
+                        results.push(self.result()?);
+                        if self.eat('}') {
+                            break;
+                        }
+                        if !self.eat(',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(MiValue::Tuple(results))
+            }
+            '[' => {
+                self.eat('[');
+                let mut items = vec![];
+                if !self.eat(']') {
+                    loop {
+                        let item = match self.peek()? {
+                            '"' | '{' | '[' => self.value()?,
+                            _ => self.result()?.1,
+                        };
+                        items.push(item);
+                        if self.eat(']') {
+                            break;
+                        }
+                        if !self.eat(',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(MiValue::List(items))
+            }
+            _ => None,
+        }
+    }
+
+    /// c风格的字符串，处理转义
+    fn c_string(&mut self) -> Option<String> {
+        if !self.eat('"') {
+            return None;
+        }
+        let mut res = String::new();
+        let mut chars = self.s.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.s = &self.s[i + 1..];
+                    return Some(res);
+                }
+                '\\' => {
+                    let (_, e) = chars.next()?;
+                    res.push(match e {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'e' => '\x1b',
+                        '0' => '\0',
+                        _ => e,
+                    });
+                }
+                _ => res.push(c),
+            }
+        }
+        None
+    }
+}
+
+/// 把字符串转成mi命令参数，需要时加上引号
+pub fn quote(s: &str) -> String {
+    if !s.is_empty()
+        && !s
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\\')
+    {
+        return s.to_string();
+    }
+    let mut res = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            _ => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// 和调试器进程的连接
+pub struct MiSession {
+    child: Child,
+    stdin: ChildStdin,
+    records: Receiver<MiRecord>,
+    token: u64,
+    /// 等待命令结果时收到的其它记录
+    pending: VecDeque<MiRecord>,
+}
+
+impl MiSession {
+    /// 启动调试器，`debugger`的文件名中包含`lldb`时使用lldb-mi的参数
+    pub fn start(debugger: &str, cwd: Option<&str>) -> io::Result<Self> {
+        let mut cmd = Command::new(debugger);
+        if debugger.contains("lldb") {
+            cmd.arg("--interpreter");
+        } else {
+            cmd.args(["--interpreter=mi2", "--quiet", "--nx"]);
+        }
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, records) = unbounded();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                log::trace!("mi <- {}", line);
+                if sender.send(parse_record(&line)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            child,
+            stdin,
+            records,
+            token: 0,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// 执行一条命令并等待结果，结果是`^error`时返回错误信息
+    pub fn exec(&mut self, cmd: &str) -> Result<MiResults, String> {
+        self.token += 1;
+        let token = self.token;
+        log::trace!("mi -> {}{}", token, cmd);
+        writeln!(self.stdin, "{}{}", token, cmd)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| e.to_string())?;
+        loop {
+            let record = self
+                .records
+                .recv()
+                .map_err(|_| "debugger exited".to_string())?;
+            match record {
+                MiRecord::Result {
+                    token: Some(t),
+                    class,
+                    results,
+                } if t == token => {
+                    return match class.as_str() {
+                        "error" => Err(get_str(&results, "msg")
+                            .unwrap_or("unknown error")
+                            .to_string()),
+                        _ => Ok(results),
+                    };
+                }
+                MiRecord::Prompt => {}
+                other => self.pending.push_back(other),
+            }
+        }
+    }
+
+    /// 等待命令结果时收到的记录
+    pub fn take_pending(&mut self) -> Option<MiRecord> {
+        self.pending.pop_front()
+    }
+
+    /// 调试器的输出，调试器退出后通道关闭
+    pub fn records(&self) -> &Receiver<MiRecord> {
+        &self.records
+    }
+
+    pub fn kill(&mut self) {
+        _ = self.exec("-gdb-exit");
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_record() {
+        let r = parse_record(
+            r#"3^done,stack=[frame={level="0",func="main",file="main.pi",fullname="/a/main.pi",line="5"},frame={level="1",func="start"}]"#,
+        );
+        let results = match r {
+            MiRecord::Result {
+                token: Some(3),
+                class,
+                results,
+            } => {
+                assert_eq!(class, "done");
+                results
+            }
+            _ => panic!("expect result record, got {:?}", r),
+        };
+        let frames = get(&results, "stack").unwrap().as_list();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].get_str("fullname"), Some("/a/main.pi"));
+        assert_eq!(frames[1].get_str("line"), None);
+
+        assert_eq!(
+            parse_record(r#"*stopped,reason="breakpoint-hit",thread-id="1""#),
+            MiRecord::Async {
+                token: None,
+                kind: AsyncKind::Exec,
+                class: "stopped".to_string(),
+                results: vec![
+                    (
+                        "reason".to_string(),
+                        MiValue::Const("breakpoint-hit".to_string())
+                    ),
+                    ("thread-id".to_string(), MiValue::Const("1".to_string())),
+                ],
+            }
+        );
+        assert_eq!(
+            parse_record(r#"~"a \"quoted\"\tline\n""#),
+            MiRecord::Stream {
+                kind: StreamKind::Console,
+                text: "a \"quoted\"\tline\n".to_string(),
+            }
+        );
+        assert_eq!(parse_record("(gdb) "), MiRecord::Prompt);
+        // 列表中的值和空的元组、列表
+        let r = parse_record(r#"^done,groups=["i1","i2"],empty={},none=[]"#);
+        if let MiRecord::Result { results, .. } = r {
+            assert_eq!(
+                get(&results, "groups").unwrap().as_list(),
+                &[
+                    MiValue::Const("i1".to_string()),
+                    MiValue::Const("i2".to_string())
+                ]
+            );
+            assert_eq!(get(&results, "empty"), Some(&MiValue::Tuple(vec![])));
+            assert!(get(&results, "none").unwrap().as_list().is_empty());
+        } else {
+            panic!("expect result record");
+        }
+        // 程序自己的输出
+        assert_eq!(
+            parse_record("hello world"),
+            MiRecord::Output("hello world".to_string())
+        );
+        assert_eq!(
+            parse_record("^done,broken=\"x"),
+            MiRecord::Output("^done,broken=\"x".to_string())
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("a.pi:3"), "a.pi:3");
+        assert_eq!(quote("/a b/c.pi:3"), "\"/a b/c.pi:3\"");
+        assert_eq!(quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote(""), "\"\"");
+    }
+}
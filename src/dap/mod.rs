@@ -0,0 +1,74 @@
+//! # dap
+//! pivot-lang debug adapter entry
+//!
+//! 通过标准输入输出实现Debug Adapter Protocol，调试由本地的gdb或lldb-mi完成。
+//! 程序需要先用`plc build`编译，调试信息由[LLVMBuilder](crate::ast::builder::llvmbuilder::LLVMBuilder)生成
+//!
+//! current features:
+//! - launch
+//! - source breakpoints (with condition)
+//! - continue, step in/over/out and pause
+//! - threads and call stack
+//! - variables and evaluate, showing pivot-lang values (see [values])
+use std::{
+    error::Error,
+    io::{self, BufReader},
+    thread,
+};
+
+pub mod adapter;
+pub mod mi;
+pub mod protocol;
+pub mod values;
+
+use crossbeam_channel::{select, unbounded};
+
+use adapter::Adapter;
+use protocol::{read_message, ProtocolMessage};
+
+pub fn start_dap() -> Result<(), Box<dyn Error + Sync + Send>> {
+    log::info!("starting pivot-lang debug adapter");
+    let (sender, receiver) = unbounded();
+    thread::spawn(move || {
+        let mut stdin = BufReader::new(io::stdin());
+        loop {
+            match read_message(&mut stdin) {
+                Ok(Some(msg)) => {
+                    if sender.send(msg).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    log::error!("failed to read dap message: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+    let mut adapter = Adapter::new(io::stdout());
+    loop {
+        adapter.drain_pending();
+        let records = adapter.records();
+        select! {
+            recv(receiver) -> msg => match msg {
+                Ok(ProtocolMessage::Request(req)) => {
+                    log::info!("dap request {}", req.command);
+                    if !adapter.handle_request(req) {
+                        break;
+                    }
+                }
+                // 客户端对反向请求的响应
+                Ok(_) => {}
+                Err(_) => break,
+            },
+            recv(records) -> record => match record {
+                Ok(record) => adapter.handle_record(record),
+                Err(_) => adapter.debugger_exited(),
+            },
+        }
+    }
+    adapter.shutdown();
+    log::info!("shutting down debug adapter");
+    Ok(())
+}
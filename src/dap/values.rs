@@ -0,0 +1,138 @@
+//! # values
+//! 按pivot-lang的写法显示调试器中的值
+//!
+//! 调试器只知道调试信息中的c风格类型，这里根据[LLVMBuilder](crate::ast::builder::llvmbuilder::LLVMBuilder)
+//! 生成调试信息时使用的类型名和布局还原出pivot-lang的类型：
+//! - `gc::string`是带有`len`、`byte_len`和`data`字段的结构体`string`，显示为字符串字面量
+//! - 定长数组被包装在结构体`arr_wrapper`中，真正的数组是它的`array`字段
+//! - 动态数组是指向结构体`dyn_arr`的指针，`array`字段中的前`len`个元素是数组的内容
+//! - 闭包是结构体`closure`，只有函数指针和捕获环境两个字段
+
+/// 值的种类，决定了值怎么显示以及展开后有哪些子节点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    String,
+    Array,
+    DynArray,
+    Pointer,
+    Closure,
+    Other,
+}
+
+pub fn value_kind(ty: &str) -> ValueKind {
+    let ty = ty.trim();
+    if ty == "dyn_arr *" {
+        ValueKind::DynArray
+    } else if ty.ends_with('*') {
+        ValueKind::Pointer
+    } else if ty == "string" {
+        ValueKind::String
+    } else if ty == "arr_wrapper" {
+        ValueKind::Array
+    } else if ty == "closure" {
+        ValueKind::Closure
+    } else {
+        ValueKind::Other
+    }
+}
+
+/// c风格的类型名转为pivot-lang的写法：`i64 *`是`*i64`，`i64 [3]`是`[i64 * 3]`
+pub fn display_type(ty: &str) -> String {
+    let ty = ty.trim();
+    if let Some(elm) = ty.strip_suffix('*') {
+        return format!("*{}", display_type(elm));
+    }
+    if let Some(stripped) = ty.strip_suffix(']') {
+        if let Some((elm, size)) = stripped.rsplit_once('[') {
+            return format!("[{} * {}]", display_type(elm), size.trim());
+        }
+    }
+    ty.to_string()
+}
+
+/// gdb用`{1, 2}`显示数组，pivot-lang中是`[1, 2]`
+pub fn display_array(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut in_str = false;
+    let mut escaped = false;
+    for c in value.chars() {
+        if in_str {
+            res.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_str = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '{' => res.push('['),
+            '}' => res.push(']'),
+            '"' => {
+                in_str = true;
+                res.push(c);
+            }
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+/// 去掉gdb在指针的值前加上的类型：`(i64 *) 0x1234`是`0x1234`
+pub fn display_pointer(value: &str) -> String {
+    let value = value.trim();
+    match value.strip_prefix('(').and_then(|v| v.split_once(')')) {
+        Some((_, addr)) => addr.trim().to_string(),
+        None => value.to_string(),
+    }
+}
+
+pub fn is_null(value: &str) -> bool {
+    let addr = display_pointer(value);
+    addr == "0x0" || addr == "0"
+}
+
+/// `-data-read-memory-bytes`返回的十六进制内容解码为字符串字面量
+pub fn decode_string(hex: &str) -> Option<String> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    Some(format!("{:?}", String::from_utf8_lossy(&bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_values() {
+        assert_eq!(value_kind("string"), ValueKind::String);
+        assert_eq!(value_kind("string *"), ValueKind::Pointer);
+        assert_eq!(value_kind("arr_wrapper"), ValueKind::Array);
+        assert_eq!(value_kind("dyn_arr *"), ValueKind::DynArray);
+        assert_eq!(value_kind("dyn_arr **"), ValueKind::Pointer);
+        assert_eq!(value_kind("i64"), ValueKind::Other);
+        assert_eq!(display_type("i64 *"), "*i64");
+        assert_eq!(display_type("Point **"), "**Point");
+        assert_eq!(display_type("i64 [3]"), "[i64 * 3]");
+        assert_eq!(display_type("i64 *[2]"), "[*i64 * 2]");
+        assert_eq!(display_array("{1, 2, 3}"), "[1, 2, 3]");
+        assert_eq!(
+            display_array(r#"{{a = 1}, "x{\"}"}"#),
+            r#"[[a = 1], "x{\"}"]"#
+        );
+        assert_eq!(display_pointer("(i64 *) 0x7ffe1234"), "0x7ffe1234");
+        assert_eq!(display_pointer("0x10"), "0x10");
+        assert!(is_null("(string *) 0x0"));
+        assert!(!is_null("0x10"));
+        assert_eq!(decode_string("68690a").unwrap(), r#""hi\n""#);
+        assert_eq!(decode_string("e4bda0").unwrap(), r#""你""#);
+        assert_eq!(decode_string("6"), None);
+        assert_eq!(decode_string("zz"), None);
+    }
+}
@@ -0,0 +1,286 @@
+//! # code action
+//!
+//! 根据客户端传来的诊断生成quick fix。
+//!
+//! 编译器能确定的修复以[`QuickFix`](crate::ast::diag::QuickFix)的形式放在诊断的`data`里，
+//! 这里直接取出来；需要源码文本的修复（删除不可达语句）按诊断的code在这里生成。
+//! 找不到模块或符号时，在编译结果中查找定义了它们的模块，生成添加`use`的修复
+use std::collections::HashMap;
+
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Diagnostic, DiagnosticSeverity,
+    NumberOrString, Position, Range, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    ast::{
+        diag::{ErrorCode, WarnCode},
+        node::program::ProgramNode,
+        plmod::Mod,
+        pltype::PLType,
+    },
+    utils::read_config::Config,
+};
+
+use super::{
+    completion::{is_pub, use_insert_pos, use_path},
+    helpers::{find_mod, normalize, position_to_offset},
+};
+
+/// 添加`use`的修复最多给出的候选模块数量
+const MAX_USE_CANDIDATES: usize = 5;
+
+/// 生成添加`use`的修复时需要的编译结果
+pub struct ImportScope<'a> {
+    /// 从项目入口开始编译的结果
+    pub root: &'a Mod,
+    pub prog: &'a ProgramNode,
+    /// 当前文件的路径
+    pub file: &'a str,
+    pub config: &'a Config,
+    /// `Kagari.toml`所在的目录
+    pub config_root: &'a str,
+}
+
+/// 诊断是否是找不到模块或符号，只有这时才需要编译项目得到[`ImportScope`]
+pub fn needs_import_scope(d: &Diagnostic) -> bool {
+    d.source.as_deref() == Some("plsp")
+        && d.severity == Some(DiagnosticSeverity::ERROR)
+        && (d.code == Some(NumberOrString::Number(ErrorCode::UNRESOLVED_MODULE as i32))
+            || d.code == Some(NumberOrString::Number(ErrorCode::SYMBOL_NOT_FOUND as i32)))
+}
+
+pub fn code_actions(
+    src: &str,
+    uri: &Url,
+    diags: &[Diagnostic],
+    scope: Option<&ImportScope>,
+) -> Vec<CodeActionOrCommand> {
+    let mut actions = vec![];
+    for d in diags {
+        if d.source.as_deref() != Some("plsp") {
+            continue;
+        }
+        let mut fixes = vec![];
+        if d.code
+            == Some(NumberOrString::Number(
+                WarnCode::UNREACHABLE_STATEMENT as i32,
+            ))
+        {
+            fixes.push(CodeAction {
+                title: "remove unreachable statement".to_string(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(single_edit(uri, unreachable_range(src, d.range), "")),
+                ..Default::default()
+            });
+        } else if let Some(data) = &d.data {
+            fixes.extend(
+                serde_json::from_value::<Vec<CodeAction>>(data.clone()).unwrap_or_default(),
+            );
+        }
+        if let Some(scope) = scope.filter(|_| needs_import_scope(d)) {
+            fixes.extend(import_fixes(src, uri, d, scope));
+        }
+        for mut fix in fixes {
+            fix.diagnostics = Some(vec![d.clone()]);
+            actions.push(CodeActionOrCommand::CodeAction(fix));
+        }
+    }
+    actions
+}
+
+/// 找不到的模块或符号在其他模块中有公开定义时，添加`use`的修复
+///
+/// 只有路径的第一段找不到时才会为`UNRESOLVED_MODULE`生成修复；
+/// `SYMBOL_NOT_FOUND`的修复会同时把原来的路径替换成`模块名::符号`
+fn import_fixes(src: &str, uri: &Url, d: &Diagnostic, scope: &ImportScope) -> Vec<CodeAction> {
+    let start = position_to_offset(src, d.range.start);
+    let end = position_to_offset(src, d.range.end);
+    let text = match src.get(start..end) {
+        Some(text) => text,
+        None => return vec![],
+    };
+    let is_mod = d.code == Some(NumberOrString::Number(ErrorCode::UNRESOLVED_MODULE as i32));
+    if is_mod && src[..start].trim_end().ends_with("::") {
+        return vec![];
+    }
+    let name = text.rsplit("::").next().unwrap().trim();
+    let mods = scope.root.all_mods();
+    let cur = match find_mod(&mods, scope.file) {
+        Some(m) => m,
+        None => return vec![],
+    };
+    let insert_pos = use_insert_pos(scope.prog);
+    let mut fixes = vec![];
+    for m in &mods {
+        if fixes.len() >= MAX_USE_CANDIDATES {
+            break;
+        }
+        if normalize(&m.path) == normalize(&cur.path) {
+            continue;
+        }
+        let use_path = match use_path(&m.path, scope.config, scope.config_root) {
+            Some(p) => p,
+            None => continue,
+        };
+        let alias = use_path.rsplit("::").next().unwrap();
+        // 当前文件中已经有同名的模块
+        if cur.submods.contains_key(alias) {
+            continue;
+        }
+        let import = TextEdit::new(
+            Range::new(insert_pos, insert_pos),
+            format!("use {};\n", use_path),
+        );
+        if is_mod {
+            if alias == name {
+                fixes.push(CodeAction {
+                    title: format!("import `{}`", use_path),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(workspace_edit(uri, vec![import])),
+                    ..Default::default()
+                });
+            }
+        } else if m
+            .types
+            .get(name)
+            .map_or(false, |tp| is_exported(m, &tp.borrow()))
+        {
+            let replace = TextEdit::new(d.range, format!("{}::{}", alias, name));
+            fixes.push(CodeAction {
+                title: format!("import `{}` and use `{}::{}`", use_path, alias, name),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(workspace_edit(uri, vec![import, replace])),
+                ..Default::default()
+            });
+        }
+    }
+    fixes
+}
+
+/// 模块`m`中定义的公开符号
+fn is_exported(m: &Mod, tp: &PLType) -> bool {
+    match tp {
+        PLType::FN(f) => f.path == m.path && !f.fntype.method && is_pub(&f.fntype.modifier),
+        PLType::STRUCT(st) | PLType::TRAIT(st) => st.path == m.path && is_pub(&st.modifier),
+        PLType::ENUM(e) => e.path == m.path && is_pub(&e.modifier),
+        _ => false,
+    }
+}
+
+fn single_edit(uri: &Url, range: Range, text: &str) -> WorkspaceEdit {
+    workspace_edit(
+        uri,
+        vec![TextEdit {
+            range,
+            new_text: text.to_string(),
+        }],
+    )
+}
+
+fn workspace_edit(uri: &Url, edits: Vec<TextEdit>) -> WorkspaceEdit {
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), edits);
+    WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    }
+}
+
+/// 不可达语句需要删除的范围
+///
+/// 诊断范围不包含语句后的分号，这里把分号一起删掉；
+/// 如果语句独占若干行，整行删除
+fn unreachable_range(src: &str, range: Range) -> Range {
+    let lines: Vec<Vec<char>> = src.split('\n').map(|l| l.chars().collect()).collect();
+    let (ls, le) = (range.start.line as usize, range.end.line as usize);
+    if le >= lines.len() {
+        return range;
+    }
+    let end_line = &lines[le];
+    let mut end = (range.end.character as usize).min(end_line.len());
+    let ws = end_line[end..]
+        .iter()
+        .take_while(|c| c.is_whitespace())
+        .count();
+    if end_line.get(end + ws) == Some(&';') {
+        end += ws + 1;
+    }
+    let before_blank = lines[ls]
+        .iter()
+        .take(range.start.character as usize)
+        .all(|c| c.is_whitespace());
+    let after_blank = end_line[end..].iter().all(|c| c.is_whitespace());
+    if before_blank && after_blank {
+        let end = if le + 1 < lines.len() {
+            Position::new(le as u32 + 1, 0)
+        } else {
+            Position::new(le as u32, end_line.len() as u32)
+        };
+        return Range::new(Position::new(ls as u32, 0), end);
+    }
+    Range::new(range.start, Position::new(le as u32, end as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(sl: u32, sc: u32, el: u32, ec: u32) -> Range {
+        Range::new(Position::new(sl, sc), Position::new(el, ec))
+    }
+
+    #[test]
+    fn test_unreachable_range() {
+        let src = "fn f() void {\n    return;\n    let a = 1;\n}\n";
+        // 独占一行的语句连同分号和换行一起删除
+        assert_eq!(
+            unreachable_range(src, range(2, 4, 2, 13)),
+            range(2, 0, 3, 0)
+        );
+        let src = "fn f() void {\n    return; let a = 1; let b = 2;\n}\n";
+        assert_eq!(
+            unreachable_range(src, range(1, 12, 1, 21)),
+            range(1, 12, 1, 22)
+        );
+    }
+
+    #[test]
+    fn test_code_actions() {
+        let uri = Url::parse("file:///test.pi").unwrap();
+        let src = "fn f() void {\n    return;\n    let a = 1;\n}\n";
+        let mut unreachable = Diagnostic::new_with_code_number(
+            range(2, 4, 2, 13),
+            lsp_types::DiagnosticSeverity::WARNING,
+            WarnCode::UNREACHABLE_STATEMENT as i32,
+            Some("plsp".to_string()),
+            "unreachable statement".to_string(),
+        );
+        let fix = CodeAction {
+            title: "insert `;`".to_string(),
+            kind: Some(CodeActionKind::QUICKFIX),
+            edit: Some(single_edit(&uri, range(1, 10, 1, 10), ";")),
+            ..Default::default()
+        };
+        let mut with_data = unreachable.clone();
+        with_data.code = Some(NumberOrString::Number(0));
+        with_data.data = Some(serde_json::to_value(vec![fix.clone()]).unwrap());
+        let actions = code_actions(src, &uri, &[unreachable.clone(), with_data.clone()], None);
+        assert_eq!(actions.len(), 2);
+        if let CodeActionOrCommand::CodeAction(a) = &actions[0] {
+            assert_eq!(a.edit, Some(single_edit(&uri, range(2, 0, 3, 0), "")));
+            assert_eq!(a.diagnostics, Some(vec![unreachable.clone()]));
+        } else {
+            panic!("expect code action");
+        }
+        if let CodeActionOrCommand::CodeAction(a) = &actions[1] {
+            assert_eq!(a.edit, fix.edit);
+            assert_eq!(a.diagnostics, Some(vec![with_data]));
+        } else {
+            panic!("expect code action");
+        }
+        // 其他来源的诊断不处理
+        unreachable.source = Some("other".to_string());
+        assert!(code_actions(src, &uri, &[unreachable], None).is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+//! # completion
+//! 补全中和整个项目有关的部分：自动导入与`completionItem/resolve`
+//!
+//! 作用域内的补全在编译时由[Ctx](crate::ast::ctx::Ctx)生成。
+//! 这里在编译结果的基础上，补充项目中其它模块的公开符号，
+//! 选中后通过`additionalTextEdits`在文件中加入对应的`use`语句
+use std::path::Path;
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Documentation, InsertTextFormat, MarkupContent, MarkupKind,
+    Position, TextEdit,
+};
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::{
+        node::{program::ProgramNode, NodeEnum},
+        plmod::Mod,
+        pltype::PLType,
+        range::Range,
+        tokens::TokenType,
+    },
+    utils::read_config::{get_config_path, Config},
+};
+
+use super::helpers::{find_mod, normalize};
+
+/// 补全项的`data`，`completionItem/resolve`时用它找到符号并加上文档
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CompletionData {
+    /// 符号所在模块的文件路径
+    pub path: String,
+    /// 符号在模块中的名字
+    pub name: String,
+}
+
+impl CompletionData {
+    pub fn new(path: &str, name: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    pub fn to_value(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+/// 项目中尚未被当前文件导入的模块的公开符号
+///
+/// `root`是从项目入口开始编译的结果，`config_root`是`Kagari.toml`所在的目录
+pub fn auto_import_completions(
+    root: &Mod,
+    prog: &ProgramNode,
+    file: &str,
+    config: &Config,
+    config_root: &str,
+) -> Vec<CompletionItem> {
+    let mods = root.all_mods();
+    let cur = match find_mod(&mods, file) {
+        Some(m) => m,
+        None => return vec![],
+    };
+    let imported: FxHashSet<_> = cur.submods.values().map(|m| normalize(&m.path)).collect();
+    let insert_pos = use_insert_pos(prog);
+    let mut items = vec![];
+    for m in &mods {
+        let path = normalize(&m.path);
+        if path == normalize(&cur.path) || imported.contains(&path) {
+            continue;
+        }
+        let use_path = match use_path(&m.path, config, config_root) {
+            Some(p) => p,
+            None => continue,
+        };
+        let alias = use_path.rsplit("::").next().unwrap().to_string();
+        // 当前文件中已经有同名的模块
+        if cur.submods.contains_key(&alias) {
+            continue;
+        }
+        let edit = TextEdit::new(
+            lsp_types::Range::new(insert_pos, insert_pos),
+            format!("use {};\n", use_path),
+        );
+        for (name, tp) in m.types.iter() {
+            let (kind, insert_text) = match &*tp.borrow() {
+                PLType::FN(f) if f.path == m.path && !f.fntype.method => {
+                    if !is_pub(&f.fntype.modifier) {
+                        continue;
+                    }
+                    (CompletionItemKind::FUNCTION, f.gen_snippet())
+                }
+                PLType::STRUCT(st) if st.path == m.path && is_pub(&st.modifier) => {
+                    (CompletionItemKind::STRUCT, name.clone())
+                }
+                PLType::TRAIT(st) if st.path == m.path && is_pub(&st.modifier) => {
+                    (CompletionItemKind::INTERFACE, name.clone())
+                }
+                PLType::ENUM(e) if e.path == m.path && is_pub(&e.modifier) => {
+                    (CompletionItemKind::ENUM, name.clone())
+                }
+                _ => continue,
+            };
+            items.push(CompletionItem {
+                label: name.clone(),
+                kind: Some(kind),
+                detail: Some(format!("use {}", use_path)),
+                insert_text: Some(format!("{}::{}", alias, insert_text)),
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                additional_text_edits: Some(vec![edit.clone()]),
+                // 排在作用域内的符号之后
+                sort_text: Some(format!("~{}", name)),
+                data: CompletionData::new(&m.path, name).to_value(),
+                ..Default::default()
+            });
+        }
+    }
+    items
+}
+
+pub fn is_pub(modifier: &Option<(TokenType, Range)>) -> bool {
+    matches!(modifier, Some((TokenType::PUB, _)))
+}
+
+/// 模块文件对应的`use`路径，项目中的模块以项目名开头，依赖中的模块以依赖名开头
+pub fn use_path(path: &str, config: &Config, config_root: &str) -> Option<String> {
+    let path = normalize(path);
+    let mut roots = vec![(config.project.clone(), normalize(config_root))];
+    for (name, dep) in config.deps.iter().flatten() {
+        roots.push((name.clone(), normalize(&dep.path)));
+    }
+    roots.into_iter().find_map(|(name, root)| {
+        let rel = path.strip_prefix(&root).ok()?.with_extension("");
+        let segs = rel
+            .components()
+            .map(|c| c.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()?;
+        if segs.is_empty() {
+            return None;
+        }
+        Some(format!("{}::{}", name, segs.join("::")))
+    })
+}
+
+/// 新的`use`语句插入在最后一条`use`语句之后，没有时插入在文件开头
+pub fn use_insert_pos(prog: &ProgramNode) -> Position {
+    prog.uses
+        .iter()
+        .filter_map(|u| match &**u {
+            NodeEnum::UseNode(u) if u.complete && !u.ids.is_empty() => Some(u.range.end.line),
+            _ => None,
+        })
+        .max()
+        .map_or(Position::new(0, 0), |line| Position::new(line as u32, 0))
+}
+
+/// 为补全项加上符号的文档注释
+pub fn resolve_completion(root: &Mod, item: &mut CompletionItem) {
+    let data = match item
+        .data
+        .clone()
+        .and_then(|d| serde_json::from_value::<CompletionData>(d).ok())
+    {
+        Some(data) => data,
+        None => return,
+    };
+    let mods = root.all_mods();
+    let tp = match find_mod(&mods, &data.path).and_then(|m| m.types.get(&data.name)) {
+        Some(tp) => tp.clone(),
+        None => return,
+    };
+    let docs = match &*tp.borrow() {
+        PLType::FN(f) => doc_string(&f.doc),
+        PLType::STRUCT(st) | PLType::TRAIT(st) => doc_string(&st.doc),
+        PLType::ENUM(e) => doc_string(&e.doc),
+        _ => return,
+    };
+    if !docs.is_empty() {
+        item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: docs,
+        }));
+    }
+}
+
+pub fn doc_string(docs: &[Box<NodeEnum>]) -> String {
+    let mut s = String::new();
+    for doc in docs {
+        if let NodeEnum::Comment(c) = &**doc {
+            s.push_str(c.comment.trim());
+            s.push('\n');
+        }
+    }
+    s
+}
+
+/// `Kagari.toml`所在的目录
+pub fn config_root(file: &str) -> Option<String> {
+    let path = get_config_path(file.to_string()).ok()?;
+    Some(Path::new(&path).parent()?.to_str()?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::utils::read_config::Dependency;
+
+    use super::*;
+
+    #[test]
+    fn test_use_path() {
+        let root = normalize("test/lsp");
+        let root = root.to_str().unwrap();
+        let mut deps = BTreeMap::new();
+        deps.insert(
+            "dep".to_string(),
+            Dependency {
+                path: normalize("test/test").to_str().unwrap().to_string(),
+                ..Default::default()
+            },
+        );
+        let config = Config {
+            project: "test".to_string(),
+            entry: "test_completion.pi".to_string(),
+            deps: Some(deps),
+            ..Default::default()
+        };
+        assert_eq!(
+            use_path("test/lsp/mod2.pi", &config, root),
+            Some("test::mod2".to_string())
+        );
+        assert_eq!(
+            use_path("test/test/st.pi", &config, root),
+            Some("dep::st".to_string())
+        );
+        assert_eq!(use_path("test/main.pi", &config, root), None);
+    }
+}
@@ -0,0 +1,222 @@
+//! # diagnostics
+//! pull模式的诊断：`textDocument/diagnostic`与`workspace/diagnostic`
+//!
+//! lsp总是从项目入口开始编译，[Diagnostics]中包含了所有被编译到的文件，
+//! 所以修改一个文件后，依赖它的文件中新出现的错误也会出现在结果中
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use lsp_types::{
+    request::Request, Diagnostic, PartialResultParams, TextDocumentIdentifier, Url,
+    WorkDoneProgressParams,
+};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::ast::diag::PLDiag;
+
+use super::helpers::normalize;
+
+/// `textDocument/diagnostic`
+///
+/// 当前使用的lsp-types中没有pull diagnostics相关的定义，这里按照lsp 3.17规范自行定义
+pub enum DocumentDiagnosticRequest {}
+
+impl Request for DocumentDiagnosticRequest {
+    type Params = DocumentDiagnosticParams;
+    type Result = DocumentDiagnosticReport;
+    const METHOD: &'static str = "textDocument/diagnostic";
+}
+
+/// `workspace/diagnostic`
+pub enum WorkspaceDiagnosticRequest {}
+
+impl Request for WorkspaceDiagnosticRequest {
+    type Params = WorkspaceDiagnosticParams;
+    type Result = WorkspaceDiagnosticReport;
+    const METHOD: &'static str = "workspace/diagnostic";
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDiagnosticParams {
+    pub text_document: TextDocumentIdentifier,
+    pub identifier: Option<String>,
+    pub previous_result_id: Option<String>,
+    #[serde(flatten)]
+    pub work_done_progress_params: WorkDoneProgressParams,
+    #[serde(flatten)]
+    pub partial_result_params: PartialResultParams,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDiagnosticParams {
+    pub identifier: Option<String>,
+    pub previous_result_ids: Vec<PreviousResultId>,
+    #[serde(flatten)]
+    pub work_done_progress_params: WorkDoneProgressParams,
+    #[serde(flatten)]
+    pub partial_result_params: PartialResultParams,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PreviousResultId {
+    pub uri: Url,
+    pub value: String,
+}
+
+/// 单个文件的诊断结果，内容和上次相同时只返回`unchanged`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum DiagnosticReport {
+    #[serde(rename_all = "camelCase")]
+    Full {
+        result_id: String,
+        items: Vec<Diagnostic>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Unchanged { result_id: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDiagnosticReport {
+    #[serde(flatten)]
+    pub report: DiagnosticReport,
+    /// 项目中其它文件的诊断结果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub related_documents: Option<HashMap<Url, DiagnosticReport>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDocumentDiagnosticReport {
+    pub uri: Url,
+    pub version: Option<i64>,
+    #[serde(flatten)]
+    pub report: DiagnosticReport,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+pub struct WorkspaceDiagnosticReport {
+    pub items: Vec<WorkspaceDocumentDiagnosticReport>,
+}
+
+/// 按文件分组诊断，被编译到但没有诊断的文件对应空列表，这样客户端能清除旧的诊断
+pub fn group_diagnostics(diags: &[(String, Vec<PLDiag>)]) -> FxHashMap<String, Vec<Diagnostic>> {
+    let mut m = FxHashMap::<String, Vec<Diagnostic>>::default();
+    for (p, diags) in diags {
+        m.entry(p.clone()).or_default();
+        diags.iter().for_each(|x| x.get_diagnostic(p, &mut m));
+    }
+    m
+}
+
+/// 诊断内容的hash，客户端下次请求时带上它，内容没变就不用重新发送
+fn result_id(items: &[Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(items).unwrap().hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+fn report(items: Vec<Diagnostic>, previous: Option<&str>) -> DiagnosticReport {
+    let result_id = result_id(&items);
+    if previous == Some(result_id.as_str()) {
+        DiagnosticReport::Unchanged { result_id }
+    } else {
+        DiagnosticReport::Full { result_id, items }
+    }
+}
+
+pub fn document_diagnostics(
+    diags: &[(String, Vec<PLDiag>)],
+    file: &str,
+    previous_result_id: Option<&str>,
+) -> DocumentDiagnosticReport {
+    let path = normalize(file);
+    let mut items = vec![];
+    let mut related = HashMap::new();
+    for (p, d) in group_diagnostics(diags) {
+        if normalize(&p) == path {
+            items = d;
+        } else if let Ok(uri) = Url::from_file_path(&p) {
+            related.insert(uri, report(d, None));
+        }
+    }
+    DocumentDiagnosticReport {
+        report: report(items, previous_result_id),
+        related_documents: if related.is_empty() {
+            None
+        } else {
+            Some(related)
+        },
+    }
+}
+
+pub fn workspace_diagnostics(
+    diags: &[(String, Vec<PLDiag>)],
+    previous: &[PreviousResultId],
+) -> WorkspaceDiagnosticReport {
+    let mut items = vec![];
+    for (p, d) in group_diagnostics(diags) {
+        let uri = match Url::from_file_path(&p) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        let previous = previous
+            .iter()
+            .find(|r| r.uri == uri)
+            .map(|r| r.value.as_str());
+        items.push(WorkspaceDocumentDiagnosticReport {
+            uri,
+            version: None,
+            report: report(d, previous),
+        });
+    }
+    items.sort_by(|a, b| a.uri.cmp(&b.uri));
+    WorkspaceDiagnosticReport { items }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{Position, Range};
+
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_report() {
+        let d = Diagnostic::new_simple(
+            Range::new(Position::new(0, 0), Position::new(0, 1)),
+            "err".to_string(),
+        );
+        let full = report(vec![d.clone()], None);
+        let id = match &full {
+            DiagnosticReport::Full { result_id, items } => {
+                assert_eq!(items, &vec![d.clone()]);
+                result_id.clone()
+            }
+            _ => panic!("expect full report"),
+        };
+        assert_eq!(
+            report(vec![d.clone()], Some(&id)),
+            DiagnosticReport::Unchanged {
+                result_id: id.clone()
+            }
+        );
+        assert!(matches!(
+            report(vec![], Some(&id)),
+            DiagnosticReport::Full { .. }
+        ));
+        let v = serde_json::to_value(DocumentDiagnosticReport {
+            report: DiagnosticReport::Unchanged { result_id: id },
+            related_documents: None,
+        })
+        .unwrap();
+        assert_eq!(v["kind"], "unchanged");
+        assert!(v["resultId"].is_string());
+        assert!(v.get("relatedDocuments").is_none());
+    }
+}
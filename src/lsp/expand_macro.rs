@@ -0,0 +1,46 @@
+//! # expand_macro
+//! 自定义请求`pivot/expandMacro`，返回光标处的宏调用展开后的代码
+//!
+//! 宏展开后的代码在编译`MacroCallNode`时记录在[Mod::macro_expansions]中
+use std::ops::Bound::{Included, Unbounded};
+
+use lsp_types::{request::Request, TextDocumentPositionParams};
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{plmod::Mod, range::Pos};
+
+use super::helpers::find_mod;
+
+/// `pivot/expandMacro`
+pub enum ExpandMacroRequest {}
+
+impl Request for ExpandMacroRequest {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<ExpandedMacro>;
+    const METHOD: &'static str = "pivot/expandMacro";
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ExpandedMacro {
+    /// 宏的名字
+    pub name: String,
+    /// 展开后的代码
+    pub expansion: String,
+}
+
+/// 光标在宏调用上时返回宏展开后的代码
+pub fn expand_macro(root: &Mod, file: &str, pos: Pos) -> Option<ExpandedMacro> {
+    let mods = root.all_mods();
+    let m = find_mod(&mods, file)?;
+    let range = pos.to(pos);
+    let expansions = m.macro_expansions.borrow();
+    // 嵌套的宏调用如`outer!(a, inner!(b), c)`，从后往前找到的第一个包含光标的调用是最内层的
+    let (_, (name, code)) = expansions
+        .range((Unbounded, Included(&range)))
+        .rev()
+        .find(|(r, _)| pos.is_in(**r))?;
+    Some(ExpandedMacro {
+        name: name.clone(),
+        expansion: code.clone(),
+    })
+}
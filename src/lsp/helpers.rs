@@ -1,6 +1,24 @@
 use crossbeam_channel::Sender;
 use lsp_server::{Message, RequestId};
-use lsp_types::{Diagnostic, DocumentSymbol, InlayHint, SemanticTokens, SemanticTokensDelta, Url};
+use lsp_types::{
+    notification::{Notification, Progress},
+    request::{InlayHintRefreshRequest, Request, WorkDoneProgressCreate},
+    Diagnostic, DocumentSymbol, InlayHint, NumberOrString, ProgressParams, ProgressParamsValue,
+    SemanticTokens, SemanticTokensDelta, Url, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressCreateParams, WorkDoneProgressEnd,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::ast::plmod::Mod;
+
+use super::{
+    diagnostics::{DocumentDiagnosticReport, WorkspaceDiagnosticReport},
+    expand_macro::ExpandedMacro,
+    hierarchy::TypeHierarchyItem,
+};
 
 pub fn send_diagnostics(sender: &Sender<Message>, uri: String, diagnostics: Vec<Diagnostic>) {
     sender
@@ -29,6 +47,19 @@ pub fn send_completions(
         .unwrap();
 }
 
+pub fn send_completion_resolve(
+    sender: &Sender<Message>,
+    id: RequestId,
+    item: lsp_types::CompletionItem,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(item).unwrap()),
+        )))
+        .unwrap();
+}
+
 pub fn send_goto_def(
     sender: &Sender<Message>,
     id: RequestId,
@@ -73,6 +104,30 @@ pub fn send_hints(sender: &Sender<Message>, id: RequestId, hints: Vec<InlayHint>
         .unwrap();
 }
 
+pub fn send_hint_resolve(sender: &Sender<Message>, id: RequestId, hint: InlayHint) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(hint).unwrap()),
+        )))
+        .unwrap();
+}
+
+/// 服务端发出的请求的编号
+static REFRESH_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// 配置变化后让客户端重新请求inlay hint
+pub fn send_inlay_hint_refresh(sender: &Sender<Message>) {
+    let id = REFRESH_ID.fetch_add(1, Ordering::Relaxed);
+    sender
+        .send(Message::Request(lsp_server::Request::new(
+            RequestId::from(format!("pivot-lang/inlayHint/refresh/{}", id)),
+            InlayHintRefreshRequest::METHOD.to_string(),
+            serde_json::Value::Null,
+        )))
+        .unwrap();
+}
+
 pub fn send_doc_symbols(sender: &Sender<Message>, id: RequestId, doc_symbols: Vec<DocumentSymbol>) {
     sender
         .send(Message::Response(lsp_server::Response::new_ok(
@@ -126,6 +181,259 @@ pub fn send_signature_help(
         .unwrap();
 }
 
+pub fn send_prepare_rename(
+    sender: &Sender<Message>,
+    id: RequestId,
+    resp: lsp_types::PrepareRenameResponse,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(resp).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_rename(sender: &Sender<Message>, id: RequestId, edit: lsp_types::WorkspaceEdit) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(edit).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_code_actions(
+    sender: &Sender<Message>,
+    id: RequestId,
+    actions: lsp_types::CodeActionResponse,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(actions).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_workspace_symbols(
+    sender: &Sender<Message>,
+    id: RequestId,
+    symbols: Vec<lsp_types::SymbolInformation>,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(symbols).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_call_hierarchy(
+    sender: &Sender<Message>,
+    id: RequestId,
+    items: Option<Vec<lsp_types::CallHierarchyItem>>,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(items).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_incoming_calls(
+    sender: &Sender<Message>,
+    id: RequestId,
+    calls: Vec<lsp_types::CallHierarchyIncomingCall>,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(calls).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_outgoing_calls(
+    sender: &Sender<Message>,
+    id: RequestId,
+    calls: Vec<lsp_types::CallHierarchyOutgoingCall>,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(calls).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_type_hierarchy(
+    sender: &Sender<Message>,
+    id: RequestId,
+    items: Option<Vec<TypeHierarchyItem>>,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(items).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_expand_macro(sender: &Sender<Message>, id: RequestId, expanded: Option<ExpandedMacro>) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(expanded).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_folding_ranges(
+    sender: &Sender<Message>,
+    id: RequestId,
+    ranges: Vec<lsp_types::FoldingRange>,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(ranges).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_selection_ranges(
+    sender: &Sender<Message>,
+    id: RequestId,
+    ranges: Vec<lsp_types::SelectionRange>,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(ranges).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_document_highlights(
+    sender: &Sender<Message>,
+    id: RequestId,
+    highlights: Option<Vec<lsp_types::DocumentHighlight>>,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(highlights).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_document_diagnostics(
+    sender: &Sender<Message>,
+    id: RequestId,
+    report: DocumentDiagnosticReport,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(report).unwrap()),
+        )))
+        .unwrap();
+}
+
+pub fn send_workspace_diagnostics(
+    sender: &Sender<Message>,
+    id: RequestId,
+    report: WorkspaceDiagnosticReport,
+) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_ok(
+            id,
+            Some(serde_json::to_value(report).unwrap()),
+        )))
+        .unwrap();
+}
+
+/// 请求在处理前被客户端取消了
+pub fn send_request_canceled(sender: &Sender<Message>, id: RequestId) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_err(
+            id,
+            lsp_server::ErrorCode::RequestCanceled as i32,
+            "request canceled".to_string(),
+        )))
+        .unwrap();
+}
+
+/// 请求的文件在处理前又被修改了，客户端收到后会重新请求
+pub fn send_content_modified(sender: &Sender<Message>, id: RequestId) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_err(
+            id,
+            lsp_server::ErrorCode::ContentModified as i32,
+            "content modified".to_string(),
+        )))
+        .unwrap();
+}
+
+/// 请求客户端创建一个进度条，之后用[send_progress_begin]和[send_progress_end]更新它
+pub fn send_progress_create(sender: &Sender<Message>, token: &str) {
+    sender
+        .send(Message::Request(lsp_server::Request::new(
+            RequestId::from(token.to_string()),
+            WorkDoneProgressCreate::METHOD.to_string(),
+            WorkDoneProgressCreateParams {
+                token: NumberOrString::String(token.to_string()),
+            },
+        )))
+        .unwrap();
+}
+
+pub fn send_progress_begin(sender: &Sender<Message>, token: &str, title: &str) {
+    send_progress(
+        sender,
+        token,
+        WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: title.to_string(),
+            cancellable: Some(false),
+            message: None,
+            percentage: None,
+        }),
+    );
+}
+
+pub fn send_progress_end(sender: &Sender<Message>, token: &str) {
+    send_progress(
+        sender,
+        token,
+        WorkDoneProgress::End(WorkDoneProgressEnd { message: None }),
+    );
+}
+
+fn send_progress(sender: &Sender<Message>, token: &str, progress: WorkDoneProgress) {
+    sender
+        .send(Message::Notification(lsp_server::Notification::new(
+            Progress::METHOD.to_string(),
+            ProgressParams {
+                token: NumberOrString::String(token.to_string()),
+                value: ProgressParamsValue::WorkDone(progress),
+            },
+        )))
+        .unwrap();
+}
+
+/// 请求无法完成时返回错误，客户端会把`message`展示给用户
+pub fn send_request_failed(sender: &Sender<Message>, id: RequestId, message: String) {
+    sender
+        .send(Message::Response(lsp_server::Response::new_err(
+            id,
+            lsp_server::ErrorCode::RequestFailed as i32,
+            message,
+        )))
+        .unwrap();
+}
+
 pub fn url_to_path(url: Url) -> String {
     dunce::canonicalize(url.to_file_path().unwrap().to_str().unwrap())
         .expect("file not exists")
@@ -134,6 +442,17 @@ pub fn url_to_path(url: Url) -> String {
         .to_string()
 }
 
+/// 文件路径规范化，用于比较不同来源的路径是否指向同一个文件
+pub fn normalize(path: &str) -> PathBuf {
+    dunce::canonicalize(path).unwrap_or_else(|_| Path::new(path).to_path_buf())
+}
+
+/// 在模块列表中找到文件对应的模块
+pub fn find_mod<'a>(mods: &'a [Mod], file: &str) -> Option<&'a Mod> {
+    let path = normalize(file);
+    mods.iter().find(|m| normalize(&m.path) == path)
+}
+
 pub fn position_to_offset(doc: &str, pos: lsp_types::Position) -> usize {
     let le = LinesWithEndings::from(doc);
     let (line, col) = (pos.line as usize, pos.character as usize);
@@ -152,6 +471,16 @@ pub fn position_to_offset(doc: &str, pos: lsp_types::Position) -> usize {
     offset
 }
 
+/// [position_to_offset]的逆运算
+pub fn offset_to_position(doc: &str, offset: usize) -> lsp_types::Position {
+    let before = &doc[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    lsp_types::Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].chars().count() as u32,
+    }
+}
+
 /// Iterator yielding every line in a string. The line includes newline character(s).
 pub struct LinesWithEndings<'a> {
     input: &'a str,
@@ -0,0 +1,323 @@
+//! # hierarchy
+//! call hierarchy与type hierarchy
+//!
+//! 函数调用关系来自编译`FuncCallNode`时记录在[Mod::calls]中的调用点，
+//! trait的实现关系来自[Mod::impls]与trait的derives。
+//! 和rename一样，遍历入口模块的依赖就能覆盖整个项目
+use std::{cell::RefCell, sync::Arc};
+
+use lsp_types::{
+    request::Request, CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall,
+    PartialResultParams, SymbolKind, TextDocumentPositionParams, Url, WorkDoneProgressParams,
+};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{
+    plmod::{FnRef, Mod},
+    pltype::PLType,
+    range::{Pos, Range},
+};
+
+use super::helpers::find_mod;
+
+fn uri_to_path(uri: &Url) -> Option<String> {
+    Some(uri.to_file_path().ok()?.to_str()?.to_string())
+}
+
+fn call_item(f: &FnRef) -> CallHierarchyItem {
+    CallHierarchyItem {
+        name: f.name.clone(),
+        kind: if f.method {
+            SymbolKind::METHOD
+        } else {
+            SymbolKind::FUNCTION
+        },
+        tags: None,
+        detail: None,
+        uri: Url::from_file_path(&f.path).unwrap(),
+        range: f.range.to_diag_range(),
+        selection_range: f.id_range.to_diag_range(),
+        data: None,
+    }
+}
+
+fn is_item(item: &CallHierarchyItem, f: &FnRef) -> bool {
+    f.range.to_diag_range() == item.range
+        && Url::from_file_path(&f.path).map_or(false, |uri| uri == item.uri)
+}
+
+/// 按函数分组调用点
+fn push_call(calls: &mut Vec<(FnRef, Vec<lsp_types::Range>)>, f: &FnRef, range: Range) {
+    let range = range.to_diag_range();
+    if let Some((_, ranges)) = calls.iter_mut().find(|(c, _)| c == f) {
+        ranges.push(range);
+    } else {
+        calls.push((f.clone(), vec![range]));
+    }
+}
+
+/// 光标在函数调用或者函数定义的函数名上时返回对应的函数
+pub fn prepare_call_hierarchy(root: &Mod, file: &str, pos: Pos) -> Option<Vec<CallHierarchyItem>> {
+    let mods = root.all_mods();
+    let m = find_mod(&mods, file)?;
+    let callee = m
+        .calls
+        .borrow()
+        .iter()
+        .find(|(r, _)| pos.is_in(**r))
+        .map(|(_, f)| f.clone());
+    let f = callee.or_else(|| m.fn_defs().into_iter().find(|f| pos.is_in(f.id_range)))?;
+    Some(vec![call_item(&f)])
+}
+
+pub fn incoming_calls(root: &Mod, item: &CallHierarchyItem) -> Vec<CallHierarchyIncomingCall> {
+    let mut calls = vec![];
+    for m in root.all_mods() {
+        let defs = m.fn_defs();
+        for (r, callee) in m.calls.borrow().iter() {
+            if !is_item(item, callee) {
+                continue;
+            }
+            // 包含调用点的函数，`defs`按起始位置排序，最后一个就是最内层的
+            if let Some(caller) = defs.iter().filter(|f| r.start.is_in(f.range)).last() {
+                push_call(&mut calls, caller, *r);
+            }
+        }
+    }
+    calls
+        .into_iter()
+        .map(|(f, from_ranges)| CallHierarchyIncomingCall {
+            from: call_item(&f),
+            from_ranges,
+        })
+        .collect()
+}
+
+pub fn outgoing_calls(root: &Mod, item: &CallHierarchyItem) -> Vec<CallHierarchyOutgoingCall> {
+    let mods = root.all_mods();
+    let m = match uri_to_path(&item.uri).and_then(|p| find_mod(&mods, &p)) {
+        Some(m) => m,
+        None => return vec![],
+    };
+    let range = Range::from_diag_range(item.range);
+    let mut calls = vec![];
+    for (r, callee) in m.calls.borrow().iter() {
+        if r.start.is_in(range) {
+            push_call(&mut calls, callee, *r);
+        }
+    }
+    calls
+        .into_iter()
+        .map(|(f, from_ranges)| CallHierarchyOutgoingCall {
+            to: call_item(&f),
+            from_ranges,
+        })
+        .collect()
+}
+
+/// `textDocument/prepareTypeHierarchy`
+///
+/// 当前使用的lsp-types中没有type hierarchy相关的定义，这里按照lsp 3.17规范自行定义
+pub enum TypeHierarchyPrepare {}
+
+impl Request for TypeHierarchyPrepare {
+    type Params = TypeHierarchyPrepareParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "textDocument/prepareTypeHierarchy";
+}
+
+/// `typeHierarchy/supertypes`
+pub enum TypeHierarchySupertypes {}
+
+impl Request for TypeHierarchySupertypes {
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "typeHierarchy/supertypes";
+}
+
+/// `typeHierarchy/subtypes`
+pub enum TypeHierarchySubtypes {}
+
+impl Request for TypeHierarchySubtypes {
+    type Params = TypeHierarchyParams;
+    type Result = Option<Vec<TypeHierarchyItem>>;
+    const METHOD: &'static str = "typeHierarchy/subtypes";
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeHierarchyPrepareParams {
+    #[serde(flatten)]
+    pub text_document_position_params: TextDocumentPositionParams,
+    #[serde(flatten)]
+    pub work_done_progress_params: WorkDoneProgressParams,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeHierarchyParams {
+    pub item: TypeHierarchyItem,
+    #[serde(flatten)]
+    pub work_done_progress_params: WorkDoneProgressParams,
+    #[serde(flatten)]
+    pub partial_result_params: PartialResultParams,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeHierarchyItem {
+    pub name: String,
+    pub kind: SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: Url,
+    pub range: lsp_types::Range,
+    pub selection_range: lsp_types::Range,
+    /// 类型的全名，形如`path..Name`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// 项目中所有的结构体和trait，以及它们之间的实现关系
+struct TypeGraph {
+    /// 全名 -> 类型
+    types: FxHashMap<String, Arc<RefCell<PLType>>>,
+    /// (子类型, 父类型)：结构体实现了trait，或者trait继承了另一个trait
+    edges: Vec<(String, String)>,
+}
+
+impl TypeGraph {
+    fn new(mods: &[Mod]) -> Self {
+        let mut types = FxHashMap::default();
+        let mut edges = vec![];
+        for m in mods {
+            for tp in m.types.values() {
+                if let PLType::STRUCT(st) | PLType::TRAIT(st) = &*tp.borrow() {
+                    if st.path == m.path {
+                        types.insert(st.get_st_full_name(), tp.clone());
+                    }
+                    if let PLType::TRAIT(_) = &*tp.borrow() {
+                        for de in &st.derives {
+                            edges.push((st.get_st_full_name(), de.borrow().get_full_elm_name()));
+                        }
+                    }
+                }
+            }
+            // `Mod::impls`中的名字是impl块里写的名字，需要在所在模块中解析
+            let prefix = format!("{}..", m.path);
+            for (st, traits) in &m.impls {
+                let st = match st.strip_prefix(&prefix).and_then(|st| resolve_type(m, st)) {
+                    Some(st) => st,
+                    None => continue,
+                };
+                let st = st.borrow().get_full_elm_name();
+                for t in traits {
+                    if let Some(t) = t.strip_prefix(&prefix).and_then(|t| resolve_type(m, t)) {
+                        edges.push((st.clone(), t.borrow().get_full_elm_name()));
+                    }
+                }
+            }
+        }
+        edges.sort();
+        edges.dedup();
+        Self { types, edges }
+    }
+
+    fn item(&self, name: &str) -> Option<TypeHierarchyItem> {
+        let tp = self.types.get(name)?;
+        let (st, kind) = match &*tp.borrow() {
+            PLType::STRUCT(st) => (st.clone(), SymbolKind::STRUCT),
+            PLType::TRAIT(st) => (st.clone(), SymbolKind::INTERFACE),
+            _ => return None,
+        };
+        Some(TypeHierarchyItem {
+            name: st.name.clone(),
+            kind,
+            detail: None,
+            uri: Url::from_file_path(&st.path).ok()?,
+            range: st.range.to_diag_range(),
+            selection_range: st.range.to_diag_range(),
+            data: Some(serde_json::Value::String(name.to_string())),
+        })
+    }
+
+    fn items(&self, names: impl Iterator<Item = String>) -> Vec<TypeHierarchyItem> {
+        names.filter_map(|n| self.item(&n)).collect()
+    }
+}
+
+/// 解析impl块中写的类型名，例如`A`、`A<T>`、`m::A`
+fn resolve_type(m: &Mod, name: &str) -> Option<Arc<RefCell<PLType>>> {
+    let name = name.split('<').next()?.trim_start_matches('*');
+    let mut segs: Vec<&str> = name.split("::").collect();
+    let id = segs.pop()?;
+    let mut m = m;
+    for s in segs {
+        m = m.submods.get(s)?;
+    }
+    m.get_type(id)
+}
+
+fn item_name(item: &TypeHierarchyItem) -> Option<String> {
+    match &item.data {
+        Some(serde_json::Value::String(name)) => Some(name.clone()),
+        _ => Some(format!("{}..{}", uri_to_path(&item.uri)?, item.name)),
+    }
+}
+
+/// 光标在结构体或trait的定义、引用上时返回对应的类型
+pub fn prepare_type_hierarchy(root: &Mod, file: &str, pos: Pos) -> Option<Vec<TypeHierarchyItem>> {
+    let mods = root.all_mods();
+    let graph = TypeGraph::new(&mods);
+    let m = find_mod(&mods, file)?;
+    let referred = m
+        .glob_refs
+        .borrow()
+        .iter()
+        .filter(|(r, _)| pos.is_in(**r))
+        .map(|(_, name)| name.split('<').next().unwrap().to_string())
+        .find(|name| graph.types.contains_key(name));
+    let name = referred.or_else(|| {
+        graph
+            .types
+            .iter()
+            .filter(|(_, tp)| {
+                matches!(&*tp.borrow(), PLType::STRUCT(st) | PLType::TRAIT(st)
+                    if st.path == m.path && pos.is_in(st.range))
+            })
+            .map(|(name, _)| name.clone())
+            .next()
+    })?;
+    Some(vec![graph.item(&name)?])
+}
+
+pub fn supertypes(root: &Mod, item: &TypeHierarchyItem) -> Vec<TypeHierarchyItem> {
+    let graph = TypeGraph::new(&root.all_mods());
+    let name = match item_name(item) {
+        Some(name) => name,
+        None => return vec![],
+    };
+    graph.items(
+        graph
+            .edges
+            .iter()
+            .filter(|(sub, _)| *sub == name)
+            .map(|(_, sup)| sup.clone()),
+    )
+}
+
+pub fn subtypes(root: &Mod, item: &TypeHierarchyItem) -> Vec<TypeHierarchyItem> {
+    let graph = TypeGraph::new(&root.all_mods());
+    let name = match item_name(item) {
+        Some(name) => name,
+        None => return vec![],
+    };
+    graph.items(
+        graph
+            .edges
+            .iter()
+            .filter(|(_, sup)| *sup == name)
+            .map(|(sub, _)| sub.clone()),
+    )
+}
@@ -0,0 +1,94 @@
+//! # implementation
+//! go to implementation
+//!
+//! 实现trait的impl块在编译时记录在[Mod::trait_impls]中，
+//! 和call hierarchy一样，遍历入口模块的依赖就能找到项目中所有的实现
+use std::ops::Bound::{Included, Unbounded};
+
+use lsp_types::{Location, Url};
+use rustc_hash::FxHashSet;
+
+use crate::ast::{plmod::Mod, pltype::PLType, range::Pos};
+
+use super::helpers::find_mod;
+
+/// 光标在trait上时返回所有实现它的impl块，在trait方法上时返回所有实现它的方法
+pub fn implementations(root: &Mod, file: &str, pos: Pos) -> Option<Vec<Location>> {
+    let mods = root.all_mods();
+    let traits = all_traits(&mods);
+    let m = find_mod(&mods, file)?;
+    let (trait_name, method) = target(m, &traits, pos)?;
+    let mut locs = vec![];
+    for m in &mods {
+        let uri = match Url::from_file_path(&m.path) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        for im in m.trait_impls.borrow().iter() {
+            if im.trait_name != trait_name {
+                continue;
+            }
+            match &method {
+                Some(method) => locs.extend(
+                    im.methods
+                        .iter()
+                        .filter(|(name, _)| name == method)
+                        .map(|(_, r)| Location::new(uri.clone(), r.to_diag_range())),
+                ),
+                None => locs.push(Location::new(uri.clone(), im.range.to_diag_range())),
+            }
+        }
+    }
+    Some(locs)
+}
+
+/// 项目中所有trait的全名
+fn all_traits(mods: &[Mod]) -> FxHashSet<String> {
+    let mut traits = FxHashSet::default();
+    for m in mods {
+        for tp in m.types.values() {
+            if let PLType::TRAIT(st) = &*tp.borrow() {
+                traits.insert(st.get_st_full_name());
+            }
+        }
+    }
+    traits
+}
+
+/// 光标处的trait全名以及trait方法名
+fn target(m: &Mod, traits: &FxHashSet<String>, pos: Pos) -> Option<(String, Option<String>)> {
+    let range = pos.to(pos);
+    let referred = m
+        .glob_refs
+        .borrow()
+        .range((Unbounded, Included(&range)))
+        .last()
+        .filter(|(r, _)| pos.is_in(**r))
+        .map(|(_, name)| name.split('<').next().unwrap().to_string());
+    if let Some(name) = referred {
+        if traits.contains(&name) {
+            return Some((name, None));
+        }
+        // trait方法的引用形如`path..Trait..method`
+        if let Some((tr, method)) = name.rsplit_once("..") {
+            if traits.contains(tr) {
+                return Some((tr.to_string(), Some(method.to_string())));
+            }
+        }
+    }
+    // 光标在当前模块的trait定义上
+    for tp in m.types.values() {
+        if let PLType::TRAIT(st) = &*tp.borrow() {
+            if st.path != m.path || !pos.is_in(st.range) {
+                continue;
+            }
+            let method = st
+                .fields
+                .values()
+                .find(|f| pos.is_in(f.range))
+                .map(|f| f.name.clone());
+            return Some((st.get_st_full_name(), method));
+        }
+    }
+    None
+}
@@ -0,0 +1,256 @@
+//! # inlay_hints
+//! inlay hint的配置与`inlayHint/resolve`
+//!
+//! 编译时[Ctx](crate::ast::ctx::Ctx)总是生成所有种类的提示，并在`data`中记录提示的种类，
+//! 返回给客户端之前再按照[InlayHintConfig]过滤，这样修改配置不需要重新编译。
+//! 标签上的悬停文本和定义位置也在编译时记录在`data`中，客户端resolve时才放进标签
+use lsp_types::{
+    InlayHint, InlayHintLabel, InlayHintLabelPart, InlayHintLabelPartTooltip, Location,
+    MarkupContent, MarkupKind, Url,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::ast::pltype::PLType;
+
+use super::completion::doc_string;
+
+/// 提示的种类，对应[InlayHintConfig]中的开关
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HintKind {
+    /// 变量的类型
+    Type,
+    /// 函数调用时的参数名
+    Parameter,
+    /// 换行的链式调用中每一段的类型
+    Chaining,
+    /// 推导出的泛型参数
+    Generic,
+    /// 访问字段或调用方法时自动解引用的层数
+    Deref,
+    /// 较长代码块的右花括号后显示代码块的名字
+    ClosingBrace,
+}
+
+/// inlay hint的`data`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HintData {
+    pub kind: HintKind,
+    /// 右花括号提示对应代码块的行数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<u32>,
+    /// resolve之后的标签，带有悬停文本和定义位置
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<InlayHintLabelPart>,
+}
+
+impl HintData {
+    pub fn new(kind: HintKind, parts: Vec<InlayHintLabelPart>) -> Self {
+        Self {
+            kind,
+            lines: None,
+            parts,
+        }
+    }
+
+    pub fn to_value(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+
+    pub fn from_hint(hint: &InlayHint) -> Option<Self> {
+        serde_json::from_value(hint.data.clone()?).ok()
+    }
+}
+
+/// 标签中的一段，悬停时显示`tooltip`，跳转到`location`
+pub fn label_part(
+    value: String,
+    tooltip: Option<String>,
+    location: Option<Location>,
+) -> InlayHintLabelPart {
+    InlayHintLabelPart {
+        value,
+        tooltip: tooltip.filter(|t| !t.is_empty()).map(|t| {
+            InlayHintLabelPartTooltip::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: t,
+            })
+        }),
+        location,
+        command: None,
+    }
+}
+
+/// 类型对应的标签，悬停时显示类型的文档，可以跳转到类型的定义
+pub fn type_label_part(value: String, pltype: &PLType) -> InlayHintLabelPart {
+    let location = pltype.get_type_def().and_then(|(path, range)| {
+        Some(Location::new(
+            Url::from_file_path(path).ok()?,
+            range.to_diag_range(),
+        ))
+    });
+    label_part(value, type_docs(pltype), location)
+}
+
+fn type_docs(pltype: &PLType) -> Option<String> {
+    match pltype {
+        PLType::STRUCT(st) | PLType::TRAIT(st) => Some(doc_string(&st.doc)),
+        PLType::ENUM(e) => Some(doc_string(&e.doc)),
+        PLType::FN(f) => Some(doc_string(&f.doc)),
+        PLType::POINTER(elm) => type_docs(&elm.borrow()),
+        _ => None,
+    }
+}
+
+/// inlay hint的配置，来自`initializationOptions`或`workspace/didChangeConfiguration`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InlayHintConfig {
+    pub type_hints: bool,
+    pub parameter_hints: bool,
+    pub chaining_hints: bool,
+    pub generic_hints: bool,
+    pub deref_hints: bool,
+    pub closing_brace_hints: bool,
+    /// 代码块至少有这么多行时才显示右花括号提示
+    pub closing_brace_min_lines: u32,
+}
+
+impl Default for InlayHintConfig {
+    fn default() -> Self {
+        Self {
+            type_hints: true,
+            parameter_hints: true,
+            chaining_hints: true,
+            generic_hints: true,
+            deref_hints: false,
+            closing_brace_hints: true,
+            closing_brace_min_lines: 25,
+        }
+    }
+}
+
+impl InlayHintConfig {
+    /// 配置可以在`pivot-lang.inlayHints`或`inlayHints`下
+    pub fn from_settings(settings: &serde_json::Value) -> Option<Self> {
+        let v = settings
+            .pointer("/pivot-lang/inlayHints")
+            .or_else(|| settings.get("inlayHints"))?;
+        serde_json::from_value(v.clone()).ok()
+    }
+
+    pub fn enabled(&self, data: &HintData) -> bool {
+        match data.kind {
+            HintKind::Type => self.type_hints,
+            HintKind::Parameter => self.parameter_hints,
+            HintKind::Chaining => self.chaining_hints,
+            HintKind::Generic => self.generic_hints,
+            HintKind::Deref => self.deref_hints,
+            HintKind::ClosingBrace => {
+                self.closing_brace_hints
+                    && data.lines.unwrap_or_default() >= self.closing_brace_min_lines
+            }
+        }
+    }
+
+    pub fn filter(&self, hints: Vec<InlayHint>) -> Vec<InlayHint> {
+        hints
+            .into_iter()
+            .filter(|h| HintData::from_hint(h).map_or(true, |d| self.enabled(&d)))
+            .collect()
+    }
+}
+
+/// 把`data`中记录的悬停文本和定义位置放进标签
+pub fn resolve_hint(hint: &mut InlayHint) {
+    if let Some(data) = HintData::from_hint(hint) {
+        if !data.parts.is_empty() {
+            hint.label = InlayHintLabel::LabelParts(data.parts);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Position;
+    use serde_json::json;
+
+    use super::*;
+
+    fn hint(data: HintData) -> InlayHint {
+        InlayHint {
+            position: Position::new(0, 0),
+            label: InlayHintLabel::String(": i64".to_string()),
+            kind: None,
+            text_edits: None,
+            tooltip: None,
+            padding_left: None,
+            padding_right: None,
+            data: data.to_value(),
+        }
+    }
+
+    #[test]
+    fn test_inlay_hint_config() {
+        let config = InlayHintConfig::from_settings(&json!({
+            "pivot-lang": {
+                "inlayHints": {
+                    "typeHints": false,
+                    "derefHints": true,
+                    "closingBraceMinLines": 2,
+                }
+            }
+        }))
+        .unwrap();
+        assert!(!config.type_hints);
+        assert!(config.deref_hints);
+        // 没有设置的项使用默认值
+        assert!(config.parameter_hints);
+        assert_eq!(
+            InlayHintConfig::from_settings(&json!({ "inlayHints": {} })),
+            Some(InlayHintConfig::default())
+        );
+        assert_eq!(InlayHintConfig::from_settings(&json!({})), None);
+
+        let mut brace = HintData::new(HintKind::ClosingBrace, vec![]);
+        brace.lines = Some(1);
+        let hints = vec![
+            hint(HintData::new(HintKind::Type, vec![])),
+            hint(HintData::new(HintKind::Deref, vec![])),
+            hint(brace.clone()),
+        ];
+        let filtered = config.filter(hints);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(
+            HintData::from_hint(&filtered[0]).unwrap().kind,
+            HintKind::Deref
+        );
+        brace.lines = Some(2);
+        assert!(config.enabled(&brace));
+    }
+
+    #[test]
+    fn test_resolve_hint() {
+        let part = label_part(
+            "i64".to_string(),
+            Some("docs".to_string()),
+            Some(Location::new(
+                Url::parse("file:///a.pi").unwrap(),
+                Default::default(),
+            )),
+        );
+        let mut h = hint(HintData::new(
+            HintKind::Type,
+            vec![label_part(": ".to_string(), None, None), part.clone()],
+        ));
+        resolve_hint(&mut h);
+        match h.label {
+            InlayHintLabel::LabelParts(parts) => {
+                assert_eq!(parts.len(), 2);
+                assert_eq!(parts[1], part);
+            }
+            _ => panic!("expect label parts"),
+        }
+    }
+}
@@ -2,39 +2,74 @@
 //! pivot-lang language server entry
 //! current features:
 //! - diagnostics
-//! - completion
+//! - completion (with auto import and resolve)
 //! - goto definition
+//! - goto type definition
+//! - goto declaration
+//! - goto implementation
 //! - find references
+//! - rename
+//! - code action
+//! - workspace symbol
+//! - call hierarchy
+//! - type hierarchy
+//! - folding range
+//! - selection range
+//! - document highlight
+//! - pull diagnostics (document and workspace)
+//! - request cancellation
+//! - configurable inlay hints (with resolve)
 use std::{
     cell::RefCell,
     error::Error,
     sync::{Arc, Mutex},
     thread::available_parallelism,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
+pub mod code_action;
+pub mod completion;
+pub mod diagnostics;
 pub mod dispatcher;
+pub mod expand_macro;
 pub mod helpers;
+pub mod hierarchy;
+pub mod implementation;
+pub mod inlay_hints;
 pub mod mem_docs;
+pub mod queue;
+pub mod rename;
 pub mod semantic_tokens;
+pub mod syntax;
 pub mod text;
+pub mod workspace_symbol;
 use log::debug;
 use lsp_types::{
-    notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument},
+    notification::{
+        DidChangeConfiguration, DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    },
     request::{
-        Completion, DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest,
-        InlayHintRequest, References, SemanticTokensFullDeltaRequest, SemanticTokensFullRequest,
-        SignatureHelpRequest,
+        CallHierarchyIncomingCalls, CallHierarchyOutgoingCalls, CallHierarchyPrepare,
+        CodeActionRequest, Completion, DocumentHighlightRequest, DocumentSymbolRequest,
+        FoldingRangeRequest, Formatting, GotoDeclaration, GotoDefinition, GotoImplementation,
+        GotoTypeDefinition, HoverRequest, InlayHintRequest, InlayHintResolveRequest,
+        PrepareRenameRequest, References, Rename, ResolveCompletionItem, SelectionRangeRequest,
+        SemanticTokensFullDeltaRequest, SemanticTokensFullRequest, SignatureHelpRequest,
+        WorkspaceSymbol,
     },
-    Diagnostic, Hover, HoverContents, InitializeParams, MarkedString, OneOf, SemanticTokenModifier,
-    SemanticTokenType, SemanticTokens, SemanticTokensDelta, SemanticTokensOptions,
-    ServerCapabilities, SignatureHelp, TextDocumentSyncKind, TextDocumentSyncOptions,
+    CallHierarchyServerCapability, CodeActionProviderCapability, DeclarationCapability,
+    FoldingRangeProviderCapability, GotoDefinitionResponse, Hover, HoverContents,
+    ImplementationProviderCapability, InitializeParams, InlayHintOptions,
+    InlayHintServerCapabilities, MarkedString, OneOf, RenameOptions,
+    SelectionRangeProviderCapability, SemanticTokenModifier, SemanticTokenType, SemanticTokens,
+    SemanticTokensDelta, SemanticTokensOptions, ServerCapabilities, SignatureHelp,
+    TextDocumentSyncKind, TextDocumentSyncOptions, TypeDefinitionProviderCapability,
 };
 
 use lsp_server::{Connection, Message};
 
 use mem_docs::MemDocs;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use threadpool::ThreadPool;
 
 use crate::{
@@ -48,17 +83,33 @@ use crate::{
     },
     db,
     lsp::{
+        diagnostics::{DocumentDiagnosticRequest, WorkspaceDiagnosticRequest},
         dispatcher::Dispatcher,
+        expand_macro::ExpandMacroRequest,
         helpers::{
-            send_completions, send_diagnostics, send_doc_symbols, send_format, send_goto_def,
-            send_hints, send_hover, send_references, send_semantic_tokens,
-            send_semantic_tokens_edit, send_signature_help, url_to_path,
+            send_call_hierarchy, send_code_actions, send_completion_resolve, send_completions,
+            send_content_modified, send_diagnostics, send_doc_symbols, send_document_diagnostics,
+            send_document_highlights, send_expand_macro, send_folding_ranges, send_format,
+            send_goto_def, send_hint_resolve, send_hints, send_hover, send_incoming_calls,
+            send_inlay_hint_refresh, send_outgoing_calls, send_prepare_rename, send_progress_begin,
+            send_progress_create, send_progress_end, send_references, send_rename,
+            send_request_canceled, send_request_failed, send_selection_ranges,
+            send_semantic_tokens, send_semantic_tokens_edit, send_signature_help,
+            send_type_hierarchy, send_workspace_diagnostics, send_workspace_symbols, url_to_path,
         },
+        hierarchy::{TypeHierarchyPrepare, TypeHierarchySubtypes, TypeHierarchySupertypes},
+        inlay_hints::InlayHintConfig,
         mem_docs::MemDocsInput,
+        queue::MessageQueue,
         semantic_tokens::diff_tokens,
+        workspace_symbol::workspace_symbols,
     },
+    utils::read_config::get_config_path,
 };
 
+/// 连续修改同一个文件时，等待下一次修改的时间
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
 pub fn start_lsp() -> Result<(), Box<dyn Error + Sync + Send>> {
     // Note that  we must have our logging only write out to stderr.
     log::info!("starting pivot-lang LSP server");
@@ -68,8 +119,11 @@ pub fn start_lsp() -> Result<(), Box<dyn Error + Sync + Send>> {
     let (connection, io_threads) = Connection::stdio();
 
     // Run the server and wait for the two threads to end (typically by trigger LSP Exit event).
-    let server_capabilities = serde_json::to_value(&ServerCapabilities {
+    let mut server_capabilities = serde_json::to_value(&ServerCapabilities {
         definition_provider: Some(OneOf::Left(true)),
+        type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
+        declaration_provider: Some(DeclarationCapability::Simple(true)),
+        implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
         document_formatting_provider: Some(OneOf::Left(true)),
         text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Options(
             TextDocumentSyncOptions {
@@ -78,16 +132,31 @@ pub fn start_lsp() -> Result<(), Box<dyn Error + Sync + Send>> {
                 ..Default::default()
             },
         )),
-        inlay_hint_provider: Some(OneOf::Left(true)),
+        inlay_hint_provider: Some(OneOf::Right(InlayHintServerCapabilities::Options(
+            InlayHintOptions {
+                work_done_progress_options: Default::default(),
+                resolve_provider: Some(true),
+            },
+        ))),
         completion_provider: Some(lsp_types::CompletionOptions {
             trigger_characters: Some(vec![".".to_string(), ":".to_string()]),
-            resolve_provider: None,
+            resolve_provider: Some(true),
             work_done_progress_options: Default::default(),
             all_commit_characters: None,
             completion_item: None,
         }),
         document_symbol_provider: Some(OneOf::Left(true)),
         references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
+        call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+        document_highlight_provider: Some(OneOf::Left(true)),
         hover_provider: Some(lsp_types::HoverProviderCapability::Simple(true)),
         signature_help_provider: Some(lsp_types::SignatureHelpOptions {
             trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
@@ -142,6 +211,12 @@ pub fn start_lsp() -> Result<(), Box<dyn Error + Sync + Send>> {
         ..Default::default()
     })
     .unwrap();
+    // lsp-types的ServerCapabilities中还没有这一项
+    server_capabilities["typeHierarchyProvider"] = true.into();
+    server_capabilities["diagnosticProvider"] = serde_json::json!({
+        "interFileDependencies": true,
+        "workspaceDiagnostics": true,
+    });
     let initialization_params = connection.initialize(server_capabilities)?;
     main_loop(connection, initialization_params)?;
     io_threads.join()?;
@@ -158,6 +233,22 @@ fn main_loop(
     let n_workers = available_parallelism().unwrap().get();
     let pool = ThreadPool::new(n_workers);
     let mut db = db::Database::default();
+    // 客户端支持pull模式的诊断时，不再主动推送诊断
+    let pull_diags = params
+        .pointer("/capabilities/textDocument/diagnostic")
+        .is_some();
+    let work_done = params
+        .pointer("/capabilities/window/workDoneProgress")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let hint_refresh = params
+        .pointer("/capabilities/workspace/inlayHint/refreshSupport")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let mut hint_config = params
+        .get("initializationOptions")
+        .and_then(InlayHintConfig::from_settings)
+        .unwrap_or_default();
     let _params: InitializeParams = serde_json::from_value(params).unwrap();
     let docs = Arc::new(Mutex::new(RefCell::new(MemDocs::new())));
     let docin = MemDocsInput::new(
@@ -171,16 +262,27 @@ fn main_loop(
     );
     let mut tokens = FxHashMap::default();
     let mut completions: Vec<Vec<lsp_types::CompletionItem>> = vec![];
+    // 已经分析过的项目，第一次打开项目中的文件时显示分析进度
+    let mut analyzed = FxHashSet::default();
+    let mut queue = MessageQueue::new(connection.receiver.clone());
 
     log::info!("starting main loop");
-    for msg in &connection.receiver {
+    while let Some(msg) = queue.next() {
         let now = Instant::now();
-        let di = Dispatcher::new(msg.clone());
         if let Message::Request(req) = &msg {
             if connection.handle_shutdown(req)? {
                 return Ok(());
             }
+            if queue.is_canceled(&req.id) {
+                send_request_canceled(&connection.sender, req.id.clone());
+                continue;
+            }
+            if queue.is_superseded(req) {
+                send_content_modified(&connection.sender, req.id.clone());
+                continue;
+            }
         }
+        let di = Dispatcher::new(msg.clone());
         di.on::<GotoDefinition, _>(|id, params| {
             let uri = url_to_path(params.text_document_position_params.text_document.uri);
             let pos = Pos::from_diag_pos(&params.text_document_position_params.position);
@@ -196,6 +298,54 @@ fn main_loop(
                 });
             }
         })
+        .on::<GotoTypeDefinition, _>(|id, params| {
+            let uri = url_to_path(params.text_document_position_params.text_document.uri);
+            let pos = Pos::from_diag_pos(&params.text_document_position_params.position);
+            docin.set_file(&mut db).to(uri);
+            docin.set_action(&mut db).to(ActionType::GotoTypeDef);
+            docin.set_params(&mut db).to(Some((pos, None)));
+            compile_dry(&db, docin);
+            let def = compile_dry::accumulated::<GotoDef>(&db, docin).pop();
+            let sender = connection.sender.clone();
+            pool.execute(move || {
+                send_goto_def(
+                    &sender,
+                    id,
+                    def.unwrap_or(GotoDefinitionResponse::Array(vec![])),
+                );
+            });
+        })
+        .on::<GotoDeclaration, _>(|id, params| {
+            let uri = url_to_path(params.text_document_position_params.text_document.uri);
+            let pos = Pos::from_diag_pos(&params.text_document_position_params.position);
+            docin.set_file(&mut db).to(uri);
+            docin.set_action(&mut db).to(ActionType::GotoDecl);
+            docin.set_params(&mut db).to(Some((pos, None)));
+            compile_dry(&db, docin);
+            let decl = compile_dry::accumulated::<GotoDef>(&db, docin).pop();
+            let sender = connection.sender.clone();
+            pool.execute(move || {
+                send_goto_def(
+                    &sender,
+                    id,
+                    decl.unwrap_or(GotoDefinitionResponse::Array(vec![])),
+                );
+            });
+        })
+        .on::<GotoImplementation, _>(|id, params| {
+            let doc = params.text_document_position_params;
+            let uri = url_to_path(doc.text_document.uri);
+            let pos = Pos::from_diag_pos(&doc.position);
+            docin.set_file(&mut db).to(uri.clone());
+            docin.set_action(&mut db).to(ActionType::Implementation);
+            let locs = compile_dry(&db, docin)
+                .and_then(|m| implementation::implementations(&m.plmod(&db), &uri, pos))
+                .unwrap_or_default();
+            let sender = connection.sender.clone();
+            pool.execute(move || {
+                send_goto_def(&sender, id, GotoDefinitionResponse::Array(locs));
+            });
+        })
         .on::<HoverRequest, _>(|id, params| {
             let uri = url_to_path(params.text_document_position_params.text_document.uri);
             let pos = Pos::from_diag_pos(&params.text_document_position_params.position);
@@ -242,6 +392,77 @@ fn main_loop(
                 send_references(&sender, id, &rf);
             });
         })
+        .on::<PrepareRenameRequest, _>(|id, params| {
+            let uri = url_to_path(params.text_document.uri);
+            let pos = Pos::from_diag_pos(&params.position);
+            docin.set_file(&mut db).to(uri.clone());
+            docin.set_action(&mut db).to(ActionType::Rename);
+            docin.set_params(&mut db).to(Some((pos, None)));
+            let re = compile_dry(&db, docin)
+                .ok_or_else(|| "failed to compile the project".to_string())
+                .and_then(|m| rename::prepare_rename(&db, docin, m.plmod(&db), &uri, pos));
+            let sender = connection.sender.clone();
+            pool.execute(move || match re {
+                Ok(resp) => send_prepare_rename(&sender, id, resp),
+                Err(e) => send_request_failed(&sender, id, e),
+            });
+        })
+        .on::<Rename, _>(|id, params| {
+            let doc = params.text_document_position;
+            let uri = url_to_path(doc.text_document.uri);
+            let pos = Pos::from_diag_pos(&doc.position);
+            docin.set_file(&mut db).to(uri.clone());
+            docin.set_action(&mut db).to(ActionType::Rename);
+            docin.set_params(&mut db).to(Some((pos, None)));
+            let re = compile_dry(&db, docin)
+                .ok_or_else(|| "failed to compile the project".to_string())
+                .and_then(|m| {
+                    rename::rename(&db, docin, m.plmod(&db), &uri, pos, &params.new_name)
+                });
+            let sender = connection.sender.clone();
+            pool.execute(move || match re {
+                Ok(edit) => send_rename(&sender, id, edit),
+                Err(e) => send_request_failed(&sender, id, e),
+            });
+        })
+        .on::<CodeActionRequest, _>(|id, params| {
+            let uri = params.text_document.uri;
+            let path = url_to_path(uri.clone());
+            let src = docs
+                .lock()
+                .unwrap()
+                .borrow_mut()
+                .get_file_content(&db, &path)
+                .map(|s| s.text(&db).clone())
+                .unwrap_or_default();
+            let diags = &params.context.diagnostics;
+            // 只有找不到模块或符号时才需要编译项目来查找可以导入的模块
+            let actions = if diags.iter().any(code_action::needs_import_scope) {
+                docin.set_file(&mut db).to(path.clone());
+                docin.set_action(&mut db).to(ActionType::Diagnostic);
+                compile_dry(&db, docin)
+                    .and_then(|m| {
+                        let prog = syntax::parse_program(&db, docin, &path)?;
+                        let config = docin.get_file_params(&db, path.clone(), false)?;
+                        let config = config.config(&db);
+                        let config_root = completion::config_root(&path)?;
+                        let root = m.plmod(&db);
+                        let scope = code_action::ImportScope {
+                            root: &root,
+                            prog: &prog,
+                            file: &path,
+                            config: &config,
+                            config_root: &config_root,
+                        };
+                        Some(code_action::code_actions(&src, &uri, diags, Some(&scope)))
+                    })
+                    .unwrap_or_else(|| code_action::code_actions(&src, &uri, diags, None))
+            } else {
+                code_action::code_actions(&src, &uri, diags, None)
+            };
+            let sender = connection.sender.clone();
+            pool.execute(move || send_code_actions(&sender, id, actions));
+        })
         .on::<Completion, _>(|id, params| {
             let uri = url_to_path(params.text_document_position.text_document.uri);
             let pos = Pos::from_diag_pos(&params.text_document_position.position);
@@ -249,19 +470,49 @@ fn main_loop(
             if params.context.is_some() {
                 trigger = params.context.unwrap().trigger_character;
             }
-            docin.set_file(&mut db).to(uri);
+            // `.`和`:`之后只补全成员，不需要自动导入
+            let auto_import = trigger.is_none();
+            docin.set_file(&mut db).to(uri.clone());
             docin.set_action(&mut db).to(ActionType::Completion);
             docin.set_edit_pos(&mut db).to(Some(pos));
             docin.set_params(&mut db).to(Some((pos, trigger)));
-            compile_dry(&db, docin);
+            let root = compile_dry(&db, docin);
             if !completions.is_empty() {
+                let mut comps = completions[0].clone();
+                if auto_import {
+                    let imports = root
+                        .and_then(|m| {
+                            let prog = syntax::parse_program(&db, docin, &uri)?;
+                            let config = docin.get_file_params(&db, uri.clone(), false)?;
+                            let config_root = completion::config_root(&uri)?;
+                            Some(completion::auto_import_completions(
+                                &m.plmod(&db),
+                                &prog,
+                                &uri,
+                                &config.config(&db),
+                                &config_root,
+                            ))
+                        })
+                        .unwrap_or_default();
+                    let labels: FxHashSet<_> = comps.iter().map(|c| c.label.clone()).collect();
+                    comps.extend(imports.into_iter().filter(|c| !labels.contains(&c.label)));
+                }
                 let sender = connection.sender.clone();
-                let comps = completions[0].clone();
                 pool.execute(move || {
                     send_completions(&sender, id, comps.clone());
                 });
             }
         })
+        .on::<ResolveCompletionItem, _>(|id, mut item| {
+            // 紧跟在补全请求之后，编译结果可以直接复用
+            if !docin.file(&db).is_empty() {
+                if let Some(m) = compile_dry(&db, docin) {
+                    completion::resolve_completion(&m.plmod(&db), &mut item);
+                }
+            }
+            let sender = connection.sender.clone();
+            pool.execute(move || send_completion_resolve(&sender, id, item));
+        })
         .on::<SemanticTokensFullRequest, _>(|id, params| {
             let uri = url_to_path(params.text_document.uri);
             docin.set_file(&mut db).to(uri.clone());
@@ -362,11 +613,17 @@ fn main_loop(
             let hints = compile_dry::accumulated::<Hints>(&db, docin);
             let sender = connection.sender.clone();
             if !hints.is_empty() {
+                let hints = hint_config.filter(hints[0].clone());
                 pool.execute(move || {
-                    send_hints(&sender, id, hints[0].clone());
+                    send_hints(&sender, id, hints);
                 });
             }
         })
+        .on::<InlayHintResolveRequest, _>(|id, mut hint| {
+            inlay_hints::resolve_hint(&mut hint);
+            let sender = connection.sender.clone();
+            pool.execute(move || send_hint_resolve(&sender, id, hint));
+        })
         .on::<DocumentSymbolRequest, _>(|id, params| {
             let uri = url_to_path(params.text_document.uri);
             docin.set_file(&mut db).to(uri);
@@ -383,8 +640,160 @@ fn main_loop(
                 });
             }
         })
+        .on::<WorkspaceSymbol, _>(|id, params| {
+            // 没有指定文件，使用最近一次请求的文件所在的项目
+            let symbols = if docin.file(&db).is_empty() {
+                vec![]
+            } else {
+                docin.set_action(&mut db).to(ActionType::WorkspaceSymbol);
+                compile_dry(&db, docin)
+                    .map(|m| workspace_symbols(&m.plmod(&db), &params.query))
+                    .unwrap_or_default()
+            };
+            let sender = connection.sender.clone();
+            pool.execute(move || send_workspace_symbols(&sender, id, symbols));
+        })
+        .on::<CallHierarchyPrepare, _>(|id, params| {
+            let doc = params.text_document_position_params;
+            let uri = url_to_path(doc.text_document.uri);
+            let pos = Pos::from_diag_pos(&doc.position);
+            docin.set_file(&mut db).to(uri.clone());
+            docin.set_action(&mut db).to(ActionType::CallHierarchy);
+            let items = compile_dry(&db, docin)
+                .and_then(|m| hierarchy::prepare_call_hierarchy(&m.plmod(&db), &uri, pos));
+            let sender = connection.sender.clone();
+            pool.execute(move || send_call_hierarchy(&sender, id, items));
+        })
+        .on::<CallHierarchyIncomingCalls, _>(|id, params| {
+            docin
+                .set_file(&mut db)
+                .to(url_to_path(params.item.uri.clone()));
+            docin.set_action(&mut db).to(ActionType::CallHierarchy);
+            let calls = compile_dry(&db, docin)
+                .map(|m| hierarchy::incoming_calls(&m.plmod(&db), &params.item))
+                .unwrap_or_default();
+            let sender = connection.sender.clone();
+            pool.execute(move || send_incoming_calls(&sender, id, calls));
+        })
+        .on::<CallHierarchyOutgoingCalls, _>(|id, params| {
+            docin
+                .set_file(&mut db)
+                .to(url_to_path(params.item.uri.clone()));
+            docin.set_action(&mut db).to(ActionType::CallHierarchy);
+            let calls = compile_dry(&db, docin)
+                .map(|m| hierarchy::outgoing_calls(&m.plmod(&db), &params.item))
+                .unwrap_or_default();
+            let sender = connection.sender.clone();
+            pool.execute(move || send_outgoing_calls(&sender, id, calls));
+        })
+        .on::<TypeHierarchyPrepare, _>(|id, params| {
+            let doc = params.text_document_position_params;
+            let uri = url_to_path(doc.text_document.uri);
+            let pos = Pos::from_diag_pos(&doc.position);
+            docin.set_file(&mut db).to(uri.clone());
+            docin.set_action(&mut db).to(ActionType::TypeHierarchy);
+            let items = compile_dry(&db, docin)
+                .and_then(|m| hierarchy::prepare_type_hierarchy(&m.plmod(&db), &uri, pos));
+            let sender = connection.sender.clone();
+            pool.execute(move || send_type_hierarchy(&sender, id, items));
+        })
+        .on::<TypeHierarchySupertypes, _>(|id, params| {
+            docin
+                .set_file(&mut db)
+                .to(url_to_path(params.item.uri.clone()));
+            docin.set_action(&mut db).to(ActionType::TypeHierarchy);
+            let items =
+                compile_dry(&db, docin).map(|m| hierarchy::supertypes(&m.plmod(&db), &params.item));
+            let sender = connection.sender.clone();
+            pool.execute(move || send_type_hierarchy(&sender, id, items));
+        })
+        .on::<TypeHierarchySubtypes, _>(|id, params| {
+            docin
+                .set_file(&mut db)
+                .to(url_to_path(params.item.uri.clone()));
+            docin.set_action(&mut db).to(ActionType::TypeHierarchy);
+            let items =
+                compile_dry(&db, docin).map(|m| hierarchy::subtypes(&m.plmod(&db), &params.item));
+            let sender = connection.sender.clone();
+            pool.execute(move || send_type_hierarchy(&sender, id, items));
+        })
+        .on::<FoldingRangeRequest, _>(|id, params| {
+            let uri = url_to_path(params.text_document.uri);
+            let ranges = syntax::parse_program(&db, docin, &uri)
+                .map(|p| syntax::folding_ranges(&p))
+                .unwrap_or_default();
+            let sender = connection.sender.clone();
+            pool.execute(move || send_folding_ranges(&sender, id, ranges));
+        })
+        .on::<SelectionRangeRequest, _>(|id, params| {
+            let uri = url_to_path(params.text_document.uri);
+            let positions: Vec<Pos> = params.positions.iter().map(Pos::from_diag_pos).collect();
+            let ranges = syntax::parse_program(&db, docin, &uri)
+                .map(|p| syntax::selection_ranges(&p, &positions))
+                .unwrap_or_default();
+            let sender = connection.sender.clone();
+            pool.execute(move || send_selection_ranges(&sender, id, ranges));
+        })
+        .on::<DocumentHighlightRequest, _>(|id, params| {
+            let doc = params.text_document_position_params;
+            let uri = url_to_path(doc.text_document.uri);
+            let pos = Pos::from_diag_pos(&doc.position);
+            docin.set_file(&mut db).to(uri.clone());
+            docin.set_action(&mut db).to(ActionType::DocumentHighlight);
+            let highlights = compile_dry(&db, docin).and_then(|m| {
+                let prog = syntax::parse_program(&db, docin, &uri)?;
+                syntax::document_highlights(&m.plmod(&db), &prog, &uri, pos)
+            });
+            let sender = connection.sender.clone();
+            pool.execute(move || send_document_highlights(&sender, id, highlights));
+        })
+        .on::<ExpandMacroRequest, _>(|id, params| {
+            let uri = url_to_path(params.text_document.uri);
+            let pos = Pos::from_diag_pos(&params.position);
+            docin.set_file(&mut db).to(uri.clone());
+            docin.set_action(&mut db).to(ActionType::ExpandMacro);
+            let expanded = compile_dry(&db, docin)
+                .and_then(|m| expand_macro::expand_macro(&m.plmod(&db), &uri, pos));
+            let sender = connection.sender.clone();
+            pool.execute(move || send_expand_macro(&sender, id, expanded));
+        })
+        .on::<DocumentDiagnosticRequest, _>(|id, params| {
+            let uri = url_to_path(params.text_document.uri);
+            docin.set_file(&mut db).to(uri.clone());
+            docin.set_action(&mut db).to(ActionType::Diagnostic);
+            docin.set_params(&mut db).to(None);
+            compile_dry(&db, docin);
+            let diags = compile_dry::accumulated::<Diagnostics>(&db, docin);
+            let sender = connection.sender.clone();
+            pool.execute(move || {
+                let report = diagnostics::document_diagnostics(
+                    &diags,
+                    &uri,
+                    params.previous_result_id.as_deref(),
+                );
+                send_document_diagnostics(&sender, id, report);
+            });
+        })
+        .on::<WorkspaceDiagnosticRequest, _>(|id, params| {
+            // 和workspace symbol一样，使用最近一次请求的文件所在的项目
+            let diags = if docin.file(&db).is_empty() {
+                vec![]
+            } else {
+                docin.set_action(&mut db).to(ActionType::Diagnostic);
+                docin.set_params(&mut db).to(None);
+                compile_dry(&db, docin);
+                compile_dry::accumulated::<Diagnostics>(&db, docin)
+            };
+            let sender = connection.sender.clone();
+            pool.execute(move || {
+                let report =
+                    diagnostics::workspace_diagnostics(&diags, &params.previous_result_ids);
+                send_workspace_diagnostics(&sender, id, report);
+            });
+        })
         .on_noti::<DidChangeTextDocument, _>(|params| {
-            let f = url_to_path(params.text_document.uri);
+            let url = params.text_document.uri;
+            let f = url_to_path(url.clone());
             for content_change in params.content_changes.iter() {
                 docs.lock().unwrap().borrow_mut().change(
                     &mut db,
@@ -398,24 +807,22 @@ fn main_loop(
                 docin.set_docs(&mut db).to(docs.clone());
             }
             docin.set_file(&mut db).to(f.clone());
+            // 修改已经生效，紧接着还有对同一个文件的修改时等到最后一次修改再重新分析
+            if queue.next_is_change(&url, DEBOUNCE) {
+                return;
+            }
 
             docin.set_action(&mut db).to(ActionType::Diagnostic);
             compile_dry(&db, docin);
             completions = compile_dry::accumulated::<Completions>(&db, docin);
+            if pull_diags {
+                return;
+            }
             let diags = compile_dry::accumulated::<Diagnostics>(&db, docin);
             let sender = connection.sender.clone();
             pool.execute(move || {
                 debug!("diags: {:#?}", diags);
-                let mut m = FxHashMap::<String, Vec<Diagnostic>>::default();
-                for (p, diags) in &diags {
-                    diags.iter().for_each(|x| x.get_diagnostic(&p, &mut m));
-                }
-                for (p, _) in &diags {
-                    if m.get(p).is_none() {
-                        send_diagnostics(&sender, p.to_string(), vec![]);
-                    }
-                }
-                for (f, d) in m {
+                for (f, d) in diagnostics::group_diagnostics(&diags) {
                     send_diagnostics(&sender, f, d);
                 }
             });
@@ -432,19 +839,40 @@ fn main_loop(
             docin.set_file(&mut db).to(f.clone());
             docin.set_action(&mut db).to(ActionType::Diagnostic);
             docin.set_params(&mut db).to(None);
+            // 第一次打开项目中的文件时需要分析整个项目，显示进度
+            let root = get_config_path(f.clone()).unwrap_or_else(|_| f.clone());
+            let token = format!("pivot-lang/analysis/{}", analyzed.len());
+            let progress = work_done && analyzed.insert(root);
+            if progress {
+                send_progress_create(&connection.sender, &token);
+                send_progress_begin(&connection.sender, &token, "Analyzing project");
+            }
             compile_dry(&db, docin);
+            if progress {
+                send_progress_end(&connection.sender, &token);
+            }
+            if pull_diags {
+                return;
+            }
             let diags = compile_dry::accumulated::<Diagnostics>(&db, docin);
             let sender = connection.sender.clone();
             pool.execute(move || {
-                let mut m = FxHashMap::<String, Vec<Diagnostic>>::default();
-                for (p, diags) in diags {
-                    diags.iter().for_each(|x| x.get_diagnostic(&p, &mut m));
-                }
-                for (f, d) in m {
+                for (f, d) in diagnostics::group_diagnostics(&diags) {
                     send_diagnostics(&sender, f, d);
                 }
             });
         })
+        .on_noti::<DidChangeConfiguration, _>(|params| {
+            if let Some(config) = InlayHintConfig::from_settings(&params.settings) {
+                if config != hint_config {
+                    hint_config = config;
+                    // 提示在返回前才按配置过滤，让客户端重新请求即可
+                    if hint_refresh {
+                        send_inlay_hint_refresh(&connection.sender);
+                    }
+                }
+            }
+        })
         .on_noti::<DidCloseTextDocument, _>(|_params| {
             // let f = url_to_path(params.text_document.uri);
             // docs.lock().unwrap().borrow_mut().remove(&f);
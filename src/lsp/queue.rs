@@ -0,0 +1,207 @@
+//! # queue
+//! lsp消息队列
+//!
+//! 编译在主线程上串行执行，处理一条消息时客户端可能已经发来了新的消息。
+//! 每次取消息时把通道里已经到达的消息都取出来，这样就能在处理请求前知道：
+//! - 请求是否已经被`$/cancelRequest`取消
+//! - 请求对应的文件是否在之后被修改，此时请求的结果已经过时
+//! - 文件是否马上又会被修改，连续的修改只需要在最后一次之后重新分析
+use std::{collections::VecDeque, time::Duration};
+
+use crossbeam_channel::Receiver;
+use lsp_server::{Message, Notification, Request, RequestId};
+use lsp_types::{
+    notification::{Cancel, DidChangeTextDocument, Notification as _},
+    request::{
+        DocumentSymbolRequest, FoldingRangeRequest, InlayHintRequest, Request as _,
+        SemanticTokensFullRequest, Shutdown,
+    },
+    CancelParams, NumberOrString, Url,
+};
+
+use super::diagnostics::DocumentDiagnosticRequest;
+
+/// 结果只和文件内容有关的请求，文件被修改后可以直接丢弃
+const SUPERSEDABLE: [&str; 5] = [
+    SemanticTokensFullRequest::METHOD,
+    InlayHintRequest::METHOD,
+    DocumentSymbolRequest::METHOD,
+    FoldingRangeRequest::METHOD,
+    DocumentDiagnosticRequest::METHOD,
+];
+
+pub struct MessageQueue {
+    receiver: Receiver<Message>,
+    pending: VecDeque<Message>,
+}
+
+impl MessageQueue {
+    pub fn new(receiver: Receiver<Message>) -> Self {
+        Self {
+            receiver,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// 取出下一条消息，没有消息时阻塞，连接关闭时返回`None`
+    pub fn next(&mut self) -> Option<Message> {
+        if self.pending.is_empty() {
+            self.pending.push_back(self.receiver.recv().ok()?);
+        }
+        self.fill();
+        self.pending.pop_front()
+    }
+
+    /// `shutdown`之后的`exit`通知由[lsp_server::Connection::handle_shutdown]直接从通道中读取，
+    /// 所以读到`shutdown`请求后不再继续取消息
+    fn fill(&mut self) {
+        if self.pending.iter().any(is_shutdown) {
+            return;
+        }
+        while let Ok(msg) = self.receiver.try_recv() {
+            let shutdown = is_shutdown(&msg);
+            self.pending.push_back(msg);
+            if shutdown {
+                break;
+            }
+        }
+    }
+
+    /// 队列中是否有取消该请求的`$/cancelRequest`
+    pub fn is_canceled(&self, id: &RequestId) -> bool {
+        self.pending.iter().any(|msg| match msg {
+            Message::Notification(n) if n.method == Cancel::METHOD => {
+                serde_json::from_value::<CancelParams>(n.params.clone())
+                    .map_or(false, |p| request_id(p.id) == *id)
+            }
+            _ => false,
+        })
+    }
+
+    /// 请求的文件在之后被修改了，请求的结果已经没有意义
+    pub fn is_superseded(&self, req: &Request) -> bool {
+        if !SUPERSEDABLE.contains(&req.method.as_str()) {
+            return false;
+        }
+        match document_uri(&req.params) {
+            Some(uri) => self.pending.iter().any(|msg| is_change_of(msg, &uri)),
+            None => false,
+        }
+    }
+
+    /// 等待至多`timeout`，如果下一条消息是同一个文件的修改返回`true`
+    ///
+    /// 用于合并连续的`didChange`：只有一连串修改中的最后一次需要重新分析
+    pub fn next_is_change(&mut self, uri: &Url, timeout: Duration) -> bool {
+        if self.pending.is_empty() {
+            match self.receiver.recv_timeout(timeout) {
+                Ok(msg) => self.pending.push_back(msg),
+                Err(_) => return false,
+            }
+            self.fill();
+        }
+        self.pending
+            .front()
+            .map_or(false, |msg| is_change_of(msg, uri))
+    }
+}
+
+fn is_shutdown(msg: &Message) -> bool {
+    matches!(msg, Message::Request(req) if req.method == Shutdown::METHOD)
+}
+
+fn request_id(id: NumberOrString) -> RequestId {
+    match id {
+        NumberOrString::Number(n) => n.into(),
+        NumberOrString::String(s) => s.into(),
+    }
+}
+
+fn document_uri(params: &serde_json::Value) -> Option<Url> {
+    let uri = params.pointer("/textDocument/uri")?.as_str()?;
+    Url::parse(uri).ok()
+}
+
+fn is_change_of(msg: &Message, uri: &Url) -> bool {
+    match msg {
+        Message::Notification(Notification { method, params })
+            if method == DidChangeTextDocument::METHOD =>
+        {
+            document_uri(params).map_or(false, |u| u == *uri)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+    use serde_json::json;
+
+    use super::*;
+
+    fn change(uri: &str) -> Message {
+        Message::Notification(Notification::new(
+            DidChangeTextDocument::METHOD.to_string(),
+            json!({"textDocument": {"uri": uri, "version": 1}, "contentChanges": []}),
+        ))
+    }
+
+    fn request(id: i32, method: &str, uri: &str) -> Request {
+        Request::new(
+            id.into(),
+            method.to_string(),
+            json!({"textDocument": {"uri": uri}}),
+        )
+    }
+
+    #[test]
+    fn test_message_queue() {
+        let (sender, receiver) = unbounded();
+        let mut queue = MessageQueue::new(receiver);
+        let tokens = request(1, SemanticTokensFullRequest::METHOD, "file:///a.pi");
+        let hover = request(2, "textDocument/hover", "file:///a.pi");
+        sender.send(Message::Request(tokens.clone())).unwrap();
+        sender.send(Message::Request(hover.clone())).unwrap();
+        sender.send(change("file:///a.pi")).unwrap();
+        sender
+            .send(Message::Notification(Notification::new(
+                Cancel::METHOD.to_string(),
+                json!({"id": 2}),
+            )))
+            .unwrap();
+        assert_eq!(queue.next(), Some(Message::Request(tokens.clone())));
+        // 文件之后被修改了
+        assert!(queue.is_superseded(&tokens));
+        // hover的结果和光标位置有关，不会被丢弃，但是已经被取消了
+        assert!(!queue.is_superseded(&hover));
+        assert!(queue.is_canceled(&hover.id));
+        assert!(!queue.is_canceled(&tokens.id));
+        assert_eq!(queue.next(), Some(Message::Request(hover)));
+        let uri = Url::parse("file:///a.pi").unwrap();
+        assert!(queue.next_is_change(&uri, Duration::from_millis(1)));
+        assert_eq!(queue.next(), Some(change("file:///a.pi")));
+        // 下一条消息是取消请求
+        assert!(!queue.next_is_change(&uri, Duration::from_millis(1)));
+        queue.next();
+        assert!(!queue.next_is_change(&uri, Duration::from_millis(1)));
+        sender.send(change("file:///b.pi")).unwrap();
+        assert!(!queue.next_is_change(&uri, Duration::from_millis(100)));
+        queue.next();
+        // exit通知留在通道中
+        let shutdown = Message::Request(Request::new(
+            3.into(),
+            Shutdown::METHOD.to_string(),
+            serde_json::Value::Null,
+        ));
+        sender.send(shutdown.clone()).unwrap();
+        sender
+            .send(Message::Notification(Notification::new(
+                "exit".to_string(),
+                serde_json::Value::Null,
+            )))
+            .unwrap();
+        assert_eq!(queue.next(), Some(shutdown));
+        assert_eq!(queue.receiver.len(), 1);
+    }
+}
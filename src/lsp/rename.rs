@@ -0,0 +1,461 @@
+//! # rename
+//! prepare rename与rename
+//!
+//! 重命名复用find references收集到的引用：局部变量的引用保存在[Mod::local_refs]中，
+//! 全局符号（函数、类型、字段、方法、trait、全局变量）的引用以全名为键记录在每个模块的
+//! [Mod::refs_map]里。lsp总是从项目的入口开始编译，所以遍历入口模块的依赖就能找到项目中
+//! 所有文件（包括没有打开的文件）里的引用。项目属于工作区时，工作区中的其他包也会被编译，
+//! 一个包中的符号在其他包中的使用也会被重命名。
+//!
+//! 记录下来的引用范围可能比标识符大（例如`mod::name`、`a.method`、`*Type<T>`），
+//! 编辑时需要在源码中找到标识符本身的位置
+use std::{
+    collections::HashMap,
+    ops::Bound::{Included, Unbounded},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use lsp_types::{Location, PrepareRenameResponse, TextEdit, Url, WorkspaceEdit};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    ast::{
+        compiler::compile_workspace_members,
+        plmod::{Mod, MutVec},
+        range::{Pos, Range},
+    },
+    nomparser::{identifier::identifier, Span},
+    utils::read_config::get_project_root,
+    Db,
+};
+
+use super::{
+    helpers::{normalize, offset_to_position, position_to_offset},
+    mem_docs::MemDocsInput,
+};
+
+const NO_SYMBOL: &str = "no symbol to rename at the cursor";
+
+enum Symbol {
+    Local(Arc<MutVec<Location>>),
+    /// 符号的全名，即[Mod::refs_map]的键
+    Global(String),
+}
+
+struct Target {
+    name: String,
+    symbol: Symbol,
+    /// 光标处标识符的范围
+    range: lsp_types::Range,
+}
+
+struct Renamer<'a> {
+    db: &'a dyn Db,
+    docin: MemDocsInput,
+    /// 入口模块以及它直接或间接依赖的所有模块，项目属于工作区时还包括其他包的模块
+    mods: Vec<Mod>,
+    sources: FxHashMap<String, Arc<String>>,
+}
+
+/// 检查光标处的符号能否重命名，返回标识符的范围与当前的名字
+pub fn prepare_rename(
+    db: &dyn Db,
+    docin: MemDocsInput,
+    root: Mod,
+    file: &str,
+    pos: Pos,
+) -> Result<PrepareRenameResponse, String> {
+    let mut renamer = Renamer::new(db, docin, root);
+    let target = renamer.target(file, pos)?;
+    renamer.edits(file, &target)?;
+    Ok(PrepareRenameResponse::RangeWithPlaceholder {
+        range: target.range,
+        placeholder: target.name,
+    })
+}
+
+/// 把光标处的符号重命名为`new_name`，新名字与作用域中已有的符号冲突时返回错误
+pub fn rename(
+    db: &dyn Db,
+    docin: MemDocsInput,
+    root: Mod,
+    file: &str,
+    pos: Pos,
+    new_name: &str,
+) -> Result<WorkspaceEdit, String> {
+    if !is_identifier(new_name) {
+        return Err(format!("`{}` is not a valid identifier", new_name));
+    }
+    let mut renamer = Renamer::new(db, docin, root);
+    let target = renamer.target(file, pos)?;
+    if target.name == new_name {
+        return Ok(WorkspaceEdit::default());
+    }
+    let edits = renamer.edits(file, &target)?;
+    renamer.check_conflicts(&target, &edits, new_name)?;
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    for (path, ranges) in edits {
+        let uri = Url::from_file_path(path).unwrap();
+        changes
+            .entry(uri)
+            .or_default()
+            .extend(ranges.into_iter().map(|r| TextEdit {
+                range: r,
+                new_text: new_name.to_string(),
+            }));
+    }
+    Ok(WorkspaceEdit {
+        changes: Some(changes),
+        ..Default::default()
+    })
+}
+
+impl<'a> Renamer<'a> {
+    fn new(db: &'a dyn Db, docin: MemDocsInput, root: Mod) -> Self {
+        let mut mods = root.all_mods();
+        let mut paths = mods
+            .iter()
+            .map(|m| m.path.clone())
+            .collect::<FxHashSet<_>>();
+        for m in compile_workspace_members(db, docin) {
+            for m in m.plmod(db).all_mods() {
+                if paths.insert(m.path.clone()) {
+                    mods.push(m);
+                }
+            }
+        }
+        Self {
+            db,
+            docin,
+            mods,
+            sources: FxHashMap::default(),
+        }
+    }
+
+    fn source(&mut self, path: &str) -> Option<Arc<String>> {
+        if let Some(src) = self.sources.get(path) {
+            return Some(src.clone());
+        }
+        let src = self.docin.get_file_content(self.db, path.to_string())?;
+        let src = Arc::new(src.text(self.db).clone());
+        self.sources.insert(path.to_string(), src.clone());
+        Some(src)
+    }
+
+    fn find_mod(&self, path: &str) -> Option<&Mod> {
+        let path = normalize(path);
+        self.mods.iter().find(|m| normalize(&m.path) == path)
+    }
+
+    /// 找到光标处的符号，局部变量优先
+    fn target(&mut self, file: &str, pos: Pos) -> Result<Target, String> {
+        let m = self.find_mod(file).ok_or(NO_SYMBOL)?.clone();
+        let src = self.source(&m.path).ok_or(NO_SYMBOL)?;
+        let range = pos.to(pos);
+        let local = m
+            .local_refs
+            .borrow()
+            .range((Unbounded, Included(&range)))
+            .last()
+            .filter(|(r, _)| pos.is_in(**r))
+            .map(|(r, refs)| (*r, refs.clone()));
+        let (name, symbol, range) = if let Some((r, refs)) = local {
+            let range = r.to_diag_range();
+            let name = slice(&src, range).to_string();
+            (name, Symbol::Local(refs), range)
+        } else {
+            let (r, key) = m
+                .glob_refs
+                .borrow()
+                .range((Unbounded, Included(&range)))
+                .last()
+                .filter(|(r, _)| pos.is_in(**r))
+                .map(|(r, key)| (*r, key.clone()))
+                .ok_or(NO_SYMBOL)?;
+            let name = symbol_name(&key).to_string();
+            let range = ident_range(&src, r.to_diag_range(), &name).ok_or(NO_SYMBOL)?;
+            (name, Symbol::Global(key), range)
+        };
+        if !pos.is_in(Range::from_diag_range(range)) || !is_identifier(&name) {
+            return Err(NO_SYMBOL.to_string());
+        }
+        if name == "self" || name == "main" {
+            return Err(format!("`{}` can't be renamed", name));
+        }
+        Ok(Target {
+            name,
+            symbol,
+            range,
+        })
+    }
+
+    /// 按文件分组的所有需要修改的标识符范围
+    ///
+    /// 符号在项目（或者项目所在的工作区）之外，例如标准库中被定义或者使用时不能重命名
+    fn edits(
+        &mut self,
+        file: &str,
+        target: &Target,
+    ) -> Result<FxHashMap<PathBuf, Vec<lsp_types::Range>>, String> {
+        let root = get_project_root(file)?;
+        let locs = match &target.symbol {
+            Symbol::Local(refs) => refs.borrow().clone(),
+            Symbol::Global(key) => self
+                .mods
+                .iter()
+                .filter_map(|m| m.refs_map.borrow().get(key).map(|r| r.borrow().clone()))
+                .flatten()
+                .collect(),
+        };
+        let mut edits: FxHashMap<PathBuf, Vec<lsp_types::Range>> = FxHashMap::default();
+        let mut seen = FxHashSet::default();
+        for loc in locs {
+            let path = match loc.uri.to_file_path() {
+                Ok(path) => path,
+                Err(_) => continue,
+            };
+            let src = match self.source(&path.to_string_lossy()) {
+                Some(src) => src,
+                None => continue,
+            };
+            // 例如方法隐式的self参数，它的类型没有对应的源码
+            let range = match ident_range(&src, loc.range, &target.name) {
+                Some(range) => range,
+                None => continue,
+            };
+            if !normalize(&path.to_string_lossy()).starts_with(&root) {
+                return Err(format!(
+                    "`{}` is used outside the project and can't be renamed",
+                    target.name
+                ));
+            }
+            let start = range.start;
+            if seen.insert((path.clone(), start.line, start.character)) {
+                edits.entry(path).or_default().push(range);
+            }
+        }
+        if edits.is_empty() {
+            return Err(NO_SYMBOL.to_string());
+        }
+        Ok(edits)
+    }
+
+    fn check_conflicts(
+        &mut self,
+        target: &Target,
+        edits: &FxHashMap<PathBuf, Vec<lsp_types::Range>>,
+        new_name: &str,
+    ) -> Result<(), String> {
+        let conflict = || {
+            Err(format!(
+                "renaming `{}` to `{}` conflicts with an existing symbol",
+                target.name, new_name
+            ))
+        };
+        if let Symbol::Global(key) = &target.symbol {
+            let keys = renamed_keys(key, new_name);
+            if self
+                .mods
+                .iter()
+                .any(|m| keys.iter().any(|k| m.refs_map.borrow().contains_key(k)))
+            {
+                return conflict();
+            }
+            // 模块顶层的符号还可能与没有被引用过的类型、全局变量重名
+            if let Some((path, _)) = key.rsplit_once("..") {
+                if let Some(m) = self.find_mod(path) {
+                    if m.types.contains_key(new_name) || m.global_table.contains_key(new_name) {
+                        return conflict();
+                    }
+                }
+            }
+        }
+        for (path, ranges) in edits {
+            let path = path.to_string_lossy();
+            let m = match self.find_mod(&path) {
+                Some(m) => m.clone(),
+                None => continue,
+            };
+            let src = match self.source(&path) {
+                Some(src) => src,
+                None => continue,
+            };
+            // 局部变量的作用范围近似为它第一次到最后一次出现之间的部分，
+            // 全局符号的每个引用处都需要检查
+            let spans = match &target.symbol {
+                Symbol::Local(_) => {
+                    let start = ranges.iter().map(|r| r.start).min_by_key(pos_key);
+                    let end = ranges.iter().map(|r| r.end).max_by_key(pos_key);
+                    vec![(pos_key(&start.unwrap()), pos_key(&end.unwrap()))]
+                }
+                Symbol::Global(_) => ranges
+                    .iter()
+                    .map(|r| (pos_key(&r.start), pos_key(&r.end)))
+                    .collect(),
+            };
+            // 与新名字同名的局部变量的作用范围
+            let mut locals: FxHashMap<*const MutVec<Location>, ((u32, u32), (u32, u32))> =
+                FxHashMap::default();
+            for (r, refs) in m.local_refs.borrow().iter() {
+                if matches!(&target.symbol, Symbol::Local(t) if Arc::ptr_eq(t, refs)) {
+                    continue;
+                }
+                let r = r.to_diag_range();
+                if slice(&src, r) != new_name {
+                    continue;
+                }
+                let (start, end) = (pos_key(&r.start), pos_key(&r.end));
+                let span = locals.entry(Arc::as_ptr(refs)).or_insert((start, end));
+                span.0 = span.0.min(start);
+                span.1 = span.1.max(end);
+            }
+            if locals
+                .values()
+                .any(|l| spans.iter().any(|s| l.0 <= s.1 && s.0 <= l.1))
+            {
+                return conflict();
+            }
+            // 局部变量会遮蔽作用范围内同名的全局符号
+            if let Symbol::Local(_) = &target.symbol {
+                if m.glob_refs.borrow().iter().any(|(r, key)| {
+                    let start = pos_key(&r.to_diag_range().start);
+                    symbol_name(key) == new_name
+                        && spans.iter().any(|s| s.0 <= start && start <= s.1)
+                }) {
+                    return conflict();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn pos_key(pos: &lsp_types::Position) -> (u32, u32) {
+    (pos.line, pos.character)
+}
+
+fn is_identifier(name: &str) -> bool {
+    matches!(
+        identifier(Span::new_extra(name, false)),
+        Ok((rest, id)) if rest.fragment().is_empty() && id.name == name
+    )
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// 全名中符号自己的名字，例如`path..|Struct::method`中的`method`
+fn symbol_name(key: &str) -> &str {
+    let name = key.rsplit("..").next().unwrap();
+    let name = name.rsplit("::").next().unwrap();
+    name.split('<').next().unwrap()
+}
+
+/// 重命名后符号的全名
+///
+/// 结构体的字段与方法不能重名，所以也会返回另一种成员的全名
+fn renamed_keys(key: &str, new_name: &str) -> Vec<String> {
+    let (prefix, last) = key.rsplit_once("..").unwrap_or(("", key));
+    let join = |last: &str| {
+        if prefix.is_empty() {
+            last.to_string()
+        } else {
+            format!("{}..{}", prefix, last)
+        }
+    };
+    if let Some(mthd) = last.strip_prefix('|') {
+        // 方法：path..|Struct::method
+        let st = mthd.rsplit_once("::").map_or(mthd, |(st, _)| st);
+        return vec![
+            join(&format!("|{}::{}", st, new_name)),
+            format!("{}..{}..{}", prefix, st, new_name),
+        ];
+    }
+    let mut keys = vec![join(new_name)];
+    if let Some((path, st)) = prefix.rsplit_once("..") {
+        // 字段：path..Struct..field
+        keys.push(format!("{}..|{}::{}", path, st, new_name));
+    }
+    keys
+}
+
+fn slice(src: &str, range: lsp_types::Range) -> &str {
+    let start = position_to_offset(src, range.start);
+    let end = position_to_offset(src, range.end);
+    src.get(start..end).unwrap_or_default()
+}
+
+/// 在引用的范围中找到标识符`name`的范围
+///
+/// 泛型参数会被忽略，有多个匹配时取最后一个，例如`mod::name`、`a.name`中的`name`
+fn ident_range(src: &str, range: lsp_types::Range, name: &str) -> Option<lsp_types::Range> {
+    let start = position_to_offset(src, range.start);
+    let text = slice(src, range);
+    let text = text.split('<').next().unwrap();
+    let offset = text
+        .match_indices(name)
+        .filter(|(i, _)| {
+            !text[..*i].chars().next_back().map_or(false, is_ident_char)
+                && !text[i + name.len()..]
+                    .chars()
+                    .next()
+                    .map_or(false, is_ident_char)
+        })
+        .last()?
+        .0;
+    Some(lsp_types::Range {
+        start: offset_to_position(src, start + offset),
+        end: offset_to_position(src, start + offset + name.len()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_name() {
+        assert_eq!(symbol_name("/a/b.pi..name"), "name");
+        assert_eq!(symbol_name("/a/b.pi..|St::method"), "method");
+        assert_eq!(symbol_name("/a/b.pi..St..field"), "field");
+        assert_eq!(symbol_name("main"), "main");
+        assert_eq!(
+            renamed_keys("/a/b.pi..St..field", "x"),
+            vec!["/a/b.pi..St..x", "/a/b.pi..|St::x"]
+        );
+        assert_eq!(
+            renamed_keys("/a/b.pi..|St::m", "x"),
+            vec!["/a/b.pi..|St::x", "/a/b.pi..St..x"]
+        );
+    }
+
+    #[test]
+    fn test_ident_range() {
+        let src = "let a = mod::name{};\nlet b:*St<name> = a.name;\n";
+        let range = |sl, sc, el, ec| lsp_types::Range {
+            start: lsp_types::Position {
+                line: sl,
+                character: sc,
+            },
+            end: lsp_types::Position {
+                line: el,
+                character: ec,
+            },
+        };
+        assert_eq!(
+            ident_range(src, range(0, 8, 0, 17), "name"),
+            Some(range(0, 13, 0, 17))
+        );
+        assert_eq!(ident_range(src, range(1, 6, 1, 15), "name"), None);
+        assert_eq!(
+            ident_range(src, range(1, 18, 1, 24), "name"),
+            Some(range(1, 20, 1, 24))
+        );
+        assert_eq!(ident_range(src, range(0, 0, 0, 0), "name"), None);
+        assert!(is_identifier("name2"));
+        assert!(!is_identifier("let"));
+        assert!(!is_identifier("a b"));
+    }
+}
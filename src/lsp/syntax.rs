@@ -0,0 +1,493 @@
+//! # syntax
+//! 基于语法树的lsp功能：folding range、selection range与document highlight
+//!
+//! 这些功能只需要当前文件的语法树，语法树来自salsa缓存的[parse]结果，
+//! 不需要重新编译。document highlight还需要编译时记录的引用，
+//! 语法树只用来区分变量的读和写
+use std::ops::Bound::{Included, Unbounded};
+
+use lsp_types::{
+    DocumentHighlight, DocumentHighlightKind, FoldingRange, FoldingRangeKind, SelectionRange, Url,
+};
+
+use crate::{
+    ast::{
+        node::{
+            function::FuncDefNode, pkg::ExternIdNode, program::ProgramNode,
+            statement::StatementsNode, types::TypedIdentifierNode, NodeEnum, RangeTrait,
+            TypeNodeEnum,
+        },
+        plmod::Mod,
+        range::{Pos, Range},
+    },
+    nomparser::parse,
+    Db,
+};
+
+use super::{helpers::find_mod, mem_docs::MemDocsInput};
+
+/// 获取文件的语法树
+pub fn parse_program(db: &dyn Db, docin: MemDocsInput, file: &str) -> Option<ProgramNode> {
+    let src = docin.get_file_content(db, file.to_string())?;
+    match *parse(db, src).ok()?.node(db) {
+        NodeEnum::Program(p) => Some(p),
+        _ => None,
+    }
+}
+
+pub fn folding_ranges(prog: &ProgramNode) -> Vec<FoldingRange> {
+    let w = Walker::new(prog);
+    let mut folds = w.folds;
+    // 连续的use语句
+    let mut uses: Vec<Range> = vec![];
+    for n in prog.nodes.iter() {
+        match &**n {
+            NodeEnum::UseNode(u) => uses.push(u.range),
+            NodeEnum::Comment(_) => {}
+            _ => fold_groups(&mut folds, &mut uses, FoldingRangeKind::Imports),
+        }
+    }
+    fold_groups(&mut folds, &mut uses, FoldingRangeKind::Imports);
+    // 连续多行的注释
+    let mut comments = w.comments;
+    comments.sort();
+    fold_groups(&mut folds, &mut comments, FoldingRangeKind::Comment);
+    folds.sort_by_key(|f| (f.start_line, f.end_line));
+    folds
+}
+
+/// 把相邻行上的范围合并成一个折叠范围
+fn fold_groups(folds: &mut Vec<FoldingRange>, ranges: &mut Vec<Range>, kind: FoldingRangeKind) {
+    let mut group: Option<(usize, usize)> = None;
+    for r in ranges.drain(..) {
+        group = match group {
+            Some((start, end)) if r.start.line <= end + 1 => Some((start, end.max(r.end.line))),
+            _ => {
+                push_fold(folds, group, kind.clone());
+                Some((r.start.line, r.end.line))
+            }
+        };
+    }
+    push_fold(folds, group, kind);
+}
+
+fn push_fold(folds: &mut Vec<FoldingRange>, lines: Option<(usize, usize)>, kind: FoldingRangeKind) {
+    if let Some((start, end)) = lines {
+        if start > 0 && end > start {
+            folds.push(FoldingRange {
+                start_line: start as u32 - 1,
+                start_character: None,
+                end_line: end as u32 - 1,
+                end_character: None,
+                kind: Some(kind),
+            });
+        }
+    }
+}
+
+/// 对每个位置，返回从最内层到最外层包含它的语法树节点
+pub fn selection_ranges(prog: &ProgramNode, positions: &[Pos]) -> Vec<SelectionRange> {
+    let w = Walker::new(prog);
+    positions
+        .iter()
+        .map(|pos| {
+            let mut ranges: Vec<Range> = w
+                .ranges
+                .iter()
+                .filter(|r| pos.is_in(**r))
+                .copied()
+                .collect();
+            // 起始位置越靠后、结束位置越靠前的节点越靠内
+            ranges.sort_by(|a, b| b.start.cmp(&a.start).then(a.end.cmp(&b.end)));
+            let mut chain: Vec<Range> = vec![];
+            for r in ranges {
+                if chain
+                    .last()
+                    .map_or(true, |last| *last != r && contains(r, *last))
+                {
+                    chain.push(r);
+                }
+            }
+            let mut selection = None;
+            for r in chain.into_iter().rev() {
+                selection = Some(SelectionRange {
+                    range: r.to_diag_range(),
+                    parent: selection.map(Box::new),
+                });
+            }
+            selection.unwrap_or_else(|| SelectionRange {
+                range: pos.to(*pos).to_diag_range(),
+                parent: None,
+            })
+        })
+        .collect()
+}
+
+/// 高亮当前文件中光标处符号的所有引用
+///
+/// 变量的定义、函数参数以及赋值语句的左侧为写，其他引用为读
+pub fn document_highlights(
+    root: &Mod,
+    prog: &ProgramNode,
+    file: &str,
+    pos: Pos,
+) -> Option<Vec<DocumentHighlight>> {
+    let mods = root.all_mods();
+    let m = find_mod(&mods, file)?;
+    let uri = Url::from_file_path(&m.path).ok()?;
+    let range = pos.to(pos);
+    let local = m
+        .local_refs
+        .borrow()
+        .range((Unbounded, Included(&range)))
+        .last()
+        .filter(|(r, _)| pos.is_in(**r))
+        .map(|(_, refs)| refs.clone());
+    let ranges: Vec<Range> = if let Some(refs) = local {
+        refs.borrow()
+            .iter()
+            .filter(|l| l.uri == uri)
+            .map(|l| Range::from_diag_range(l.range))
+            .collect()
+    } else {
+        let glob_refs = m.glob_refs.borrow();
+        let key = glob_refs
+            .range((Unbounded, Included(&range)))
+            .last()
+            .filter(|(r, _)| pos.is_in(**r))
+            .map(|(_, key)| key.clone())?;
+        glob_refs
+            .iter()
+            .filter(|(_, k)| **k == key)
+            .map(|(r, _)| *r)
+            .collect()
+    };
+    let writes = Walker::new(prog).writes;
+    let mut highlights: Vec<DocumentHighlight> = ranges
+        .into_iter()
+        .map(|r| DocumentHighlight {
+            range: r.to_diag_range(),
+            kind: Some(if writes.iter().any(|w| contains(*w, r)) {
+                DocumentHighlightKind::WRITE
+            } else {
+                DocumentHighlightKind::READ
+            }),
+        })
+        .collect();
+    highlights.sort_by_key(|h| (h.range.start, h.range.end));
+    highlights.dedup();
+    Some(highlights)
+}
+
+fn contains(outer: Range, inner: Range) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+/// 赋值语句左侧被写入的变量
+///
+/// `a.b[i] = x`中只有`a`被写入，下标`i`只被读取；`*p = x`中的`p`也只被读取
+fn assign_root(node: &NodeEnum) -> Option<Range> {
+    match node {
+        NodeEnum::Var(v) => Some(v.range),
+        NodeEnum::Primary(p) => assign_root(&p.value),
+        NodeEnum::Take(t) => assign_root(&t.head),
+        NodeEnum::ArrayElementNode(a) => assign_root(&a.arr),
+        NodeEnum::ParanthesesNode(p) => assign_root(&p.node),
+        _ => None,
+    }
+}
+
+/// 遍历语法树，收集各个功能需要的范围
+#[derive(Default)]
+struct Walker {
+    /// 所有节点的范围
+    ranges: Vec<Range>,
+    /// 变量定义、函数参数以及赋值语句左侧的范围
+    writes: Vec<Range>,
+    /// 所有注释的范围
+    comments: Vec<Range>,
+    /// 函数体、结构体、trait、impl、enum与宏定义
+    folds: Vec<FoldingRange>,
+}
+
+impl Walker {
+    fn new(prog: &ProgramNode) -> Self {
+        let mut w = Self::default();
+        w.push(prog.range);
+        prog.nodes.iter().for_each(|n| w.node(n));
+        w
+    }
+
+    fn push(&mut self, range: Range) {
+        // 编译器插入的节点没有范围
+        if range != Default::default() {
+            self.ranges.push(range);
+        }
+    }
+
+    /// 折叠从`start`行到`end`之前的一行，保留块结尾的`}`
+    fn fold(&mut self, start: Pos, end: Pos) {
+        push_fold(
+            &mut self.folds,
+            Some((start.line, end.line.saturating_sub(1))),
+            FoldingRangeKind::Region,
+        );
+    }
+
+    fn nodes(&mut self, nodes: &[Box<NodeEnum>]) {
+        nodes.iter().for_each(|n| self.node(n));
+    }
+
+    fn node(&mut self, node: &NodeEnum) {
+        self.push(node.range());
+        match node {
+            NodeEnum::Def(d) => {
+                self.push(d.var.range);
+                self.writes.push(d.var.range);
+                self.opt_tp(&d.tp);
+                if let Some(e) = &d.exp {
+                    self.node(e);
+                }
+            }
+            NodeEnum::Ret(r) => {
+                if let Some(v) = &r.value {
+                    self.node(v);
+                }
+            }
+            NodeEnum::Assign(a) => {
+                if let Some(r) = assign_root(&a.var) {
+                    self.writes.push(r);
+                }
+                self.node(&a.var);
+                self.node(&a.exp);
+            }
+            NodeEnum::If(i) => {
+                self.node(&i.cond);
+                self.sts(&i.then);
+                if let Some(els) = &i.els {
+                    self.node(els);
+                }
+            }
+            NodeEnum::While(w) => {
+                self.node(&w.cond);
+                self.sts(&w.body);
+            }
+            NodeEnum::For(f) => {
+                if let Some(pre) = &f.pre {
+                    self.node(pre);
+                }
+                self.node(&f.cond);
+                if let Some(opt) = &f.opt {
+                    self.node(opt);
+                }
+                self.sts(&f.body);
+            }
+            NodeEnum::Expr(b) => {
+                self.node(&b.left);
+                self.node(&b.right);
+            }
+            NodeEnum::FuncDef(f) => self.func(f),
+            NodeEnum::FuncCall(c) => {
+                self.node(&c.callee);
+                if let Some(g) = &c.generic_params {
+                    self.push(g.range);
+                    g.generics.iter().flatten().for_each(|t| self.tp(t));
+                }
+                self.nodes(&c.paralist);
+            }
+            NodeEnum::StructDef(s) => {
+                self.nodes(&s.precom);
+                self.nodes(&s.doc);
+                self.push(s.id.range);
+                if let Some(g) = &s.generics {
+                    self.push(g.range);
+                }
+                for (field, _, _) in s.fields.iter() {
+                    self.typed_id(field, false);
+                }
+                self.fold(s.id.range.start, s.range.end);
+            }
+            NodeEnum::StructInit(s) => {
+                self.tp(&s.typename);
+                for f in s.fields.iter() {
+                    self.push(f.range);
+                    self.push(f.id.range);
+                    self.node(&f.exp);
+                }
+            }
+            NodeEnum::Take(t) => {
+                self.node(&t.head);
+                if let Some(f) = &t.field {
+                    self.push(f.range);
+                }
+            }
+            NodeEnum::Un(u) => self.node(&u.exp),
+            NodeEnum::Primary(p) => self.node(&p.value),
+            NodeEnum::Comment(c) => self.comments.push(c.range),
+            NodeEnum::STS(s) => self.sts(s),
+            NodeEnum::Program(p) => self.nodes(&p.nodes),
+            NodeEnum::STInitField(f) => {
+                self.push(f.id.range);
+                self.node(&f.exp);
+            }
+            NodeEnum::StErrorNode(e) => self.node(&e.st),
+            NodeEnum::Global(g) => {
+                self.push(g.var.range);
+                self.writes.push(g.var.range);
+                self.node(&g.exp);
+            }
+            NodeEnum::UseNode(u) => u.ids.iter().for_each(|id| self.push(id.range)),
+            NodeEnum::ExternIdNode(e) => self.extern_id(e),
+            NodeEnum::ArrayInitNode(a) => self.nodes(&a.exps),
+            NodeEnum::DynArrayInitNode(a) => {
+                self.tp(&a.tp);
+                self.node(&a.len);
+            }
+            NodeEnum::ArrayElementNode(a) => {
+                self.node(&a.arr);
+                self.node(&a.index);
+            }
+            NodeEnum::PointerOpNode(p) => self.node(&p.value),
+            NodeEnum::ParanthesesNode(p) => self.node(&p.node),
+            NodeEnum::ImplNode(i) => {
+                if let Some(g) = &i.generics {
+                    self.push(g.range);
+                }
+                self.tp(&i.target);
+                if let Some((t, _)) = &i.impl_trait {
+                    self.tp(t);
+                }
+                i.methods.iter().for_each(|m| self.func(m));
+                i.comments.iter().for_each(|c| self.nodes(c));
+                self.fold(i.range.start, i.range.end);
+            }
+            NodeEnum::TraitDefNode(t) => {
+                self.push(t.id.range);
+                t.derives.iter().for_each(|d| self.tp(d));
+                t.methods.iter().for_each(|m| self.func(m));
+                self.fold(t.id.range.start, t.range.end);
+            }
+            NodeEnum::MacroLoopStatementNode(m) => self.node(&m.statements),
+            NodeEnum::MacroNode(m) => {
+                self.push(m.id.range);
+                for r in m.rules.iter() {
+                    self.push(r.range);
+                    self.node(&r.body);
+                }
+                self.fold(m.id.range.start, m.range.end);
+            }
+            NodeEnum::MacroCallNode(m) => self.node(&m.callee),
+            NodeEnum::EnumDef(e) => {
+                self.nodes(&e.precom);
+                self.nodes(&e.doc);
+                self.push(e.id.range);
+                for v in e.variants.iter() {
+                    self.push(v.range);
+                    self.push(v.id.range);
+                    v.fields.iter().for_each(|f| self.tp(f));
+                }
+                self.fold(e.id.range.start, e.range.end);
+            }
+            NodeEnum::Match(m) => {
+                self.node(&m.value);
+                for arm in m.arms.iter() {
+                    self.push(arm.range);
+                    if let Some(p) = &arm.pattern {
+                        self.push(p.range);
+                        self.extern_id(p);
+                    }
+                    for b in arm.bindings.iter().flatten() {
+                        self.push(b.range);
+                        self.writes.push(b.range);
+                    }
+                    self.node(&arm.body);
+                }
+            }
+            NodeEnum::Closure(c) => {
+                c.paralist.iter().for_each(|p| self.typed_id(p, true));
+                self.opt_tp(&c.ret);
+                self.sts(&c.body);
+                self.fold(c.body.range.start, c.body.range.end);
+            }
+            NodeEnum::As(a) => {
+                self.node(&a.expr);
+                self.tp(&a.ty);
+            }
+            _ => {}
+        }
+    }
+
+    fn sts(&mut self, sts: &StatementsNode) {
+        self.push(sts.range);
+        self.nodes(&sts.statements);
+    }
+
+    fn func(&mut self, f: &FuncDefNode) {
+        self.push(f.range);
+        self.nodes(&f.precom);
+        self.nodes(&f.doc);
+        self.push(f.id.range);
+        if let Some(g) = &f.generics {
+            self.push(g.range);
+        }
+        f.paralist.iter().for_each(|p| self.typed_id(p, true));
+        self.tp(&f.ret);
+        for b in f.trait_bounds.iter().flatten() {
+            self.push(b.range);
+            self.push(b.generic.range);
+            self.tp(&b.impl_trait);
+        }
+        if let Some(body) = &f.body {
+            self.sts(body);
+            self.fold(body.range.start, body.range.end);
+        }
+    }
+
+    fn typed_id(&mut self, id: &TypedIdentifierNode, is_param: bool) {
+        self.push(id.range);
+        self.push(id.id.range);
+        if is_param {
+            self.writes.push(id.id.range);
+        }
+        self.tp(&id.typenode);
+    }
+
+    fn extern_id(&mut self, e: &ExternIdNode) {
+        e.ns.iter().for_each(|ns| self.push(ns.range));
+        self.push(e.id.range);
+    }
+
+    fn opt_tp(&mut self, tp: &Option<Box<TypeNodeEnum>>) {
+        if let Some(tp) = tp {
+            self.tp(tp);
+        }
+    }
+
+    fn tp(&mut self, tp: &TypeNodeEnum) {
+        self.push(tp.range());
+        match tp {
+            TypeNodeEnum::BasicTypeNode(t) => {
+                if let Some(id) = &t.id {
+                    self.push(id.range);
+                    self.extern_id(id);
+                }
+                if let Some(g) = &t.generic_params {
+                    self.push(g.range);
+                    g.generics.iter().flatten().for_each(|t| self.tp(t));
+                }
+            }
+            TypeNodeEnum::ArrayTypeNode(a) => {
+                self.tp(&a.id);
+                if let Some(size) = &a.size {
+                    self.node(size);
+                }
+            }
+            TypeNodeEnum::PointerTypeNode(p) => self.tp(&p.elm),
+            TypeNodeEnum::FuncTypeNode(f) => self.func(f),
+            TypeNodeEnum::ClosureTypeNode(c) => {
+                c.arg_types.iter().for_each(|t| self.tp(t));
+                self.tp(&c.ret_type);
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! # workspace symbol
+//! 在项目以及它的依赖的所有模块中模糊搜索符号
+//!
+//! 符号来自编译时生成的[Mod::doc_symbols]，lsp总是从项目入口开始编译，
+//! 所以入口模块的依赖覆盖了`Kagari.toml`能访问到的所有模块
+use lsp_types::{DocumentSymbol, Location, SymbolInformation, SymbolKind, Url};
+
+use crate::ast::plmod::Mod;
+
+/// 最多返回的符号数量
+const MAX_SYMBOLS: usize = 256;
+
+pub fn workspace_symbols(root: &Mod, query: &str) -> Vec<SymbolInformation> {
+    let mut res = vec![];
+    for m in root.all_mods() {
+        let uri = match Url::from_file_path(&m.path) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        for s in m.doc_symbols.borrow().iter() {
+            collect_symbols(s, None, &uri, query, &mut res);
+        }
+    }
+    res.sort_by(|(s1, a), (s2, b)| (s1, a.name.len(), &a.name).cmp(&(s2, b.name.len(), &b.name)));
+    res.into_iter().take(MAX_SYMBOLS).map(|(_, s)| s).collect()
+}
+
+fn collect_symbols(
+    s: &DocumentSymbol,
+    container: Option<&str>,
+    uri: &Url,
+    query: &str,
+    res: &mut Vec<(u8, SymbolInformation)>,
+) {
+    let container_name = if s.kind == SymbolKind::OBJECT {
+        // `impl A`块本身不是符号，它的方法以`A`作为容器
+        s.name.trim_start_matches("impl ").to_string()
+    } else {
+        if let Some(score) = fuzzy_match(&s.name, query) {
+            #[allow(deprecated)]
+            res.push((
+                score,
+                SymbolInformation {
+                    name: s.name.clone(),
+                    kind: s.kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location::new(uri.clone(), s.selection_range),
+                    container_name: container.map(|c| c.to_string()),
+                },
+            ));
+        }
+        s.name.clone()
+    };
+    for c in s.children.iter().flatten() {
+        collect_symbols(c, Some(&container_name), uri, query, res);
+    }
+}
+
+/// 忽略大小写的模糊匹配，`query`中的字符需要按顺序出现在`name`中
+///
+/// 返回值越小匹配得越好：0为前缀，1为子串，2为子序列
+fn fuzzy_match(name: &str, query: &str) -> Option<u8> {
+    let name = name.to_lowercase();
+    let query = query.to_lowercase();
+    if name.starts_with(&query) {
+        return Some(0);
+    }
+    if name.contains(&query) {
+        return Some(1);
+    }
+    let mut chars = name.chars();
+    if query.chars().all(|q| chars.any(|c| c == q)) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert_eq!(fuzzy_match("testSt", ""), Some(0));
+        assert_eq!(fuzzy_match("testSt", "TEST"), Some(0));
+        assert_eq!(fuzzy_match("testSt", "st"), Some(1));
+        assert_eq!(fuzzy_match("testSt", "tst"), Some(2));
+        assert_eq!(fuzzy_match("testSt", "tsx"), None);
+    }
+}
@@ -44,6 +44,7 @@ pub trait Db: salsa::DbWithJar<Jar> {
 }
 
 mod ast;
+mod dap;
 mod db;
 mod flow;
 mod lsp;
@@ -52,20 +53,25 @@ mod utils;
 use std::{
     cell::RefCell,
     path::Path,
+    process::exit,
     sync::{Arc, Mutex},
 };
 
 use ast::{
     accumulators,
-    compiler::{self, ActionType, HashOptimizationLevel},
+    builder::llvmbuilder::check_target,
+    compiler::{self, ActionType, HashOptimizationLevel, TargetOptions},
     node::program,
 };
 use clap::{CommandFactory, Parser, Subcommand};
+use colored::Colorize;
 use db::Database;
+use kagari::resolve::ResolveOptions;
 use lsp::{
     mem_docs::{self, MemDocsInput},
     start_lsp,
 };
+use utils::read_config;
 
 /// Pivot Lang compiler program
 #[derive(Parser)]
@@ -122,10 +128,38 @@ struct Cli {
     #[arg(long)]
     flow: bool,
 
+    /// print the program with all macro calls expanded
+    #[arg(long)]
+    expand_macros: bool,
+
     /// generate ir
     #[arg(long)]
     genir: bool,
 
+    /// do not use or update the incremental compilation cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// require Kagari.lock to be up to date, fail instead of updating it
+    #[arg(long, global = true)]
+    locked: bool,
+
+    /// never access the network, only use dependencies downloaded before
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// target triple to compile for, e.g. aarch64-unknown-linux-gnu
+    #[arg(long, global = true, value_parser)]
+    target: Option<String>,
+
+    /// target cpu, defaults to the host cpu (or `generic` when cross compiling)
+    #[arg(long, global = true, value_parser)]
+    cpu: Option<String>,
+
+    /// target cpu features, e.g. +neon,-fp-armv8
+    #[arg(long, global = true, value_parser)]
+    features: Option<String>,
+
     /// optimization level, 0-3
     #[arg(short = 'O', value_parser, default_value = "0")]
     optimization: u64,
@@ -137,14 +171,49 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum RunCommand {
-    /// JIT run the compiled program
+    /// Build current project into `<target_dir>/<profile>/`
+    Build {
+        /// Build with the release profile
+        #[arg(long)]
+        release: bool,
+        /// Directory for all generated artifacts
+        #[arg(long, value_parser)]
+        target_dir: Option<String>,
+        /// Only build this package of the workspace
+        #[arg(short, long, value_parser, conflicts_with = "all")]
+        package: Option<String>,
+        /// Build all packages of the workspace
+        #[arg(long)]
+        all: bool,
+    },
+    /// Build and run current project, or JIT run a compiled `.bc` file
     Run {
-        /// Name of the compiled file
+        /// Name of the compiled file to JIT run
         #[arg(value_parser)]
-        name: String,
+        name: Option<String>,
+        /// Build with the release profile
+        #[arg(long)]
+        release: bool,
+        /// Directory for all generated artifacts
+        #[arg(long, value_parser)]
+        target_dir: Option<String>,
+        /// Package of the workspace to run
+        #[arg(short, long, value_parser)]
+        package: Option<String>,
+        /// Arguments passed to the program
+        #[arg(last = true, value_parser)]
+        args: Vec<String>,
+    },
+    /// Remove the build artifacts and caches of current project
+    Clean {
+        /// Directory for all generated artifacts
+        #[arg(long, value_parser)]
+        target_dir: Option<String>,
     },
     /// Start the language server
     Lsp,
+    /// Start the debug adapter, debugging is done by a local gdb or lldb-mi
+    Dap,
     /// Format current project
     Fmt,
     /// Make a new pl package at path
@@ -152,6 +221,12 @@ enum RunCommand {
         #[clap(value_parser)]
         name: String,
     },
+    /// Run the tests marked with `#[test]` in current project
+    Test {
+        /// Only run the tests whose name contains the filter
+        #[arg(value_parser)]
+        filter: Option<String>,
+    },
 }
 
 fn main() {
@@ -164,12 +239,26 @@ fn main() {
         _ => panic!("optimization level must be 0-3"),
     };
 
+    let target = TargetOptions {
+        triple: cli.target.clone(),
+        cpu: cli.cpu.clone(),
+        features: cli.features.clone(),
+    };
+    if let Err(e) = check_target(&target) {
+        eprintln!("{}", e.bright_red());
+        exit(1);
+    }
+
     let mut logger = stderrlog::new();
     logger
         .module(module_path!())
         .quiet(cli.quiet)
         .verbosity(cli.verbose as usize);
 
+    read_config::set_resolve_options(ResolveOptions {
+        locked: cli.locked,
+        offline: cli.offline,
+    });
     let fmt = match cli.command {
         Some(RunCommand::Fmt) => true,
         _ => false,
@@ -185,6 +274,11 @@ fn main() {
             printast: cli.printast,
             flow: cli.flow,
             fmt,
+            expand_macros: cli.expand_macros,
+            no_cache: cli.no_cache,
+            target,
+            test_harness: false,
+            target_dir: None,
             optimization: opt,
         };
         let action = if cli.flow {
@@ -193,6 +287,8 @@ fn main() {
             ActionType::PrintAst
         } else if fmt {
             ActionType::Fmt
+        } else if cli.expand_macros {
+            ActionType::PrintMacroExpansion
         } else {
             ActionType::Compile
         };
@@ -200,7 +296,7 @@ fn main() {
             &db,
             Arc::new(Mutex::new(RefCell::new(mem_docs::MemDocs::new()))),
             abs.to_str().unwrap().to_string(),
-            op,
+            op.clone(),
             action,
             None,
             None,
@@ -208,7 +304,26 @@ fn main() {
         compiler::compile(&db, mem, cli.out.clone(), op);
     } else if let Some(command) = cli.command {
         match command {
-            RunCommand::Run { name } => {
+            RunCommand::Build {
+                release,
+                target_dir,
+                package,
+                all,
+            } => {
+                logger.timestamp(stderrlog::Timestamp::Off).init().unwrap();
+                utils::plc_build::build_project(&utils::plc_build::BuildOptions {
+                    profile: utils::plc_build::Profile::new(release),
+                    target_dir,
+                    genir: cli.genir,
+                    no_cache: cli.no_cache,
+                    target,
+                    package,
+                    all,
+                });
+            }
+            RunCommand::Run {
+                name: Some(name), ..
+            } => {
                 #[cfg(feature = "jit")]
                 compiler::run(Path::new(name.as_str()), opt.to_llvm());
                 #[cfg(not(feature = "jit"))]
@@ -217,6 +332,30 @@ fn main() {
                     name
                 );
             }
+            RunCommand::Run {
+                name: None,
+                release,
+                target_dir,
+                package,
+                args,
+            } => {
+                logger.timestamp(stderrlog::Timestamp::Off).init().unwrap();
+                utils::plc_build::run_project(
+                    &utils::plc_build::BuildOptions {
+                        profile: utils::plc_build::Profile::new(release),
+                        target_dir,
+                        genir: cli.genir,
+                        no_cache: cli.no_cache,
+                        target,
+                        package,
+                        all: false,
+                    },
+                    args,
+                );
+            }
+            RunCommand::Clean { target_dir } => {
+                utils::plc_build::clean_project(target_dir);
+            }
             RunCommand::Lsp {} => {
                 logger
                     .timestamp(stderrlog::Timestamp::Microsecond)
@@ -224,12 +363,23 @@ fn main() {
                     .unwrap();
                 start_lsp().unwrap();
             }
+            RunCommand::Dap => {
+                logger
+                    .timestamp(stderrlog::Timestamp::Microsecond)
+                    .init()
+                    .unwrap();
+                dap::start_dap().unwrap();
+            }
             RunCommand::Fmt {} => {
                 println!("fmt command is not implemented yet");
             }
             RunCommand::New { name } => {
                 utils::plc_new::init_package(name);
             }
+            RunCommand::Test { filter } => {
+                logger.timestamp(stderrlog::Timestamp::Off).init().unwrap();
+                utils::plc_test::run_tests(filter, opt, cli.no_cache);
+            }
         }
     } else {
         println!("No file provided");
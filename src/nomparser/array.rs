@@ -1,4 +1,4 @@
-use internal_macro::test_parser;
+use internal_macro::{test_parser, test_parser_error};
 use nom::{
     combinator::{map_res, opt},
     multi::{many0, separated_list0},
@@ -8,7 +8,7 @@ use nom::{
 
 use crate::nomparser::Span;
 use crate::{
-    ast::node::types::ArrayInitNode,
+    ast::node::types::{ArrayInitNode, DynArrayInitNode},
     ast::tokens::TokenType,
     ast::{diag::ErrorCode, node::error::ErrorNode},
 };
@@ -40,6 +40,30 @@ pub fn array_init(input: Span) -> IResult<Span, Box<NodeEnum>> {
     )(input)
 }
 
+#[test_parser("[i64 * 10;]")]
+#[test_parser("[i64 * n * 2;]")]
+#[test_parser("[ *Node<T> * len ; ]")]
+#[test_parser_error("[i64 * 10]")]
+/// ```ebnf
+/// dyn_array_init = "[" type_name "*" logic_exp ";" "]" ;
+/// ```
+pub fn dyn_array_init(input: Span) -> IResult<Span, Box<NodeEnum>> {
+    map_res(
+        tuple((
+            tag_token_symbol(TokenType::LBRACKET),
+            type_name,
+            tag_token_symbol(TokenType::MUL),
+            logic_exp,
+            tag_token_symbol(TokenType::SEMI),
+            tag_token_symbol(TokenType::RBRACKET),
+        )),
+        |((_, lb), tp, _, len, _, (_, rb))| {
+            let range = lb.start.to(rb.end);
+            res_enum(DynArrayInitNode { tp, len, range }.into())
+        },
+    )(input)
+}
+
 #[test_parser("[123]")]
 /// ```ebnf
 /// array_element_op = ('[' logic_exp ']') ;
@@ -1,7 +1,8 @@
 use nom::{
     branch::alt,
     combinator::{map_res, opt},
-    sequence::{preceded, tuple},
+    multi::{many0, separated_list1},
+    sequence::{delimited, preceded, terminated, tuple},
     IResult,
 };
 
@@ -265,3 +266,124 @@ pub fn continue_statement(input: Span) -> IResult<Span, Box<NodeEnum>> {
         },
     )(input)
 }
+
+/// ```ebnf
+/// match_binding = identifier | "_" ;
+/// ```
+fn match_binding(input: Span) -> IResult<Span, Box<VarNode>> {
+    alt((
+        identifier,
+        map_res(tag_token_word(TokenType::INGNORE), |(_, range)| {
+            res_box(Box::new(VarNode {
+                name: TokenType::INGNORE.get_str().to_string(),
+                range,
+            }))
+        }),
+    ))(input)
+}
+
+#[test_parser("_ => 1")]
+#[test_parser("Shape::Empty => 1")]
+#[test_parser("Shape::Circle(r) => r * 2.0")]
+#[test_parser(
+    "m::Shape::Rect(w, _) => {
+    return w;
+}"
+)]
+#[test_parser_error("Shape::Circle() => 1")]
+/// ```ebnf
+/// match_arm = ("_" | extern_identifier ("(" match_binding ("," match_binding)* ")")?) "=>" (statement_block | logic_exp) ;
+/// ```
+fn match_arm(input: Span) -> IResult<Span, Box<MatchArmNode>> {
+    map_res(
+        tuple((
+            many0(del_newline_or_space!(comment)),
+            alt((
+                map_res(tag_token_word(TokenType::INGNORE), |(_, range)| {
+                    Ok::<_, ()>((None, range))
+                }),
+                map_res(extern_identifier, |id| match *id {
+                    NodeEnum::ExternIdNode(id) => {
+                        let range = id.range;
+                        Ok::<_, ()>((Some(Box::new(id)), range))
+                    }
+                    _ => Err(()),
+                }),
+            )),
+            opt(delimited(
+                tag_token_symbol(TokenType::LPAREN),
+                separated_list1(tag_token_symbol(TokenType::COMMA), match_binding),
+                tag_token_symbol(TokenType::RPAREN),
+            )),
+            tag_token_symbol(TokenType::ARROW),
+            del_newline_or_space!(alt((
+                map_res(statement_block, |n| res_enum(n.into())),
+                parse_with_ex(logic_exp, false),
+            ))),
+        )),
+        |(coms, (pattern, start), bindings, _, body)| {
+            let range = start.start.to(body.range().end);
+            res_box(Box::new(MatchArmNode {
+                pattern,
+                bindings,
+                body,
+                range,
+                comments: vec![coms],
+            }))
+        },
+    )(input)
+}
+
+#[test_parser(
+    "match s {
+    Shape::Circle(r) => {
+        a = r;
+    }
+    Shape::Rect(w, h) => {
+        a = w * h;
+    }
+    _ => {}
+}"
+)]
+#[test_parser(
+    "match s {
+    Shape::Circle(r) => r,
+    Shape::Empty => 0.0,
+}"
+)]
+#[test_parser("match s {}")]
+#[test_parser_error(
+    "matchs {
+    _ => 1,
+}"
+)]
+/// ```ebnf
+/// match_exp = "match" logic_exp "{" (match_arm ","?)* "}" ;
+/// ```
+pub fn match_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
+    map_res(
+        delspace(tuple((
+            tag_token_word(TokenType::MATCH),
+            parse_with_ex(logic_exp, true),
+            del_newline_or_space!(tag_token_symbol(TokenType::LBRACE)),
+            many0(terminated(
+                del_newline_or_space!(match_arm),
+                opt(tag_token_symbol_ex(TokenType::COMMA)),
+            )),
+            many0(del_newline_or_space!(comment)),
+            del_newline_or_space!(tag_token_symbol(TokenType::RBRACE)),
+        ))),
+        |((_, start), value, _, arms, coms, (_, end))| {
+            let range = start.start.to(end.end);
+            res_enum(
+                MatchNode {
+                    value,
+                    arms,
+                    range,
+                    comments: vec![coms],
+                }
+                .into(),
+            )
+        },
+    )(input)
+}
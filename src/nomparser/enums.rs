@@ -0,0 +1,127 @@
+use crate::nomparser::Span;
+use crate::{
+    ast::node::enums::{EnumDefNode, EnumVariantNode},
+    ast::node::{NodeEnum, RangeTrait},
+    ast::tokens::TokenType,
+};
+use internal_macro::{test_parser, test_parser_error};
+use nom::{
+    combinator::{map_res, opt},
+    multi::{many0, separated_list0},
+    sequence::{delimited, tuple},
+    IResult,
+};
+
+use super::*;
+
+#[test_parser("Empty")]
+#[test_parser("Circle(f64)")]
+#[test_parser("Rect(f64, *A)")]
+#[test_parser(
+    "/// doc
+    Circle(f64)"
+)]
+/// ```ebnf
+/// enum_variant = identifier ("(" (type_name ("," type_name)*)? ")")? ;
+/// ```
+pub fn enum_variant(input: Span) -> IResult<Span, Box<EnumVariantNode>> {
+    map_res(
+        tuple((
+            many0(del_newline_or_space!(comment)),
+            identifier,
+            opt(delimited(
+                tag_token_symbol(TokenType::LPAREN),
+                separated_list0(tag_token_symbol(TokenType::COMMA), type_name),
+                tag_token_symbol(TokenType::RPAREN),
+            )),
+        )),
+        |(doc, id, fields)| {
+            let mut range = id.range;
+            if let Some(fields) = &fields {
+                if let Some(last) = fields.last() {
+                    range = range.start.to(last.range().end);
+                }
+            }
+            let mut docs = vec![];
+            for d in doc {
+                if let NodeEnum::Comment(com) = *d {
+                    if com.is_doc {
+                        docs.push(Box::new(NodeEnum::Comment(com)));
+                    }
+                }
+            }
+            res_box(Box::new(EnumVariantNode {
+                id,
+                fields: fields.unwrap_or_default(),
+                doc: docs,
+                range,
+            }))
+        },
+    )(input)
+}
+
+#[test_parser(
+    "enum Shape {
+    Circle(f64),
+    Rect(f64, f64),
+    Empty,
+}"
+)]
+#[test_parser(
+    "pub enum Shape {
+    Circle(f64),
+    Empty
+}"
+)]
+#[test_parser("enum Empty {}")]
+#[test_parser_error(
+    "enumShape {
+    Empty,
+}"
+)]
+#[test_parser_error(
+    "pubenum Shape {
+    Empty,
+}"
+)]
+/// ```ebnf
+/// enum_def = "pub"? "enum" identifier "{" (enum_variant ("," enum_variant)* ","?)? "}" ;
+/// ```
+pub fn enum_def(input: Span) -> IResult<Span, Box<TopLevel>> {
+    map_res(
+        tuple((
+            many0(del_newline_or_space!(comment)),
+            modifiable(tag_token_word(TokenType::ENUM), TokenType::PUB),
+            identifier,
+            del_newline_or_space!(tag_token_symbol(TokenType::LBRACE)),
+            separated_list0(
+                tag_token_symbol_ex(TokenType::COMMA),
+                del_newline_or_space!(enum_variant),
+            ),
+            opt(tag_token_symbol_ex(TokenType::COMMA)),
+            many0(del_newline_or_space!(comment)),
+            del_newline_or_space!(tag_token_symbol(TokenType::RBRACE)),
+        )),
+        |(doc, (modifier, (_, start)), id, _, variants, _, _, (_, end))| {
+            let range = start.start.to(end.end);
+            let mut docs = vec![];
+            let mut precoms = vec![];
+            for d in doc {
+                if let NodeEnum::Comment(com) = *d {
+                    if com.is_doc {
+                        docs.push(Box::new(NodeEnum::Comment(com.clone())));
+                    }
+                    precoms.push(Box::new(NodeEnum::Comment(com)));
+                }
+            }
+            Ok::<_, ()>(Box::new(TopLevel::EnumDef(EnumDefNode {
+                precom: precoms,
+                doc: docs,
+                id,
+                variants,
+                range,
+                modifier,
+            })))
+        },
+    )(input)
+}
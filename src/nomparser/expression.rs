@@ -1,8 +1,8 @@
 use nom::{
     branch::alt,
-    combinator::{map_res, opt},
+    combinator::{map_res, not, opt},
     multi::many0,
-    sequence::{delimited, pair, preceded, tuple},
+    sequence::{delimited, pair, preceded, terminated, tuple},
     IResult,
 };
 
@@ -32,7 +32,49 @@ pub fn logic_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
 #[test_parser("a==b")]
 #[test_parser("a!=b")]
 fn compare_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
-    parse_bin_ops!(add_exp, GEQ, LEQ, NE, EQ, LESS, GREATER)(input)
+    parse_bin_ops!(bit_or_exp, GEQ, LEQ, NE, EQ, LESS, GREATER)(input)
+}
+
+/// 位运算符`&`和`|`后面不能紧跟相同的符号，否则是逻辑运算符`&&`和`||`
+fn bit_op(token: TokenType) -> impl Fn(Span) -> IResult<Span, (TokenType, Range)> {
+    move |input| terminated(tag_token_symbol(token), not(tag_token(token)))(input)
+}
+
+#[test_parser("a | b")]
+#[test_parser("a | b & c ^ d")]
+#[test_parser("a || b | c")]
+fn bit_or_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
+    delspace(map_res(
+        tuple((
+            bit_xor_exp,
+            many0(tuple((bit_op(TokenType::BIT_OR), bit_xor_exp))),
+        )),
+        create_bin,
+    ))(input)
+}
+
+#[test_parser("a ^ b")]
+fn bit_xor_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
+    parse_bin_ops!(bit_and_exp, BIT_XOR)(input)
+}
+
+#[test_parser("a & b")]
+#[test_parser("a & &b")]
+#[test_parser("a && b & c")]
+fn bit_and_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
+    delspace(map_res(
+        tuple((
+            shift_exp,
+            many0(tuple((bit_op(TokenType::BIT_AND), shift_exp))),
+        )),
+        create_bin,
+    ))(input)
+}
+
+#[test_parser("a << 1")]
+#[test_parser("a >> b + 1")]
+fn shift_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
+    parse_bin_ops!(add_exp, LEFT_SHIFT, RIGHT_SHIFT)(input)
 }
 
 #[test_parser("a + 1")]
@@ -43,12 +85,45 @@ fn add_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
 
 #[test_parser("1 * 1")]
 #[test_parser("1 / 1")]
+#[test_parser("1 % 1")]
 fn mul_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
-    parse_bin_ops!(unary_exp, MUL, DIV)(input)
+    parse_bin_ops!(cast_exp, MUL, DIV, MOD)(input)
+}
+
+/// ```ebnf
+/// cast_exp = unary_exp ("as" type_name)*;
+/// ```
+#[test_parser("a as i64")]
+#[test_parser("a as u8 as *i8")]
+#[test_parser("-1 as u64")]
+#[test_parser_error("a asi64")]
+fn cast_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
+    delspace(map_res(
+        pair(
+            unary_exp,
+            many0(preceded(tag_token_word(TokenType::AS), type_name)),
+        ),
+        |(exp, tps)| {
+            let mut exp = exp;
+            for ty in tps {
+                let range = exp.range().start.to(ty.range().end);
+                exp = Box::new(
+                    AsNode {
+                        expr: exp,
+                        ty,
+                        range,
+                    }
+                    .into(),
+                );
+            }
+            res_box(exp)
+        },
+    ))(input)
 }
 
 #[test_parser("-1")]
 #[test_parser("!a")]
+#[test_parser("~a")]
 #[test_parser_error("+a")]
 fn unary_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
     delspace(alt((
@@ -58,6 +133,7 @@ fn unary_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
                 alt((
                     tag_token_symbol(TokenType::MINUS),
                     tag_token_symbol(TokenType::NOT),
+                    tag_token_symbol(TokenType::BIT_NOT),
                 )),
                 pointer_exp,
             )),
@@ -159,6 +235,7 @@ fn complex_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
                                 range,
                                 callee: res,
                                 paralist: args,
+                                params_range,
                                 comments: vec![op.1],
                             }
                             .into(),
@@ -212,8 +289,11 @@ fn primary_exp(input: Span) -> IResult<Span, Box<NodeEnum>> {
                 bool_const,
                 parantheses_exp,
                 struct_init,
+                dyn_array_init,
                 array_init,
                 macro_call_exp,
+                match_exp,
+                closure,
                 extern_identifier,
                 string_literal,
             )),
@@ -6,7 +6,10 @@ use nom::{
     IResult,
 };
 
-use crate::{ast::node::function::FuncDefNode, ast::tokens::TokenType};
+use crate::{
+    ast::node::function::{ClosureNode, FuncDefNode},
+    ast::tokens::TokenType,
+};
 use crate::{ast::node::interface::TraitBoundNode, nomparser::Span};
 
 use internal_macro::{test_parser, test_parser_error};
@@ -14,7 +17,7 @@ use internal_macro::{test_parser, test_parser_error};
 use super::*;
 
 /// ```ebnf
-/// function_def = "fn" identifier "(" (typed_identifier (","typed_identifier)*)? ")" type_name (statement_block | newline) ;
+/// function_def = test_attr? "fn" identifier "(" (typed_identifier (","typed_identifier)*)? ")" type_name (statement_block | newline) ;
 /// ```
 #[test_parser(
     "fn f(  x: int, y  : int  ) int {
@@ -52,11 +55,21 @@ use super::*;
     "
 )]
 #[test_parser("fn f( \n) int;")]
+#[test_parser(
+    "/// doc
+    #[test]
+    fn f() void {
+        return;
+    }
+    "
+)]
 #[test_parser_error("fnf( \n) int;")]
+#[test_parser_error("#[tset] fn f() void {return;}")]
 pub fn function_def(input: Span) -> IResult<Span, Box<TopLevel>> {
     map_res(
         tuple((
             many0(del_newline_or_space!(comment)),
+            opt(del_newline_or_space!(test_attr)),
             modifiable(tag_token_word(TokenType::FN), TokenType::PUB),
             identifier,
             opt(generic_type_def),
@@ -83,6 +96,7 @@ pub fn function_def(input: Span) -> IResult<Span, Box<TopLevel>> {
         )),
         |(
             doc,
+            test,
             (modifier, (_, start)),
             id,
             generics,
@@ -117,12 +131,36 @@ pub fn function_def(input: Span) -> IResult<Span, Box<TopLevel>> {
                 generics,
                 body,
                 modifier,
+                is_test: test.is_some(),
             };
             Ok::<_, ()>(Box::new(TopLevel::FuncType(node)))
         },
     )(input)
 }
 
+/// ```ebnf
+/// test_attr = "#" "[" "test" "]" ;
+/// ```
+#[test_parser("#[test]")]
+#[test_parser("# [ test ]")]
+#[test_parser_error("#[tests]")]
+fn test_attr(input: Span) -> IResult<Span, Range> {
+    map_res(
+        tuple((
+            tag_token_symbol(TokenType::HASH),
+            tag_token_symbol(TokenType::LBRACKET),
+            identifier,
+            tag_token_symbol(TokenType::RBRACKET),
+        )),
+        |((_, start), _, id, (_, end))| {
+            if id.name != "test" {
+                return Err(());
+            }
+            Ok(start.start.to(end.end))
+        },
+    )(input)
+}
+
 /// ```ebnf
 /// call_function_op = "(" (logic_exp (","logic_exp)*)? ")" ;
 /// ```
@@ -149,6 +187,52 @@ pub fn call_function_op(input: Span) -> IResult<Span, (ComplexOp, Vec<Box<NodeEn
     ))(input)
 }
 
+/// ```ebnf
+/// closure = "move"? "|" (typed_identifier (","typed_identifier)*)? "|" ("=>" type_name)? statement_block ;
+/// ```
+#[test_parser(
+    "|a: i64, b: i64| => i64 {
+        return a + b;
+    }"
+)]
+#[test_parser("|| {return;}")]
+#[test_parser(
+    "move |x: i64| => i64 {
+        return x + y;
+    }"
+)]
+#[test_parser("|f: |i64| => i64| => i64 {return f(1);}")]
+#[test_parser_error("movex |x: i64| {return;}")]
+pub fn closure(input: Span) -> IResult<Span, Box<NodeEnum>> {
+    map_res(
+        tuple((
+            opt(tag_token_word(TokenType::MOVE)),
+            tag_token_symbol(TokenType::GENERIC_SEP),
+            del_newline_or_space!(separated_list0(
+                tag_token_symbol(TokenType::COMMA),
+                del_newline_or_space!(typed_identifier),
+            )),
+            tag_token_symbol(TokenType::GENERIC_SEP),
+            opt(preceded(tag_token_symbol(TokenType::ARROW), type_name)),
+            statement_block,
+        )),
+        |(is_move, (_, start), paralist, _, ret, body)| {
+            let start = is_move.map_or(start, |(_, r)| r);
+            let range = start.start.to(body.range.end);
+            res_enum(
+                ClosureNode {
+                    paralist,
+                    ret,
+                    body,
+                    is_move: is_move.is_some(),
+                    range,
+                }
+                .into(),
+            )
+        },
+    )(input)
+}
+
 pub fn trait_bound(input: Span) -> IResult<Span, Box<TraitBoundNode>> {
     map_res(
         tuple((identifier, tag_token_symbol(TokenType::COLON), type_name)),
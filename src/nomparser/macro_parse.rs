@@ -1,15 +1,17 @@
 use nom::{
     branch::alt,
-    bytes::complete::{is_a, take_while1},
-    combinator::{map_res, recognize},
+    bytes::complete::{is_a, tag, take_while1},
+    character::complete::{digit1, one_of},
+    combinator::{consumed, map_res, opt, peek, recognize},
     multi::{many0, many1},
-    sequence::{delimited, preceded, tuple},
+    sequence::{delimited, pair, preceded, tuple},
     IResult,
 };
 
 use crate::{
     ast::node::macro_nodes::{
-        MacroLoopStatementNode, MacroMatchExp, MacroMatchParameter, MacroNode, MacroRuleNode,
+        macro_vars_in, MacroLoopStatementNode, MacroMatchExp, MacroMatchParameter, MacroNode,
+        MacroRuleNode,
     },
     nomparser::Span,
 };
@@ -39,6 +41,9 @@ macro_rules! alt_tokens {
 ///     | "(" macro_match_exp * ")"
 ///     ;
 #[test_parser("$a:@id")]
+#[test_parser("$t:@ty")]
+#[test_parser("$b:@block")]
+#[test_parser("($l:@lit, $($t:@tt)*)")]
 #[test_parser("()")]
 #[test_parser("$($a:@id,)*")]
 #[test_parser("($($fmt:@id,+1dasda,)* / $a:@expr)")]
@@ -55,6 +60,10 @@ fn macro_match_exp(input: Span) -> IResult<Span, MacroMatchExp> {
                     MACRO_TYPE_EXPR,
                     MACRO_TYPE_STMTS,
                     MACRO_TYPE_STMT,
+                    MACRO_TYPE_TY,
+                    MACRO_TYPE_BLOCK,
+                    MACRO_TYPE_LIT,
+                    MACRO_TYPE_TT,
                 ),
             )),
             |(dollar, id, _, tp)| {
@@ -114,7 +123,7 @@ fn macro_match_exp(input: Span) -> IResult<Span, MacroMatchExp> {
 fn macro_body_loop_parser(origin: Span) -> IResult<Span, Box<NodeEnum>> {
     let (input, _) = tag_token_symbol(TokenType::DOLLAR)(origin)?;
     let (input, _) = tag_token_symbol(TokenType::LPAREN)(input)?;
-    let (input, statements) = many0(del_newline_or_space!(statement))(input)?;
+    let (input, (code, statements)) = consumed(many0(del_newline_or_space!(statement)))(input)?;
     let (input, _) = tag_token_symbol(TokenType::RPAREN)(input)?;
     let (input, _) = tag_token_symbol(TokenType::MUL)(input)?;
     let range = Range::new(origin, input);
@@ -122,6 +131,7 @@ fn macro_body_loop_parser(origin: Span) -> IResult<Span, Box<NodeEnum>> {
         input,
         Box::new(NodeEnum::MacroLoopStatementNode(MacroLoopStatementNode {
             statements: Box::new(StatementsNode { statements, range }.into()),
+            vars: macro_vars_in(&code),
             range,
         })),
     ))
@@ -133,6 +143,7 @@ fn macro_body_loop_parser(origin: Span) -> IResult<Span, Box<NodeEnum>> {
     }"#
 )]
 #[test_parser(r#"macro test {}"#)]
+#[test_parser(r#"pub macro test {}"#)]
 #[test_parser(
     r#"macro test {
         ($($a:@id = $b:@expr,)*) => {
@@ -149,7 +160,8 @@ fn macro_body_loop_parser(origin: Span) -> IResult<Span, Box<NodeEnum>> {
 )]
 pub fn macro_parser(origin: Span) -> IResult<Span, Box<TopLevel>> {
     // a macro node can have multiple rules
-    let (input, _) = tag_token_symbol(TokenType::MACRO)(origin)?;
+    let (input, (modifier, _)) =
+        modifiable(tag_token_word(TokenType::MACRO), TokenType::PUB)(origin)?;
     let (input, id) = identifier(input)?;
     // rules
     let (input, _) = del_newline_or_space!(tag_token_symbol(TokenType::LBRACE))(input)?;
@@ -163,6 +175,7 @@ pub fn macro_parser(origin: Span) -> IResult<Span, Box<TopLevel>> {
             rules,
             range,
             file: "".into(),
+            modifier,
         })))),
     ))
 }
@@ -229,3 +242,62 @@ fn any_exp_with_parens(origin: Span) -> IResult<Span, String> {
         }),
     ))(origin)
 }
+
+/// token_tree =
+///     | "(" token_tree* ")"
+///     | "[" token_tree* "]"
+///     | "{" token_tree* "}"
+///     | string_literal
+///     | number
+///     | "$"? identifier
+///     | punctuation
+///     ;
+#[test_parser("a")]
+#[test_parser("$a")]
+#[test_parser("1.5")]
+#[test_parser("<=")]
+#[test_parser(r#""a)b""#)]
+#[test_parser("(a + [b, c] * {d})")]
+#[test_parser("{\n    let a = 1;\n}")]
+#[test_parser_error(")")]
+#[test_parser_error("(a")]
+pub fn token_tree(input: Span) -> IResult<Span, Span> {
+    del_newline_or_space!(alt((
+        recognize(delimited(
+            tag("("),
+            many0(token_tree),
+            del_newline_or_space!(tag(")"))
+        )),
+        recognize(delimited(
+            tag("["),
+            many0(token_tree),
+            del_newline_or_space!(tag("]"))
+        )),
+        recognize(delimited(
+            tag("{"),
+            many0(token_tree),
+            del_newline_or_space!(tag("}"))
+        )),
+        // 字符串的parser是streaming的，先确认是字符串避免在输入结束时返回Incomplete
+        preceded(peek(tag("\"")), recognize(string_literal::string_literal)),
+        recognize(pair(digit1, opt(pair(tag("."), digit1)))),
+        recognize(pair(
+            opt(tag("$")),
+            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+        )),
+        alt((
+            tag("=="),
+            tag("!="),
+            tag("<="),
+            tag(">="),
+            tag("&&"),
+            tag("||"),
+            tag("::"),
+            tag("=>"),
+            tag("->"),
+            tag("<<"),
+            tag(">>"),
+        )),
+        recognize(one_of("+-*/%=<>!&|^~.,;:?@#")),
+    )))(input)
+}
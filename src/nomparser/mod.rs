@@ -1,6 +1,7 @@
 use crate::{
     ast::node::*,
     ast::node::{
+        enums::EnumDefNode,
         function::FuncDefNode,
         global::GlobalNode,
         interface::TraitDefNode,
@@ -28,6 +29,7 @@ use self::{
     comment::*,
     constval::*,
     control::*,
+    enums::*,
     error::{alt_except, except},
     expression::*,
     function::*,
@@ -47,6 +49,7 @@ pub mod array;
 pub mod comment;
 pub mod constval;
 pub mod control;
+pub mod enums;
 pub mod expression;
 pub mod function;
 pub mod helper;
@@ -69,6 +72,7 @@ pub enum TopLevel {
     Use(Box<NodeEnum>),
     ImplDef(ImplNode),
     TraitDef(TraitDefNode),
+    EnumDef(EnumDefNode),
 }
 
 #[derive(Clone)]
@@ -33,6 +33,7 @@ pub fn program(input: Span) -> IResult<Span, Box<NodeEnum>> {
     let mut uses = vec![];
     let mut traits = vec![];
     let mut trait_impls = vec![];
+    let mut enums = vec![];
     loop {
         let top = top_level_statement(input);
         if let Ok((i, t)) = top {
@@ -97,6 +98,10 @@ pub fn program(input: Span) -> IResult<Span, Box<NodeEnum>> {
                     traits.push(tr.clone());
                     nodes.push(Box::new(tr.into()));
                 }
+                TopLevel::EnumDef(e) => {
+                    enums.push(e.clone());
+                    nodes.push(Box::new(e.into()));
+                }
             }
             input = i;
         } else if let Err(err) = top {
@@ -117,6 +122,7 @@ pub fn program(input: Span) -> IResult<Span, Box<NodeEnum>> {
             uses,
             traits,
             trait_impls,
+            enums,
         }
         .into(),
     );
@@ -127,6 +133,7 @@ fn top_level_statement(input: Span) -> IResult<Span, Box<TopLevel>> {
     delspace(alt((
         del_newline_or_space!(function_def),
         del_newline_or_space!(struct_def),
+        del_newline_or_space!(enum_def),
         del_newline_or_space!(impl_def),
         del_newline_or_space!(macro_parser),
         map_res(
@@ -75,6 +75,7 @@ pub fn statement_block(input: Span) -> IResult<Span, StatementsNode> {
 /// | if_statement
 /// | while_statement
 /// | for_statement
+/// | match_exp
 /// | break_statement
 /// | continue_statement
 /// | return_statement
@@ -89,6 +90,7 @@ pub fn statement(input: Span) -> IResult<Span, Box<NodeEnum>> {
         if_statement,
         while_statement,
         for_statement,
+        match_exp,
         break_statement,
         continue_statement,
         return_statement,
@@ -139,14 +141,40 @@ pub fn new_variable(input: Span) -> IResult<Span, Box<NodeEnum>> {
 }
 
 #[test_parser("a = 1")]
+#[test_parser("a += 1")]
+#[test_parser("a.b[i] -= c * 2")]
+#[test_parser("*a <<= 2")]
+#[test_parser("a >>= 2")]
+#[test_parser("a |= b | c")]
+#[test_parser_error("a =+ 1")]
+/// ```ebnf
+/// assignment = pointer_exp ("=" | "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "|=" | "^=" | "<<=" | ">>=") logic_exp ;
+/// ```
 pub fn assignment(input: Span) -> IResult<Span, Box<NodeEnum>> {
     delspace(map_res(
-        tuple((pointer_exp, tag_token_symbol(TokenType::ASSIGN), logic_exp)),
-        |(left, _op, right)| {
+        tuple((
+            pointer_exp,
+            alt((
+                tag_token_symbol(TokenType::ASSIGN),
+                tag_token_symbol(TokenType::PLUS_ASSIGN),
+                tag_token_symbol(TokenType::MINUS_ASSIGN),
+                tag_token_symbol(TokenType::MUL_ASSIGN),
+                tag_token_symbol(TokenType::DIV_ASSIGN),
+                tag_token_symbol(TokenType::MOD_ASSIGN),
+                tag_token_symbol(TokenType::BIT_AND_ASSIGN),
+                tag_token_symbol(TokenType::BIT_OR_ASSIGN),
+                tag_token_symbol(TokenType::BIT_XOR_ASSIGN),
+                tag_token_symbol(TokenType::LEFT_SHIFT_ASSIGN),
+                tag_token_symbol(TokenType::RIGHT_SHIFT_ASSIGN),
+            )),
+            logic_exp,
+        )),
+        |(left, op, right)| {
             let range = left.range().start.to(right.range().end);
             res_enum(
                 AssignNode {
                     var: left,
+                    op,
                     exp: right,
                     range,
                 }
@@ -1,6 +1,6 @@
 use crate::nomparser::Span;
 use crate::{
-    ast::node::types::{ArrayTypeNameNode, TypeNameNode},
+    ast::node::types::{ArrayTypeNameNode, ClosureTypeNode, TypeNameNode},
     ast::{
         node::{
             interface::TraitDefNode,
@@ -14,7 +14,7 @@ use nom::sequence::preceded;
 use nom::{
     branch::alt,
     combinator::{map_res, opt},
-    multi::{many0, separated_list1},
+    multi::{many0, separated_list0, separated_list1},
     sequence::{pair, tuple},
     IResult,
 };
@@ -25,7 +25,7 @@ pub fn type_name(input: Span) -> IResult<Span, Box<TypeNodeEnum>> {
     delspace(map_res(
         pair(
             many0(tag_token_symbol(TokenType::TAKE_VAL)),
-            alt((basic_type, array_type)),
+            alt((basic_type, array_type, closure_type)),
         ),
         |(pts, n)| {
             let mut node = n;
@@ -61,17 +61,23 @@ pub fn basic_type(input: Span) -> IResult<Span, Box<TypeNodeEnum>> {
     ))(input)
 }
 
+#[test_parser("[i64 * 3]")]
+#[test_parser("[i64]")]
+#[test_parser("[[i64] * 3]")]
+#[test_parser_error("[i64 *]")]
+/// ```ebnf
+/// array_type = "[" type_name ("*" number)? "]" ;
+/// ```
 fn array_type(input: Span) -> IResult<Span, Box<TypeNodeEnum>> {
     map_res(
         tuple((
             tag_token_symbol(TokenType::LBRACKET),
             type_name,
-            tag_token_symbol(TokenType::MUL),
-            number,
+            opt(preceded(tag_token_symbol(TokenType::MUL), number)),
             tag_token_symbol(TokenType::RBRACKET),
         )),
-        |(_, tp, _, size, _)| {
-            let range = size.range().start.to(tp.range().end);
+        |((_, lb), tp, size, (_, rb))| {
+            let range = lb.start.to(rb.end);
 
             Ok::<_, ()>(Box::new(TypeNodeEnum::ArrayTypeNode(ArrayTypeNameNode {
                 id: tp,
@@ -82,6 +88,40 @@ fn array_type(input: Span) -> IResult<Span, Box<TypeNodeEnum>> {
     )(input)
 }
 
+#[test_parser("|i64, i64| => i64")]
+#[test_parser("|| => void")]
+#[test_parser("|*i64|")]
+#[test_parser("|i64| => |i64| => i64")]
+/// ```enbf
+/// closure_type = "|" (type_name ("," type_name)*)? "|" ("=>" type_name)? ;
+/// ```
+fn closure_type(input: Span) -> IResult<Span, Box<TypeNodeEnum>> {
+    map_res(
+        tuple((
+            tag_token_symbol(TokenType::GENERIC_SEP),
+            separated_list0(tag_token_symbol(TokenType::COMMA), type_name),
+            tag_token_symbol(TokenType::GENERIC_SEP),
+            opt(preceded(tag_token_symbol(TokenType::ARROW), type_name)),
+        )),
+        |((_, start), arg_types, (_, end), ret)| {
+            let range = match &ret {
+                Some(ret) => start.start.to(ret.range().end),
+                None => start.start.to(end.end),
+            };
+            let ret_type = ret.unwrap_or_else(|| {
+                let mut ret = TypeNameNode::new_from_str("void");
+                ret.range = end;
+                Box::new(TypeNodeEnum::BasicTypeNode(ret))
+            });
+            Ok::<_, ()>(Box::new(TypeNodeEnum::ClosureTypeNode(ClosureTypeNode {
+                arg_types,
+                ret_type,
+                range,
+            })))
+        },
+    )(input)
+}
+
 /// ```enbf
 /// generic_type_def = "<" identifier ("|" identifier)* ">" ;
 /// ```
@@ -155,8 +195,8 @@ pub fn trait_def(input: Span) -> IResult<Span, Box<TraitDefNode>> {
             many0(del_newline_or_space!(function_def)),
             del_newline_or_space!(tag_token_symbol(TokenType::RBRACE)),
         )),
-        |((modifier, _), id, derives, _, defs, (_, rr))| {
-            let range = id.range().start.to(rr.end);
+        |((modifier, (_, start)), id, derives, _, defs, (_, rr))| {
+            let range = start.start.to(rr.end);
             let mut de = vec![];
             if let Some(derives) = derives {
                 for d in derives {
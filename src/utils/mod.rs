@@ -1,4 +1,6 @@
+pub mod plc_build;
 pub mod plc_new;
+pub mod plc_test;
 pub mod read_config;
 pub mod test_symbol;
 
@@ -0,0 +1,368 @@
+use std::{
+    cell::RefCell,
+    env, fs,
+    path::{Path, PathBuf},
+    process::{exit, Command},
+    sync::{Arc, Mutex},
+};
+
+use colored::Colorize;
+use kagari::manifest::find_workspace;
+
+use crate::{
+    ast::{
+        compiler::{self, ActionType, HashOptimizationLevel, TargetOptions},
+        node::NodeEnum,
+    },
+    db::Database,
+    lsp::mem_docs::{self, MemDocsInput},
+    nomparser::{program::program, Span},
+    utils::read_config::{load_project_config_from, Config},
+};
+
+/// 构建配置，决定优化等级以及产物所在的子目录
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Debug,
+    Release,
+}
+
+impl Profile {
+    pub fn new(release: bool) -> Self {
+        if release {
+            Profile::Release
+        } else {
+            Profile::Debug
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Profile::Debug => "debug",
+            Profile::Release => "release",
+        }
+    }
+
+    pub fn optimization(&self) -> HashOptimizationLevel {
+        match self {
+            Profile::Debug => HashOptimizationLevel::None,
+            Profile::Release => HashOptimizationLevel::Aggressive,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    pub profile: Profile,
+    /// 覆盖Kagari.toml中的`target_dir`
+    pub target_dir: Option<String>,
+    pub genir: bool,
+    pub no_cache: bool,
+    pub target: TargetOptions,
+    /// 只构建工作区中的这个包
+    pub package: Option<String>,
+    /// 构建工作区中的所有包
+    pub all: bool,
+}
+
+/// 项目的入口是否定义了main函数
+///
+/// 没有main的项目是库，只能作为其他项目的依赖被编译。入口无法读取或解析时返回true，
+/// 交给编译器报告错误
+fn is_binary(root: &Path, config: &Config) -> bool {
+    let text = match fs::read_to_string(root.join(&config.entry)) {
+        Ok(text) => text,
+        Err(_) => return true,
+    };
+    match program(Span::new_extra(&text, false)) {
+        Ok((_, node)) => match *node {
+            NodeEnum::Program(prog) => prog
+                .fntypes
+                .iter()
+                .any(|f| f.id.name == "main" && !f.declare),
+            _ => true,
+        },
+        Err(_) => true,
+    }
+}
+
+/// 当前项目所在的工作区（不在工作区中时为项目本身）的根目录与配置，以及需要构建的包
+///
+/// 没有指定`package`和`all`时构建当前项目，在只有`[workspace]`的根目录中时构建所有成员。
+/// 批量构建时跳过库，显式选择库时报错
+fn select_packages(
+    root: PathBuf,
+    config: Config,
+    package: &Option<String>,
+    all: bool,
+) -> Result<(PathBuf, Config, Vec<(PathBuf, Config)>), String> {
+    let (ws_root, ws_config) =
+        find_workspace(&root).unwrap_or_else(|| (root.clone(), config.clone()));
+    let ws_root = dunce::canonicalize(ws_root).map_err(|e| e.to_string())?;
+    let mut packages = vec![];
+    if !ws_config.entry.is_empty() {
+        packages.push((ws_root.clone(), ws_config.clone()));
+    }
+    for dir in ws_config.members(&ws_root) {
+        let dir = dunce::canonicalize(dir).map_err(|e| e.to_string())?;
+        let config = Config::load(&dir)?;
+        packages.push((dir, config));
+    }
+    let selected = match package {
+        Some(name) => {
+            let selected = packages
+                .into_iter()
+                .filter(|(_, c)| &c.project == name)
+                .collect::<Vec<_>>();
+            if selected.is_empty() {
+                return Err(format!("package `{}` not found", name));
+            }
+            selected
+        }
+        None if all || config.entry.is_empty() => packages
+            .into_iter()
+            .filter(|(root, config)| is_binary(root, config))
+            .collect(),
+        None => vec![(root, config)],
+    };
+    if let Some((_, config)) = selected
+        .iter()
+        .find(|(root, config)| !is_binary(root, config))
+    {
+        return Err(format!(
+            "package `{}` is a library, it is built as a dependency of the packages using it",
+            config.project
+        ));
+    }
+    if selected.is_empty() {
+        return Err("no package to build".to_string());
+    }
+    Ok((ws_root, ws_config, selected))
+}
+
+/// 构建`dir`所在的项目，成功时返回生成的可执行文件路径
+///
+/// 产物位于`<target_dir>/<profile>/`，交叉编译时位于`<target_dir>/<triple>/<profile>/`，
+/// 可执行文件以项目名命名。工作区的成员共用工作区根目录下的产物目录与模块的bitcode，
+/// bitcode没有变化时会复用上次生成的目标文件
+pub fn build(dir: &Path, opts: &BuildOptions) -> Result<Vec<PathBuf>, String> {
+    let (root, config) = load_project_config_from(dir)?;
+    let (ws_root, ws_config, packages) = select_packages(root, config, &opts.package, opts.all)?;
+    let target_dir = ws_root.join(get_target_dir(&opts.target_dir, &ws_config));
+    let mut out_dir = target_dir.clone();
+    if let Some(triple) = &opts.target.triple {
+        out_dir.push(triple);
+    }
+    out_dir.push(opts.profile.name());
+    fs::create_dir_all(&out_dir).map_err(|e| e.to_string())?;
+    packages
+        .iter()
+        .map(|(root, config)| build_package(opts, root, config, &target_dir, &out_dir))
+        .collect()
+}
+
+fn build_package(
+    opts: &BuildOptions,
+    root: &Path,
+    config: &Config,
+    target_dir: &Path,
+    out_dir: &Path,
+) -> Result<PathBuf, String> {
+    let out = out_dir.join(&config.project);
+    let windows = match &opts.target.triple {
+        Some(triple) => triple.contains("windows"),
+        None => cfg!(target_os = "windows"),
+    };
+    let exe = if windows {
+        out.with_extension("exe")
+    } else {
+        out.clone()
+    };
+    _ = fs::remove_file(&exe);
+
+    let db = Database::default();
+    let op = compiler::Options {
+        genir: opts.genir,
+        printast: false,
+        flow: false,
+        fmt: false,
+        expand_macros: false,
+        no_cache: opts.no_cache,
+        target: opts.target.clone(),
+        test_harness: false,
+        target_dir: Some(target_dir.to_path_buf()),
+        optimization: opts.profile.optimization(),
+    };
+    let entry = root.join(&config.entry);
+    let mem = MemDocsInput::new(
+        &db,
+        Arc::new(Mutex::new(RefCell::new(mem_docs::MemDocs::new()))),
+        entry.to_str().unwrap().to_string(),
+        op.clone(),
+        ActionType::Compile,
+        None,
+        None,
+    );
+    compiler::compile(&db, mem, out.to_str().unwrap().to_string(), op);
+    if !exe.exists() {
+        return Err(format!("failed to build project {}", config.project));
+    }
+    Ok(exe)
+}
+
+pub(crate) fn get_target_dir(target_dir: &Option<String>, config: &Config) -> String {
+    target_dir
+        .clone()
+        .or_else(|| config.target_dir.clone())
+        .unwrap_or_else(|| "target".to_string())
+}
+
+/// 删除`dir`所在项目的构建产物与增量编译缓存
+///
+/// 未配置`target_dir`时产物位于项目根目录的target下，所以它和`target_dir`都会被删除
+pub fn clean(dir: &Path, target_dir: Option<String>) -> Result<(), String> {
+    let (root, config) = load_project_config_from(dir)?;
+    let (root, config) = find_workspace(&root).unwrap_or((root, config));
+    let mut dirs = vec![root.join("target")];
+    let custom = root.join(get_target_dir(&target_dir, &config));
+    if !dirs.contains(&custom) {
+        dirs.push(custom);
+    }
+    for dir in dirs {
+        if dir.exists() {
+            fs::remove_dir_all(&dir)
+                .map_err(|e| format!("failed to remove {}: {}", dir.display(), e))?;
+            eprintln!("{} `{}`", "Removed".green().bold(), dir.display());
+        }
+    }
+    Ok(())
+}
+
+/// 当前目录
+fn current_dir() -> PathBuf {
+    env::current_dir().unwrap_or_else(|e| {
+        eprintln!("{}", e.to_string().bright_red());
+        exit(1);
+    })
+}
+
+pub fn clean_project(target_dir: Option<String>) {
+    if let Err(e) = clean(&current_dir(), target_dir) {
+        eprintln!("{}", e.bright_red());
+        exit(1);
+    }
+}
+
+pub fn build_project(opts: &BuildOptions) {
+    if let Err(e) = build(&current_dir(), opts) {
+        eprintln!("{}", e.bright_red());
+        exit(1);
+    }
+}
+
+/// 构建并运行当前项目，以程序的退出码退出
+pub fn run_project(opts: &BuildOptions, args: Vec<String>) {
+    let exe = match build(&current_dir(), opts) {
+        Ok(mut exes) if exes.len() == 1 => exes.remove(0),
+        Ok(_) => {
+            eprintln!(
+                "{}",
+                "the workspace has multiple packages, use `-p` to specify which one to run"
+                    .bright_red()
+            );
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e.bright_red());
+            exit(1);
+        }
+    };
+    eprintln!("{} `{}`", "Running".green().bold(), exe.display());
+    match Command::new(&exe).args(args).status() {
+        Ok(status) => exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!("failed to run {}: {}", exe.display(), e).bright_red()
+            );
+            exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        assert_eq!(Profile::new(false), Profile::Debug);
+        assert_eq!(Profile::new(true).name(), "release");
+        assert_eq!(
+            Profile::Release.optimization(),
+            HashOptimizationLevel::Aggressive
+        );
+        assert_eq!(Profile::Debug.optimization(), HashOptimizationLevel::None);
+    }
+
+    #[test]
+    fn test_select_packages() {
+        let ws = dunce::canonicalize("test/workspace").unwrap();
+        let app = ws.join("app");
+        let select = |dir: &Path, package: Option<&str>, all: bool| {
+            let config = Config::load(dir).unwrap();
+            select_packages(
+                dir.to_path_buf(),
+                config,
+                &package.map(|p| p.to_string()),
+                all,
+            )
+            .map(|(root, _, packages)| {
+                assert_eq!(root, ws);
+                packages
+                    .into_iter()
+                    .map(|(_, c)| c.project)
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(select(&app, None, false).unwrap(), vec!["app"]);
+        // lib没有main，批量构建时被跳过
+        assert_eq!(select(&app, None, true).unwrap(), vec!["app"]);
+        assert!(select(&app, Some("lib"), false)
+            .unwrap_err()
+            .contains("is a library"));
+        assert!(select(&ws.join("lib"), None, false).is_err());
+        assert!(select(&app, Some("nope"), false).is_err());
+        // 只有[workspace]的根目录中构建所有成员
+        assert_eq!(select(&ws, None, false).unwrap(), vec!["app"]);
+    }
+
+    #[test]
+    fn test_build_workspace() {
+        let _l = crate::utils::plc_new::tests::TEST_COMPILE_MUTEX
+            .lock()
+            .unwrap();
+        let ws = dunce::canonicalize("test/workspace").unwrap();
+        let opts = BuildOptions {
+            profile: Profile::Debug,
+            target_dir: None,
+            genir: false,
+            no_cache: false,
+            target: TargetOptions::default(),
+            package: None,
+            all: true,
+        };
+        let exes = build(&ws.join("app"), &opts).unwrap();
+        let out = ws.join("target").join("debug").join("app");
+        let expected = if cfg!(target_os = "windows") {
+            out.with_extension("exe")
+        } else {
+            out
+        };
+        assert_eq!(exes, vec![expected.clone()]);
+        // 构建不改变进程的当前目录
+        assert_ne!(env::current_dir().unwrap(), ws);
+        let status = Command::new(&expected).status().unwrap();
+        assert!(status.success());
+    }
+}
@@ -87,6 +87,11 @@ pub mod tests {
             printast: false,
             flow: false,
             fmt: false,
+            expand_macros: false,
+            no_cache: false,
+            target: Default::default(),
+            test_harness: false,
+            target_dir: None,
             optimization: HashOptimizationLevel::Aggressive,
         };
 
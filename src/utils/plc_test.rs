@@ -0,0 +1,385 @@
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    process::{exit, Command},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use colored::Colorize;
+use kagari::manifest::find_workspace;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    ast::{
+        compiler::{self, ActionType, HashOptimizationLevel},
+        node::NodeEnum,
+    },
+    db::Database,
+    lsp::mem_docs::{self, MemDocsInput},
+    nomparser::{program::program, Span},
+    utils::{plc_build::get_target_dir, read_config::load_project_config},
+};
+
+/// 生成的测试入口文件名，放在项目的target目录下
+const HARNESS_NAME: &str = "test_harness";
+
+/// 被`#[test]`标记的测试函数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestFn {
+    /// 函数所在模块的use路径，如`project1::test::ops`
+    pub mod_path: String,
+    pub name: String,
+}
+
+impl TestFn {
+    /// use之后用来引用模块的名字，即路径的最后一段
+    pub fn mod_name(&self) -> &str {
+        self.mod_path.rsplit("::").next().unwrap()
+    }
+    /// 报告中显示的名字，去掉了项目名前缀
+    pub fn display_name(&self) -> String {
+        let path = self
+            .mod_path
+            .split_once("::")
+            .map_or(self.mod_path.as_str(), |(_, p)| p);
+        format!("{}::{}", path, self.name)
+    }
+}
+
+/// 收集项目中所有的测试函数
+///
+/// 会跳过target目录、隐藏目录以及带有自己的Kagari.toml的子项目。
+/// 定义了main函数的文件不能被测试入口引用（会产生两个main），其中有测试时返回错误
+pub fn discover_tests(root: &Path, project: &str) -> Result<Vec<TestFn>, String> {
+    let mut files = vec![];
+    collect_pi_files(root, &mut files);
+    files.sort();
+    let mut tests = vec![];
+    for file in files {
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let node = match program(Span::new_extra(&text, false)) {
+            Ok((_, node)) => node,
+            Err(e) => {
+                log::warn!("failed to parse {}: {:?}", file.display(), e);
+                continue;
+            }
+        };
+        let prog = match *node {
+            NodeEnum::Program(prog) => prog,
+            _ => continue,
+        };
+        let names = prog
+            .fntypes
+            .iter()
+            .filter(|f| f.is_test && !f.id.name.starts_with('|'))
+            .map(|f| f.id.name.clone())
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            continue;
+        }
+        if prog
+            .fntypes
+            .iter()
+            .any(|f| f.id.name == "main" && !f.declare)
+        {
+            return Err(format!(
+                "{} defines `main`, its {} test(s) cannot be run, please move them to another module",
+                file.display(),
+                names.len()
+            ));
+        }
+        let rel = file.strip_prefix(root).unwrap().with_extension("");
+        let mut mod_path = project.to_string();
+        for c in rel.components() {
+            mod_path.push_str("::");
+            mod_path.push_str(&c.as_os_str().to_string_lossy());
+        }
+        for name in names {
+            tests.push(TestFn {
+                mod_path: mod_path.clone(),
+                name,
+            });
+        }
+    }
+    Ok(tests)
+}
+
+fn collect_pi_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == "target" || name.starts_with('.') || path.join("Kagari.toml").exists() {
+                continue;
+            }
+            collect_pi_files(&path, files);
+        } else if path.extension().map_or(false, |e| e == "pi") {
+            files.push(path);
+        }
+    }
+}
+
+/// 生成测试入口的源码
+///
+/// 入口通过运行时函数`pl_test_index`得知需要运行的测试，每次只运行一个
+pub fn gen_harness(tests: &[TestFn]) -> String {
+    let mut src = String::new();
+    let mut used = vec![];
+    for t in tests {
+        if !used.contains(&&t.mod_path) {
+            src.push_str(&format!("use {};\n", t.mod_path));
+            used.push(&t.mod_path);
+        }
+    }
+    src.push_str("\nfn pl_test_index() i64;\n\npub fn main() i64 {\n");
+    src.push_str("    let idx = pl_test_index();\n");
+    for (i, t) in tests.iter().enumerate() {
+        src.push_str(&format!(
+            "    if idx == {} {{\n        {}::{}();\n        return 0;\n    }}\n",
+            i,
+            t.mod_name(),
+            t.name
+        ));
+    }
+    src.push_str("    return 1;\n}\n");
+    src
+}
+
+/// 把测试分为若干组，每组生成一个测试入口
+///
+/// 模块在入口中以路径的最后一段引用，不同路径下的同名模块会被分到不同的组中
+fn group_tests(tests: Vec<TestFn>) -> Vec<Vec<TestFn>> {
+    let mut groups: Vec<(FxHashMap<String, String>, Vec<TestFn>)> = vec![];
+    for t in tests {
+        let i = groups
+            .iter()
+            .position(|(names, _)| names.get(t.mod_name()).map_or(true, |p| p == &t.mod_path))
+            .unwrap_or_else(|| {
+                groups.push(Default::default());
+                groups.len() - 1
+            });
+        let (names, group) = &mut groups[i];
+        names.insert(t.mod_name().to_string(), t.mod_path.clone());
+        group.push(t);
+    }
+    groups.into_iter().map(|(_, g)| g).collect()
+}
+
+/// 生成并编译一个测试入口，返回可执行文件的路径
+fn build_harness(
+    target: &Path,
+    name: &str,
+    tests: &[TestFn],
+    optimization: HashOptimizationLevel,
+    no_cache: bool,
+) -> Option<PathBuf> {
+    let harness = target.join(name).with_extension("pi");
+    fs::write(&harness, gen_harness(tests)).unwrap();
+    let out = target.join(name);
+    let exe = if cfg!(target_os = "windows") {
+        out.with_extension("exe")
+    } else {
+        out.clone()
+    };
+    _ = fs::remove_file(&exe);
+    let db = Database::default();
+    let op = compiler::Options {
+        genir: false,
+        printast: false,
+        flow: false,
+        fmt: false,
+        expand_macros: false,
+        no_cache,
+        target: Default::default(),
+        test_harness: true,
+        target_dir: Some(target.to_path_buf()),
+        optimization,
+    };
+    let mem = MemDocsInput::new(
+        &db,
+        Arc::new(Mutex::new(RefCell::new(mem_docs::MemDocs::new()))),
+        harness.to_str().unwrap().to_string(),
+        op.clone(),
+        ActionType::Compile,
+        None,
+        None,
+    );
+    compiler::compile(&db, mem, out.to_str().unwrap().to_string(), op);
+    exe.exists().then_some(exe)
+}
+
+pub fn run_tests(filter: Option<String>, optimization: HashOptimizationLevel, no_cache: bool) {
+    let (root, config) = match load_project_config() {
+        Ok(re) => re,
+        Err(e) => {
+            eprintln!("{}", e.bright_red());
+            exit(1);
+        }
+    };
+    let all = match discover_tests(&root, &config.project) {
+        Ok(all) => all,
+        Err(e) => {
+            eprintln!("{}", e.bright_red());
+            exit(1);
+        }
+    };
+    let tests = all
+        .iter()
+        .filter(|t| {
+            filter
+                .as_ref()
+                .map_or(true, |f| t.display_name().contains(f.as_str()))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    let filtered = all.len() - tests.len();
+    if tests.is_empty() {
+        println!("\nrunning 0 tests\n");
+        println!(
+            "test result: {}. 0 passed; 0 failed; {} filtered out",
+            "ok".green(),
+            filtered
+        );
+        return;
+    }
+
+    // 与`plc build`一致，工作区中的成员共享工作区的target目录
+    let (ws_root, ws_config) =
+        find_workspace(&root).unwrap_or_else(|| (root.clone(), config.clone()));
+    let target = ws_root.join(get_target_dir(&None, &ws_config));
+    fs::create_dir_all(&target).unwrap();
+    let total = tests.len();
+    let mut runs = vec![];
+    for (i, group) in group_tests(tests).into_iter().enumerate() {
+        let name = if i == 0 {
+            HARNESS_NAME.to_string()
+        } else {
+            format!("{}_{}", HARNESS_NAME, i)
+        };
+        let exe = match build_harness(&target, &name, &group, optimization, no_cache) {
+            Some(exe) => exe,
+            None => {
+                eprintln!("{}", "failed to build the test harness".bright_red());
+                exit(1);
+            }
+        };
+        runs.extend(
+            group
+                .into_iter()
+                .enumerate()
+                .map(|(idx, t)| (exe.clone(), idx, t)),
+        );
+    }
+
+    println!("\nrunning {} tests", total);
+    let start = Instant::now();
+    let mut failures = vec![];
+    for (exe, i, t) in runs {
+        let name = t.display_name();
+        let now = Instant::now();
+        // 每个测试都在单独的进程中运行，pl_panic只会结束当前测试
+        let output = Command::new(&exe)
+            .env("PL_TEST_INDEX", i.to_string())
+            .output();
+        let elapsed = now.elapsed();
+        match output {
+            Ok(o) if o.status.success() => {
+                println!("test {} ... {} ({:.2?})", name, "ok".green(), elapsed);
+            }
+            Ok(o) => {
+                println!("test {} ... {} ({:.2?})", name, "FAILED".red(), elapsed);
+                let mut msg = String::from_utf8_lossy(&o.stdout).to_string();
+                msg.push_str(&String::from_utf8_lossy(&o.stderr));
+                failures.push((name, msg));
+            }
+            Err(e) => {
+                println!("test {} ... {} ({:.2?})", name, "FAILED".red(), elapsed);
+                failures.push((name, format!("failed to run test: {}", e)));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        println!("\nfailures:\n");
+        for (name, msg) in failures.iter() {
+            println!("---- {} ----\n{}", name, msg);
+        }
+        println!("failures:");
+        for (name, _) in failures.iter() {
+            println!("    {}", name);
+        }
+    }
+    let result = if failures.is_empty() {
+        "ok".green()
+    } else {
+        "FAILED".red()
+    };
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} filtered out; finished in {:.2?}\n",
+        result,
+        total - failures.len(),
+        failures.len(),
+        filtered,
+        start.elapsed()
+    );
+    if !failures.is_empty() {
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_tests() {
+        let root = dunce::canonicalize("test").unwrap();
+        let tests = discover_tests(&root, "project1").unwrap();
+        assert!(tests.contains(&TestFn {
+            mod_path: "project1::test::ops".to_string(),
+            name: "test_cast".to_string(),
+        }));
+        let src = gen_harness(&tests);
+        assert!(src.contains("use project1::test::ops;"));
+        assert!(src.contains("ops::test_cast();"));
+        assert_eq!(group_tests(tests).len(), 1);
+        // 定义了main的文件中的测试无法被测试入口调用
+        let root = dunce::canonicalize("test/harness_main").unwrap();
+        let err = discover_tests(&root, "hmain").unwrap_err();
+        assert!(err.contains("defines `main`"), "{}", err);
+    }
+
+    #[test]
+    fn test_group_tests() {
+        let t = |mod_path: &str, name: &str| TestFn {
+            mod_path: mod_path.to_string(),
+            name: name.to_string(),
+        };
+        let groups = group_tests(vec![
+            t("p::a::util", "t1"),
+            t("p::b::util", "t2"),
+            t("p::a::util", "t3"),
+            t("p::ops", "t4"),
+        ]);
+        // 不同路径下的同名模块在不同的测试入口中
+        assert_eq!(
+            groups,
+            vec![
+                vec![
+                    t("p::a::util", "t1"),
+                    t("p::a::util", "t3"),
+                    t("p::ops", "t4")
+                ],
+                vec![t("p::b::util", "t2")],
+            ]
+        );
+    }
+}
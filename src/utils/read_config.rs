@@ -1,11 +1,22 @@
-use std::{env, fs::read_to_string, path::PathBuf};
+use std::{
+    env,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
 
+use kagari::{
+    manifest::find_workspace,
+    resolve::{resolve, ResolveOptions},
+};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
 use rustc_hash::FxHashMap;
-use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 use crate::{ast::compiler::COMPILE_PROGRESS, nomparser::SourceProgram, Db};
 
+pub use kagari::manifest::{Config, Dependency};
+
 pub fn get_config_path(current: String) -> Result<String, &'static str> {
     let mut cur_path = PathBuf::from(current);
     if cur_path.is_file() && !cur_path.pop() {
@@ -41,34 +52,87 @@ pub fn get_config_path(current: String) -> Result<String, &'static str> {
     return get_config_path(next_path);
 }
 
-#[derive(Deserialize, Clone, Debug, PartialEq, Eq, Default, Hash)]
-pub struct Config {
-    pub project: String,
-    pub entry: String,
-    pub deps: Option<BTreeMap<String, Dependency>>,
-    #[serde(skip)]
-    pub root: String,
+/// 从当前目录向上查找并解析Kagari.toml，返回项目根目录与配置
+///
+/// 只解析配置文件本身，不会处理依赖
+pub fn load_project_config() -> Result<(PathBuf, Config), String> {
+    let cwd = env::current_dir().map_err(|e| e.to_string())?;
+    load_project_config_from(&cwd)
 }
 
-#[derive(Deserialize, Clone, Debug, PartialEq, Eq, Default, Hash)]
-pub struct Dependency {
-    pub version: Option<String>,
-    #[serde(default)]
-    pub path: String,
-    pub git: Option<String>,
-    pub head: Option<String>,
+/// 从`dir`向上查找Kagari.toml，返回项目的根目录与配置
+pub fn load_project_config_from(dir: &Path) -> Result<(PathBuf, Config), String> {
+    let config_path = get_config_path(dir.to_string_lossy().to_string())?;
+    let root = dunce::canonicalize(Path::new(&config_path).parent().unwrap())
+        .map_err(|e| e.to_string())?;
+    let config = read_to_string(&config_path)
+        .map_err(|e| format!("{:?}", e))
+        .and_then(|s| toml::from_str::<Config>(&s).map_err(|e| format!("{:?}", e)))
+        .map_err(|e| format!("配置文件解析错误:{}", e))?;
+    Ok((root, config))
 }
 
-#[derive(Deserialize, Clone, Debug, PartialEq, Eq, Default, Hash, Serialize)]
-pub struct ModSum {
-    pub name: String,
-    pub git: Option<GitInfo>,
+/// 文件所在项目的根目录，项目属于工作区时返回工作区的根目录
+pub fn get_project_root(file: &str) -> Result<PathBuf, String> {
+    let config_path = get_config_path(file.to_string())?;
+    let root = dunce::canonicalize(Path::new(&config_path).parent().unwrap())
+        .map_err(|e| e.to_string())?;
+    match find_workspace(&root) {
+        Some((ws_root, _)) => dunce::canonicalize(ws_root).map_err(|e| e.to_string()),
+        None => Ok(root),
+    }
+}
+
+/// 包的依赖项，以及解析出的依赖名和源码目录
+type ResolvedDeps = (
+    Option<BTreeMap<String, Dependency>>,
+    BTreeMap<String, PathBuf>,
+);
+
+lazy_static! {
+    static ref RESOLVE_OPTIONS: Mutex<ResolveOptions> = Mutex::new(ResolveOptions::default());
+    /// 依赖包使用依赖它的项目解析出的版本，保证同名的包只有一个版本
+    static ref RESOLVED: Mutex<FxHashMap<PathBuf, ResolvedDeps>> =
+        Mutex::new(FxHashMap::default());
 }
 
-#[derive(Deserialize, Clone, Debug, PartialEq, Eq, Default, Hash, Serialize)]
-pub struct GitInfo {
-    pub url: String,
-    pub commit: String,
+/// 设置依赖解析的选项（`--locked`、`--offline`）
+pub fn set_resolve_options(options: ResolveOptions) {
+    *RESOLVE_OPTIONS.lock() = options;
+}
+
+/// 解析项目的依赖，返回依赖名和源码目录
+fn resolve_deps(
+    root: &Path,
+    config: &Config,
+    third_party: &Path,
+) -> Result<BTreeMap<String, PathBuf>, String> {
+    if let Some((deps, resolved)) = RESOLVED.lock().get(root) {
+        if deps == &config.deps {
+            return Ok(resolved.clone());
+        }
+    }
+    let options = *RESOLVE_OPTIONS.lock();
+    // 工作区的成员使用整个工作区解析出的依赖
+    let workspace = find_workspace(root).filter(|(ws_root, _)| ws_root != root);
+    let resolve = match &workspace {
+        Some((ws_root, ws_config)) => {
+            let third_party = ws_config.third_party_dir(ws_root)?;
+            resolve(ws_root, ws_config, &third_party, options)
+        }
+        None => resolve(root, config, third_party, options),
+    }
+    .map_err(|e| e.to_string())?;
+    let mut cache = RESOLVED.lock();
+    for pkg in resolve.packages.values() {
+        if let Ok(c) = Config::load(&pkg.path) {
+            cache.insert(pkg.path.clone(), (c.deps, resolve.deps_of(Some(&pkg.name))));
+        }
+    }
+    Ok(match workspace {
+        Some(_) => resolve.deps_of(Some(&config.project)),
+        None => resolve.deps_of(None),
+    })
 }
 
 #[salsa::tracked]
@@ -76,6 +140,7 @@ pub fn get_config(db: &dyn Db, entry: SourceProgram) -> Result<Config, String> {
     let config = entry.text(db);
     let mut config_root = PathBuf::from(entry.path(db)); // xxx/Kagari.toml
     config_root.pop();
+    let config_root = dunce::canonicalize(&config_root).map_err(|e| format!("error: {:?}", e))?;
     let re = toml::from_str(config);
     if let Err(re) = re {
         return Err(format!("配置文件解析错误:{:?}", re));
@@ -88,7 +153,6 @@ pub fn get_config(db: &dyn Db, entry: SourceProgram) -> Result<Config, String> {
     }
     let mut deps = BTreeMap::<String, Dependency>::default();
     let libroot = dunce::canonicalize(PathBuf::from(libroot.unwrap())).unwrap();
-    let lib_path = libroot.clone();
     let libroot = libroot.read_dir();
     if libroot.is_err() {
         return Err("KAGARI_LIB_ROOT没有指向合法的目录，无法找到系统库".to_string());
@@ -107,124 +171,32 @@ pub fn get_config(db: &dyn Db, entry: SourceProgram) -> Result<Config, String> {
             }
         }
     }
-    let binding = lib_path.join("thirdparty");
-    let third_party = binding.to_str().unwrap();
-    let mut i = 1;
-    let mut err = None;
-    let lockfile = config_root.join("Kagari.lock");
-    let mut sums = toml::from_str::<FxHashMap<String, ModSum>>(
-        &read_to_string(lockfile.clone()).unwrap_or_default(),
-    )
-    .unwrap_or_default();
-    let mut sum_changed = false;
-    if config.deps.is_none() {
-        config.deps = Some(deps);
-    } else {
-        let mut rawdeps = config.deps.clone().unwrap();
+    let third_party = config.third_party_dir(&config_root)?;
+    if let Some(rawdeps) = &config.deps {
         let pb = &COMPILE_PROGRESS;
         if pb.length().is_none() {
             pb.set_length(rawdeps.len() as u64);
         } else {
             pb.inc_length(rawdeps.len() as u64);
         }
-        // pb.set_prefix(format!("[{:3}/{:3}]", pb.position(), pb.length().unwrap()));
         pb.set_message("正在分析依赖");
-        rawdeps.iter_mut().for_each(|(k, v)| {
-            v.git
-                .clone()
-                .and_then(|git| {
-                    pb.set_message("正在下载依赖");
-                    // pb.set_prefix(format!("[{:3}/{:3}]", pb.position(), pb.length().unwrap()));
-                    i += 1;
-                    v.head
-                        .clone()
-                        .or_else(|| {
-                            pb.abandon_with_message(format!(
-                                "类型为git的依赖项{}未指定分支，无法下载依赖",
-                                k
-                            ));
-                            err = Some("类型为git的依赖项未指定分支，无法下载依赖".to_string());
-                            None
-                        })
-                        .map(|mut b| {
-                            let (child, target) = kagari::download_repo(&git, third_party);
-                            pb.set_message(format!("正在下载依赖{}", k));
-                            if child.is_some() {
-                                child.unwrap().unwrap();
-                            }
-                            if let Some(sum) = sums.get(k) {
-                                b = sum.git.clone().unwrap().commit;
-                            } else {
-                                sum_changed = true;
-                            }
-                            let target = kagari::cp_to_hash_dir(target.to_str().unwrap(), &b);
-                            sums.insert(
-                                k.clone(),
-                                ModSum {
-                                    name: k.clone(),
-                                    git: Some(GitInfo {
-                                        url: git,
-                                        commit: target
-                                            .file_name()
-                                            .unwrap()
-                                            .to_str()
-                                            .unwrap()
-                                            .to_string(),
-                                    }),
-                                },
-                            );
-                            let mut dep = Dependency::default();
-                            dep.path = target.to_string_lossy().to_string();
-                            deps.insert(k.clone(), dep);
-                        })
-                })
-                .or_else(|| {
-                    // pb.set_prefix(format!("[{:3}/{:3}]", pb.position(), pb.length().unwrap()));
-                    i += 1;
-                    pb.set_message(format!("正在分析依赖{}", k));
-                    if PathBuf::from(&v.path).is_absolute() {
-                        _ = dunce::canonicalize(&v.path)
-                            .map(|p| {
-                                v.path = p.to_str().unwrap().to_string();
-                                p
-                            })
-                            .map_err(|e| {
-                                pb.abandon_with_message(format!("error: {:?}", e));
-                                err = Some(format!("error: {:?}", e));
-                                format!("error: {:?}", e)
-                            });
-                    } else {
-                        _ = dunce::canonicalize(config_root.join(&v.path))
-                            .map(|p| {
-                                v.path = p.to_str().unwrap().to_string();
-                                p
-                            })
-                            .map_err(|e| {
-                                pb.abandon_with_message(format!("error: {:?}", e));
-                                err = Some(format!("error: {:?}", e));
-                                format!("error: {:?}", e)
-                            });
-                    }
-                    deps.insert(k.clone(), v.clone());
-                    None
-                });
-            pb.inc(1);
-        });
-        if err.is_some() {
-            return Err(err.unwrap());
+        let resolved = resolve_deps(&config_root, &config, &third_party).map_err(|e| {
+            pb.abandon_with_message(e.clone());
+            e
+        })?;
+        pb.inc(rawdeps.len() as u64);
+        for (name, path) in resolved {
+            deps.insert(
+                name,
+                Dependency {
+                    path: path.to_string_lossy().to_string(),
+                    ..Default::default()
+                },
+            );
         }
-        config.deps = Some(deps);
-    }
-    if sum_changed {
-        toml::to_string_pretty(&sums)
-            .map_err(|e| format!("error: {:?}", e))
-            .and_then(|s| std::fs::write(lockfile, s).map_err(|e| format!("error: {:?}", e)))?;
     }
-    config.root = dunce::canonicalize(config_root.clone())
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
+    config.deps = Some(deps);
+    config.root = config_root.to_str().unwrap().to_string();
     config.entry = dunce::canonicalize(config_root.join(&config.entry))
         .unwrap()
         .to_str()
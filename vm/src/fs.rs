@@ -0,0 +1,186 @@
+//! 文件相关的运行时函数
+//!
+//! 打开的文件保存在全局的文件表中，pivot lang代码持有的是表中的下标。
+//! 下标0、1、2固定为stdin、stdout、stderr
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, Read, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use internal_macro::is_runtime;
+
+use crate::{blocking, copy_bytes, copy_str, set_result};
+
+/// 打开文件的模式，需要与planglib/std/fs.pi中`open`、`create`、`append`传入的值保持一致
+const MODE_READ: i64 = 0;
+const MODE_WRITE: i64 = 1;
+const MODE_APPEND: i64 = 2;
+
+const STDIN: i64 = 0;
+const STDOUT: i64 = 1;
+const STDERR: i64 = 2;
+
+static FILES: Mutex<Vec<Option<File>>> = Mutex::new(Vec::new());
+
+#[cfg(feature = "jit")]
+pub fn reg() {
+    add_symbol_pl_fs_open();
+    add_symbol_pl_fs_close();
+    add_symbol_pl_fs_read();
+    add_symbol_pl_fs_read_all();
+    add_symbol_pl_fs_write();
+    add_symbol_pl_fs_exists();
+    add_symbol_pl_fs_remove();
+    add_symbol_pl_fs_create_dir();
+    add_symbol_pl_stdin_read_line();
+}
+
+fn fd_to_idx(fd: i64) -> usize {
+    (fd - 3) as usize
+}
+
+/// 对fd对应的文件执行f，fd不存在时返回None
+///
+/// 文件表的锁不会在阻塞期间持有，文件句柄会先被clone出来
+fn with_file<T>(fd: i64, f: impl FnOnce(&mut File) -> io::Result<T>) -> Option<T> {
+    if fd < 3 {
+        return None;
+    }
+    let mut file = {
+        let files = FILES.lock().unwrap();
+        files.get(fd_to_idx(fd))?.as_ref()?.try_clone().ok()?
+    };
+    blocking(|| f(&mut file)).ok()
+}
+
+/// 打开文件，失败时返回-1
+#[is_runtime]
+fn pl_fs_open(path: *const u8, len: i64, mode: i64) -> i64 {
+    let path = copy_str(path, len);
+    let file = blocking(|| match mode {
+        MODE_READ => File::open(&path),
+        MODE_WRITE => File::create(&path),
+        MODE_APPEND => OpenOptions::new().append(true).create(true).open(&path),
+        _ => Err(io::ErrorKind::InvalidInput.into()),
+    });
+    let file = match file {
+        Ok(file) => file,
+        Err(_) => return -1,
+    };
+    let mut files = FILES.lock().unwrap();
+    let idx = match files.iter().position(|f| f.is_none()) {
+        Some(idx) => {
+            files[idx] = Some(file);
+            idx
+        }
+        None => {
+            files.push(Some(file));
+            files.len() - 1
+        }
+    };
+    idx as i64 + 3
+}
+
+/// 关闭文件，fd不存在时返回false
+#[is_runtime]
+fn pl_fs_close(fd: i64) -> bool {
+    if fd < 3 {
+        return false;
+    }
+    let mut files = FILES.lock().unwrap();
+    match files.get_mut(fd_to_idx(fd)) {
+        Some(f) => f.take().is_some(),
+        None => false,
+    }
+}
+
+/// 最多读取max个字节，返回读到的字节数，到达文件末尾时返回0，失败时返回-1
+///
+/// 读到的内容通过`pl_take_result`获取
+#[is_runtime]
+fn pl_fs_read(fd: i64, max: i64) -> i64 {
+    let mut buf = vec![0; max.max(0) as usize];
+    let n = if fd == STDIN {
+        blocking(|| io::stdin().read(&mut buf)).ok()
+    } else {
+        with_file(fd, |f| f.read(&mut buf))
+    };
+    match n {
+        Some(n) => {
+            buf.truncate(n);
+            set_result(buf)
+        }
+        None => -1,
+    }
+}
+
+/// 读取剩余的全部内容，返回字节数，失败时返回-1
+#[is_runtime]
+fn pl_fs_read_all(fd: i64) -> i64 {
+    let mut buf = vec![];
+    let n = if fd == STDIN {
+        blocking(|| io::stdin().read_to_end(&mut buf)).ok()
+    } else {
+        with_file(fd, |f| f.read_to_end(&mut buf))
+    };
+    match n {
+        Some(_) => set_result(buf),
+        None => -1,
+    }
+}
+
+/// 写入len个字节，返回是否成功
+#[is_runtime]
+fn pl_fs_write(fd: i64, data: *const u8, len: i64) -> bool {
+    let data = copy_bytes(data, len);
+    match fd {
+        STDOUT => blocking(|| io::stdout().write_all(&data).and(io::stdout().flush())).is_ok(),
+        STDERR => blocking(|| io::stderr().write_all(&data)).is_ok(),
+        _ => with_file(fd, |f| f.write_all(&data)).is_some(),
+    }
+}
+
+#[is_runtime]
+fn pl_fs_exists(path: *const u8, len: i64) -> bool {
+    let path = copy_str(path, len);
+    blocking(|| Path::new(&path).exists())
+}
+
+/// 删除文件或空文件夹
+#[is_runtime]
+fn pl_fs_remove(path: *const u8, len: i64) -> bool {
+    let path = copy_str(path, len);
+    blocking(|| {
+        let path = Path::new(&path);
+        if path.is_dir() {
+            fs::remove_dir(path)
+        } else {
+            fs::remove_file(path)
+        }
+    })
+    .is_ok()
+}
+
+/// 创建文件夹，父文件夹不存在时会一起创建
+#[is_runtime]
+fn pl_fs_create_dir(path: *const u8, len: i64) -> bool {
+    let path = copy_str(path, len);
+    blocking(|| fs::create_dir_all(&path)).is_ok()
+}
+
+/// 从stdin读取一行，返回字节数（不包括换行符），到达末尾时返回-1
+#[is_runtime]
+fn pl_stdin_read_line() -> i64 {
+    let mut line = String::new();
+    match blocking(|| io::stdin().lock().read_line(&mut line)) {
+        Ok(0) | Err(_) => -1,
+        Ok(_) => {
+            let end = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(end);
+            set_result(line.into_bytes())
+        }
+    }
+}
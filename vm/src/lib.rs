@@ -1,11 +1,14 @@
 #![allow(improper_ctypes_definitions)]
 #![allow(clippy::missing_safety_doc)]
 
-use std::process::exit;
+use std::{cell::RefCell, env, process::exit};
 
 use backtrace::Backtrace;
 use internal_macro::is_runtime;
+pub mod fs;
 pub mod gc;
+pub mod process;
+pub mod time;
 
 #[is_runtime]
 fn test_vm_link() -> i64 {
@@ -20,24 +23,66 @@ fn printi64ln(i: i64) {
 #[cfg(feature = "jit")]
 pub fn reg() {
     gc::reg();
+    fs::reg();
+    process::reg();
+    time::reg();
+    add_symbol_pl_take_result();
 }
 
 #[is_runtime]
 fn pl_panic() {
-    println!("pivot lang panic occured!");
-    let bt = Backtrace::new();
-    println!("{:?}", bt);
+    match panic_location() {
+        Some(loc) => println!("pivot lang panic occured at {}", loc),
+        None => println!("pivot lang panic occured!"),
+    }
+    print_backtrace();
     exit(1);
 }
 
 #[is_runtime]
-fn ptr_to_int(ptr: *const u8) -> i64 {
-    ptr as i64
+fn pl_assert_failed() {
+    match panic_location() {
+        Some(loc) => println!("assertion failed at {}", loc),
+        None => println!("assertion failed"),
+    }
+    print_backtrace();
+    exit(1);
+}
+
+fn print_backtrace() {
+    let bt = Backtrace::new();
+    println!("{:?}", bt);
+}
+
+/// 从调用栈中找到第一个位于pivot lang源码中的栈帧，
+/// `core/panic.pi`中的帧会被跳过，这样`assert`报告的是调用者的位置
+fn panic_location() -> Option<String> {
+    let bt = Backtrace::new();
+    for frame in bt.frames() {
+        for symbol in frame.symbols() {
+            let (file, line) = match (symbol.filename(), symbol.lineno()) {
+                (Some(file), Some(line)) => (file, line),
+                _ => continue,
+            };
+            if file.extension().map_or(true, |e| e != "pi") || file.ends_with("core/panic.pi") {
+                continue;
+            }
+            return Some(match symbol.colno() {
+                Some(col) => format!("{}:{}:{}", file.display(), line, col),
+                None => format!("{}:{}", file.display(), line),
+            });
+        }
+    }
+    None
 }
 
+/// `plc test`生成的测试入口通过这个函数获取需要运行的测试序号
 #[is_runtime]
-fn int_to_ptr(i: i64) -> *const u8 {
-    i as *const u8
+fn pl_test_index() -> i64 {
+    env::var("PL_TEST_INDEX")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(-1)
 }
 
 #[is_runtime]
@@ -45,3 +90,51 @@ fn print_raw(bs: *const u8, len: i64) {
     let s = std::str::from_utf8(unsafe { std::slice::from_raw_parts(bs, len as usize) }).unwrap();
     print!("{}", s);
 }
+
+thread_local! {
+    /// 运行时函数产生的变长结果暂存在这里，由pivot lang代码分配好
+    /// 内存后通过`pl_take_result`取走
+    static RESULT: RefCell<Vec<u8>> = RefCell::new(vec![]);
+}
+
+/// 暂存运行时函数的结果，返回它的字节数
+///
+/// 结果不直接写进gc堆：阻塞期间gc可能运行并移动对象，
+/// 所以只能在阻塞结束之后，由pivot lang代码自己分配内存并复制
+pub(crate) fn set_result(bs: Vec<u8>) -> i64 {
+    let len = bs.len() as i64;
+    RESULT.with(|r| *r.borrow_mut() = bs);
+    len
+}
+
+/// 把暂存的结果复制到dst，dst至少要有上一次返回的长度那么大
+#[is_runtime]
+fn pl_take_result(dst: *mut u8) {
+    RESULT.with(|r| {
+        let bs = r.take();
+        std::ptr::copy_nonoverlapping(bs.as_ptr(), dst, bs.len());
+    });
+}
+
+/// 执行可能阻塞的操作（同步io、sleep等）
+///
+/// 期间当前线程被标记为stuck，gc不会等待它到达安全点，
+/// 所以`f`中不能访问gc堆上的内存
+pub(crate) fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    immix::thread_stuck_start();
+    let re = f();
+    immix::thread_stuck_end();
+    re
+}
+
+/// 把pivot lang传进来的字节复制一份，之后就可以在阻塞期间使用
+pub(crate) unsafe fn copy_bytes(bs: *const u8, len: i64) -> Vec<u8> {
+    if len <= 0 {
+        return vec![];
+    }
+    std::slice::from_raw_parts(bs, len as usize).to_vec()
+}
+
+pub(crate) unsafe fn copy_str(bs: *const u8, len: i64) -> String {
+    String::from_utf8_lossy(&copy_bytes(bs, len)).into_owned()
+}
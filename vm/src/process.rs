@@ -0,0 +1,54 @@
+//! 命令行参数、环境变量与进程相关的运行时函数
+
+use std::{env, process::exit};
+
+use internal_macro::is_runtime;
+
+use crate::{copy_str, set_result};
+
+#[cfg(feature = "jit")]
+pub fn reg() {
+    add_symbol_pl_argc();
+    add_symbol_pl_argv();
+    add_symbol_pl_getenv();
+    add_symbol_pl_setenv();
+    add_symbol_pl_exit();
+}
+
+#[is_runtime]
+fn pl_argc() -> i64 {
+    env::args_os().len() as i64
+}
+
+/// 第i个命令行参数的字节数，i越界时返回-1
+///
+/// 参数的内容通过`pl_take_result`获取
+#[is_runtime]
+fn pl_argv(i: i64) -> i64 {
+    if i < 0 {
+        return -1;
+    }
+    match env::args_os().nth(i as usize) {
+        Some(arg) => set_result(arg.to_string_lossy().into_owned().into_bytes()),
+        None => -1,
+    }
+}
+
+/// 环境变量的字节数，不存在时返回-1
+#[is_runtime]
+fn pl_getenv(name: *const u8, len: i64) -> i64 {
+    match env::var_os(copy_str(name, len)) {
+        Some(v) => set_result(v.to_string_lossy().into_owned().into_bytes()),
+        None => -1,
+    }
+}
+
+#[is_runtime]
+fn pl_setenv(name: *const u8, name_len: i64, value: *const u8, value_len: i64) {
+    env::set_var(copy_str(name, name_len), copy_str(value, value_len));
+}
+
+#[is_runtime]
+fn pl_exit(code: i64) {
+    exit(code as i32);
+}
@@ -0,0 +1,45 @@
+//! 时间相关的运行时函数
+
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use internal_macro::is_runtime;
+
+use crate::blocking;
+
+#[cfg(feature = "jit")]
+pub fn reg() {
+    add_symbol_pl_unix_millis();
+    add_symbol_pl_monotonic_nanos();
+    add_symbol_pl_sleep_millis();
+}
+
+/// 距离unix纪元的毫秒数
+#[is_runtime]
+fn pl_unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// 单调递增的纳秒数，只能用来计算时间间隔
+#[is_runtime]
+fn pl_monotonic_nanos() -> i64 {
+    static START: Mutex<Option<Instant>> = Mutex::new(None);
+    START
+        .lock()
+        .unwrap()
+        .get_or_insert_with(Instant::now)
+        .elapsed()
+        .as_nanos() as i64
+}
+
+#[is_runtime]
+fn pl_sleep_millis(ms: i64) {
+    if ms > 0 {
+        blocking(|| thread::sleep(Duration::from_millis(ms as u64)));
+    }
+}